@@ -0,0 +1,105 @@
+//! 目录扫描、哈希、打包、应用各环节的基准测试
+//!
+//! 默认在临时目录生成 `small` 规模的数据；较大的样本先用
+//! `cargo run --release --example bench_data -- <dir> --bench-mode large` 生成，
+//! 再以 `DFT_BENCH_DATA=<dir> cargo bench` 运行。
+
+mod support;
+
+use bin_diff_tool::patch::{apply_patch, create_patch};
+use bin_diff_tool::utils::{HashAlgorithm, hash_files, scan_directory, set_quiet};
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use std::path::PathBuf;
+use support::{BenchProfile, copy_tree, generate};
+use tempfile::TempDir;
+
+struct BenchData {
+    source: PathBuf,
+    target: PathBuf,
+    bytes: u64,
+    // 生成在临时目录中的数据随之删除
+    _temp: Option<TempDir>,
+}
+
+fn bench_data() -> BenchData {
+    set_quiet(true);
+    if let Some(dir) = std::env::var_os("DFT_BENCH_DATA") {
+        let dir = PathBuf::from(dir);
+        let (source, target) = (dir.join("source"), dir.join("target"));
+        let bytes = walkdir::WalkDir::new(&target)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.metadata().ok())
+            .filter(|m| m.is_file())
+            .map(|m| m.len())
+            .sum();
+        return BenchData {
+            source,
+            target,
+            bytes,
+            _temp: None,
+        };
+    }
+    let temp = TempDir::new().unwrap();
+    let profile = BenchProfile::SMALL;
+    let (source, target) = generate(temp.path(), &profile).unwrap();
+    BenchData {
+        source,
+        target,
+        bytes: profile.total_bytes(),
+        _temp: Some(temp),
+    }
+}
+
+fn pipeline(c: &mut Criterion) {
+    let data = bench_data();
+    let work = TempDir::new().unwrap();
+    let patch = work.path().join("bench.tgz");
+    create_patch(&data.source, &data.target, &patch).unwrap();
+
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Bytes(data.bytes));
+    group.sample_size(10);
+    group.bench_function("scan_directory", |b| {
+        b.iter(|| scan_directory(&data.target).unwrap())
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("hash");
+    group.throughput(Throughput::Bytes(data.bytes));
+    group.sample_size(10);
+    for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+        group.bench_function(algorithm.to_string(), |b| {
+            b.iter(|| hash_files(&data.target, algorithm, 4).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("package");
+    group.throughput(Throughput::Bytes(data.bytes));
+    group.sample_size(10);
+    group.bench_function("create_patch", |b| {
+        let output = work.path().join("create.tgz");
+        b.iter(|| create_patch(&data.source, &data.target, &output).unwrap())
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("apply");
+    group.throughput(Throughput::Bytes(data.bytes));
+    group.sample_size(10);
+    group.bench_function("apply_patch", |b| {
+        b.iter_batched(
+            || {
+                let dir = TempDir::new().unwrap();
+                copy_tree(&data.source, dir.path()).unwrap();
+                dir
+            },
+            |dir| apply_patch(dir.path(), &patch).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
@@ -0,0 +1,115 @@
+//! 基准测试与 `bench_data` 示例共用的合成数据生成器
+//!
+//! 生成内容固定 (按文件序号播种的伪随机数据)，同一规模多次生成的结果完全相同，
+//! 便于在优化前后对比。
+
+// 基准测试与示例各只用到其中一部分
+#![allow(dead_code)]
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// 合成数据的规模
+#[derive(Debug, Clone, Copy)]
+pub struct BenchProfile {
+    pub name: &'static str,
+    /// 源目录的文件数
+    pub files: usize,
+    /// 每个文件的大小 (字节)
+    pub file_size: usize,
+}
+
+impl BenchProfile {
+    /// 约 3 MiB，`cargo bench` 未指定数据时使用
+    pub const SMALL: Self = Self {
+        name: "small",
+        files: 200,
+        file_size: 16 << 10,
+    };
+    /// 约 128 MiB
+    pub const MEDIUM: Self = Self {
+        name: "medium",
+        files: 1000,
+        file_size: 128 << 10,
+    };
+    /// 约 1 GiB 的大文件样本
+    pub const LARGE: Self = Self {
+        name: "large",
+        files: 256,
+        file_size: 4 << 20,
+    };
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::SMALL, Self::MEDIUM, Self::LARGE]
+            .into_iter()
+            .find(|profile| profile.name == name)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        (self.files * self.file_size) as u64
+    }
+}
+
+/// 在 `root` 下生成 `source/` 与 `target/` 两个版本，返回两者的路径
+///
+/// 目标版本相对源版本：每 10 个文件修改 1 个 (中间改写一小段)，每 25 个删除 1 个，
+/// 另外新增 1/20 的文件。
+pub fn generate(root: &Path, profile: &BenchProfile) -> io::Result<(PathBuf, PathBuf)> {
+    let source = root.join("source");
+    let target = root.join("target");
+    for i in 0..profile.files {
+        let relative = file_path(i);
+        let mut content = pseudo_random_bytes(profile.file_size, i as u64 + 1);
+        write(&source.join(&relative), &content)?;
+        if i % 25 == 1 {
+            continue;
+        }
+        if i % 10 == 0 {
+            let middle = content.len() / 2;
+            let end = (middle + 256).min(content.len());
+            content[middle..end].iter_mut().for_each(|b| *b = !*b);
+        }
+        write(&target.join(&relative), &content)?;
+    }
+    for i in profile.files..profile.files + profile.files / 20 {
+        let content = pseudo_random_bytes(profile.file_size, i as u64 + 1);
+        write(&target.join(file_path(i)), &content)?;
+    }
+    Ok((source, target))
+}
+
+/// 复制整个目录树
+pub fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in WalkDir::new(from).into_iter().filter_map(|e| e.ok()) {
+        let relative = entry.path().strip_prefix(from).unwrap();
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(to.join(relative))?;
+        } else {
+            fs::copy(entry.path(), to.join(relative))?;
+        }
+    }
+    Ok(())
+}
+
+fn file_path(index: usize) -> PathBuf {
+    PathBuf::from(format!("dir{:02}/file{:05}.bin", index % 16, index))
+}
+
+fn write(path: &Path, content: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, content)
+}
+
+fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
@@ -0,0 +1,40 @@
+//! 生成基准测试用的合成数据
+//!
+//! `cargo run --release --example bench_data -- <dir> --bench-mode small|medium|large`
+
+#[path = "../benches/support/mod.rs"]
+mod support;
+
+use anyhow::{Context, Result, bail};
+use std::path::PathBuf;
+use support::{BenchProfile, generate};
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let mut dir = None;
+    let mut profile = BenchProfile::SMALL;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bench-mode" => {
+                let name = args.next().context("--bench-mode 需要一个值")?;
+                profile = BenchProfile::from_name(&name)
+                    .with_context(|| format!("未知的规模: {} (可选 small、medium、large)", name))?;
+            }
+            _ if dir.is_none() => dir = Some(PathBuf::from(arg)),
+            _ => bail!("多余的参数: {}", arg),
+        }
+    }
+    let dir = dir.context("用法: bench_data <dir> [--bench-mode small|medium|large]")?;
+
+    let (source, target) = generate(&dir, &profile)?;
+    println!(
+        "已生成 {} 规模数据 ({} 个文件, 约 {} MiB): {}, {}",
+        profile.name,
+        profile.files,
+        profile.total_bytes() >> 20,
+        source.display(),
+        target.display()
+    );
+    println!("运行: DFT_BENCH_DATA={} cargo bench", dir.display());
+    Ok(())
+}
@@ -1,17 +1,188 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
 use clap::Parser;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
 use bin_diff_tool::cli::{Cli, Commands};
-use bin_diff_tool::patch::{apply_patch, create_patch, merge_patches, show_patch};
+use bin_diff_tool::patch::{
+    ApplyOptions, AuditLog, BatchOptions, BatchPolicy, CompressionOptions, CreateOptions,
+    MergeOptions, MultiSpec, Patch, PatchError, REPORT_SCHEMA_VERSION, RemoteTarget, RunSummary,
+    SCHEMA_NAMES, ShowOptions, Snapshot, Timings, WarningPolicy, analyze_patch, apply_batch,
+    apply_fixup, apply_patch_into, apply_patch_remote, apply_patch_with_options, create_fixup,
+    create_multi_patches, create_patch_with_options, derive_patch, generate_signing_key,
+    inspect_directory, inspect_patch, json_schema, json_schemas, merge_patches_with_options,
+    patch_changelog, revert_patch, show_directory_info, show_patch_with_options, sign_patch,
+    validate_patch, verify_patch_signatures, watch_snapshot, write_html_report,
+};
+use bin_diff_tool::utils::{
+    DownloadOptions, HashManifest, IgnoreRules, PatchCache, download_file, fetch_cached,
+    hash_files, is_quiet, is_url, set_quiet, set_status_log, set_status_to_stderr, show_paged,
+    write_status_log,
+};
+
+/// 表示标准输入/标准输出的补丁路径
+const STDIO_PATH: &str = "-";
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let command = cli.command.name();
+
+    // 补丁包写到标准输出时，其他输出一律改写到标准错误
+    let patch_to_stdout = matches!(&cli.command, Commands::Diff { output, .. } if is_stdio(output));
+    if patch_to_stdout && cli.json {
+        return Err(anyhow!("补丁包写到标准输出时不能使用 --json"));
+    }
+    set_status_to_stderr(patch_to_stdout);
+    // JSON 模式下标准输出只包含结果；静默模式下除错误外不输出任何内容
+    set_quiet(cli.json || cli.quiet);
+
+    // 服务模式下终端不输出任何进度，全部写入日志
+    let service_log = cli.command.service_log();
+    if let Some(log) = &service_log {
+        set_status_log(Some(log)).with_context(|| format!("无法打开日志文件: {:?}", log))?;
+        set_quiet(true);
+        write_status_log(&format!("开始应用补丁 (dft {})", env!("CARGO_PKG_VERSION")));
+    }
+
+    let summary_file = cli.command.summary_file().map(Path::to_path_buf);
+    let result = run(cli.command, cli.json);
+    // 成功时的摘要由各子命令写入，这里只补上失败的情况
+    if let (Some(summary_file), Err(err)) = (&summary_file, &result) {
+        RunSummary::failed(command, err).save(summary_file)?;
+    }
+    if let Some(report) = &cli.report {
+        let envelope = result_envelope(command, &result);
+        fs::write(report, serde_json::to_string_pretty(&envelope)?)
+            .with_context(|| format!("无法写入报告文件: {:?}", report))?;
+    }
+
+    if service_log.is_some() {
+        let code = match &result {
+            Err(err) => {
+                write_status_log(&format!("错误: {:#}", err));
+                1
+            }
+            Ok((value, _)) if has_warnings(value) => 2,
+            Ok(_) => 0,
+        };
+        write_status_log(&format!("结束，退出码 {}", code));
+        if cli.json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&result_envelope(command, &result))?
+            );
+        }
+        std::process::exit(code);
+    }
+
+    if cli.json {
+        let envelope = result_envelope(command, &result);
+        println!("{}", serde_json::to_string_pretty(&envelope)?);
+        if result.is_err() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Err(err) = &result
+        && command != "validate"
+        && is_invalid_archive(err)
+    {
+        eprintln!("提示: 可用 `dft validate <补丁包>` 检查补丁包是否完整");
+    }
+    let (_, timings) = result?;
+    if cli.timings
+        && !cli.quiet
+        && let Some(timings) = timings
+    {
+        if patch_to_stdout {
+            eprintln!("{}", timings);
+        } else {
+            println!("{}", timings);
+        }
+    }
+    Ok(())
+}
+
+/// 错误是否因为补丁包无法识别、已损坏或缺少校验和
+fn is_invalid_archive(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<PatchError>(),
+            Some(PatchError::InvalidArchive { .. } | PatchError::MissingChecksums)
+        )
+    })
+}
+
+/// 结果中是否带有警告 (单个补丁的 `warnings` 或批量应用中各补丁的警告)
+fn has_warnings(result: &Value) -> bool {
+    let non_empty = |value: &Value| value.as_array().is_some_and(|a| !a.is_empty());
+    non_empty(&result["warnings"])
+        || result["entries"]
+            .as_array()
+            .is_some_and(|results| results.iter().any(|r| non_empty(&r["report"]["warnings"])))
+}
+
+/// 命令结果的 JSON 外层结构 (`--json` 输出与 `--report` 文件)
+fn result_envelope(command: &str, result: &Result<(Value, Option<Timings>)>) -> Value {
+    match result {
+        Ok((result, _)) => json!({
+            "schema_version": REPORT_SCHEMA_VERSION,
+            "command": command,
+            "ok": true,
+            "result": result,
+        }),
+        Err(err) => json!({
+            "schema_version": REPORT_SCHEMA_VERSION,
+            "command": command,
+            "ok": false,
+            "error": format!("{:#}", err),
+        }),
+    }
+}
 
-    match cli.command {
+/// 执行子命令，返回结构化结果与阶段耗时
+fn run(command: Commands, json: bool) -> Result<(Value, Option<Timings>)> {
+    let result = match command {
         Commands::Diff {
             source_dir,
             target_dir,
             output,
+            rename_threshold,
+            delta_min_size,
+            delta_max_ratio,
+            no_renames,
+            fast,
+            hashes_from,
+            source_snapshot,
+            target_snapshot,
+            jobs,
+            zstd_dict,
+            base_patch,
+            alt_source,
+            max_memory,
+            track_attributes,
+            include_metadata_only,
+            description,
+            author,
+            notes_file,
+            notes,
+            normalize_eol,
+            ignore,
+            ignore_source,
+            ignore_target,
+            respect_gitignore,
+            replace_dir,
+            full_manifest,
+            merge_config,
+            compression,
+            level,
+            compression_threads,
         } => {
             if !source_dir.exists() {
                 return Err(anyhow!("源目录不存在: {:?}", source_dir));
@@ -19,21 +190,337 @@ fn main() -> Result<()> {
             if !target_dir.exists() {
                 return Err(anyhow!("目标目录不存在: {:?}", target_dir));
             }
-            create_patch(&source_dir, &target_dir, &output)?;
+            let mut options = CreateOptions::new()
+                .with_rename_threshold((!no_renames).then_some(rename_threshold))
+                .with_delta_min_size(delta_min_size)
+                .with_delta_max_ratio(delta_max_ratio)
+                .with_fast(fast)
+                .with_jobs(jobs)
+                .with_zstd_dictionary(zstd_dict)
+                .with_track_attributes(track_attributes)
+                .with_include_metadata_only(include_metadata_only)
+                .with_normalize_eol(normalize_eol)
+                .with_respect_gitignore(respect_gitignore)
+                .with_full_manifest(full_manifest)
+                .with_compression(compression.into())
+                .with_compression_options(compression_options(level, compression_threads));
+            options = with_ignore_rules(options, &ignore, &ignore_source, &ignore_target);
+            for dir in replace_dir {
+                options = options.with_replace_dir(dir);
+            }
+            for pattern in &merge_config {
+                options = options.with_merge_config(pattern);
+            }
+            if let Some(base_patch) = base_patch {
+                options = options.with_base_patch(base_patch);
+            }
+            for dir in alt_source {
+                if !dir.is_dir() {
+                    return Err(anyhow!("基线目录不存在: {:?}", dir));
+                }
+                options = options.with_alternative_source(dir);
+            }
+            if let Some(limit) = max_memory {
+                options = options.with_max_memory(limit);
+            }
+            if let Some(manifest) = hashes_from {
+                options = options.with_hash_manifest(HashManifest::load(&manifest)?);
+            }
+            if let Some(snapshot) = source_snapshot {
+                options = options.with_source_snapshot(Snapshot::load(&snapshot)?);
+            }
+            if let Some(snapshot) = target_snapshot {
+                options = options.with_target_snapshot(Snapshot::load(&snapshot)?);
+            }
+            if let Some(description) = description {
+                options = options.with_description(description);
+            }
+            if let Some(author) = author {
+                options = options.with_author(author);
+            }
+            if let Some(notes_file) = notes_file {
+                let notes = fs::read_to_string(&notes_file)
+                    .with_context(|| format!("无法读取变更说明: {:?}", notes_file))?;
+                options = options.with_notes(notes);
+            }
+            if let Some(notes) = notes {
+                let notice = fs::read_to_string(&notes)
+                    .with_context(|| format!("无法读取公告文件: {:?}", notes))?;
+                options = options.with_notice(notice);
+            }
+            let report = if is_stdio(&output) {
+                let spool = SpoolFile::new("stdout")?;
+                let report =
+                    create_patch_with_options(&source_dir, &target_dir, spool.path(), &options)?;
+                if spool.path().exists() {
+                    let mut stdout = BufWriter::new(io::stdout().lock());
+                    io::copy(&mut File::open(spool.path())?, &mut stdout)?;
+                    stdout.flush()?;
+                }
+                report
+            } else {
+                create_patch_with_options(&source_dir, &target_dir, &output, &options)?
+            };
+            (serde_json::to_value(&report)?, Some(report.timings))
         }
-        Commands::Apply { target_dir, patch } => {
-            if !target_dir.exists() {
+        Commands::DiffMulti {
+            spec,
+            output_dir,
+            rename_threshold,
+            no_renames,
+            jobs,
+            description,
+            compression,
+            level,
+            compression_threads,
+        } => {
+            let mut spec = MultiSpec::load(&spec)?;
+            if let Some(output_dir) = output_dir {
+                spec.output_dir = output_dir;
+            }
+            let mut options = CreateOptions::new()
+                .with_rename_threshold((!no_renames).then_some(rename_threshold))
+                .with_jobs(jobs)
+                .with_compression(compression.into())
+                .with_compression_options(compression_options(level, compression_threads));
+            if let Some(description) = description {
+                options = options.with_description(description);
+            }
+            let report = create_multi_patches(&spec, &options)?;
+            (serde_json::to_value(&report)?, None)
+        }
+        Commands::Apply {
+            target_dir,
+            patch,
+            batch,
+            stop_on_error: _,
+            skip_failed,
+            output,
+            link_unchanged,
+            base_patch,
+            strict,
+            skip_corrupt,
+            dry_run,
+            restrict_to,
+            replace_on_reboot,
+            atomic,
+            rollback,
+            resume,
+            checkpoint_every,
+            sync,
+            keep,
+            protect,
+            protect_file,
+            verify,
+            pre_cmd,
+            post_cmd,
+            hook_failure,
+            deny,
+            allow,
+            audit_log,
+            summary_file,
+            no_notice,
+            service_mode: _,
+            log_file: _,
+            remote,
+            remote_dft,
+        } => {
+            let remote = remote.as_deref().map(RemoteTarget::parse).transpose()?;
+            if let Some(target_dir) = &target_dir
+                && !target_dir.exists()
+            {
                 return Err(anyhow!("目标目录不存在: {:?}", target_dir));
             }
+            let mut options = ApplyOptions::new()
+                .with_link_unchanged(link_unchanged.into())
+                .with_strict(strict)
+                .with_skip_corrupt(skip_corrupt)
+                .with_dry_run(dry_run)
+                .with_replace_on_reboot(replace_on_reboot)
+                .with_atomic(atomic)
+                .with_resume(resume)
+                .with_checkpoint_interval(checkpoint_every)
+                .with_sync(sync)
+                .with_hook_policy(hook_failure.into());
+            if let Some(base_patch) = base_patch {
+                options = options.with_base_patch(base_patch);
+            }
+            if let Some(restrict_to) = restrict_to {
+                options = options.with_restrict_to(restrict_to);
+            }
+            if let Some(rollback) = rollback {
+                options = options.with_rollback(rollback);
+            }
+            if let Some(verify) = verify {
+                options = options.with_verify(verify);
+            }
+            for pattern in &keep {
+                options = options.with_sync_keep(pattern);
+            }
+            if let Some(path) = protect_file {
+                options = options.with_protected_rules(IgnoreRules::from_file(&path)?);
+            }
+            for pattern in &protect {
+                options = options.with_protected(pattern);
+            }
+            if let Some(command) = pre_cmd {
+                options = options.with_pre_command(command);
+            }
+            if let Some(command) = post_cmd {
+                options = options.with_post_command(command);
+            }
+            let mut policy = WarningPolicy::new();
+            for kind in allow {
+                policy = policy.allow(kind);
+            }
+            for kind in deny {
+                policy = policy.deny(kind);
+            }
+            options = options.with_warning_policy(policy);
+            if let Some(audit_log) = audit_log {
+                options = options.with_event_sink(AuditLog::open(&audit_log)?);
+            }
+
+            if let Some(batch) = batch {
+                let target_dir = target_dir.context("缺少目标目录")?;
+                let policy = if skip_failed {
+                    BatchPolicy::SkipFailed
+                } else {
+                    BatchPolicy::StopOnError
+                };
+                let options = BatchOptions::new()
+                    .with_apply_options(options)
+                    .with_policy(policy);
+                let report = apply_batch(&target_dir, &batch, &options)?;
+                if report.failed() > 0 {
+                    return Err(anyhow!("批量应用中有 {} 个补丁失败", report.failed()));
+                }
+                return Ok((serde_json::to_value(&report)?, None));
+            }
+
+            // 从标准输入读取的补丁先落盘，解包与记录补丁哈希都需要完整文件
+            let patch = patch.context("缺少补丁包路径")?;
+            let stdin_spool = if is_stdio(&patch) {
+                let spool = SpoolFile::new("stdin")?;
+                io::copy(&mut io::stdin().lock(), &mut File::create(spool.path())?)?;
+                Some(spool)
+            } else {
+                None
+            };
+            let patch = match &stdin_spool {
+                Some(spool) => spool.path().to_path_buf(),
+                None => local_patch(patch)?,
+            };
+            if !patch.exists() {
+                return Err(anyhow!("补丁包不存在: {:?}", patch));
+            }
+            // 静默与服务模式下只写入日志
+            if !no_notice && let Some(notice) = Patch::open(&patch)?.notice()? {
+                show_paged(&format!("补丁公告:\n{}", notice.trim_end()));
+            }
+            // 远端 dft 自行输出结果，本地不再生成报告
+            if let Some(remote) = remote {
+                apply_patch_remote(&remote, &patch, &options, &remote_dft)?;
+                return Ok((json!({ "host": remote.host, "path": remote.path }), None));
+            }
+            let target_dir = target_dir.context("缺少目标目录")?;
+            let report = match output {
+                Some(output) => apply_patch_into(&target_dir, &output, &patch, &options)?,
+                None => apply_patch_with_options(&target_dir, &patch, &options)?,
+            };
+            if let Some(summary_file) = &summary_file {
+                report.run_summary().save(summary_file)?;
+            }
+            if let Some(verification) = &report.verification
+                && !verification.is_valid()
+            {
+                return Err(anyhow!(
+                    "复验失败，以下文件与补丁登记的哈希不一致: {}",
+                    verification.mismatched.join(", ")
+                ));
+            }
+            (serde_json::to_value(&report)?, Some(report.timings))
+        }
+        Commands::Revert { target_dir, patch } => {
+            if !patch.exists() {
+                return Err(anyhow!("回滚补丁不存在: {:?}", patch));
+            }
+            let report = revert_patch(&target_dir, &patch)?;
+            (serde_json::to_value(&report)?, Some(report.timings))
+        }
+        Commands::Validate { patch } => {
             if !patch.exists() {
                 return Err(anyhow!("补丁包不存在: {:?}", patch));
             }
-            apply_patch(&target_dir, &patch)?;
+            let report = validate_patch(&patch)?;
+            if !report.is_valid() {
+                return Err(anyhow!("补丁包校验失败: {}", report.problems()));
+            }
+            (serde_json::to_value(&report)?, None)
+        }
+        Commands::Analyze { patch } => {
+            if !patch.exists() {
+                return Err(anyhow!("补丁包不存在: {:?}", patch));
+            }
+            (serde_json::to_value(analyze_patch(&patch)?)?, None)
+        }
+        Commands::Keygen { output } => {
+            let secret = output.with_extension("key");
+            let public = output.with_extension("pub");
+            generate_signing_key(&secret, &public)?;
+            (json!({ "secret_key": secret, "public_key": public }), None)
+        }
+        Commands::Sign { patch, key, signer } => {
+            let block = sign_patch(&patch, &key, &signer)?;
+            (json!({ "signatures": block }), None)
+        }
+        Commands::Verify {
+            patch,
+            trusted,
+            threshold,
+        } => {
+            let report = verify_patch_signatures(&patch, &trusted, threshold)?;
+            if !report.is_valid() {
+                return Err(anyhow!(
+                    "有效签名不足: {} / {}",
+                    report.valid_signers(),
+                    report.threshold
+                ));
+            }
+            (serde_json::to_value(&report)?, None)
+        }
+        Commands::Download {
+            url,
+            output,
+            max_retries,
+            timeout,
+            sha256,
+            no_cache,
+        } => {
+            let mut options = DownloadOptions::new().with_max_retries(max_retries);
+            if let Some(timeout) = timeout {
+                options = options.with_timeout(Duration::from_secs(timeout));
+            }
+            let bytes = if no_cache {
+                download_file(&url, &output, &options)?
+            } else {
+                let cached = PatchCache::open_default()?.fetch(&url, sha256.as_ref(), &options)?;
+                fs::copy(&cached, &output).with_context(|| format!("无法写入文件: {:?}", output))?
+            };
+            (
+                json!({ "url": url, "output": output, "bytes": bytes }),
+                None,
+            )
         }
         Commands::Append {
             first_patch,
             second_patch,
             output,
+            strict,
+            compression,
+            level,
+            compression_threads,
+            summary_file,
         } => {
             if !first_patch.exists() {
                 return Err(anyhow!("第一个补丁包不存在: {:?}", first_patch));
@@ -41,15 +528,235 @@ fn main() -> Result<()> {
             if !second_patch.exists() {
                 return Err(anyhow!("第二个补丁包不存在: {:?}", second_patch));
             }
-            merge_patches(&first_patch, &second_patch, &output)?;
+            let mut options = MergeOptions::new()
+                .with_strict(strict)
+                .with_compression_options(compression_options(level, compression_threads));
+            if let Some(compression) = compression {
+                options = options.with_compression(compression.into());
+            }
+            let report =
+                merge_patches_with_options(&first_patch, &second_patch, &output, &options)?;
+            if let Some(summary_file) = &summary_file {
+                report.run_summary().save(summary_file)?;
+            }
+            (serde_json::to_value(&report)?, Some(report.timings))
+        }
+        Commands::Derive {
+            older_patch,
+            newer_patch,
+            output,
+        } => {
+            for patch in [&older_patch, &newer_patch] {
+                if !patch.exists() {
+                    return Err(anyhow!("补丁包不存在: {:?}", patch));
+                }
+            }
+            let report = derive_patch(&older_patch, &newer_patch, &output)?;
+            (serde_json::to_value(&report)?, Some(report.timings))
+        }
+        Commands::MakeFixup {
+            base_patch,
+            fixed_patch,
+            output,
+        } => {
+            if !base_patch.exists() {
+                return Err(anyhow!("补丁包不存在: {:?}", base_patch));
+            }
+            if !fixed_patch.exists() {
+                return Err(anyhow!("修正后的补丁包不存在: {:?}", fixed_patch));
+            }
+            let report = create_fixup(&base_patch, &fixed_patch, &output)?;
+            (serde_json::to_value(&report)?, Some(report.timings))
         }
-        Commands::Show { patch } => {
+        Commands::Fixup {
+            base_patch,
+            fixup,
+            output,
+        } => {
+            if !base_patch.exists() {
+                return Err(anyhow!("补丁包不存在: {:?}", base_patch));
+            }
+            if !fixup.exists() {
+                return Err(anyhow!("修正包不存在: {:?}", fixup));
+            }
+            let report = apply_fixup(&base_patch, &fixup, &output)?;
+            (serde_json::to_value(&report)?, Some(report.timings))
+        }
+        Commands::Show {
+            patch,
+            no_preview,
+            preview_lines,
+            changelog,
+        } => {
+            let patch = local_patch(patch)?;
             if !patch.exists() {
                 return Err(anyhow!("补丁包不存在: {:?}", patch));
             }
-            show_patch(&patch)?;
+            if let Some(format) = changelog {
+                let changelog = patch_changelog(&patch, format.into())?;
+                if json {
+                    (json!({ "changelog": changelog }), None)
+                } else {
+                    if !is_quiet() {
+                        print!("{}", changelog);
+                    }
+                    (Value::Null, None)
+                }
+            } else if json {
+                (serde_json::to_value(inspect_patch(&patch)?)?, None)
+            } else {
+                let lines = if no_preview { 0 } else { preview_lines };
+                show_patch_with_options(&patch, &ShowOptions::new().with_preview_lines(lines))?;
+                (Value::Null, None)
+            }
+        }
+        Commands::Watch { dir, snapshot } => {
+            watch_snapshot(&dir, &snapshot, &AtomicBool::new(false))?;
+            (Value::Null, None)
         }
+        Commands::Info { dir } => {
+            if json {
+                (serde_json::to_value(inspect_directory(&dir)?)?, None)
+            } else {
+                show_directory_info(&dir)?;
+                (Value::Null, None)
+            }
+        }
+        Commands::InspectDir {
+            source_dir,
+            target_dir,
+            output,
+            ignore,
+            ignore_source,
+            ignore_target,
+            respect_gitignore,
+        } => {
+            let options = with_ignore_rules(
+                CreateOptions::new().with_respect_gitignore(respect_gitignore),
+                &ignore,
+                &ignore_source,
+                &ignore_target,
+            );
+            let comparison = write_html_report(&source_dir, &target_dir, &output, &options)?;
+            (serde_json::to_value(&comparison)?, None)
+        }
+        Commands::Hash { path, algo, jobs } => {
+            let hashes = hash_files(&path, algo, jobs)?;
+            if !is_quiet() {
+                for (file, hash) in &hashes {
+                    println!("{}  {}", hash, file);
+                }
+            }
+            (json!({ "algorithm": algo, "files": hashes }), None)
+        }
+        Commands::Schema { name, output } => {
+            let schemas = match &name {
+                Some(name) => {
+                    let schema = json_schema(name).with_context(|| {
+                        let names: Vec<&str> = SCHEMA_NAMES.iter().map(|(n, _)| *n).collect();
+                        format!("未知的格式: {} (可选: {})", name, names.join(", "))
+                    })?;
+                    BTreeMap::from([(name.clone(), schema)])
+                }
+                None => json_schemas()
+                    .into_iter()
+                    .map(|(name, schema)| (name.to_string(), schema))
+                    .collect(),
+            };
+            match output {
+                Some(dir) => {
+                    fs::create_dir_all(&dir)
+                        .with_context(|| format!("无法创建目录: {}", dir.display()))?;
+                    let mut written = Vec::new();
+                    for (name, schema) in &schemas {
+                        let path = dir.join(format!("{}.schema.json", name));
+                        fs::write(&path, serde_json::to_string_pretty(schema)? + "\n")
+                            .with_context(|| format!("无法写入: {}", path.display()))?;
+                        if !is_quiet() {
+                            println!("已写入: {}", path.display());
+                        }
+                        written.push(path);
+                    }
+                    (json!({ "written": written }), None)
+                }
+                None => {
+                    let result = match name {
+                        Some(name) => schemas[&name].clone(),
+                        None => json!(schemas),
+                    };
+                    if !is_quiet() {
+                        println!("{}", serde_json::to_string_pretty(&result)?);
+                    }
+                    (result, None)
+                }
+            }
+        }
+    };
+
+    Ok(result)
+}
+
+/// 把 `--ignore`/`--ignore-source`/`--ignore-target` 加入比较选项
+fn with_ignore_rules(
+    mut options: CreateOptions,
+    ignore: &[String],
+    ignore_source: &[String],
+    ignore_target: &[String],
+) -> CreateOptions {
+    for pattern in ignore {
+        options = options.with_ignore(pattern);
     }
+    for pattern in ignore_source {
+        options.source_ignore.add(pattern);
+    }
+    for pattern in ignore_target {
+        options.target_ignore.add(pattern);
+    }
+    options
+}
 
-    Ok(())
+/// `--level`/`--compression-threads` 对应的压缩选项
+fn compression_options(level: Option<u32>, threads: usize) -> CompressionOptions {
+    let options = CompressionOptions::new().with_threads(threads);
+    match level {
+        Some(level) => options.with_level(level),
+        None => options,
+    }
+}
+
+/// URL 形式的补丁先下载到缓存目录，返回本地路径
+fn local_patch(patch: PathBuf) -> Result<PathBuf> {
+    match patch.to_str() {
+        Some(url) if is_url(url) => fetch_cached(url, &DownloadOptions::new()),
+        _ => Ok(patch),
+    }
+}
+
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO_PATH
+}
+
+/// 在标准输入/输出与补丁文件之间中转用的临时文件，离开作用域时删除
+struct SpoolFile {
+    path: PathBuf,
+}
+
+impl SpoolFile {
+    fn new(kind: &str) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("dft_{}_{}.tgz", kind, std::process::id()));
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(Self { path })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
 }
@@ -1,11 +1,30 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
+
+use crate::patch::{ChangelogFormat, CompressionFormat, HookPolicy, VerifyMode, WarningKind};
+use crate::utils::{HashAlgorithm, HashResult, LinkMode, MemoryLimit, STATE_DIR, is_url};
 
 /// 二进制文件增量更新工具
 #[derive(Parser)]
 #[command(name = "dft")]
 #[command(about = "二进制文件增量更新工具", long_about = None)]
 pub struct Cli {
+    /// 以 JSON 格式输出命令结果
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// 静默模式：除错误外不输出任何内容，结果通过退出码或 `--report` 文件获取
+    #[arg(short, long, global = true, conflicts_with = "json")]
+    pub quiet: bool,
+
+    /// 把与 `--json` 相同格式的结果写到该文件 (命令失败时同样写入)
+    #[arg(long, global = true, value_name = "FILE", value_parser = parse_path)]
+    pub report: Option<PathBuf>,
+
+    /// 结束时打印各阶段耗时与吞吐率
+    #[arg(long, global = true)]
+    pub timings: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -15,34 +34,626 @@ pub enum Commands {
     /// 对比两个目录，生成补丁包
     Diff {
         /// 源目录 (旧版本)
+        #[arg(value_parser = parse_path)]
         source_dir: PathBuf,
         /// 目标目录 (新版本)
+        #[arg(value_parser = parse_path)]
         target_dir: PathBuf,
-        /// 输出补丁包路径
-        #[arg(short, long)]
+        /// 输出补丁包路径，`-` 表示写到标准输出
+        #[arg(short, long, value_parser = parse_path)]
         output: PathBuf,
+        /// 识别“改名且小改动”文件的相似度阈值 (0.0 ~ 1.0)
+        #[arg(long, default_value_t = 0.5)]
+        rename_threshold: f64,
+        /// 小于该大小 (字节) 的文件不做增量，直接存放完整文件
+        #[arg(long, value_name = "BYTES", default_value_t = 0)]
+        delta_min_size: u64,
+        /// 增量中新数据占文件大小的比例超过该值 (0.0 ~ 1.0) 时回退为存放完整文件
+        #[arg(long, value_name = "RATIO", default_value_t = 0.5)]
+        delta_max_ratio: f64,
+        /// 不识别重命名文件，全部按删除 + 新增处理
+        #[arg(long)]
+        no_renames: bool,
+        /// 快速模式：大小与修改时间相同的文件视为未变更，跳过哈希计算
+        #[arg(long)]
+        fast: bool,
+        /// 复用外部 SHA256 清单 (JSON) 中的哈希，避免重复计算
+        #[arg(long, value_name = "MANIFEST", value_parser = parse_path)]
+        hashes_from: Option<PathBuf>,
+        /// 使用源目录的快照文件 (由 `dft watch` 维护)，不再扫描源目录
+        #[arg(long, value_name = "SNAPSHOT", value_parser = parse_path)]
+        source_snapshot: Option<PathBuf>,
+        /// 使用目标目录的快照文件 (由 `dft watch` 维护)，不再扫描目标目录
+        #[arg(long, value_name = "SNAPSHOT", value_parser = parse_path)]
+        target_snapshot: Option<PathBuf>,
+        /// 并行线程数，大于 1 时按顶层子目录分片并行比较与打包
+        #[arg(short, long, default_value_t = 1)]
+        jobs: usize,
+        /// 用新增/修改的小文件训练 zstd 字典，以字典压缩大量相似的小文件
+        #[arg(long)]
+        zstd_dict: bool,
+        /// 基础补丁包 (上一版本的补丁)，与其内容相同或相近的文件只保存引用或增量
+        #[arg(long, value_name = "PATCH", value_parser = parse_path)]
+        base_patch: Option<PathBuf>,
+        /// 同样要兼容的其他基线目录 (可多次指定)，其中与源目录不同的文件登记多个原始哈希
+        #[arg(long, value_name = "DIR", value_parser = parse_path)]
+        alt_source: Vec<PathBuf>,
+        /// 内存峰值上限 (如 `256MB`)：减少并行线程，过大的文件不做改名识别与增量
+        #[arg(long, value_name = "SIZE")]
+        max_memory: Option<MemoryLimit>,
+        /// 比较权限与修改时间，列出内容相同但属性不同的文件
+        #[arg(long)]
+        track_attributes: bool,
+        /// 把仅属性不同的文件写入补丁，应用时同步其权限与修改时间
+        #[arg(long, requires = "track_attributes")]
+        include_metadata_only: bool,
+        /// 补丁描述，写入元数据
+        #[arg(long)]
+        description: Option<String>,
+        /// 补丁作者，写入元数据
+        #[arg(long)]
+        author: Option<String>,
+        /// 从文件读取变更说明 (可为多行，如 CHANGELOG.md)，写入元数据
+        #[arg(long, value_name = "FILE", value_parser = parse_path)]
+        notes_file: Option<PathBuf>,
+        /// 补丁自带的公告文件 (如 notes.md)，存为补丁内的 `NOTICE.md`，应用前展示给用户
+        #[arg(long, value_name = "FILE", value_parser = parse_path)]
+        notes: Option<PathBuf>,
+        /// 比较前归一化文本文件的行尾 (CRLF/LF) 与 UTF-8 BOM，只有这些差异的文件不计为修改
+        #[arg(long)]
+        normalize_eol: bool,
+        /// 比较时两侧都忽略的路径 (可多次指定)，如 `logs/`、`*.tmp`
+        #[arg(long, value_name = "PATTERN")]
+        ignore: Vec<String>,
+        /// 只在源目录中忽略的路径 (可多次指定)
+        #[arg(long, value_name = "PATTERN")]
+        ignore_source: Vec<String>,
+        /// 只在目标目录中忽略的路径 (可多次指定)
+        #[arg(long, value_name = "PATTERN")]
+        ignore_target: Vec<String>,
+        /// 遵循两侧目录中的 `.gitignore` (含 `.git/info/exclude` 与全局规则)，并忽略 `.git/`
+        #[arg(long)]
+        respect_gitignore: bool,
+        /// 整体替换该目录 (可多次指定)：其中的文件完整存放，应用时删除目标中补丁未包含的文件
+        #[arg(long, value_name = "DIR")]
+        replace_dir: Vec<String>,
+        /// 登记目标目录的全量清单 (所有文件及哈希)，供 `apply --sync` 删除未登记的文件
+        #[arg(long)]
+        full_manifest: bool,
+        /// 应用时与玩家改动按键合并而非覆盖的配置文件 (可多次指定)，如 `config/**/*.json`
+        #[arg(long, value_name = "PATTERN")]
+        merge_config: Vec<String>,
+        /// 补丁包的压缩格式
+        #[arg(long, value_enum, default_value_t = Compression::Gzip)]
+        compression: Compression,
+        /// 压缩级别 (gzip/xz 为 0-9，zstd 为 1-22)，默认为各格式的默认级别
+        #[arg(long, value_name = "N")]
+        level: Option<u32>,
+        /// 压缩线程数 (zstd、xz)，gzip 不支持多线程
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        compression_threads: usize,
+    },
+    /// 按配置文件一次为多个平台生成补丁包，共用的文件只压缩一次，并输出统一清单
+    DiffMulti {
+        /// 配置文件 (TOML)，列出各平台的源目录与目标目录
+        #[arg(long, value_parser = parse_path)]
+        spec: PathBuf,
+        /// 输出目录，覆盖配置文件中的 `output_dir`
+        #[arg(short, long, value_parser = parse_path)]
+        output_dir: Option<PathBuf>,
+        /// 识别“改名且小改动”文件的相似度阈值 (0.0 ~ 1.0)
+        #[arg(long, default_value_t = 0.5)]
+        rename_threshold: f64,
+        /// 不识别重命名文件，全部按删除 + 新增处理
+        #[arg(long)]
+        no_renames: bool,
+        /// 并行线程数，大于 1 时按顶层子目录分片并行比较与打包
+        #[arg(short, long, default_value_t = 1)]
+        jobs: usize,
+        /// 补丁描述，写入各补丁包的元数据
+        #[arg(long)]
+        description: Option<String>,
+        /// 补丁包的压缩格式
+        #[arg(long, value_enum, default_value_t = Compression::Gzip)]
+        compression: Compression,
+        /// 压缩级别 (gzip/xz 为 0-9，zstd 为 1-22)，默认为各格式的默认级别
+        #[arg(long, value_name = "N")]
+        level: Option<u32>,
+        /// 压缩线程数 (zstd、xz)，gzip 不支持多线程
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        compression_threads: usize,
     },
     /// 应用补丁包到目标目录
     Apply {
         /// 目标目录
+        #[arg(required_unless_present = "remote", value_parser = parse_path)]
+        target_dir: Option<PathBuf>,
+        /// 补丁包路径或 http(s) URL，`-` 表示从标准输入读取
+        #[arg(short, long, required_unless_present = "batch", value_parser = parse_patch_source)]
+        patch: Option<PathBuf>,
+        /// 按版本链顺序依次应用目录下的所有补丁包
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["patch", "output", "remote"], value_parser = parse_path)]
+        batch: Option<PathBuf>,
+        /// 批量应用时遇到失败立即停止 (默认)
+        #[arg(long, requires = "batch", conflicts_with = "skip_failed")]
+        stop_on_error: bool,
+        /// 批量应用时跳过失败的补丁继续应用后续补丁
+        #[arg(long, requires = "batch")]
+        skip_failed: bool,
+        /// 将结果输出到新目录，目标目录保持不变
+        #[arg(short, long, value_parser = parse_path)]
+        output: Option<PathBuf>,
+        /// 输出到新目录时，未变更文件的放置方式
+        #[arg(long, value_enum, default_value_t = LinkUnchanged::Copy, requires = "output")]
+        link_unchanged: LinkUnchanged,
+        /// 补丁引用了基础补丁包时，提供该基础补丁包
+        #[arg(long, value_name = "PATCH", value_parser = parse_path)]
+        base_patch: Option<PathBuf>,
+        /// 严格模式：修改目标目录前先校验补丁中每个条目的哈希与本地文件的原始哈希，不一致时不做任何修改
+        #[arg(long)]
+        strict: bool,
+        /// 跳过损坏的条目继续应用其余文件，最后列出损坏项
+        #[arg(long, conflicts_with = "strict")]
+        skip_corrupt: bool,
+        /// 试运行：列出将要新增、修改、删除的文件与校验和冲突，不修改目标目录，也不执行前置、后置命令
+        #[arg(long, conflicts_with_all = ["batch", "resume"])]
+        dry_run: bool,
+        /// 限制所有写入与删除都位于该目录之内 (解析符号链接后)，逃出时立即失败
+        #[arg(long, value_name = "DIR", value_parser = parse_path)]
+        restrict_to: Option<PathBuf>,
+        /// Windows 上文件被占用 (运行中的启动器、已加载的 dll) 时安排在重启后替换，需要管理员权限
+        #[arg(long)]
+        replace_on_reboot: bool,
+        /// 原子应用：新文件先暂存并核对哈希再改名就位，任何一步失败都回滚到应用前的状态
+        #[arg(long, conflicts_with_all = ["resume", "replace_on_reboot", "remote"])]
+        atomic: bool,
+        /// 记录被覆盖、删除的文件的原始内容，应用后写出回滚补丁 (如 `rollback.tgz`)，供 `dft revert` 撤销这次更新
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["batch", "remote", "resume", "replace_on_reboot"], value_parser = parse_path)]
+        rollback: Option<PathBuf>,
+        /// 从上次中断处的检查点继续应用，跳过已完成的条目
+        #[arg(long, conflicts_with = "output")]
+        resume: bool,
+        /// 每完成多少个条目写一次检查点 (0 表示不写)
+        #[arg(long, value_name = "N", default_value_t = 100)]
+        checkpoint_every: usize,
+        /// 同步模式：按补丁的全量清单删除目标目录中所有未登记的文件
+        #[arg(long, conflicts_with = "remote")]
+        sync: bool,
+        /// 同步模式下保留的路径 (可多次指定)，如 `saves/`、`options.txt`
+        #[arg(long, value_name = "PATTERN", requires = "sync")]
+        keep: Vec<String>,
+        /// 受保护的路径 (可多次指定)，如 `options.txt`、`saves/**`：即使补丁声明删除或修改也不触碰
+        #[arg(long, value_name = "PATTERN", conflicts_with = "remote")]
+        protect: Vec<String>,
+        /// 从文件读取受保护的路径 (每行一条规则，`#` 开头为注释)
+        #[arg(long, value_name = "FILE", conflicts_with = "remote", value_parser = parse_path)]
+        protect_file: Option<PathBuf>,
+        /// 应用后复验写入的文件：`full` 全量，`sample:5%` 按比例随机抽样
+        #[arg(long, value_name = "MODE")]
+        verify: Option<VerifyMode>,
+        /// 应用前通过系统 shell 执行的命令 (如停服脚本)，工作目录为目标目录；在补丁通过预检后才执行
+        #[arg(long, value_name = "CMD", conflicts_with = "remote")]
+        pre_cmd: Option<String>,
+        /// 应用后执行的命令 (如起服脚本)，应用失败时同样执行
+        #[arg(long, value_name = "CMD", conflicts_with = "remote")]
+        post_cmd: Option<String>,
+        /// 前置/后置命令失败时的处理方式
+        #[arg(long, value_enum, default_value_t = HookFailure::Abort, conflicts_with = "remote")]
+        hook_failure: HookFailure,
+        /// 把该类警告升级为错误并中止应用 (可多次指定)：
+        /// checksum-mismatch、missing-target、extra-file、case-mismatch
+        #[arg(long, value_name = "KIND")]
+        deny: Vec<WarningKind>,
+        /// 忽略该类警告，不记录也不输出 (可多次指定)
+        #[arg(long, value_name = "KIND")]
+        allow: Vec<WarningKind>,
+        /// 把每个文件变更与阶段完成事件以 JSON 行追加到该审计日志
+        #[arg(long, value_name = "FILE", conflicts_with = "remote", value_parser = parse_path)]
+        audit_log: Option<PathBuf>,
+        /// 把变更统计、耗时与是否有警告写到该 JSON 文件 (失败时同样写入)，供 CI 后续步骤读取
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["batch", "remote"], value_parser = parse_path)]
+        summary_file: Option<PathBuf>,
+        /// 应用前不展示补丁自带的公告
+        #[arg(long)]
+        no_notice: bool,
+        /// 服务模式 (计划任务、托管面板调用)：不做任何交互，输出全部写入日志文件，
+        /// 退出码表示结果：0 成功，2 成功但有警告，1 失败
+        #[arg(long, conflicts_with = "remote")]
+        service_mode: bool,
+        /// 服务模式的日志文件，默认为目标目录下的 `.dft/service.log`
+        #[arg(long, value_name = "FILE", requires = "service_mode", value_parser = parse_path)]
+        log_file: Option<PathBuf>,
+        /// 通过 ssh 应用到远程目录 (`user@host:/path`)，远端需要安装 dft
+        #[arg(long, value_name = "USER@HOST:PATH", conflicts_with_all = ["target_dir", "output", "base_patch"])]
+        remote: Option<String>,
+        /// 远端 dft 可执行文件的路径
+        #[arg(long, value_name = "PATH", default_value = "dft", requires = "remote")]
+        remote_dft: String,
+    },
+    /// 用 `apply --rollback` 生成的回滚补丁撤销那次更新；更新后又被改动过的文件不一致时不做任何修改
+    Revert {
+        /// 目标目录
+        #[arg(value_parser = parse_path)]
         target_dir: PathBuf,
+        /// 回滚补丁路径
+        #[arg(short, long, value_parser = parse_path)]
+        patch: PathBuf,
+    },
+    /// 校验补丁包完整性 (条目哈希与 checksums.toml 交叉核对)
+    Validate {
+        /// 补丁包路径
+        #[arg(value_parser = parse_path)]
+        patch: PathBuf,
+    },
+    /// 分析补丁包内容：重复内容块、适合改为增量的比例、各压缩算法的预估体积
+    Analyze {
         /// 补丁包路径
-        #[arg(short, long)]
+        #[arg(value_parser = parse_path)]
         patch: PathBuf,
     },
-    /// 合并两个补丁包
+    /// 生成 ed25519 签名密钥对 (`<NAME>.key` 私钥与 `<NAME>.pub` 公钥)
+    Keygen {
+        /// 密钥文件名前缀
+        #[arg(short, long, value_name = "NAME", value_parser = parse_path)]
+        output: PathBuf,
+    },
+    /// 为补丁包签名，签名追加到 `<补丁包>.sig` 签名块
+    Sign {
+        /// 补丁包路径
+        #[arg(value_parser = parse_path)]
+        patch: PathBuf,
+        /// 私钥文件
+        #[arg(long, value_parser = parse_path)]
+        key: PathBuf,
+        /// 签名者名称
+        #[arg(long)]
+        signer: String,
+    },
+    /// 校验补丁包的签名块，至少 `--threshold` 个受信任签名者的签名有效时通过
+    Verify {
+        /// 补丁包路径
+        #[arg(value_parser = parse_path)]
+        patch: PathBuf,
+        /// 受信任的公钥文件 (可多次指定)
+        #[arg(long, required = true, value_name = "PUB", value_parser = parse_path)]
+        trusted: Vec<PathBuf>,
+        /// 至少需要的有效签名数
+        #[arg(long, default_value_t = 1)]
+        threshold: usize,
+    },
+    /// 下载补丁包，对 429/5xx 与连接错误按指数退避重试
+    Download {
+        /// 补丁包 URL
+        url: String,
+        /// 保存路径
+        #[arg(short, long, value_parser = parse_path)]
+        output: PathBuf,
+        /// 最大重试次数
+        #[arg(long, default_value_t = 3)]
+        max_retries: u32,
+        /// 整体超时 (秒)，包含所有重试与等待
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+        /// 期望的 SHA256：缓存中已有该内容时不再下载，下载的内容不一致时报错
+        #[arg(long, value_name = "HEX")]
+        sha256: Option<HashResult>,
+        /// 不使用本地缓存，直接下载到保存路径
+        #[arg(long, conflicts_with = "sha256")]
+        no_cache: bool,
+    },
+    /// 合并两个补丁包 (别名 `merge`)
+    #[command(visible_alias = "merge")]
     Append {
         /// 第一个补丁包 (较早版本)
+        #[arg(value_parser = parse_path)]
         first_patch: PathBuf,
         /// 第二个补丁包 (较新版本)
+        #[arg(value_parser = parse_path)]
         second_patch: PathBuf,
         /// 输出合并后的补丁包路径
-        #[arg(short, long)]
+        #[arg(short, long, value_parser = parse_path)]
+        output: PathBuf,
+        /// 严格校验补丁链：版本号首尾相接、第二个补丁依赖的文件与第一个补丁的结果一致，否则拒绝合并
+        #[arg(long)]
+        strict: bool,
+        /// 合并结果的压缩格式，默认沿用第一个补丁包的格式
+        #[arg(long, value_enum)]
+        compression: Option<Compression>,
+        /// 合并结果的压缩级别 (gzip/xz 为 0-9，zstd 为 1-22)
+        #[arg(long, value_name = "N")]
+        level: Option<u32>,
+        /// 压缩线程数 (zstd、xz)
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        compression_threads: usize,
+        /// 把变更统计与耗时写到该 JSON 文件 (失败时同样写入)，供 CI 后续步骤读取
+        #[arg(long, value_name = "FILE", value_parser = parse_path)]
+        summary_file: Option<PathBuf>,
+    },
+    /// 由同一起始版本的两个补丁 (v1→v2 与 v1→v4) 推导 v2→v4 的补丁
+    Derive {
+        /// 到较早目标版本的补丁包 (v1→v2)
+        #[arg(value_parser = parse_path)]
+        older_patch: PathBuf,
+        /// 到较新目标版本的补丁包 (v1→v4)
+        #[arg(value_parser = parse_path)]
+        newer_patch: PathBuf,
+        /// 输出推导出的补丁包路径
+        #[arg(short, long, value_parser = parse_path)]
+        output: PathBuf,
+    },
+    /// 比较两个补丁包，生成只包含差异条目的修正包
+    MakeFixup {
+        /// 已发布的补丁包
+        #[arg(value_parser = parse_path)]
+        base_patch: PathBuf,
+        /// 修正后的补丁包
+        #[arg(value_parser = parse_path)]
+        fixed_patch: PathBuf,
+        /// 输出修正包路径 (如 fix.dftfix)
+        #[arg(short, long, value_parser = parse_path)]
+        output: PathBuf,
+    },
+    /// 用修正包重建修正后的补丁包
+    Fixup {
+        /// 已发布的补丁包
+        #[arg(value_parser = parse_path)]
+        base_patch: PathBuf,
+        /// 修正包路径
+        #[arg(value_parser = parse_path)]
+        fixup: PathBuf,
+        /// 输出修正后的补丁包路径
+        #[arg(short, long, value_parser = parse_path)]
         output: PathBuf,
     },
     /// 显示补丁包内容
     Show {
-        /// 补丁包路径
+        /// 补丁包路径或 http(s) URL
+        #[arg(value_parser = parse_patch_source)]
         patch: PathBuf,
+        /// 不显示修改的文本文件的内容预览
+        #[arg(long)]
+        no_preview: bool,
+        /// 文本文件预览的行数
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 20,
+            conflicts_with = "no_preview"
+        )]
+        preview_lines: usize,
+        /// 输出按目录分组的更新日志 (可直接粘贴发布)，代替文件清单
+        #[arg(long, value_enum, value_name = "FORMAT")]
+        changelog: Option<Changelog>,
+    },
+    /// 长驻监听目录，根据文件系统事件增量维护快照文件
+    Watch {
+        /// 要监听的目录
+        #[arg(value_parser = parse_path)]
+        dir: PathBuf,
+        /// 快照文件路径 (JSON)
+        #[arg(short, long, value_parser = parse_path)]
+        snapshot: PathBuf,
+    },
+    /// 显示目录统计、指纹与最近应用的补丁
+    Info {
+        /// 要检查的目录
+        #[arg(value_parser = parse_path)]
+        dir: PathBuf,
+    },
+    /// 比较任意两个目录，生成包含差异树、大小变化图表与文本 diff 片段的静态 HTML 报告
+    InspectDir {
+        /// 源目录 (旧版本)
+        #[arg(value_parser = parse_path)]
+        source_dir: PathBuf,
+        /// 目标目录 (新版本)
+        #[arg(value_parser = parse_path)]
+        target_dir: PathBuf,
+        /// HTML 报告的输出路径
+        #[arg(short, long, value_parser = parse_path)]
+        output: PathBuf,
+        /// 比较时两侧都忽略的路径 (可多次指定)，如 `logs/`、`*.tmp`
+        #[arg(long, value_name = "PATTERN")]
+        ignore: Vec<String>,
+        /// 只在源目录中忽略的路径 (可多次指定)
+        #[arg(long, value_name = "PATTERN")]
+        ignore_source: Vec<String>,
+        /// 只在目标目录中忽略的路径 (可多次指定)
+        #[arg(long, value_name = "PATTERN")]
+        ignore_target: Vec<String>,
+        /// 遵循两侧目录中的 `.gitignore` (含 `.git/info/exclude` 与全局规则)，并忽略 `.git/`
+        #[arg(long)]
+        respect_gitignore: bool,
     },
+    /// 输出单个文件或整个目录的哈希清单
+    Hash {
+        /// 文件或目录
+        #[arg(value_parser = parse_path)]
+        path: PathBuf,
+        /// 哈希算法: sha256 或 blake3
+        #[arg(long, default_value = "sha256")]
+        algo: HashAlgorithm,
+        /// 并行线程数，按顶层子目录分片
+        #[arg(short, long, default_value_t = 1)]
+        jobs: usize,
+    },
+    /// 输出补丁元数据与命令结果的 JSON Schema
+    Schema {
+        /// 只输出该格式：metadata、checksums、create-report、apply-report、run-summary (默认全部)
+        name: Option<String>,
+        /// 把每个格式写到该目录下的 `<名称>.schema.json`，不输出到标准输出
+        #[arg(short, long, value_name = "DIR", value_parser = parse_path)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// 未变更文件的放置方式
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LinkUnchanged {
+    /// 复制文件
+    Copy,
+    /// 硬链接
+    Hard,
+    /// 写时复制克隆
+    Reflink,
+}
+
+/// 外部命令失败时的处理方式
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HookFailure {
+    /// 前置命令失败时不应用补丁，后置命令失败时命令返回错误
+    Abort,
+    /// 记录到报告中并继续
+    Continue,
+}
+
+/// 补丁包的压缩格式
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Compression {
+    /// gzip (兼容性最好)
+    Gzip,
+    /// zstd (解压更快)
+    Zstd,
+    /// xz (体积最小，压缩较慢)
+    Xz,
+}
+
+impl From<Compression> for CompressionFormat {
+    fn from(value: Compression) -> Self {
+        match value {
+            Compression::Gzip => CompressionFormat::Gzip,
+            Compression::Zstd => CompressionFormat::Zstd,
+            Compression::Xz => CompressionFormat::Xz,
+        }
+    }
+}
+
+/// 更新日志格式
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Changelog {
+    /// Markdown
+    Md,
+}
+
+impl From<Changelog> for ChangelogFormat {
+    fn from(value: Changelog) -> Self {
+        match value {
+            Changelog::Md => ChangelogFormat::Markdown,
+        }
+    }
+}
+
+impl From<HookFailure> for HookPolicy {
+    fn from(value: HookFailure) -> Self {
+        match value {
+            HookFailure::Abort => HookPolicy::Abort,
+            HookFailure::Continue => HookPolicy::Continue,
+        }
+    }
+}
+
+impl From<LinkUnchanged> for LinkMode {
+    fn from(value: LinkUnchanged) -> Self {
+        match value {
+            LinkUnchanged::Copy => LinkMode::Copy,
+            LinkUnchanged::Hard => LinkMode::Hard,
+            LinkUnchanged::Reflink => LinkMode::Reflink,
+        }
+    }
+}
+
+impl Commands {
+    /// 需要写入结果摘要的文件 (`--summary-file`)
+    pub fn summary_file(&self) -> Option<&Path> {
+        match self {
+            Commands::Apply { summary_file, .. } | Commands::Append { summary_file, .. } => {
+                summary_file.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    /// 服务模式 (`apply --service-mode`) 下写入的日志文件
+    pub fn service_log(&self) -> Option<PathBuf> {
+        match self {
+            Commands::Apply {
+                service_mode: true,
+                log_file,
+                target_dir,
+                ..
+            } => Some(match (log_file, target_dir) {
+                (Some(log_file), _) => log_file.clone(),
+                (None, Some(target_dir)) if target_dir.is_dir() => {
+                    target_dir.join(STATE_DIR).join("service.log")
+                }
+                (None, _) => PathBuf::from("dft_service.log"),
+            }),
+            _ => None,
+        }
+    }
+
+    /// 子命令名称，用于结构化输出
+    pub fn name(&self) -> &'static str {
+        match self {
+            Commands::Diff { .. } => "diff",
+            Commands::DiffMulti { .. } => "diff-multi",
+            Commands::Apply { .. } => "apply",
+            Commands::Revert { .. } => "revert",
+            Commands::Validate { .. } => "validate",
+            Commands::Analyze { .. } => "analyze",
+            Commands::Keygen { .. } => "keygen",
+            Commands::Sign { .. } => "sign",
+            Commands::Verify { .. } => "verify",
+            Commands::Download { .. } => "download",
+            Commands::Append { .. } => "append",
+            Commands::Derive { .. } => "derive",
+            Commands::MakeFixup { .. } => "make-fixup",
+            Commands::Fixup { .. } => "fixup",
+            Commands::Show { .. } => "show",
+            Commands::Watch { .. } => "watch",
+            Commands::Info { .. } => "info",
+            Commands::InspectDir { .. } => "inspect-dir",
+            Commands::Hash { .. } => "hash",
+            Commands::Schema { .. } => "schema",
+        }
+    }
+}
+
+/// 解析命令行中的路径参数
+///
+/// 去掉两侧的空白与引号 (Windows 下 `"C:\dir\"` 会以 `C:\dir"` 传入)、展开开头的 `~`、
+/// 去掉末尾多余的分隔符，再转为绝对路径，之后的错误信息因此都打印规范化后的路径。
+/// `-` (标准输入/输出) 原样保留。
+pub fn parse_path(value: &str) -> Result<PathBuf, String> {
+    let value = value.trim().trim_matches(['"', '\'']);
+    if value.is_empty() {
+        return Err("路径不能为空".to_string());
+    }
+    if value == "-" {
+        return Ok(PathBuf::from(value));
+    }
+
+    let mut path = match value.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(std::path::is_separator) => {
+            let home = std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .ok_or_else(|| format!("无法展开 {}: 未设置 HOME", value))?;
+            format!("{}{}", home.to_string_lossy(), rest)
+        }
+        _ => value.to_string(),
+    };
+    // 保留根目录本身 (`/`、`C:\`)
+    while path.len() > 1 && path.ends_with(std::path::is_separator) && !path.ends_with(":\\") {
+        path.pop();
+    }
+
+    std::path::absolute(&path).map_err(|e| format!("无法解析路径 {}: {}", path, e))
+}
+
+/// 解析补丁包参数：http(s) URL 原样保留 (使用前下载到缓存目录)，其余按 [`parse_path`] 处理
+pub fn parse_patch_source(value: &str) -> Result<PathBuf, String> {
+    let trimmed = value.trim().trim_matches(['"', '\'']);
+    if is_url(trimmed) {
+        return Ok(PathBuf::from(trimmed));
+    }
+    parse_path(value)
 }
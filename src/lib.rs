@@ -28,10 +28,18 @@
 //! ).unwrap();
 //! ```
 
+#[cfg(feature = "cli")]
 pub mod cli;
 pub mod patch;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod updater;
 pub mod utils;
 
 // 重新导出常用类型
-pub use patch::{Checksums, FileDiff, Metadata, ModifiedChecksum};
-pub use patch::{apply_patch, create_patch, merge_patches, show_patch};
+pub use patch::{ApplyOptions, CreateOptions, apply_patch_with_options, create_patch_with_options};
+pub use patch::{ApplyReport, CreateReport, MergeReport, PatchError, PatchInfo, PatchResult};
+pub use patch::{
+    Checksums, FileDiff, Metadata, ModifiedChecksum, MovedChecksum, RenamedChecksum, Snapshot,
+};
+pub use patch::{apply_patch, compare_snapshots, create_patch, merge_patches, show_patch};
@@ -1,13 +1,99 @@
+mod analyze;
 mod apply;
+mod base;
+mod batch;
+mod case;
+mod changelog;
+mod checkpoint;
+mod compare;
+mod compression;
+mod config_merge;
 mod create;
+mod delta;
+mod derive;
+mod dictionary;
 mod diff;
+mod error;
+mod events;
+pub(crate) mod fault;
+mod fixup;
+mod hooks;
+mod info;
+mod journal;
+mod loaded;
 mod merge;
 mod metadata;
+mod multi;
+mod platform;
+mod policy;
+mod reader;
+mod remote;
+mod report;
+mod restrict;
+mod rollback;
+mod roundtrip;
+#[cfg(feature = "schema")]
+mod schema;
 mod show;
+#[cfg(feature = "signing")]
+mod signature;
+mod snapshot;
+mod state;
+mod validate;
+mod verify;
+#[cfg(feature = "watch")]
+mod watch;
 
-pub use apply::apply_patch;
-pub use create::create_patch;
-pub use diff::{FileDiff, compare_directories};
-pub use merge::merge_patches;
-pub use metadata::{Checksums, Metadata, ModifiedChecksum};
-pub use show::show_patch;
+pub use analyze::analyze_patch;
+pub use apply::{ApplyOptions, apply_patch, apply_patch_into, apply_patch_with_options};
+pub(crate) use batch::order_patch_chain;
+pub use batch::{BatchOptions, BatchPolicy, apply_batch};
+pub use changelog::{ChangelogFormat, patch_changelog, render_changelog};
+pub use compare::{compare_directories_detailed, render_html_report, write_html_report};
+pub use compression::{CompressionFormat, CompressionOptions};
+pub use config_merge::{ConfigMerger, ConfigMergers, JsonMerger, TomlMerger};
+pub use create::{CreateOptions, create_patch, create_patch_with_options};
+pub use derive::derive_patch;
+pub use diff::{
+    FileDiff, compare_directories, compare_directories_fast, compare_directories_with_attributes,
+    compare_directories_with_options,
+};
+pub use error::{ArchiveIssue, MismatchReason, PatchError, PatchResult};
+pub use events::{AuditLog, EventBus, EventSink, PatchEvent, StatusPrinter};
+pub use fixup::{FixupEntry, FixupManifest, apply_fixup, create_fixup};
+pub use hooks::HookPolicy;
+pub use info::{inspect_directory, show_directory_info};
+pub use loaded::LoadedPatch;
+pub use merge::{MergeOptions, merge_patches, merge_patches_with_options};
+pub use metadata::{
+    BaseRef, Checksums, EntryEncoding, FileSizes, Metadata, ModifiedChecksum, MovedChecksum,
+    RenamedChecksum, TOOL_VERSION,
+};
+pub use multi::{MULTI_MANIFEST, MultiSpec, PlatformSpec, create_multi_patches};
+pub use platform::Platform;
+pub use policy::{WarningAction, WarningKind, WarningPolicy};
+pub use reader::{Patch, PatchEntry};
+pub use remote::{RemoteTarget, SSH_PROGRAM_ENV, apply_patch_remote};
+pub use report::{
+    ApplyReport, BatchEntry, BatchReport, BatchStatus, ComparedFile, CompressionEstimate,
+    CreateReport, DirectoryComparison, DirectoryInfo, FixupReport, HookReport, MergeReport,
+    MultiPatchReport, PatchAnalysis, PatchInfo, PlatformPatch, REPORT_SCHEMA_VERSION, RenamedEntry,
+    RoundtripReport, RunSummary, SignatureCheck, SignatureReport, SignatureStatus, StageTiming,
+    Timings, ValidationReport, VerificationReport,
+};
+pub use rollback::revert_patch;
+pub use roundtrip::{verify_roundtrip, verify_roundtrip_with_options};
+#[cfg(feature = "schema")]
+pub use schema::{SCHEMA_NAMES, json_schema, json_schemas};
+pub use show::{ShowOptions, inspect_patch, show_patch, show_patch_with_options};
+#[cfg(feature = "signing")]
+pub use signature::{
+    PatchSignature, SignatureBlock, generate_signing_key, sign_patch, signature_path,
+    verify_patch_signatures,
+};
+pub use snapshot::{Snapshot, compare_snapshots};
+pub use state::AppliedState;
+pub use validate::validate_patch;
+pub use verify::VerifyMode;
+#[cfg(feature = "watch")]
+pub use watch::watch_snapshot;
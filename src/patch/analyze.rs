@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use super::base::BASED_DIR;
+use super::delta::{Chunker, chunk_hash};
+use super::dictionary::DICTIONARY_ENTRY;
+use super::error::PatchResult;
+use super::reader::{Patch, TarStream};
+use super::report::{CompressionEstimate, PatchAnalysis};
+use crate::utils::{format_size, status};
+
+/// 条目中重复块的字节数达到该比例时，认为该条目适合改为相对已有内容的增量
+const DELTA_CANDIDATE_RATIO: f64 = 0.5;
+
+/// 预估体积时尝试的压缩算法与级别 (第一个为补丁包当前使用的格式)
+const ESTIMATES: &[(&str, i32)] = &[("gzip", 6), ("gzip", 9), ("zstd", 3), ("zstd", 19)];
+
+/// 统计补丁包中的重复内容块、适合改为增量的条目比例，并估算各压缩算法下的体积
+///
+/// 按补丁内的存放顺序流式读取条目，字典压缩的条目先解压；与前面出现过的块
+/// (包括同一条目中) 内容相同的块计为重复。
+pub fn analyze_patch(patch_path: &Path) -> PatchResult<PatchAnalysis> {
+    status!("正在分析补丁包...");
+    let patch = Patch::open(patch_path)?;
+    let checksums = patch.checksums();
+    let dictionary = if checksums.dictionary_compressed.is_empty() {
+        None
+    } else {
+        let mut data = Vec::new();
+        patch
+            .read_entry(DICTIONARY_ENTRY)
+            .context("补丁中缺少 zstd 字典")?
+            .read_to_end(&mut data)?;
+        Some(data)
+    };
+
+    let mut analysis = PatchAnalysis {
+        patch_size: fs::metadata(patch_path)?.len(),
+        ..Default::default()
+    };
+    let mut estimators = ESTIMATES
+        .iter()
+        .map(|&(algorithm, level)| Estimator::new(algorithm, level))
+        .collect::<Result<Vec<_>>>()?;
+    let mut seen = HashSet::new();
+    let mut chunker = Chunker::default();
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    let mut stream = TarStream::open(patch_path)?;
+    while let Some(entry) = stream.next_entry()? {
+        let Some((section, path)) = entry.path.split_once('/') else {
+            // 元数据、校验和与字典
+            stream.skip_data(entry.size)?;
+            continue;
+        };
+        let is_delta = section == "renamed"
+            || section == BASED_DIR
+            || section == "modified" && checksums.delta_modified.iter().any(|p| p == path);
+        let compressed = (section == "added" || section == "modified")
+            && checksums.dictionary_compressed.iter().any(|p| p == path);
+
+        let (size, duplicate) = stream.with_data(entry.size, |data| {
+            let mut reader: Box<dyn Read + '_> = if compressed {
+                let dictionary = dictionary.as_deref().context("补丁中缺少 zstd 字典")?;
+                Box::new(zstd::stream::read::Decoder::with_dictionary(
+                    BufReader::new(data),
+                    dictionary,
+                )?)
+            } else {
+                Box::new(data)
+            };
+            let mut size = 0u64;
+            let mut duplicate = 0u64;
+            let mut count_chunk = |chunk: &[u8]| {
+                analysis.chunks += 1;
+                if !seen.insert(chunk_hash(chunk)) {
+                    analysis.duplicate_chunks += 1;
+                    duplicate += chunk.len() as u64;
+                }
+            };
+            loop {
+                let read = reader
+                    .read(&mut buffer)
+                    .with_context(|| format!("无法读取条目: {}", entry.path))?;
+                if read == 0 {
+                    break;
+                }
+                size += read as u64;
+                chunker.update(&buffer[..read], &mut count_chunk);
+                for estimator in &mut estimators {
+                    estimator.write_all(&buffer[..read])?;
+                }
+            }
+            chunker.finish(&mut count_chunk);
+            Ok((size, duplicate))
+        })?;
+
+        analysis.entries += 1;
+        analysis.content_bytes += size;
+        analysis.duplicate_bytes += duplicate;
+        if is_delta {
+            analysis.delta_bytes += size;
+        } else if size > 0 && duplicate as f64 >= size as f64 * DELTA_CANDIDATE_RATIO {
+            analysis.delta_candidates.push(entry.path.clone());
+            analysis.delta_candidate_bytes += size;
+        }
+    }
+    for (estimator, &(algorithm, level)) in estimators.into_iter().zip(ESTIMATES) {
+        analysis.compression.push(CompressionEstimate {
+            algorithm: algorithm.to_string(),
+            level,
+            size: estimator.finish()?,
+        });
+    }
+
+    print_analysis(&analysis);
+    Ok(analysis)
+}
+
+fn print_analysis(analysis: &PatchAnalysis) {
+    status!("补丁包大小: {}", format_size(analysis.patch_size));
+    status!(
+        "  条目: {} 个，解压后 {} (其中增量 {})",
+        analysis.entries,
+        format_size(analysis.content_bytes),
+        format_size(analysis.delta_bytes)
+    );
+    status!(
+        "  重复块: {} / {} 个，共 {} ({:.1}%)",
+        analysis.duplicate_chunks,
+        analysis.chunks,
+        format_size(analysis.duplicate_bytes),
+        analysis.duplicate_ratio() * 100.0
+    );
+    status!(
+        "  适合改为增量: {} 个条目，共 {} ({:.1}%)",
+        analysis.delta_candidates.len(),
+        format_size(analysis.delta_candidate_bytes),
+        analysis.delta_candidate_ratio() * 100.0
+    );
+    for path in &analysis.delta_candidates {
+        status!("    ~ {}", path);
+    }
+    status!("  预估体积 (不含 tar 头):");
+    for (i, estimate) in analysis.compression.iter().enumerate() {
+        status!(
+            "    {} -{}: {}{}",
+            estimate.algorithm,
+            estimate.level,
+            format_size(estimate.size),
+            if i == 0 { " (当前格式)" } else { "" }
+        );
+    }
+}
+
+/// 只统计写入字节数的输出
+#[derive(Default)]
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 把数据压缩到计数器中，得到压缩后的体积
+enum Estimator {
+    Gzip(GzEncoder<ByteCounter>),
+    Zstd(zstd::stream::write::Encoder<'static, ByteCounter>),
+}
+
+impl Estimator {
+    fn new(algorithm: &str, level: i32) -> Result<Self> {
+        Ok(match algorithm {
+            "gzip" => Self::Gzip(GzEncoder::new(
+                ByteCounter::default(),
+                Compression::new(level as u32),
+            )),
+            _ => Self::Zstd(zstd::stream::write::Encoder::new(
+                ByteCounter::default(),
+                level,
+            )?),
+        })
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Gzip(encoder) => encoder.write_all(data),
+            Self::Zstd(encoder) => encoder.write_all(data),
+        }
+    }
+
+    fn finish(self) -> io::Result<u64> {
+        Ok(match self {
+            Self::Gzip(encoder) => encoder.finish()?.0,
+            Self::Zstd(encoder) => encoder.finish()?.0,
+        })
+    }
+}
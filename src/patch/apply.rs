@@ -1,56 +1,906 @@
-use anyhow::{Context, Result};
-use flate2::read::GzDecoder;
+use anyhow::{Context, Result, bail};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::BufReader;
-use std::path::Path;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
 use tar::Archive;
 use walkdir::WalkDir;
 
-use super::metadata::Checksums;
-use crate::utils::compute_file_hash;
+use super::base::{BASED_DIR, resolve_base_refs};
+use super::case::CaseIndex;
+use super::checkpoint::Checkpoint;
+use super::compare::{count_line_changes, line_changes};
+use super::compression::open_decoder;
+use super::config_merge::{ConfigMerger, ConfigMergers, MERGE_BASE_DIR};
+use super::delta::apply_encoded;
+use super::error::{MismatchReason, PatchError, PatchResult};
+use super::events::{EventBus, EventSink, PatchEvent};
+use super::hooks::{HookPolicy, run_hook};
+use super::journal::{Journal, STAGING_DIR};
+use super::loaded::LoadedPatch;
+use super::metadata::{Checksums, FileSizes};
+use super::platform::Platform;
+use super::policy::{WarningAction, WarningKind, WarningPolicy};
+use super::reader::diagnose_archive;
+use super::report::{ApplyReport, RenamedEntry, Timings};
+use super::restrict::PathGuard;
+use super::rollback::RollbackCapture;
+use super::state::AppliedState;
+use super::validate::verify_entries;
+use super::verify::{VerifyMode, verify_applied};
+use crate::utils::modname::find_upgrades;
+use crate::utils::{
+    HashResult, IgnoreRules, LinkMode, STATE_DIR, compute_file_hash, compute_hash, copy_file,
+    format_size_delta, is_file_in_use, link_file, move_on_reboot, parallel_map, status, walk_files,
+};
+
+/// 并行放置文件的最大线程数
+const MAX_PLACE_JOBS: usize = 8;
+
+/// 默认每完成多少个条目写一次检查点
+const DEFAULT_CHECKPOINT_INTERVAL: usize = 100;
+
+/// 移动文件时的暂存目录 (位于 `.dft` 下，与目标目录同一文件系统)
+const MOVING_DIR: &str = "moving";
+
+/// 应用补丁包的选项
+#[derive(Debug, Clone)]
+pub struct ApplyOptions {
+    /// 输出到新目录时，未变更文件从基础目录放入新目录的方式
+    pub link_unchanged: LinkMode,
+    /// 补丁引用的基础补丁 (内容从中取得)
+    pub base_patch: Option<PathBuf>,
+    /// 严格模式：修改目标目录前先核对补丁中每个条目的哈希，以及要修改、重命名、移动的本地文件
+    /// 的原始哈希，发现损坏或不一致立即中止，目标目录保持不变
+    pub strict: bool,
+    /// 跳过解压失败或哈希不一致的条目，继续应用其余文件
+    pub skip_corrupt: bool,
+    /// 试运行：只核对目标目录并列出将要进行的变更与校验和冲突，不做任何修改，也不执行外部命令
+    pub dry_run: bool,
+    /// 所有写入与删除都必须落在该目录之内 (解析符号链接后)，否则立即失败
+    pub restrict_to: Option<PathBuf>,
+    /// 文件被占用 (Windows 上运行中的程序或已加载的 dll) 时，安排在重启后替换或删除
+    pub replace_on_reboot: bool,
+    /// 原子应用：新文件先写入 `.dft/staging` 并核对哈希，再逐个改名就位；被覆盖、删除的原文件
+    /// 记入回滚日志，任何一步失败都恢复到应用前的状态
+    pub atomic: bool,
+    /// 记录被覆盖、删除的文件的原始内容，应用完成后在该路径写出撤销这次应用的回滚补丁
+    /// (格式与普通补丁相同，用 `revert_patch` 应用)
+    pub rollback: Option<PathBuf>,
+    /// 应用完成后复验写入的文件 (全量或抽样)
+    pub verify: Option<VerifyMode>,
+    /// 文件变更、校验和不匹配、阶段完成等事件的订阅者 (默认打印到控制台)
+    pub events: EventBus,
+    /// 各类警告的处理方式 (忽略、警告或报错)
+    pub warning_policy: WarningPolicy,
+    /// 应用前执行的外部命令 (如停服脚本)
+    pub pre_command: Option<String>,
+    /// 应用后执行的外部命令 (如起服脚本)，应用失败时同样执行
+    pub post_command: Option<String>,
+    /// 外部命令失败时中止还是继续
+    pub hook_policy: HookPolicy,
+    /// 每完成多少个条目写一次检查点 (0 表示不写)
+    pub checkpoint_interval: usize,
+    /// 从上次中断处的检查点继续，跳过已完成的条目
+    pub resume: bool,
+    /// 同步模式：按补丁中的全量清单删除目标目录中所有未登记的文件
+    pub sync: bool,
+    /// 同步模式下保留的路径 (白名单)，即使未登记也不删除
+    pub sync_keep: IgnoreRules,
+    /// 受保护的路径 (如 `options.txt`、`saves/**`)：即使补丁声明删除或修改也不触碰
+    pub protected: IgnoreRules,
+    /// 合并玩家改动过的配置文件的插件 (默认处理 JSON 与 TOML)
+    pub config_mergers: ConfigMergers,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> Self {
+        Self {
+            link_unchanged: LinkMode::default(),
+            base_patch: None,
+            strict: false,
+            skip_corrupt: false,
+            dry_run: false,
+            restrict_to: None,
+            replace_on_reboot: false,
+            atomic: false,
+            rollback: None,
+            verify: None,
+            events: EventBus::default(),
+            warning_policy: WarningPolicy::default(),
+            pre_command: None,
+            post_command: None,
+            hook_policy: HookPolicy::default(),
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            resume: false,
+            sync: false,
+            sync_keep: IgnoreRules::new(),
+            protected: IgnoreRules::new(),
+            config_mergers: ConfigMergers::default(),
+        }
+    }
+}
+
+impl ApplyOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_link_unchanged(mut self, mode: LinkMode) -> Self {
+        self.link_unchanged = mode;
+        self
+    }
+
+    pub fn with_base_patch(mut self, base_patch: impl Into<PathBuf>) -> Self {
+        self.base_patch = Some(base_patch.into());
+        self
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn with_skip_corrupt(mut self, skip_corrupt: bool) -> Self {
+        self.skip_corrupt = skip_corrupt;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_restrict_to(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.restrict_to = Some(dir.into());
+        self
+    }
+
+    pub fn with_replace_on_reboot(mut self, replace_on_reboot: bool) -> Self {
+        self.replace_on_reboot = replace_on_reboot;
+        self
+    }
+
+    pub fn with_atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+
+    pub fn with_rollback(mut self, output: impl Into<PathBuf>) -> Self {
+        self.rollback = Some(output.into());
+        self
+    }
+
+    pub fn with_verify(mut self, mode: VerifyMode) -> Self {
+        self.verify = Some(mode);
+        self
+    }
+
+    pub fn with_warning_policy(mut self, policy: WarningPolicy) -> Self {
+        self.warning_policy = policy;
+        self
+    }
+
+    pub fn with_pre_command(mut self, command: impl Into<String>) -> Self {
+        self.pre_command = Some(command.into());
+        self
+    }
+
+    pub fn with_post_command(mut self, command: impl Into<String>) -> Self {
+        self.post_command = Some(command.into());
+        self
+    }
+
+    pub fn with_hook_policy(mut self, policy: HookPolicy) -> Self {
+        self.hook_policy = policy;
+        self
+    }
+
+    pub fn with_checkpoint_interval(mut self, interval: usize) -> Self {
+        self.checkpoint_interval = interval;
+        self
+    }
+
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    pub fn with_sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// 同步模式下保留匹配 `pattern` 的路径
+    pub fn with_sync_keep(mut self, pattern: &str) -> Self {
+        self.sync_keep.add(pattern);
+        self
+    }
+
+    /// 保护匹配 `pattern` 的路径，应用时永不修改或删除
+    pub fn with_protected(mut self, pattern: &str) -> Self {
+        self.protected.add(pattern);
+        self
+    }
+
+    pub fn with_protected_rules(mut self, rules: IgnoreRules) -> Self {
+        self.protected = rules;
+        self
+    }
+
+    /// 注册配置合并插件，优先于内置的 JSON 与 TOML 插件
+    pub fn with_config_merger(mut self, merger: impl ConfigMerger + 'static) -> Self {
+        let mut mergers = ConfigMergers::empty();
+        mergers.register(merger);
+        mergers.extend(&self.config_mergers);
+        self.config_mergers = mergers;
+        self
+    }
+
+    /// 替换整个事件总线 (例如用 [`EventBus::empty`] 关闭控制台输出)
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// 追加一个事件订阅者
+    pub fn with_event_sink(mut self, sink: impl EventSink + 'static) -> Self {
+        self.events.subscribe(sink);
+        self
+    }
+}
 
 /// 应用补丁包
-pub fn apply_patch(target_dir: &Path, patch_path: &Path) -> Result<()> {
-    println!("正在解压补丁包...");
+pub fn apply_patch(target_dir: &Path, patch_path: &Path) -> PatchResult<ApplyReport> {
+    apply_patch_with_options(target_dir, patch_path, &ApplyOptions::default())
+}
+
+/// 以 `base_dir` 为基础，把应用补丁后的结果生成到新目录 `output_dir`，基础目录保持不变
+pub fn apply_patch_into(
+    base_dir: &Path,
+    output_dir: &Path,
+    patch_path: &Path,
+    options: &ApplyOptions,
+) -> PatchResult<ApplyReport> {
+    Ok(apply_into(base_dir, output_dir, patch_path, options)?)
+}
+
+fn apply_into(
+    base_dir: &Path,
+    output_dir: &Path,
+    patch_path: &Path,
+    options: &ApplyOptions,
+) -> Result<ApplyReport> {
+    if output_dir.exists() && fs::read_dir(output_dir)?.next().is_some() {
+        bail!("输出目录已存在且非空: {}", output_dir.display());
+    }
+    // 试运行不创建输出目录，直接列出基础目录需要的变更
+    if options.dry_run {
+        return Ok(apply_patch_with_options(base_dir, patch_path, options)?);
+    }
+
+    status!("正在准备输出目录...");
+    let guard = PathGuard::new(options.restrict_to.as_deref())?;
+    guard.check(output_dir)?;
+    let stage = Instant::now();
+    let mut prepared_bytes = 0;
+    for entry in walk_files(base_dir) {
+        let relative_path = entry.path().strip_prefix(base_dir)?;
+        let dest = output_dir.join(relative_path);
+        guard.check(&dest)?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        link_file(entry.path(), &dest, options.link_unchanged)?;
+        prepared_bytes += entry.metadata()?.len();
+    }
+    fs::create_dir_all(output_dir)?;
+
+    let mut timings = Timings::new();
+    record_stage(&mut timings, "prepare", stage, prepared_bytes, options);
+
+    let mut report = apply_patch_with_options(output_dir, patch_path, options)?;
+    timings.extend(report.timings);
+    report.timings = timings;
+    Ok(report)
+}
+
+/// 按指定选项应用补丁包
+///
+/// 设置了前置、后置命令时分别在应用前后执行：前置命令在补丁解包并通过 `--strict` 等预检之后
+/// 才执行，预检失败时两者都不执行；前置命令执行后，后置命令在应用失败时同样执行 (例如重新起服)。
+pub fn apply_patch_with_options(
+    target_dir: &Path,
+    patch_path: &Path,
+    options: &ApplyOptions,
+) -> PatchResult<ApplyReport> {
+    if !target_dir.is_dir() {
+        return Err(PatchError::TargetNotFound(target_dir.to_path_buf()));
+    }
+    Ok(apply_with_hooks(target_dir, patch_path, options)?)
+}
+
+/// 依次执行预检、前置命令、应用补丁与后置命令
+fn apply_with_hooks(
+    target_dir: &Path,
+    patch_path: &Path,
+    options: &ApplyOptions,
+) -> Result<ApplyReport> {
+    let mut hooks = Vec::new();
+    let mut started = false;
+    let mut pre_hook = || -> Result<()> {
+        if let Some(command) = &options.pre_command
+            && !options.dry_run
+        {
+            let hook = run_hook("pre", command, target_dir, patch_path)?;
+            let failed = !hook.success;
+            let status = hook.describe_status();
+            hooks.push(hook);
+            if failed && options.hook_policy == HookPolicy::Abort {
+                bail!("前置命令失败 ({})，未应用补丁: {}", status, command);
+            }
+        }
+        started = true;
+        Ok(())
+    };
+
+    let result = apply_patch_unhooked(target_dir, patch_path, options, &mut pre_hook);
+
+    // 预检或前置命令未通过时补丁没有开始应用，不执行后置命令
+    if let Some(command) = &options.post_command
+        && !options.dry_run
+        && started
+    {
+        let hook = run_hook("post", command, target_dir, patch_path);
+        let hook = match (hook, result.is_ok()) {
+            (Ok(hook), _) => hook,
+            (Err(e), true) => return Err(e),
+            // 应用本身的错误更重要，后置命令无法执行时只输出提示
+            (Err(e), false) => {
+                status!("  ! {:#}", e);
+                return result;
+            }
+        };
+        let failed = !hook.success;
+        let status = hook.describe_status();
+        hooks.push(hook);
+        if failed && options.hook_policy == HookPolicy::Abort && result.is_ok() {
+            bail!("后置命令失败 ({}): {}", status, command);
+        }
+    }
+
+    let mut report = result?;
+    report.hooks = hooks;
+    Ok(report)
+}
+
+fn apply_patch_unhooked(
+    target_dir: &Path,
+    patch_path: &Path,
+    options: &ApplyOptions,
+    pre_hook: &mut dyn FnMut() -> Result<()>,
+) -> Result<ApplyReport> {
+    let guard = PathGuard::new(options.restrict_to.as_deref())?;
+    guard.check(target_dir)?;
+
+    status!("正在解压补丁包...");
+    let mut timings = Timings::new();
 
-    // 创建临时目录
+    // 解压补丁包到临时目录 (清除上次失败残留的内容，结束或失败时自动清理)
+    let stage = Instant::now();
     let temp_dir = std::env::temp_dir().join(format!("dft_apply_{}", std::process::id()));
-    fs::create_dir_all(&temp_dir)?;
+    let mut patch = if options.skip_corrupt {
+        LoadedPatch::load_lenient(patch_path, temp_dir)?
+    } else {
+        LoadedPatch::load(patch_path, temp_dir)?
+    };
+    let patch_bytes = fs::metadata(patch_path)?.len();
+    record_stage(&mut timings, "extract", stage, patch_bytes, options);
 
-    // 解压补丁包
-    extract_patch(patch_path, &temp_dir)?;
+    guard.check(&target_dir.join(STATE_DIR))?;
+    let patch_hash = compute_file_hash(patch_path)?;
+    let report = match apply_loaded(
+        target_dir,
+        &mut patch,
+        &patch_hash,
+        options,
+        &guard,
+        timings,
+        pre_hook,
+    ) {
+        Ok(report) => report,
+        Err(e) => {
+            if Checkpoint::exists(target_dir) {
+                status!("已完成的条目已记录到检查点，修复问题后可使用 --resume 继续");
+            }
+            return Err(e);
+        }
+    };
+    drop(patch);
+    if report.read_only || report.dry_run {
+        if report.dry_run {
+            status!("试运行，未做任何修改。将要进行的变更:");
+        } else {
+            status!("目标目录只读，未做任何修改。需要的变更:");
+        }
+        status!("  {}", report.summary());
+        if !report.warnings.is_empty() {
+            status!(
+                "  {} 个警告 (校验和冲突等)，实际应用时同样会出现",
+                report.warnings.len()
+            );
+        }
+        return Ok(report);
+    }
+    AppliedState::new(patch_path, patch_hash, &report).save(target_dir)?;
 
-    // 读取校验和信息
-    let checksums = load_checksums(&temp_dir)?;
+    status!("补丁应用完成!");
+    status!("  {}", report.summary());
+    status!("  大小变化: {}", format_size_delta(report.size_delta()));
+    if !report.modified.is_empty() {
+        status!("  修改的文件:");
+        for path in &report.modified {
+            status!("    * {} ({})", path, report.describe_modification(path));
+        }
+    }
+    for upgrade in &report.upgrades {
+        status!("  {}", upgrade);
+    }
+    if let Some(verification) = &report.verification {
+        status!(
+            "  复验 ({}): {} / {} 个文件, {} 个不一致",
+            verification.mode,
+            verification.checked,
+            verification.total,
+            verification.mismatched.len()
+        );
+    }
+    if !report.corrupt.is_empty() {
+        status!("以下条目已损坏，未应用 (需单独补发):");
+        for path in &report.corrupt {
+            status!("  ! {}", path);
+        }
+    }
+    Ok(report)
+}
 
-    println!("正在应用补丁...");
+fn apply_loaded(
+    target_dir: &Path,
+    patch: &mut LoadedPatch,
+    patch_hash: &HashResult,
+    options: &ApplyOptions,
+    guard: &PathGuard,
+    mut timings: Timings,
+    pre_hook: &mut dyn FnMut() -> Result<()>,
+) -> Result<ApplyReport> {
+    let temp_dir = patch.workspace().to_path_buf();
+    resolve_base_refs(
+        &temp_dir,
+        &mut patch.checksums,
+        options.base_patch.as_deref(),
+    )?;
+    if options.strict {
+        let validation = verify_entries(patch)?;
+        if !validation.is_valid() {
+            bail!("补丁包条目损坏: {}", validation.problems());
+        }
+    }
+    if options.sync && patch.checksums.manifest.is_empty() {
+        bail!("补丁未登记全量清单，无法同步 (生成补丁时使用 --full-manifest)");
+    }
+    if options.atomic && (options.resume || options.replace_on_reboot) {
+        bail!("原子应用失败时会整体回滚，不能与从检查点继续或重启后替换同时使用");
+    }
+    if options.rollback.is_some() && (options.resume || options.replace_on_reboot) {
+        bail!("生成回滚补丁需要完整记录应用前的文件，不能与从检查点继续或重启后替换同时使用");
+    }
+    let mut report = ApplyReport::default();
+    if options.skip_corrupt {
+        skip_corrupt_entries(patch, &mut report)?;
+    }
+    let checksums = &patch.checksums;
+    if options.strict {
+        check_originals(target_dir, checksums, options)?;
+    }
+    check_delta_bases(target_dir, checksums, options)?;
+    pre_hook()?;
+
+    // 试运行与只读目标 (如容器镜像层) 只检查，列出需要的变更
+    if options.dry_run || is_read_only(target_dir) {
+        if options.dry_run {
+            status!("试运行，正在检查需要的变更...");
+        } else {
+            status!("目标目录只读，正在检查需要的变更...");
+        }
+        plan_changes(target_dir, checksums, options, &mut report)?;
+        report.dry_run = options.dry_run;
+        report.read_only = !options.dry_run;
+        report.timings = timings;
+        report.upgrades = find_upgrades(
+            report.deleted.iter().map(String::as_str),
+            report.added.iter().map(String::as_str),
+        );
+        return Ok(report);
+    }
+
+    Journal::recover(target_dir)?;
+    status!("正在应用补丁...");
+    let stage = Instant::now();
+    let old_sizes = collect_old_sizes(target_dir, checksums);
+    // 原子应用失败时整体回滚，不需要检查点
+    let checkpoint_interval = if options.atomic {
+        0
+    } else {
+        options.checkpoint_interval
+    };
+    let mut progress = Progress {
+        cases: CaseIndex::new(target_dir),
+        checkpoint: Checkpoint::open(target_dir, patch_hash, checkpoint_interval, options.resume)?,
+        journal: options
+            .atomic
+            .then(|| Journal::open(target_dir))
+            .transpose()?,
+        rollback: options
+            .rollback
+            .as_ref()
+            .map(|_| RollbackCapture::open(target_dir))
+            .transpose()?,
+    };
+    let entries_dir = if options.atomic {
+        status!("正在暂存新文件...");
+        stage_entries(target_dir, &temp_dir, checksums, options)?
+    } else {
+        temp_dir.clone()
+    };
+
+    // 移动文件
+    apply_moves(
+        target_dir,
+        checksums,
+        guard,
+        &mut progress,
+        options,
+        &mut report,
+    )?;
+
+    // 重命名文件
+    apply_renames(
+        target_dir,
+        &entries_dir,
+        checksums,
+        guard,
+        &mut progress,
+        options,
+        &mut report,
+    )?;
 
     // 删除文件
-    apply_deletions(target_dir, &checksums)?;
+    apply_deletions(
+        target_dir,
+        checksums,
+        guard,
+        &mut progress,
+        options,
+        &mut report,
+    )?;
 
     // 添加新文件
-    apply_additions(target_dir, &temp_dir, &checksums)?;
+    apply_additions(
+        target_dir,
+        &entries_dir,
+        checksums,
+        guard,
+        &mut progress,
+        options,
+        &mut report,
+    )?;
 
     // 应用修改
-    apply_modifications(target_dir, &temp_dir, &checksums)?;
+    apply_modifications(
+        target_dir,
+        &entries_dir,
+        checksums,
+        guard,
+        &mut progress,
+        options,
+        &mut report,
+    )?;
 
-    // 清理临时目录
-    fs::remove_dir_all(&temp_dir)?;
+    // 删除整体替换的目录中补丁未包含的文件
+    apply_replace_dirs(
+        target_dir,
+        checksums,
+        guard,
+        &mut progress,
+        options,
+        &mut report,
+    )?;
 
-    println!("补丁应用完成!");
-    Ok(())
+    // 同步模式：删除全量清单中未登记的文件
+    if options.sync {
+        apply_sync(
+            target_dir,
+            checksums,
+            guard,
+            &mut progress,
+            options,
+            &mut report,
+        )?;
+    }
+
+    // 同步仅属性变化的文件
+    apply_attributes(
+        target_dir,
+        checksums,
+        guard,
+        &mut progress,
+        options,
+        &mut report,
+    )?;
+    if let (Some(output), Some(capture)) = (&options.rollback, &progress.rollback) {
+        let compression = patch.metadata.as_ref().map(|m| m.compression);
+        let rollback = capture.write(
+            output,
+            patch.metadata.as_ref(),
+            compression.unwrap_or_default(),
+        )?;
+        status!("回滚补丁已写入: {}", output.display());
+        status!("  {}", rollback.summary());
+        report.rollback = Some(output.clone());
+    }
+    progress.checkpoint.finish()?;
+    if let Some(journal) = progress.journal.take() {
+        journal.commit()?;
+    }
+
+    let written_bytes = report
+        .added
+        .iter()
+        .chain(&report.modified)
+        .chain(
+            report
+                .renamed
+                .iter()
+                .map(|r| &r.to)
+                .filter(|to| !checksums.moved.contains_key(*to)),
+        )
+        .filter_map(|path| fs::metadata(target_dir.join(path)).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    record_stage(&mut timings, "apply", stage, written_bytes, options);
+    report.size_changes = size_changes(target_dir, &temp_dir, &old_sizes, &report);
+
+    if let Some(mode) = options.verify {
+        let stage = Instant::now();
+        let verification = verify_applied(target_dir, checksums, &report, mode)?;
+        record_stage(
+            &mut timings,
+            "verify",
+            stage,
+            verification.checked_bytes,
+            options,
+        );
+        report.verification = Some(verification);
+    }
+    report.timings = timings;
+    report.upgrades = find_upgrades(
+        report.deleted.iter().map(String::as_str),
+        report.added.iter().map(String::as_str),
+    );
+    Ok(report)
+}
+
+/// 各阶段共用的应用进度：已知的文件名大小写与检查点
+struct Progress {
+    cases: CaseIndex,
+    checkpoint: Checkpoint,
+    /// 原子应用的回滚日志
+    journal: Option<Journal>,
+    /// 生成回滚补丁时记录的原文件
+    rollback: Option<RollbackCapture>,
+}
+
+impl Progress {
+    /// 即将改动目标文件：生成回滚补丁时保存原文件的副本
+    fn capture(&mut self, path: &str) -> Result<()> {
+        match &mut self.rollback {
+            Some(rollback) => rollback.capture(path),
+            None => Ok(()),
+        }
+    }
+
+    /// 即将覆盖或删除目标文件：原子应用时把原文件移入回滚日志
+    fn preserve(&mut self, path: &str) -> Result<()> {
+        self.capture(path)?;
+        match &mut self.journal {
+            Some(journal) => journal.preserve(path),
+            None => Ok(()),
+        }
+    }
+
+    /// 即将原地改动或移走目标文件：原子应用时在回滚日志中保留一份副本
+    fn keep_copy(&mut self, path: &str) -> Result<()> {
+        self.capture(path)?;
+        match &mut self.journal {
+            Some(journal) => journal.keep_copy(path),
+            None => Ok(()),
+        }
+    }
+
+    /// 删除目标文件；原子应用时改为移入回滚日志
+    fn remove(&mut self, target_path: &Path, path: &str) -> Result<()> {
+        self.capture(path)?;
+        match &mut self.journal {
+            Some(journal) => journal.preserve(path),
+            None => Ok(fs::remove_file(target_path)?),
+        }
+    }
+}
+
+/// 原子应用的第一阶段：把要放置的新增、修改与重命名文件 (以及配置合并基准) 复制到目标目录的暂存区
+/// 并核对哈希，返回与解包目录结构相同的暂存目录；这一步失败时目标目录尚未改动
+fn stage_entries(
+    target_dir: &Path,
+    temp_dir: &Path,
+    checksums: &Checksums,
+    options: &ApplyOptions,
+) -> Result<PathBuf> {
+    let staging_dir = target_dir.join(STATE_DIR).join(STAGING_DIR);
+    let _ = fs::remove_dir_all(&staging_dir);
+    let platform = Platform::current();
+    let added = checksums
+        .added
+        .iter()
+        .map(|(path, hash)| ("added", path, Some(hash)));
+    // 合并基准在合并时核对
+    let modified = checksums
+        .modified
+        .iter()
+        .map(|(path, checksum)| ("modified", path, Some(&checksum.modified)));
+    let merge_bases = checksums
+        .merge_bases
+        .iter()
+        .map(|path| (MERGE_BASE_DIR, path, None));
+    for (dir, path, expected) in added.chain(modified).chain(merge_bases) {
+        let source = temp_dir.join(dir).join(path);
+        if !checksums.applies_to(path, &platform)
+            || options.protected.is_ignored(Path::new(path))
+            || !source.is_file()
+        {
+            continue;
+        }
+        let staged = staging_dir.join(dir).join(path);
+        if let Some(parent) = staged.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        copy_file(&source, &staged).with_context(|| format!("无法暂存文件: {}", path))?;
+        // 以增量存放的修改文件此时目标目录尚未改动，先由原文件还原成完整内容再核对；
+        // 目标已是新内容的会被跳过，不必还原
+        let target_path = target_dir.join(path);
+        if dir == "modified"
+            && checksums.delta_modified.iter().any(|p| p == path)
+            && let Some(expected) = expected
+        {
+            if !already_applied(&target_path, expected) {
+                restore_delta(&target_path, &staged, expected)
+                    .with_context(|| format!("无法由增量还原修改文件: {}", path))?;
+            }
+            continue;
+        }
+        if let Some(expected) = expected
+            && compute_file_hash(&staged)? != *expected
+        {
+            bail!("暂存的文件与补丁登记的哈希不一致: {}", path);
+        }
+    }
+    // 重命名条目同样由原位置的文件还原后暂存并核对；源文件不存在时由重命名阶段报错
+    for (to, renamed) in &checksums.renamed {
+        let from_path = target_dir.join(&renamed.from);
+        if !checksums.applies_to(to, &platform)
+            || options.protected.is_ignored(Path::new(to))
+            || options.protected.is_ignored(Path::new(&renamed.from))
+            || !from_path.is_file()
+        {
+            continue;
+        }
+        let staged = staging_dir.join("renamed").join(to);
+        restore_renamed(&temp_dir.join("renamed").join(to), &from_path, &staged, to)?;
+        if compute_file_hash(&staged)? != renamed.modified {
+            bail!("暂存的文件与补丁登记的哈希不一致: {}", to);
+        }
+    }
+    Ok(staging_dir)
 }
 
+/// 应用前记录将被删除、修改、重命名或覆盖的文件的大小
+fn collect_old_sizes(target_dir: &Path, checksums: &Checksums) -> HashMap<String, u64> {
+    checksums
+        .deleted
+        .iter()
+        .chain(checksums.modified.keys())
+        .chain(checksums.added.keys())
+        .chain(checksums.renamed.values().map(|r| &r.from))
+        .chain(checksums.moved.values().map(|m| &m.from))
+        .filter_map(|path| {
+            Some((
+                path.clone(),
+                fs::metadata(target_dir.join(path)).ok()?.len(),
+            ))
+        })
+        .collect()
+}
+
+/// 各文件应用前后的大小；安排在重启后替换的文件按补丁中的新内容计算
+fn size_changes(
+    target_dir: &Path,
+    temp_dir: &Path,
+    old_sizes: &HashMap<String, u64>,
+    report: &ApplyReport,
+) -> BTreeMap<String, FileSizes> {
+    let new_size = |section: &str, path: &str| {
+        let file = if report.pending_reboot.iter().any(|p| p == path) {
+            temp_dir.join(section).join(path)
+        } else {
+            target_dir.join(path)
+        };
+        fs::metadata(file).ok().map(|m| m.len())
+    };
+    let mut changes = BTreeMap::new();
+    for path in &report.added {
+        let sizes = FileSizes::new(old_sizes.get(path).copied(), new_size("added", path));
+        changes.insert(path.clone(), sizes);
+    }
+    for path in &report.modified {
+        let sizes = FileSizes::new(old_sizes.get(path).copied(), new_size("modified", path));
+        changes.insert(path.clone(), sizes);
+    }
+    for path in &report.deleted {
+        changes.insert(
+            path.clone(),
+            FileSizes::new(old_sizes.get(path).copied(), None),
+        );
+    }
+    for renamed in &report.renamed {
+        let sizes = FileSizes::new(
+            old_sizes.get(&renamed.from).copied(),
+            fs::metadata(target_dir.join(&renamed.to))
+                .ok()
+                .map(|m| m.len()),
+        );
+        changes.insert(renamed.to.clone(), sizes);
+    }
+    changes
+}
+
+/// 解包补丁包，按文件头识别压缩格式，兼容 PAX 扩展头、GNU 长路径条目以及多个分卷拼接的补丁包
 pub(crate) fn extract_patch(patch_path: &Path, dest_dir: &Path) -> Result<()> {
-    let file = File::open(patch_path)?;
-    let decoder = GzDecoder::new(BufReader::new(file));
-    let mut archive = Archive::new(decoder);
-    archive.unpack(dest_dir)?;
+    let mut archive = Archive::new(open_decoder(patch_path)?);
+    archive
+        .unpack(dest_dir)
+        .map_err(|e| PatchError::InvalidArchive {
+            path: patch_path.to_path_buf(),
+            reason: diagnose_archive(patch_path, e.to_string()),
+        })?;
     Ok(())
 }
 
+/// 逐个条目解包，遇到损坏的数据时停止并返回错误信息，已解出的条目保留
+pub(crate) fn extract_patch_lenient(patch_path: &Path, dest_dir: &Path) -> Result<Option<String>> {
+    let mut archive = Archive::new(open_decoder(patch_path)?);
+    for entry in archive.entries()? {
+        if let Err(e) = entry.and_then(|mut entry| entry.unpack_in(dest_dir)) {
+            return Ok(Some(e.to_string()));
+        }
+    }
+    Ok(None)
+}
+
 pub(crate) fn load_checksums(temp_dir: &Path) -> Result<Checksums> {
     let checksums_path = temp_dir.join("checksums.toml");
+    if !checksums_path.is_file() {
+        return Err(PatchError::MissingChecksums.into());
+    }
     let checksums_content =
         fs::read_to_string(&checksums_path).with_context(|| "无法读取 checksums.toml")?;
     let checksums: Checksums =
@@ -58,12 +908,261 @@ pub(crate) fn load_checksums(temp_dir: &Path) -> Result<Checksums> {
     Ok(checksums)
 }
 
-fn apply_deletions(target_dir: &Path, checksums: &Checksums) -> Result<()> {
+/// 就地移动内容未变的文件：先全部移入 `.dft/moving` 再移到新路径，避免移动链互相覆盖
+fn apply_moves(
+    target_dir: &Path,
+    checksums: &Checksums,
+    guard: &PathGuard,
+    progress: &mut Progress,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    if checksums.moved.is_empty() {
+        return Ok(());
+    }
+    let staging_dir = target_dir.join(STATE_DIR).join(MOVING_DIR);
+    let platform = Platform::current();
+
+    let mut staged = Vec::new();
+    for (to, moved) in &checksums.moved {
+        if skip_for_platform(Path::new(to), checksums, &platform, options, report)
+            || skip_protected(Path::new(to), options, report)
+            || skip_protected(Path::new(&moved.from), options, report)
+        {
+            continue;
+        }
+        let from_path = target_dir.join(&moved.from);
+        if progress.checkpoint.is_done("move", to)
+            || options.resume
+                && already_applied(&target_dir.join(to), &moved.hash)
+                && !from_path.exists()
+        {
+            report.renamed.push(RenamedEntry {
+                from: moved.from.clone(),
+                to: to.to_string(),
+            });
+            continue;
+        }
+
+        let staged_path = staging_dir.join(to);
+        // 上次中断时可能已移入暂存目录
+        if !(options.resume && !from_path.exists() && staged_path.is_file()) {
+            if !from_path.exists() {
+                bail!("移动的源文件不存在: {}", moved.from);
+            }
+            guard.check(&from_path)?;
+            progress
+                .cases
+                .correct(&from_path, &moved.from, &options.warning_policy, report)?;
+            let actual = compute_file_hash(&from_path)?;
+            if actual != moved.hash {
+                warn_checksum_mismatch(&moved.from, &moved.hash, actual, options, report)?;
+            }
+            if let Some(parent) = staged_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            progress.keep_copy(&moved.from)?;
+            move_file(&from_path, &staged_path)?;
+            if let Some(parent) = from_path.parent() {
+                let _ = fs::remove_dir(parent); // 忽略错误，目录可能非空
+            }
+        }
+        guard.check(&target_dir.join(to))?;
+        staged.push((to, moved, staged_path));
+    }
+
+    for (to, moved, staged_path) in staged {
+        let target_path = target_dir.join(to);
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        progress.preserve(to)?;
+        move_file(&staged_path, &target_path)?;
+        progress.cases.record(&target_path);
+        options.events.emit(PatchEvent::FileRenamed {
+            from: moved.from.clone(),
+            to: to.to_string(),
+        });
+        report.renamed.push(RenamedEntry {
+            from: moved.from.clone(),
+            to: to.to_string(),
+        });
+        progress.checkpoint.record("move", to)?;
+    }
+    let _ = fs::remove_dir_all(&staging_dir);
+    progress.checkpoint.flush()
+}
+
+/// 移动文件，跨文件系统时退回为复制后删除
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_err() {
+        copy_file(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+fn apply_renames(
+    target_dir: &Path,
+    entries_dir: &Path,
+    checksums: &Checksums,
+    guard: &PathGuard,
+    progress: &mut Progress,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    let renamed_dir = entries_dir.join("renamed");
+    let staging_dir = entries_dir.join("renamed_staging");
+    let platform = Platform::current();
+
+    let mut staged = Vec::new();
+    for (to, renamed) in &checksums.renamed {
+        if skip_for_platform(Path::new(to), checksums, &platform, options, report)
+            || skip_protected(Path::new(to), options, report)
+            || skip_protected(Path::new(&renamed.from), options, report)
+        {
+            continue;
+        }
+        if progress.checkpoint.is_done("rename", to)
+            || options.resume
+                && already_applied(&target_dir.join(to), &renamed.modified)
+                && !target_dir.join(&renamed.from).exists()
+        {
+            report.renamed.push(RenamedEntry {
+                from: renamed.from.clone(),
+                to: to.to_string(),
+            });
+            continue;
+        }
+
+        let from_path = target_dir.join(&renamed.from);
+        if !from_path.exists() {
+            bail!("重命名的源文件不存在: {}", renamed.from);
+        }
+        guard.check(&from_path)?;
+        progress
+            .cases
+            .correct(&from_path, &renamed.from, &options.warning_policy, report)?;
+        guard.check(&target_dir.join(to))?;
+        let actual = compute_file_hash(&from_path)?;
+        if actual != renamed.original {
+            warn_checksum_mismatch(&renamed.from, &renamed.original, actual, options, report)?;
+        }
+
+        // 先在临时目录中还原所有文件，避免重命名链互相覆盖；原子应用时已在暂存区还原并核对
+        let staged_path = if options.atomic {
+            let staged_path = renamed_dir.join(to);
+            if !staged_path.is_file() {
+                bail!("补丁中缺少重命名数据: {}", to);
+            }
+            staged_path
+        } else {
+            let staged_path = staging_dir.join(to);
+            restore_renamed(&renamed_dir.join(to), &from_path, &staged_path, to)?;
+            staged_path
+        };
+
+        staged.push((renamed, to, staged_path));
+    }
+
+    for (renamed, _, _) in &staged {
+        let from_path = target_dir.join(&renamed.from);
+        progress.remove(&from_path, &renamed.from)?;
+        if let Some(parent) = from_path.parent() {
+            let _ = fs::remove_dir(parent); // 忽略错误，目录可能非空
+        }
+    }
+
+    for (renamed, to, staged_path) in &staged {
+        let target_path = target_dir.join(to);
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        progress.preserve(to)?;
+        if options.atomic {
+            move_file(staged_path, &target_path)?;
+        } else {
+            copy_file(staged_path, &target_path)?;
+        }
+        progress.cases.record(&target_path);
+        options.events.emit(PatchEvent::FileRenamed {
+            from: renamed.from.clone(),
+            to: to.to_string(),
+        });
+        report.renamed.push(RenamedEntry {
+            from: renamed.from.clone(),
+            to: to.to_string(),
+        });
+        progress.checkpoint.record("rename", to)?;
+    }
+    progress.checkpoint.flush()
+}
+
+/// 由原位置的文件 `base` 与补丁中的重命名数据 `delta` 还原新文件到 `output`
+fn restore_renamed(delta: &Path, base: &Path, output: &Path, to: &str) -> Result<()> {
+    let delta_file = File::open(delta).with_context(|| format!("补丁中缺少重命名数据: {}", to))?;
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut base = File::open(base)?;
+    let mut out = BufWriter::new(File::create(output)?);
+    apply_encoded(&mut BufReader::new(delta_file), &mut base, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn apply_deletions(
+    target_dir: &Path,
+    checksums: &Checksums,
+    guard: &PathGuard,
+    progress: &mut Progress,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    let platform = Platform::current();
     for deleted_file in &checksums.deleted {
+        if skip_for_platform(
+            Path::new(deleted_file),
+            checksums,
+            &platform,
+            options,
+            report,
+        ) || skip_protected(Path::new(deleted_file), options, report)
+        {
+            continue;
+        }
+        if progress.checkpoint.is_done("delete", deleted_file) {
+            report.deleted.push(deleted_file.clone());
+            continue;
+        }
         let target_path = target_dir.join(deleted_file);
         if target_path.exists() {
-            fs::remove_file(&target_path)?;
-            println!("  - {}", deleted_file);
+            guard.check(&target_path)?;
+            progress
+                .cases
+                .correct(&target_path, deleted_file, &options.warning_policy, report)?;
+            progress.capture(deleted_file)?;
+            let removed = match &mut progress.journal {
+                Some(journal) => journal.preserve(deleted_file).map(|_| Ok(())),
+                None => Ok(fs::remove_file(&target_path)),
+            };
+            let pending_reboot = match removed? {
+                Err(e) if options.replace_on_reboot && is_file_in_use(&e) => {
+                    move_on_reboot(&target_path, None)?;
+                    report.pending_reboot.push(deleted_file.clone());
+                    true
+                }
+                result => {
+                    result?;
+                    false
+                }
+            };
+            options.events.emit(PatchEvent::FileDeleted {
+                path: deleted_file.clone(),
+                pending_reboot,
+            });
+            report.deleted.push(deleted_file.clone());
+            progress.checkpoint.record("delete", deleted_file)?;
 
             // 清理空目录
             if let Some(parent) = target_path.parent() {
@@ -71,52 +1170,984 @@ fn apply_deletions(target_dir: &Path, checksums: &Checksums) -> Result<()> {
             }
         }
     }
+    progress.checkpoint.flush()
+}
+
+/// 整体替换的目录中，删除补丁未包含 (新增、修改或重命名) 的文件及随之变空的目录
+fn apply_replace_dirs(
+    target_dir: &Path,
+    checksums: &Checksums,
+    guard: &PathGuard,
+    progress: &mut Progress,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    for path in replace_dir_extras(target_dir, checksums)? {
+        if skip_protected(Path::new(&path), options, report) {
+            continue;
+        }
+        let target_path = target_dir.join(&path);
+        guard.check(&target_path)?;
+        progress.remove(&target_path, &path)?;
+        options.events.emit(PatchEvent::FileDeleted {
+            path: path.clone(),
+            pending_reboot: false,
+        });
+        report.deleted.push(path.clone());
+        progress.checkpoint.record("delete", &path)?;
+    }
+    for dir in &checksums.replace_dirs {
+        // 自底向上清理空目录，替换的目录本身保留
+        for entry in WalkDir::new(target_dir.join(dir))
+            .min_depth(1)
+            .contents_first(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_dir())
+        {
+            let _ = fs::remove_dir(entry.path()); // 忽略错误，目录可能非空
+        }
+    }
+    progress.checkpoint.flush()
+}
+
+/// 把目标目录对齐到全量清单：删除未登记且不在白名单中的文件，清单中缺失的文件给出警告
+fn apply_sync(
+    target_dir: &Path,
+    checksums: &Checksums,
+    guard: &PathGuard,
+    progress: &mut Progress,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    for path in sync_extras(target_dir, checksums, options)? {
+        if skip_protected(Path::new(&path), options, report) {
+            continue;
+        }
+        let target_path = target_dir.join(&path);
+        guard.check(&target_path)?;
+        progress.remove(&target_path, &path)?;
+        options.events.emit(PatchEvent::FileDeleted {
+            path: path.clone(),
+            pending_reboot: false,
+        });
+        report.deleted.push(path.clone());
+        progress.checkpoint.record("delete", &path)?;
+        if let Some(parent) = target_path.parent() {
+            let _ = fs::remove_dir(parent); // 忽略错误，目录可能非空
+        }
+    }
+    let platform = Platform::current();
+    for path in checksums.manifest.keys() {
+        if checksums.applies_to(path, &platform) && !target_dir.join(path).is_file() {
+            let message = format!("{} 登记在全量清单中，但目标目录中不存在", path);
+            warn(WarningKind::MissingTarget, message, options, report)?;
+        }
+    }
+    progress.checkpoint.flush()
+}
+
+/// 目标目录中未登记在全量清单、也不在白名单中的文件
+fn sync_extras(
+    target_dir: &Path,
+    checksums: &Checksums,
+    options: &ApplyOptions,
+) -> Result<Vec<String>> {
+    // 删除与重命名的源文件由各自的阶段处理
+    let handled: HashSet<&String> = checksums
+        .deleted
+        .iter()
+        .chain(checksums.renamed.values().map(|r| &r.from))
+        .chain(checksums.moved.values().map(|m| &m.from))
+        .collect();
+    let mut extras = Vec::new();
+    for entry in walk_files(target_dir) {
+        let relative_path = entry.path().strip_prefix(target_dir)?;
+        let path = relative_path.to_string_lossy().to_string();
+        if !checksums.manifest.contains_key(&path)
+            && !handled.contains(&path)
+            && !options.sync_keep.is_ignored(relative_path)
+        {
+            extras.push(path);
+        }
+    }
+    extras.sort();
+    Ok(extras)
+}
+
+/// 整体替换的目录中存在、但补丁未包含的文件
+fn replace_dir_extras(target_dir: &Path, checksums: &Checksums) -> Result<Vec<String>> {
+    let mut extras = Vec::new();
+    for dir in &checksums.replace_dirs {
+        for entry in walk_files(&target_dir.join(dir)) {
+            let path = entry
+                .path()
+                .strip_prefix(target_dir)?
+                .to_string_lossy()
+                .to_string();
+            // 删除与重命名的源文件由各自的阶段处理
+            if !checksums.added.contains_key(&path)
+                && !checksums.modified.contains_key(&path)
+                && !checksums.renamed.contains_key(&path)
+                && !checksums.deleted.contains(&path)
+                && !checksums.renamed.values().any(|r| r.from == path)
+                && !checksums.moved.contains_key(&path)
+                && !checksums.moved.values().any(|m| m.from == path)
+            {
+                extras.push(path);
+            }
+        }
+    }
+    extras.sort();
+    Ok(extras)
+}
+
+fn apply_attributes(
+    target_dir: &Path,
+    checksums: &Checksums,
+    guard: &PathGuard,
+    progress: &mut Progress,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    let platform = Platform::current();
+    for path in sorted_keys(&checksums.attributes) {
+        if skip_for_platform(Path::new(path), checksums, &platform, options, report)
+            || skip_protected(Path::new(path), options, report)
+        {
+            continue;
+        }
+        let target_path = target_dir.join(path);
+        if !target_path.is_file() {
+            let message = format!("{} 不存在，未同步其权限与修改时间", path);
+            warn(WarningKind::MissingTarget, message, options, report)?;
+            continue;
+        }
+        guard.check(&target_path)?;
+        progress.keep_copy(path)?;
+        checksums.attributes[path].apply_to(&target_path)?;
+        options
+            .events
+            .emit(PatchEvent::AttributesUpdated { path: path.clone() });
+        report.attributes.push(path.clone());
+    }
     Ok(())
 }
 
-fn apply_additions(target_dir: &Path, temp_dir: &Path, _checksums: &Checksums) -> Result<()> {
-    let added_dir = temp_dir.join("added");
-    if !added_dir.exists() {
-        return Ok(());
+fn apply_additions(
+    target_dir: &Path,
+    entries_dir: &Path,
+    checksums: &Checksums,
+    guard: &PathGuard,
+    progress: &mut Progress,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    let added_dir = entries_dir.join("added");
+    let platform = Platform::current();
+    let mut pending = Vec::new();
+    for path in sorted_keys(&checksums.added) {
+        let relative_path = Path::new(path);
+        if skip_for_platform(relative_path, checksums, &platform, options, report)
+            || skip_protected(relative_path, options, report)
+        {
+            continue;
+        }
+        let target_path = target_dir.join(relative_path);
+        if progress.checkpoint.is_done("add", path)
+            || options.resume && already_applied(&target_path, &checksums.added[path])
+        {
+            report.added.push(path.clone());
+            continue;
+        }
+        if skip_unchanged(&target_path, path, &checksums.added[path], options, report) {
+            progress.checkpoint.record("add", path)?;
+            continue;
+        }
+        let source_path = added_dir.join(relative_path);
+        if !source_path.is_file() {
+            bail!("补丁中缺少新增文件: {}", path);
+        }
+        guard.check(&target_path)?;
+        progress
+            .cases
+            .correct(&target_path, path, &options.warning_policy, report)?;
+        if target_path.exists() && !checksums.in_replace_dir(path) {
+            let message = format!("{} 已存在，将被补丁中的新文件覆盖", path);
+            warn(WarningKind::ExtraFile, message, options, report)?;
+        }
+
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        pending.push((path, source_path, target_path));
     }
 
-    for entry in WalkDir::new(&added_dir).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            let relative_path = entry.path().strip_prefix(&added_dir)?;
-            let target_path = target_dir.join(relative_path);
+    for batch in pending.chunks(progress.checkpoint.batch_size()) {
+        for (path, _, _) in batch {
+            progress.preserve(path)?;
+        }
+        let placed = place_files(batch, options)?;
+        for ((path, _, target_path), placed) in batch.iter().zip(placed) {
+            progress.cases.record(target_path);
+            if !placed {
+                report.pending_reboot.push(path.to_string());
+            }
+            options.events.emit(PatchEvent::FileAdded {
+                path: path.to_string(),
+                pending_reboot: !placed,
+            });
+            report.added.push(path.to_string());
+            progress.checkpoint.record("add", path)?;
+        }
+    }
+    progress.checkpoint.flush()
+}
 
-            if let Some(parent) = target_path.parent() {
-                fs::create_dir_all(parent)?;
+fn apply_modifications(
+    target_dir: &Path,
+    entries_dir: &Path,
+    checksums: &Checksums,
+    guard: &PathGuard,
+    progress: &mut Progress,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    let modified_dir = entries_dir.join("modified");
+    let platform = Platform::current();
+    let mut pending = Vec::new();
+    for path in sorted_keys(&checksums.modified) {
+        let relative_path = Path::new(path);
+        if skip_for_platform(relative_path, checksums, &platform, options, report)
+            || skip_protected(relative_path, options, report)
+        {
+            continue;
+        }
+        let target_path = target_dir.join(relative_path);
+        // 已修改的文件不再是原始内容，不能再核对原始校验和
+        if progress.checkpoint.is_done("modify", path)
+            || options.resume && already_applied(&target_path, &checksums.modified[path].modified)
+        {
+            report.modified.push(path.clone());
+            continue;
+        }
+        // 补丁已应用过的文件不再写入，重复应用时只需计算哈希
+        if may_be_applied(&target_path, path, checksums)
+            && skip_unchanged(
+                &target_path,
+                path,
+                &checksums.modified[path].modified,
+                options,
+                report,
+            )
+        {
+            progress.checkpoint.record("modify", path)?;
+            continue;
+        }
+        let source_path = modified_dir.join(relative_path);
+        if !source_path.is_file() {
+            bail!("补丁中缺少修改文件: {}", path);
+        }
+
+        guard.check(&target_path)?;
+        progress
+            .cases
+            .correct(&target_path, path, &options.warning_policy, report)?;
+
+        // 玩家改动过的配置文件与新版本按键合并
+        if let Some(merger) = mergeable_config(&target_path, path, checksums, options)? {
+            let base_path = entries_dir.join(MERGE_BASE_DIR).join(relative_path);
+            let original = &checksums.modified[path].original;
+            match merge_config(merger, &base_path, &target_path, &source_path, original) {
+                Ok(content) => {
+                    if let Ok(local) = fs::read_to_string(&target_path) {
+                        let changes = count_line_changes(&local, &content);
+                        report.line_changes.insert(path.clone(), changes);
+                    }
+                    progress.preserve(path)?;
+                    fs::write(&target_path, content)
+                        .with_context(|| format!("无法写入合并后的配置: {}", path))?;
+                    options.events.emit(PatchEvent::FileModified {
+                        path: path.clone(),
+                        pending_reboot: false,
+                    });
+                    options.events.emit(PatchEvent::ConfigMerged {
+                        path: path.clone(),
+                        merger: merger.name().to_string(),
+                    });
+                    report.modified.push(path.clone());
+                    report.merged.push(path.clone());
+                    progress.checkpoint.record("modify", path)?;
+                    continue;
+                }
+                Err(e) => options.events.emit(PatchEvent::ConfigMergeFailed {
+                    path: path.clone(),
+                    error: format!("{:#}", e),
+                }),
+            }
+        }
+
+        // 验证原始文件校验和
+        verify_original_checksum(&target_path, relative_path, checksums, options, report)?;
+        // 原子应用时增量已在暂存时还原
+        if !options.atomic && checksums.delta_modified.iter().any(|p| p == path) {
+            restore_delta(
+                &target_path,
+                &source_path,
+                &checksums.modified[path].modified,
+            )
+            .with_context(|| format!("无法由增量还原修改文件: {}", path))?;
+        }
+        if let Some(changes) = line_changes(&target_path, &source_path) {
+            report.line_changes.insert(path.clone(), changes);
+        }
+
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        pending.push((path, source_path, target_path));
+    }
+
+    for batch in pending.chunks(progress.checkpoint.batch_size()) {
+        for (path, _, _) in batch {
+            progress.preserve(path)?;
+        }
+        let placed = place_files(batch, options)?;
+        for ((path, _, _), placed) in batch.iter().zip(placed) {
+            if !placed {
+                report.pending_reboot.push(path.to_string());
             }
-            fs::copy(entry.path(), &target_path)?;
-            println!("  + {}", relative_path.display());
+            options.events.emit(PatchEvent::FileModified {
+                path: path.to_string(),
+                pending_reboot: !placed,
+            });
+            report.modified.push(path.to_string());
+            progress.checkpoint.record("modify", path)?;
         }
     }
+    progress.checkpoint.flush()
+}
+
+/// 补丁附带了合并基准、本地文件已被改动且有插件能处理时，返回该插件
+fn mergeable_config<'a>(
+    target_path: &Path,
+    path: &str,
+    checksums: &Checksums,
+    options: &'a ApplyOptions,
+) -> Result<Option<&'a dyn ConfigMerger>> {
+    if !checksums.merge_bases.iter().any(|p| p == path) || !target_path.is_file() {
+        return Ok(None);
+    }
+    let Some(merger) = options.config_mergers.find(Path::new(path)) else {
+        return Ok(None);
+    };
+    if checksums.modified[path].matches_original(&compute_file_hash(target_path)?) {
+        return Ok(None);
+    }
+    Ok(Some(merger))
+}
+
+/// 以补丁中的旧版本为基准，合并本地文件与新版本
+fn merge_config(
+    merger: &dyn ConfigMerger,
+    base_path: &Path,
+    local_path: &Path,
+    new_path: &Path,
+    original: &HashResult,
+) -> Result<String> {
+    let base = fs::read(base_path).context("补丁中缺少合并基准")?;
+    if compute_hash(&base) != *original {
+        bail!("补丁中的合并基准与原始哈希不一致");
+    }
+    let text = |data: Vec<u8>| String::from_utf8(data).context("不是 UTF-8 文本");
+    Ok(merger.merge(
+        &text(base)?,
+        &text(fs::read(local_path)?)?,
+        &text(fs::read(new_path)?)?,
+    )?)
+}
+
+/// 以目标目录中的旧文件为基础还原增量存放的修改文件，还原结果替换解包目录中的增量
+fn restore_delta(target_path: &Path, entry_path: &Path, expected: &HashResult) -> Result<()> {
+    let mut restored = entry_path.as_os_str().to_owned();
+    restored.push(".restored");
+    let restored = PathBuf::from(restored);
+    let mut input = BufReader::new(File::open(entry_path)?);
+    let mut out = BufWriter::new(File::create(&restored)?);
+    apply_encoded(&mut input, &mut File::open(target_path)?, &mut out)?;
+    out.flush()?;
+    drop(out);
+    if compute_file_hash(&restored)? != *expected {
+        fs::remove_file(&restored)?;
+        bail!("还原结果与补丁登记的哈希不一致，本地文件不是补丁的起始版本");
+    }
+    fs::rename(&restored, entry_path)?;
     Ok(())
 }
 
-fn apply_modifications(target_dir: &Path, temp_dir: &Path, checksums: &Checksums) -> Result<()> {
-    let modified_dir = temp_dir.join("modified");
-    if !modified_dir.exists() {
-        return Ok(());
+/// 目标文件已是补丁中的新内容 (补丁应用过，或中断前已写入但未记入检查点)
+fn already_applied(target_path: &Path, expected: &HashResult) -> bool {
+    target_path.is_file() && compute_file_hash(target_path).is_ok_and(|hash| hash == *expected)
+}
+
+/// 目标文件已是补丁中的新内容 (补丁应用过) 时不再写入，记为未变更
+fn skip_unchanged(
+    target_path: &Path,
+    path: &str,
+    expected: &HashResult,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> bool {
+    if !already_applied(target_path, expected) {
+        return false;
+    }
+    options.events.emit(PatchEvent::FileUnchanged {
+        path: path.to_string(),
+    });
+    report.unchanged.push(path.to_string());
+    true
+}
+
+/// 补丁登记了新版本的大小且与目标文件不同时，目标文件一定不是新内容，不必计算哈希
+fn may_be_applied(target_path: &Path, path: &str, checksums: &Checksums) -> bool {
+    match checksums.sizes.get(path).and_then(|sizes| sizes.new) {
+        Some(size) => fs::metadata(target_path).is_ok_and(|metadata| metadata.len() == size),
+        None => true,
+    }
+}
+
+/// 并行放置一批已通过检查的文件，结果按输入顺序返回
+///
+/// 数万个小文件时逐个 create/write/close 的等待占主导，多个线程同时写入可以合并这些等待。
+fn place_files(files: &[(&String, PathBuf, PathBuf)], options: &ApplyOptions) -> Result<Vec<bool>> {
+    let jobs = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_PLACE_JOBS);
+    parallel_map(files.iter().collect(), jobs, |(_, source, target)| {
+        place_file(source, target, options)
+    })
+}
+
+/// 把补丁中的文件放到目标位置，返回是否已立即生效
+///
+/// 目标文件被占用且启用了重启后替换时，新内容先写到同目录下的 `.dft_pending` 文件，
+/// 再安排在重启后替换目标文件。
+fn place_file(source: &Path, target: &Path, options: &ApplyOptions) -> Result<bool> {
+    // 原子应用时文件已在同一文件系统的暂存区中，改名即可就位
+    if options.atomic {
+        move_file(source, target)?;
+        return Ok(true);
+    }
+    let err = match copy_file(source, target) {
+        Ok(_) => return Ok(true),
+        Err(err) => err,
+    };
+    let in_use = err
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(is_file_in_use);
+    if !options.replace_on_reboot || !in_use {
+        return Err(err);
     }
 
-    for entry in WalkDir::new(&modified_dir)
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let pending = target.with_file_name(format!(".{}.dft_pending", name));
+    fs::copy(source, &pending).with_context(|| format!("无法写入待替换文件: {:?}", pending))?;
+    move_on_reboot(&pending, Some(target))?;
+    Ok(false)
+}
+
+/// 目标目录是否不可写：权限标记为只读，或位于只读文件系统上
+fn is_read_only(target_dir: &Path) -> bool {
+    if fs::metadata(target_dir).is_ok_and(|metadata| metadata.permissions().readonly()) {
+        return true;
+    }
+    let probe = target_dir.join(format!(".dft_write_probe_{}", std::process::id()));
+    match File::create(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            false
+        }
+        Err(e) => matches!(
+            e.kind(),
+            io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+        ),
+    }
+}
+
+/// 不修改目标目录，按应用时的规则把需要的变更记录到报告中
+fn plan_changes(
+    target_dir: &Path,
+    checksums: &Checksums,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    let platform = Platform::current();
+    for (to, moved) in &checksums.moved {
+        if skip_for_platform(Path::new(to), checksums, &platform, options, report)
+            || skip_protected(Path::new(to), options, report)
+            || skip_protected(Path::new(&moved.from), options, report)
+        {
+            continue;
+        }
+        let from_path = target_dir.join(&moved.from);
+        if !from_path.exists() {
+            let message = format!("移动的源文件不存在: {}", moved.from);
+            warn(WarningKind::MissingTarget, message, options, report)?;
+            continue;
+        }
+        let actual = compute_file_hash(&from_path)?;
+        if actual != moved.hash {
+            warn_checksum_mismatch(&moved.from, &moved.hash, actual, options, report)?;
+        }
+        options.events.emit(PatchEvent::RenamePlanned {
+            from: moved.from.clone(),
+            to: to.clone(),
+        });
+        report.renamed.push(RenamedEntry {
+            from: moved.from.clone(),
+            to: to.clone(),
+        });
+    }
+    for to in sorted_keys(&checksums.renamed) {
+        let renamed = &checksums.renamed[to];
+        if skip_for_platform(Path::new(to), checksums, &platform, options, report)
+            || skip_protected(Path::new(to), options, report)
+            || skip_protected(Path::new(&renamed.from), options, report)
+        {
+            continue;
+        }
+        let from_path = target_dir.join(&renamed.from);
+        if !from_path.exists() {
+            let message = format!("重命名的源文件不存在: {}", renamed.from);
+            warn(WarningKind::MissingTarget, message, options, report)?;
+            continue;
+        }
+        let actual = compute_file_hash(&from_path)?;
+        if actual != renamed.original {
+            warn_checksum_mismatch(&renamed.from, &renamed.original, actual, options, report)?;
+        }
+        options.events.emit(PatchEvent::RenamePlanned {
+            from: renamed.from.clone(),
+            to: to.clone(),
+        });
+        report.renamed.push(RenamedEntry {
+            from: renamed.from.clone(),
+            to: to.clone(),
+        });
+    }
+    for deleted_file in &checksums.deleted {
+        let skipped = skip_for_platform(
+            Path::new(deleted_file),
+            checksums,
+            &platform,
+            options,
+            report,
+        ) || skip_protected(Path::new(deleted_file), options, report);
+        if !skipped && target_dir.join(deleted_file).exists() {
+            options.events.emit(PatchEvent::DeletePlanned {
+                path: deleted_file.clone(),
+            });
+            report.deleted.push(deleted_file.clone());
+        }
+    }
+    let mut extras = replace_dir_extras(target_dir, checksums)?;
+    if options.sync {
+        extras.extend(sync_extras(target_dir, checksums, options)?);
+        extras.sort();
+        extras.dedup();
+    }
+    for path in extras {
+        if !skip_protected(Path::new(&path), options, report) {
+            options
+                .events
+                .emit(PatchEvent::DeletePlanned { path: path.clone() });
+            report.deleted.push(path);
+        }
+    }
+    for path in sorted_keys(&checksums.added) {
+        if !skip_for_platform(Path::new(path), checksums, &platform, options, report)
+            && !skip_protected(Path::new(path), options, report)
+        {
+            if skip_unchanged(
+                &target_dir.join(path),
+                path,
+                &checksums.added[path],
+                options,
+                report,
+            ) {
+                continue;
+            }
+            if target_dir.join(path).exists() && !checksums.in_replace_dir(path) {
+                let message = format!("{} 已存在，将被补丁中的新文件覆盖", path);
+                warn(WarningKind::ExtraFile, message, options, report)?;
+            }
+            options
+                .events
+                .emit(PatchEvent::AddPlanned { path: path.clone() });
+            report.added.push(path.clone());
+        }
+    }
+    for path in sorted_keys(&checksums.modified) {
+        let relative_path = Path::new(path);
+        if skip_for_platform(relative_path, checksums, &platform, options, report)
+            || skip_protected(relative_path, options, report)
+        {
+            continue;
+        }
+        let target_path = target_dir.join(path);
+        if may_be_applied(&target_path, path, checksums)
+            && skip_unchanged(
+                &target_path,
+                path,
+                &checksums.modified[path].modified,
+                options,
+                report,
+            )
+        {
+            continue;
+        }
+        verify_original_checksum(&target_path, relative_path, checksums, options, report)?;
+        options
+            .events
+            .emit(PatchEvent::ModifyPlanned { path: path.clone() });
+        report.modified.push(path.clone());
+    }
+    Ok(())
+}
+
+/// 从待应用的条目中剔除损坏或缺失的条目，记录到报告中
+fn skip_corrupt_entries(patch: &mut LoadedPatch, report: &mut ApplyReport) -> Result<()> {
+    if let Some(error) = patch.extract_error() {
+        report
+            .warnings
+            .push(format!("补丁包数据损坏，部分条目可能缺失: {}", error));
+    }
+
+    let validation = verify_entries(patch)?;
+    let mut corrupt: Vec<String> = validation
+        .corrupt
         .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        if entry.file_type().is_file() {
-            let relative_path = entry.path().strip_prefix(&modified_dir)?;
-            let target_path = target_dir.join(relative_path);
+        .chain(validation.missing)
+        .collect();
+    corrupt.extend(
+        patch
+            .checksums
+            .renamed
+            .keys()
+            .filter(|to| !patch.entry_path("renamed", to).is_file())
+            .cloned(),
+    );
+    corrupt.sort();
 
-            // 验证原始文件校验和
-            verify_original_checksum(&target_path, relative_path, checksums)?;
+    let checksums = &mut patch.checksums;
+    for path in &corrupt {
+        checksums.added.remove(path);
+        checksums.modified.remove(path);
+        checksums.renamed.remove(path);
+    }
+    report.corrupt = corrupt;
+    Ok(())
+}
 
-            if let Some(parent) = target_path.parent() {
-                fs::create_dir_all(parent)?;
+fn sorted_keys<V>(map: &HashMap<String, V>) -> Vec<&String> {
+    let mut keys: Vec<_> = map.keys().collect();
+    keys.sort();
+    keys
+}
+
+/// 以 checksums.toml 为白名单检查补丁内容：
+/// 同一路径同时登记为新增与修改时无法确定应用结果 (取决于条目顺序)，
+/// 读取补丁时直接拒绝，而不是按某种遍历顺序静默择一
+pub(crate) fn check_section_conflicts(checksums: &Checksums) -> Result<()> {
+    let mut conflicts: Vec<&String> = checksums
+        .added
+        .keys()
+        .filter(|path| checksums.modified.contains_key(*path))
+        .collect();
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    conflicts.sort();
+    bail!(
+        "补丁中同一路径同时登记为新增与修改: {}",
+        conflicts
+            .iter()
+            .map(|path| path.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+/// 登记的路径必须是目标目录内的相对路径，归档中未登记的数据文件一律拒绝
+pub(crate) fn check_patch_entries(temp_dir: &Path, checksums: &Checksums) -> Result<()> {
+    check_declared_paths(checksums)?;
+
+    let sections: [(&str, HashSet<&Path>); 5] = [
+        ("added", checksums.added.keys().map(Path::new).collect()),
+        (
+            "modified",
+            checksums.modified.keys().map(Path::new).collect(),
+        ),
+        ("renamed", checksums.renamed.keys().map(Path::new).collect()),
+        (
+            BASED_DIR,
+            checksums
+                .base_refs
+                .iter()
+                .filter(|(_, base_ref)| base_ref.delta)
+                .map(|(path, _)| Path::new(path))
+                .collect(),
+        ),
+        (
+            MERGE_BASE_DIR,
+            checksums.merge_bases.iter().map(Path::new).collect(),
+        ),
+    ];
+    let mut unlisted = Vec::new();
+    for (section, allowed) in &sections {
+        let section_dir = temp_dir.join(section);
+        for entry in WalkDir::new(&section_dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let relative_path = entry.path().strip_prefix(&section_dir)?;
+            if !allowed.contains(relative_path) {
+                unlisted.push(format!("{}/{}", section, relative_path.display()));
             }
-            fs::copy(entry.path(), &target_path)?;
-            println!("  * {}", relative_path.display());
+        }
+    }
+    if !unlisted.is_empty() {
+        bail!(
+            "补丁包含未在 checksums.toml 中登记的文件: {}",
+            unlisted.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// 登记的路径必须是目标目录内的相对路径，平台标签必须可以识别
+pub(crate) fn check_declared_paths(checksums: &Checksums) -> Result<()> {
+    let declared = checksums
+        .added
+        .keys()
+        .chain(checksums.modified.keys())
+        .chain(&checksums.deleted)
+        .chain(checksums.renamed.keys())
+        .chain(checksums.renamed.values().map(|r| &r.from))
+        .chain(checksums.moved.keys())
+        .chain(checksums.moved.values().map(|m| &m.from))
+        .chain(checksums.attributes.keys())
+        .chain(&checksums.replace_dirs)
+        .chain(checksums.base_refs.values().map(|r| &r.blob));
+    for path in declared {
+        if !is_contained_path(Path::new(path)) {
+            bail!("补丁登记了目标目录之外的路径: {}", path);
+        }
+    }
+    for (path, tag) in &checksums.platforms {
+        if Platform::parse(tag).is_none() {
+            bail!("补丁登记了无法识别的平台 {}: {}", tag, path);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn is_contained_path(path: &Path) -> bool {
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// 受保护的路径 (玩家存档、个人设置等) 永不修改或删除，跳过并记录到报告
+fn skip_protected(relative_path: &Path, options: &ApplyOptions, report: &mut ApplyReport) -> bool {
+    if !options.protected.is_ignored(relative_path) {
+        return false;
+    }
+    let path = relative_path.to_string_lossy().to_string();
+    options
+        .events
+        .emit(PatchEvent::FileProtected { path: path.clone() });
+    report.protected.push(path);
+    true
+}
+
+fn skip_for_platform(
+    relative_path: &Path,
+    checksums: &Checksums,
+    platform: &Platform,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> bool {
+    let relative_str = relative_path.to_string_lossy();
+    if checksums.applies_to(&relative_str, platform) {
+        return false;
+    }
+    options.events.emit(PatchEvent::FileSkipped {
+        path: relative_str.to_string(),
+        platform: checksums.platforms[relative_str.as_ref()].to_string(),
+    });
+    report.skipped.push(relative_str.to_string());
+    true
+}
+
+/// 记录阶段耗时并通知订阅者
+fn record_stage(
+    timings: &mut Timings,
+    name: &str,
+    start: Instant,
+    bytes: u64,
+    options: &ApplyOptions,
+) {
+    timings.record(name, start, bytes);
+    if let Some(stage) = timings.stages.last() {
+        options
+            .events
+            .emit(PatchEvent::StageCompleted(stage.clone()));
+    }
+}
+
+/// 按警告策略记录一条警告并输出
+fn warn(
+    kind: WarningKind,
+    message: String,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    if options
+        .warning_policy
+        .raise(kind, message.clone(), report)?
+    {
+        status!("  ! 警告: {}", message);
+    }
+    Ok(())
+}
+
+fn warn_checksum_mismatch(
+    path: &str,
+    expected: &HashResult,
+    actual: HashResult,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
+) -> Result<()> {
+    if options.warning_policy.action(WarningKind::ChecksumMismatch) == WarningAction::Error {
+        return Err(PatchError::ChecksumMismatch {
+            path: path.to_string(),
+            expected: expected.clone(),
+            actual,
+            reason: MismatchReason::Denied,
+        }
+        .into());
+    }
+    let message = format!("{} 的校验和不匹配，可能已被修改", path);
+    if options
+        .warning_policy
+        .raise(WarningKind::ChecksumMismatch, message, report)?
+    {
+        options.events.emit(PatchEvent::ChecksumMismatch {
+            path: path.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// 严格模式下，在修改目标目录前核对所有要修改、重命名与移动的本地文件，任一与原始哈希不一致即中止
+///
+/// 已是新内容的文件 (重复应用或从检查点继续) 与会按键合并的配置文件不算不一致；
+/// 不存在的文件由 `missing-target` 警告处理。
+fn check_originals(target_dir: &Path, checksums: &Checksums, options: &ApplyOptions) -> Result<()> {
+    let platform = Platform::current();
+    let skipped = |path: &str, from: &str| {
+        !checksums.applies_to(path, &platform)
+            || options.protected.is_ignored(Path::new(path))
+            || options.protected.is_ignored(Path::new(from))
+    };
+    let mut mismatches = Vec::new();
+    for (path, checksum) in &checksums.modified {
+        let target_path = target_dir.join(path);
+        // 缺少原文件的增量条目由 check_delta_bases 拒绝
+        if skipped(path, path) || !target_path.is_file() {
+            continue;
+        }
+        let actual = compute_file_hash(&target_path)?;
+        if checksum.matches_original(&actual)
+            || actual == checksum.modified
+            || mergeable_config(&target_path, path, checksums, options)?.is_some()
+        {
+            continue;
+        }
+        mismatches.push((path.clone(), checksum.original.clone(), actual));
+    }
+    let sources = checksums
+        .renamed
+        .iter()
+        .map(|(to, renamed)| (to, &renamed.from, &renamed.original))
+        .chain(
+            checksums
+                .moved
+                .iter()
+                .map(|(to, moved)| (to, &moved.from, &moved.hash)),
+        );
+    for (to, from, original) in sources {
+        let from_path = target_dir.join(from);
+        if skipped(to, from) || !from_path.is_file() {
+            continue;
+        }
+        let actual = compute_file_hash(&from_path)?;
+        if actual != *original {
+            mismatches.push((from.clone(), original.clone(), actual));
+        }
+    }
+
+    mismatches.sort_by(|a, b| a.0.cmp(&b.0));
+    for (path, _, _) in &mismatches {
+        status!("  ! {} 的校验和不匹配，未做任何修改", path);
+    }
+    match mismatches.into_iter().next() {
+        Some((path, expected, actual)) => Err(PatchError::ChecksumMismatch {
+            path,
+            expected,
+            actual,
+            reason: MismatchReason::Strict,
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// 以增量存放的修改文件只能由补丁的起始版本还原，应用前确认每个增量的原文件都在且未被改动，
+/// 避免删除、新增等阶段已执行后才在还原时失败 (已是新内容的视为应用过)
+fn check_delta_bases(
+    target_dir: &Path,
+    checksums: &Checksums,
+    options: &ApplyOptions,
+) -> Result<()> {
+    let platform = Platform::current();
+    for path in &checksums.delta_modified {
+        if !checksums.applies_to(path, &platform) || options.protected.is_ignored(Path::new(path)) {
+            continue;
+        }
+        let checksum = &checksums.modified[path];
+        let target_path = target_dir.join(path);
+        if !target_path.is_file() {
+            status!("  ! {} 缺少作为增量基准的原文件，未做任何修改", path);
+            bail!("以增量存放的修改文件缺少原文件: {}", path);
+        }
+        let actual = compute_file_hash(&target_path)?;
+        // 增量基于主基线计算，其他基线无法还原
+        if actual != checksum.original
+            && actual != checksum.modified
+            && mergeable_config(&target_path, path, checksums, options)?.is_none()
+        {
+            status!("  ! {} 不是增量的起始版本，未做任何修改", path);
+            bail!(
+                "以增量存放的修改文件只能由补丁的起始版本还原，本地文件已被修改: {}",
+                path
+            );
         }
     }
     Ok(())
@@ -126,18 +2157,25 @@ fn verify_original_checksum(
     target_path: &Path,
     relative_path: &Path,
     checksums: &Checksums,
+    options: &ApplyOptions,
+    report: &mut ApplyReport,
 ) -> Result<()> {
     let relative_str = relative_path.to_string_lossy().to_string();
-    if let Some(checksum) = checksums.modified.get(&relative_str)
-        && target_path.exists()
-    {
-        let current_hash = compute_file_hash(target_path)?;
-        if current_hash != checksum.original {
-            println!(
-                "  ! 警告: {} 的校验和不匹配，可能已被修改",
-                relative_path.display()
-            );
+    let Some(checksum) = checksums.modified.get(&relative_str) else {
+        return Ok(());
+    };
+    if !target_path.exists() {
+        if checksums.delta_modified.contains(&relative_str) {
+            bail!("以增量存放的修改文件缺少原文件: {}", relative_str);
         }
+        let message = format!("要修改的文件不存在，将直接写入: {}", relative_str);
+        return warn(WarningKind::MissingTarget, message, options, report);
+    }
+    let actual = compute_file_hash(target_path)?;
+    if !checksum.matches_original(&actual) {
+        warn_checksum_mismatch(&relative_str, &checksum.original, actual, options, report)?;
+    } else if !checksum.alternatives.is_empty() {
+        report.baselines.insert(relative_str, actual);
     }
     Ok(())
 }
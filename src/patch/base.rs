@@ -0,0 +1,169 @@
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::delta::{Delta, DeltaPolicy, apply_encoded};
+use super::loaded::LoadedPatch;
+use super::metadata::Checksums;
+use crate::utils::{HashResult, compute_file_hash};
+
+/// 补丁内存放相对基础补丁条目的增量的目录
+pub(crate) const BASED_DIR: &str = "based";
+
+/// 已解包的基础补丁 (通常是上一个发布的补丁)，按内容哈希与路径索引其中的文件
+pub(crate) struct BasePatch {
+    patch: LoadedPatch,
+    hash: HashResult,
+    by_hash: HashMap<HashResult, String>,
+    by_path: HashMap<String, String>,
+}
+
+impl BasePatch {
+    pub fn open(patch_path: &Path, dir: PathBuf) -> Result<Self> {
+        let hash = compute_file_hash(patch_path)?;
+        let patch = LoadedPatch::load(patch_path, dir)
+            .with_context(|| format!("无法解包基础补丁: {:?}", patch_path))?;
+        let checksums = &patch.checksums;
+
+        // 只索引完整存放在基础补丁中的文件，基础补丁自身的引用与增量不再向前追溯
+        let stored = checksums
+            .added
+            .iter()
+            .map(|(path, hash)| ("added", path, hash))
+            .chain(
+                checksums
+                    .modified
+                    .iter()
+                    .map(|(path, checksum)| ("modified", path, &checksum.modified)),
+            )
+            .filter(|(_, path, _)| {
+                !checksums.base_refs.contains_key(*path) && !checksums.delta_modified.contains(path)
+            });
+
+        let mut by_hash = HashMap::new();
+        let mut by_path = HashMap::new();
+        for (section, path, file_hash) in stored {
+            let blob = format!("{}/{}", section, path);
+            if !patch.workspace().join(&blob).is_file() {
+                continue;
+            }
+            by_hash.insert(file_hash.clone(), blob.clone());
+            by_path.insert(path.clone(), blob);
+        }
+
+        Ok(Self {
+            patch,
+            hash,
+            by_hash,
+            by_path,
+        })
+    }
+
+    pub fn hash(&self) -> &HashResult {
+        &self.hash
+    }
+
+    /// 内容完全相同的条目
+    pub fn find_identical(&self, hash: &HashResult) -> Option<&str> {
+        self.by_hash.get(hash).map(String::as_str)
+    }
+
+    /// 基础补丁中是否完整存放了同路径的条目
+    pub fn contains_path(&self, path: &str) -> bool {
+        self.by_path.contains_key(path)
+    }
+
+    /// 计算 `target` 文件相对基础补丁中同路径 (`path`) 条目的增量，
+    /// 文件大小或增量大小不满足 `policy` 时返回 `None`
+    pub fn encode_delta(
+        &self,
+        path: &str,
+        target: &Path,
+        policy: &DeltaPolicy,
+    ) -> Result<Option<(String, Delta)>> {
+        let Some(blob) = self.by_path.get(path) else {
+            return Ok(None);
+        };
+        let base_path = self.patch.workspace().join(blob);
+        if !policy.accepts_size(fs::metadata(&base_path)?.len())
+            || !policy.accepts_size(fs::metadata(target)?.len())
+        {
+            return Ok(None);
+        }
+        let base = fs::read(base_path)?;
+        let target = fs::read(target)?;
+        let delta = Delta::encode(&base, &target);
+        if !policy.accepts(&delta) {
+            return Ok(None);
+        }
+        Ok(Some((blob.clone(), delta)))
+    }
+
+    fn blob_path(&self, blob: &str) -> Result<PathBuf> {
+        let path = self.patch.workspace().join(blob);
+        if !path.is_file() {
+            bail!("基础补丁中不存在条目: {}", blob);
+        }
+        Ok(path)
+    }
+}
+
+/// 从基础补丁还原解包目录中引用基础补丁的条目，并清除对应的登记
+pub(crate) fn resolve_base_refs(
+    temp_dir: &Path,
+    checksums: &mut Checksums,
+    base_patch: Option<&Path>,
+) -> Result<()> {
+    if checksums.base_refs.is_empty() {
+        return Ok(());
+    }
+    let expected = checksums.base_patch.clone();
+    let Some(base_patch) = base_patch else {
+        bail!(
+            "补丁引用了基础补丁中的内容，请指定基础补丁 (SHA256: {})",
+            expected.map(|h| h.to_hex()).unwrap_or_default()
+        );
+    };
+
+    let base = BasePatch::open(base_patch, temp_dir.with_extension("base"))?;
+    if let Some(expected) = &expected
+        && base.hash() != expected
+    {
+        bail!("基础补丁不匹配: 需要 {}，实际为 {}", expected, base.hash());
+    }
+
+    for (path, base_ref) in std::mem::take(&mut checksums.base_refs) {
+        let (section, expected_hash) = match checksums.added.get(&path) {
+            Some(hash) => ("added", hash),
+            None => match checksums.modified.get(&path) {
+                Some(checksum) => ("modified", &checksum.modified),
+                None => bail!("基础补丁引用了未登记的文件: {}", path),
+            },
+        };
+        let blob = base.blob_path(&base_ref.blob)?;
+        let dest = temp_dir.join(section).join(&path);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if base_ref.delta {
+            let delta_path = temp_dir.join(BASED_DIR).join(&path);
+            let mut input = BufReader::new(
+                File::open(&delta_path).with_context(|| format!("补丁中缺少增量数据: {}", path))?,
+            );
+            let mut out = BufWriter::new(File::create(&dest)?);
+            apply_encoded(&mut input, &mut File::open(&blob)?, &mut out)?;
+            out.flush()?;
+        } else {
+            fs::copy(&blob, &dest)?;
+        }
+
+        if compute_file_hash(&dest)? != *expected_hash {
+            bail!("从基础补丁还原的文件校验失败: {}", path);
+        }
+    }
+    checksums.base_patch = None;
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::apply::{ApplyOptions, apply_patch_with_options};
+use super::error::PatchResult;
+use super::metadata::Metadata;
+use super::reader::Patch;
+use super::report::{BatchEntry, BatchReport, BatchStatus};
+use crate::utils::{parse_rfc3339, status};
+
+/// 批量应用时某个补丁失败后的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BatchPolicy {
+    /// 停止，后续补丁不再应用
+    #[default]
+    StopOnError,
+    /// 记录失败并继续应用后续补丁
+    SkipFailed,
+}
+
+/// 批量应用补丁包的选项
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// 应用每个补丁时使用的选项
+    pub apply: ApplyOptions,
+    pub policy: BatchPolicy,
+}
+
+impl BatchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_apply_options(mut self, apply: ApplyOptions) -> Self {
+        self.apply = apply;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: BatchPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+/// 批量应用时识别为补丁包的文件扩展名
+const PATCH_EXTENSIONS: &[&str] = &[".tgz", ".tar.gz", ".tar.zst", ".tar.xz"];
+
+/// 把 `patches_dir` 下的所有补丁包 (`.tgz`/`.tar.gz`/`.tar.zst`/`.tar.xz`) 按版本链顺序依次应用到 `target_dir`
+pub fn apply_batch(
+    target_dir: &Path,
+    patches_dir: &Path,
+    options: &BatchOptions,
+) -> PatchResult<BatchReport> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(patches_dir)
+        .with_context(|| format!("无法读取补丁目录: {}", patches_dir.display()))?
+    {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_file() && PATCH_EXTENSIONS.iter().any(|ext| name.ends_with(ext)) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut report = BatchReport::default();
+    let mut stopped = false;
+    for (path, opened) in order_patch_chain(paths) {
+        if stopped {
+            report
+                .entries
+                .push(BatchEntry::new(path, BatchStatus::NotRun));
+            continue;
+        }
+
+        status!("=== {} ===", path.display());
+        let result = opened.and_then(|_| {
+            apply_patch_with_options(target_dir, &path, &options.apply).map_err(Into::into)
+        });
+        match result {
+            Ok(apply_report) => {
+                let mut entry = BatchEntry::new(path, BatchStatus::Applied);
+                entry.report = Some(apply_report);
+                report.entries.push(entry);
+            }
+            Err(e) => {
+                status!("  ! 应用失败: {:#}", e);
+                let mut entry = BatchEntry::new(path, BatchStatus::Failed);
+                entry.error = Some(format!("{:#}", e));
+                report.entries.push(entry);
+                stopped = options.policy == BatchPolicy::StopOnError;
+            }
+        }
+    }
+
+    status!("{}", report.table());
+    Ok(report)
+}
+
+/// 按版本链排序：某补丁的 `source_version` 是另一补丁的 `target_version` 时排在其后，
+/// 其余按创建时间、文件名排序。无法读取元数据的补丁排在最后，应用时报告其错误。
+pub(crate) fn order_patch_chain(paths: Vec<PathBuf>) -> Vec<(PathBuf, Result<Option<Metadata>>)> {
+    let mut pending: Vec<(PathBuf, Option<Metadata>)> = Vec::new();
+    let mut unreadable = Vec::new();
+    for path in paths {
+        match Patch::open(&path) {
+            Ok(patch) => {
+                let metadata = patch.metadata().cloned();
+                pending.push((path, metadata));
+            }
+            Err(e) => unreadable.push((path, Err(e.into()))),
+        }
+    }
+
+    let created_at = |metadata: &Option<Metadata>| parse_rfc3339(&metadata.as_ref()?.created_at);
+    pending.sort_by(|(a_path, a), (b_path, b)| {
+        created_at(a)
+            .cmp(&created_at(b))
+            .then_with(|| a_path.cmp(b_path))
+    });
+
+    let mut ordered = Vec::new();
+    while !pending.is_empty() {
+        // 选出第一个不依赖于其他待应用补丁的补丁 (版本链成环时退回按时间顺序)
+        let ready = (0..pending.len())
+            .find(|&i| {
+                let Some(source) = pending[i]
+                    .1
+                    .as_ref()
+                    .and_then(|m| m.source_version.as_ref())
+                else {
+                    return true;
+                };
+                !pending.iter().enumerate().any(|(j, (_, other))| {
+                    j != i && other.as_ref().and_then(|m| m.target_version.as_ref()) == Some(source)
+                })
+            })
+            .unwrap_or(0);
+        let (path, metadata) = pending.remove(ready);
+        ordered.push((path, Ok(metadata)));
+    }
+    ordered.extend(unreadable);
+    ordered
+}
@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use super::policy::{WarningKind, WarningPolicy};
+use super::report::ApplyReport;
+use crate::utils::status;
+
+/// 大小写不敏感的文件系统 (Windows、macOS 默认) 上按目录枚举做精确的大小写匹配
+///
+/// 这类文件系统上补丁中的 `foo.jar` 与磁盘上的 `Foo.jar` 是同一个文件，`exists()` 为真，
+/// 但报告与后续操作中的名字却对不上。应用前先把这样的文件改名为补丁中的大小写，
+/// 之后的校验、删除与写入都针对名字一致的文件。大小写敏感的文件系统上不做任何处理。
+pub(crate) struct CaseIndex {
+    enabled: bool,
+    /// 目录 -> 目录下的文件名
+    listings: HashMap<PathBuf, Vec<OsString>>,
+}
+
+impl CaseIndex {
+    pub fn new(target_dir: &Path) -> Self {
+        Self {
+            enabled: is_case_insensitive(target_dir),
+            listings: HashMap::new(),
+        }
+    }
+
+    /// 磁盘上与 `path` 只有文件名大小写不同的文件
+    fn variant(&mut self, path: &Path) -> Result<Option<PathBuf>> {
+        if !self.enabled || fs::symlink_metadata(path).is_err() {
+            return Ok(None);
+        }
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Ok(None);
+        };
+        let listing = self.listing(parent)?;
+        if listing.iter().any(|entry| entry == name) {
+            return Ok(None);
+        }
+        let lower = name.to_string_lossy().to_lowercase();
+        Ok(listing
+            .iter()
+            .find(|entry| entry.to_string_lossy().to_lowercase() == lower)
+            .map(|entry| parent.join(entry)))
+    }
+
+    /// 把只有大小写不同的已有文件改名为 `path` 的大小写，并记录警告
+    pub fn correct(
+        &mut self,
+        path: &Path,
+        display: &str,
+        policy: &WarningPolicy,
+        report: &mut ApplyReport,
+    ) -> Result<()> {
+        let Some(actual) = self.variant(path)? else {
+            return Ok(());
+        };
+        let warning = format!(
+            "{} 与补丁中的 {} 只有大小写不同，已改名为补丁中的大小写",
+            actual.display(),
+            display
+        );
+        let recorded = policy.raise(WarningKind::CaseMismatch, warning.clone(), report)?;
+
+        // 大小写不敏感的文件系统上直接改名可能不生效，经由临时名字中转
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp = path.with_file_name(format!(".{}.dft_case", name));
+        fs::rename(&actual, &temp)
+            .and_then(|_| fs::rename(&temp, path))
+            .with_context(|| format!("无法校正文件名大小写: {:?} -> {:?}", actual, path))?;
+
+        let actual_name = actual.file_name().unwrap_or_default().to_os_string();
+        if let Some(listing) = path.parent().and_then(|p| self.listings.get_mut(p)) {
+            listing.retain(|entry| *entry != actual_name);
+            listing.push(path.file_name().unwrap_or_default().to_os_string());
+        }
+        if recorded {
+            status!("  ! {}", warning);
+        }
+        Ok(())
+    }
+
+    /// 新写入文件后记录其名字，同一目录不必重新枚举
+    pub fn record(&mut self, path: &Path) {
+        if let (Some(parent), Some(name)) = (path.parent(), path.file_name())
+            && let Some(listing) = self.listings.get_mut(parent)
+            && !listing.iter().any(|entry| entry == name)
+        {
+            listing.push(name.to_os_string());
+        }
+    }
+
+    fn listing(&mut self, dir: &Path) -> Result<&Vec<OsString>> {
+        if !self.listings.contains_key(dir) {
+            let mut names = Vec::new();
+            for entry in fs::read_dir(dir).with_context(|| format!("无法读取目录: {:?}", dir))?
+            {
+                names.push(entry?.file_name());
+            }
+            self.listings.insert(dir.to_path_buf(), names);
+        }
+        Ok(&self.listings[dir])
+    }
+}
+
+/// 在目录中创建探测文件，检查换成大写的名字是否指向同一个文件
+fn is_case_insensitive(dir: &Path) -> bool {
+    let probe = dir.join(format!(".dft_case_probe_{}", std::process::id()));
+    if File::create(&probe).is_err() {
+        return false;
+    }
+    let upper = dir.join(format!(".DFT_CASE_PROBE_{}", std::process::id()));
+    let insensitive = upper.exists();
+    let _ = fs::remove_file(&probe);
+    insensitive
+}
@@ -0,0 +1,142 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+
+use super::error::PatchResult;
+use super::report::PatchInfo;
+use super::show::inspect_patch;
+use crate::utils::modname::{ModName, find_upgrades};
+
+/// 位于补丁根目录的文件在更新日志中的分组名
+const ROOT_GROUP: &str = "根目录";
+
+/// 更新日志的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangelogFormat {
+    Markdown,
+}
+
+/// 读取补丁包并生成更新日志
+pub fn patch_changelog(patch_path: &Path, format: ChangelogFormat) -> PatchResult<String> {
+    Ok(render_changelog(&inspect_patch(patch_path)?, format))
+}
+
+/// 按顶层目录分组，把补丁内容整理为可直接发布的更新日志
+pub fn render_changelog(info: &PatchInfo, format: ChangelogFormat) -> String {
+    match format {
+        ChangelogFormat::Markdown => render_markdown(info),
+    }
+}
+
+#[derive(Default)]
+struct Group {
+    added: Vec<String>,
+    updated: Vec<String>,
+    deleted: Vec<String>,
+    renamed: Vec<String>,
+}
+
+fn render_markdown(info: &PatchInfo) -> String {
+    let checksums = &info.checksums;
+    let upgrades = find_upgrades(
+        checksums.deleted.iter().map(String::as_str),
+        checksums.added.keys().map(String::as_str),
+    );
+    let folded: HashSet<&str> = upgrades
+        .iter()
+        .flat_map(|u| [u.from.as_str(), u.to.as_str()])
+        .collect();
+
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+    let mut push = |path: &str, item: String, pick: fn(&mut Group) -> &mut Vec<String>| {
+        pick(groups.entry(group_of(path).to_string()).or_default()).push(item);
+    };
+    for path in checksums
+        .added
+        .keys()
+        .filter(|p| !folded.contains(p.as_str()))
+    {
+        push(path, describe(path), |g| &mut g.added);
+    }
+    for upgrade in &upgrades {
+        let item = format!(
+            "{} {} → {}",
+            upgrade.name, upgrade.from_version, upgrade.to_version
+        );
+        push(&upgrade.to, item, |g| &mut g.updated);
+    }
+    for path in checksums.modified.keys() {
+        push(path, describe(path), |g| &mut g.updated);
+    }
+    for path in checksums
+        .deleted
+        .iter()
+        .filter(|p| !folded.contains(p.as_str()))
+    {
+        push(path, describe(path), |g| &mut g.deleted);
+    }
+    for (to, renamed) in &checksums.renamed {
+        let item = format!("{} → {}", describe(&renamed.from), describe(to));
+        push(to, item, |g| &mut g.renamed);
+    }
+    for (to, moved) in &checksums.moved {
+        let item = format!("{} → {}", describe(&moved.from), describe(to));
+        push(to, item, |g| &mut g.renamed);
+    }
+
+    let mut out = String::new();
+    let title = info
+        .metadata
+        .as_ref()
+        .and_then(|m| m.target_version.as_deref())
+        .map(|v| format!("# 更新说明 ({})", v))
+        .unwrap_or_else(|| "# 更新说明".to_string());
+    let _ = writeln!(out, "{}\n", title);
+    if let Some(metadata) = &info.metadata {
+        if let Some(desc) = &metadata.description {
+            let _ = writeln!(out, "{}\n", desc);
+        }
+        if let Some(author) = &metadata.author {
+            let _ = writeln!(out, "作者: {}\n", author);
+        }
+        if let Some(notes) = &metadata.notes {
+            let _ = writeln!(out, "{}\n", notes.trim_end());
+        }
+    }
+    let _ = writeln!(out, "> {}", checksums.summary());
+
+    for (name, group) in &mut groups {
+        let _ = writeln!(out, "\n## {}", name);
+        for (title, items) in [
+            ("新增", &mut group.added),
+            ("更新", &mut group.updated),
+            ("删除", &mut group.deleted),
+            ("重命名", &mut group.renamed),
+        ] {
+            if items.is_empty() {
+                continue;
+            }
+            items.sort();
+            let _ = writeln!(out, "\n### {}\n", title);
+            for item in items.iter() {
+                let _ = writeln!(out, "- {}", item);
+            }
+        }
+    }
+    out
+}
+
+fn group_of(path: &str) -> &str {
+    match path.split_once('/') {
+        Some((first, _)) => first,
+        None => ROOT_GROUP,
+    }
+}
+
+/// 文件的显示名称，能从文件名猜出 mod 名与版本时显示为 `jei (15.2.0.27)`
+fn describe(path: &str) -> String {
+    match ModName::parse(path) {
+        Some(parsed) => format!("{} ({})", parsed.name, parsed.version),
+        None => format!("`{}`", path.rsplit('/').next().unwrap_or(path)),
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::{Context, Result, bail};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::fault;
+use crate::utils::{HashResult, STATE_DIR, status};
+
+const CHECKPOINT_FILE: &str = "checkpoint";
+
+/// 应用补丁的检查点，保存在 `.dft/checkpoint`
+///
+/// 第一行为补丁包的哈希，其后每行一个已完成的条目 (`阶段\t路径`)。
+/// 每完成 `interval` 个条目追加写入并落盘一次；应用成功后删除。
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    file: Option<File>,
+    interval: usize,
+    /// 上次中断前完成的条目 (`阶段\t路径`)
+    completed: HashSet<String>,
+    pending: Vec<(&'static str, String)>,
+}
+
+impl Checkpoint {
+    /// 打开目标目录的检查点；`resume` 时读取上次中断前完成的条目，否则从头开始
+    ///
+    /// `interval` 为 0 时不写检查点。
+    pub fn open(
+        target_dir: &Path,
+        patch_hash: &HashResult,
+        interval: usize,
+        resume: bool,
+    ) -> Result<Self> {
+        let path = target_dir.join(STATE_DIR).join(CHECKPOINT_FILE);
+        let mut completed = HashSet::new();
+        if resume {
+            match fs::read_to_string(&path) {
+                Ok(content) => {
+                    let mut lines = content.lines();
+                    let header = lines.next().unwrap_or_default();
+                    if header.strip_prefix("patch ") != Some(patch_hash.to_string().as_str()) {
+                        bail!("检查点属于另一个补丁包，无法继续: {}", path.display());
+                    }
+                    completed.extend(lines.map(str::to_string));
+                    status!("从检查点继续，跳过 {} 个已完成的条目", completed.len());
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    status!("未找到检查点，从头开始应用");
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("无法读取检查点: {}", path.display()));
+                }
+            }
+        }
+
+        let file = if interval == 0 {
+            None
+        } else {
+            fs::create_dir_all(target_dir.join(STATE_DIR))?;
+            let mut file = if completed.is_empty() {
+                let mut file = File::create(&path)?;
+                writeln!(file, "patch {}", patch_hash)?;
+                file
+            } else {
+                OpenOptions::new().append(true).open(&path)?
+            };
+            file.flush()?;
+            Some(file)
+        };
+        Ok(Self {
+            path,
+            file,
+            interval,
+            completed,
+            pending: Vec::new(),
+        })
+    }
+
+    /// 上次中断前是否已完成该条目
+    pub fn is_done(&self, kind: &str, path: &str) -> bool {
+        !self.completed.is_empty() && self.completed.contains(&format!("{}\t{}", kind, path))
+    }
+
+    /// 并行放置文件时每批的数量，完成一批写一次检查点
+    pub fn batch_size(&self) -> usize {
+        if self.file.is_some() {
+            self.interval
+        } else {
+            usize::MAX
+        }
+    }
+
+    /// 记录一个已完成的条目，累计满 `interval` 个时写入
+    pub fn record(&mut self, kind: &'static str, path: &str) -> Result<()> {
+        if self.file.is_some() {
+            self.pending.push((kind, path.to_string()));
+            if self.pending.len() >= self.interval {
+                self.flush()?;
+            }
+        }
+        fault::entry_done(kind, path)
+    }
+
+    /// 把尚未写入的条目追加到检查点文件并落盘
+    pub fn flush(&mut self) -> Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut content = String::new();
+        for (kind, path) in self.pending.drain(..) {
+            content.push_str(&format!("{}\t{}\n", kind, path));
+        }
+        file.write_all(content.as_bytes())
+            .and_then(|_| file.sync_data())
+            .with_context(|| format!("无法写入检查点: {}", self.path.display()))
+    }
+
+    /// 应用完成，删除检查点
+    pub fn finish(mut self) -> Result<()> {
+        self.pending.clear();
+        if self.file.take().is_some() && self.path.exists() {
+            fs::remove_file(&self.path)
+                .with_context(|| format!("无法删除检查点: {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// 目标目录中是否留有检查点 (应用中断后可用 `--resume` 继续)
+    pub fn exists(target_dir: &Path) -> bool {
+        target_dir.join(STATE_DIR).join(CHECKPOINT_FILE).is_file()
+    }
+}
+
+impl Drop for Checkpoint {
+    /// 中途出错时尽量保留已完成的条目
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
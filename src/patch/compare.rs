@@ -0,0 +1,314 @@
+use anyhow::{Context, Result, bail};
+use similar::{ChangeTag, TextDiff};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use super::create::CreateOptions;
+use super::diff::{FileDiff, compare_directories_with_options};
+use super::error::PatchResult;
+use super::report::{ComparedFile, DirectoryComparison, LineChanges};
+use crate::utils::{format_size, format_size_delta, is_text_file, scan_file_stats, status};
+
+/// 超过该大小的文本文件不生成 diff 片段
+const TEXT_DIFF_MAX_BYTES: u64 = 256 * 1024;
+
+/// 每个 diff 片段最多保留的行数
+const TEXT_DIFF_MAX_LINES: usize = 200;
+
+/// 大小变化图表中列出的文件数
+const SIZE_CHART_FILES: usize = 20;
+
+/// 比较任意两个目录，收集差异文件的大小变化与文本 diff 片段
+///
+/// `options` 中的忽略规则、快速模式等与生成补丁时的含义相同。
+pub fn compare_directories_detailed(
+    source_dir: &Path,
+    target_dir: &Path,
+    options: &CreateOptions,
+) -> PatchResult<DirectoryComparison> {
+    Ok(compare_detailed(source_dir, target_dir, options)?)
+}
+
+fn compare_detailed(
+    source_dir: &Path,
+    target_dir: &Path,
+    options: &CreateOptions,
+) -> Result<DirectoryComparison> {
+    for dir in [source_dir, target_dir] {
+        if !dir.is_dir() {
+            bail!("目录不存在: {:?}", dir);
+        }
+    }
+    let options = &*options.resolve_gitignore(source_dir, target_dir)?;
+    let mut source_stats = scan_file_stats(source_dir)?;
+    let mut target_stats = scan_file_stats(target_dir)?;
+    source_stats.retain(|path, _| !options.source_ignore.is_ignored(path));
+    target_stats.retain(|path, _| !options.target_ignore.is_ignored(path));
+
+    let mut files = Vec::new();
+    for diff in compare_directories_with_options(source_dir, target_dir, options)? {
+        let path = diff.path();
+        let (change, from) = match &diff {
+            FileDiff::Added(_) => ("added", None),
+            FileDiff::Deleted(_) => ("deleted", None),
+            FileDiff::Modified(_) | FileDiff::MetadataOnly(_) => ("modified", None),
+            FileDiff::Renamed { from, .. } => ("renamed", Some(from)),
+        };
+        let text_diff = if change == "modified" {
+            text_diff(&source_dir.join(path), &target_dir.join(path))?
+        } else {
+            None
+        };
+        files.push(ComparedFile {
+            path: path.to_string_lossy().replace('\\', "/"),
+            change: change.to_string(),
+            from: from.map(|from| from.to_string_lossy().replace('\\', "/")),
+            old_size: source_stats.get(from.unwrap_or(path)).map(|stat| stat.size),
+            new_size: target_stats.get(path).map(|stat| stat.size),
+            text_diff,
+        });
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(DirectoryComparison {
+        source: source_dir.to_path_buf(),
+        target: target_dir.to_path_buf(),
+        source_size: source_stats.values().map(|stat| stat.size).sum(),
+        target_size: target_stats.values().map(|stat| stat.size).sum(),
+        files,
+    })
+}
+
+/// 比较两个目录并把结果写成静态 HTML 报告
+pub fn write_html_report(
+    source_dir: &Path,
+    target_dir: &Path,
+    output: &Path,
+    options: &CreateOptions,
+) -> PatchResult<DirectoryComparison> {
+    status!("正在比较目录...");
+    let comparison = compare_directories_detailed(source_dir, target_dir, options)?;
+    fs::write(output, render_html_report(&comparison))
+        .with_context(|| format!("无法写入 HTML 报告: {}", output.display()))?;
+    status!("HTML 报告已写入: {}", output.display());
+    Ok(comparison)
+}
+
+/// 两个文件都是不太大的文本文件时，生成带 3 行上下文的统一格式 diff
+fn text_diff(old: &Path, new: &Path) -> Result<Option<String>> {
+    let Some((old_text, new_text)) = read_text_pair(old, new)? else {
+        return Ok(None);
+    };
+    let diff = TextDiff::from_lines(&old_text, &new_text)
+        .unified_diff()
+        .context_radius(3)
+        .to_string();
+    let mut lines: Vec<&str> = diff.lines().take(TEXT_DIFF_MAX_LINES + 1).collect();
+    let mut snippet = String::new();
+    if lines.len() > TEXT_DIFF_MAX_LINES {
+        lines.truncate(TEXT_DIFF_MAX_LINES);
+        lines.push("... (更多差异省略)");
+    }
+    for line in lines {
+        snippet.push_str(line);
+        snippet.push('\n');
+    }
+    Ok(Some(snippet))
+}
+
+/// 两个文件都是不太大的文本文件时，统计新增与删除的行数；无法读取时同样返回 `None`
+pub(crate) fn line_changes(old: &Path, new: &Path) -> Option<LineChanges> {
+    let (old_text, new_text) = read_text_pair(old, new).ok()??;
+    Some(count_line_changes(&old_text, &new_text))
+}
+
+/// 统计两段文本之间新增与删除的行数
+pub(crate) fn count_line_changes(old: &str, new: &str) -> LineChanges {
+    let mut changes = LineChanges::default();
+    for change in TextDiff::from_lines(old, new).iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => changes.added += 1,
+            ChangeTag::Delete => changes.removed += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    changes
+}
+
+/// 两个文件都不超过 [`TEXT_DIFF_MAX_BYTES`] 且都是 UTF-8 文本时读出二者的内容
+fn read_text_pair(old: &Path, new: &Path) -> Result<Option<(String, String)>> {
+    for path in [old, new] {
+        if fs::metadata(path)?.len() > TEXT_DIFF_MAX_BYTES || !is_text_file(path) {
+            return Ok(None);
+        }
+    }
+    match (
+        String::from_utf8(fs::read(old)?),
+        String::from_utf8(fs::read(new)?),
+    ) {
+        (Ok(old_text), Ok(new_text)) => Ok(Some((old_text, new_text))),
+        _ => Ok(None),
+    }
+}
+
+/// 目录树中的一个节点 (目录名 -> 子节点，叶子为差异文件)
+#[derive(Default)]
+struct TreeNode<'a> {
+    dirs: BTreeMap<&'a str, TreeNode<'a>>,
+    files: Vec<&'a ComparedFile>,
+}
+
+/// 生成面向非技术读者的静态 HTML 报告：差异树、大小变化图表与文本 diff 片段
+pub fn render_html_report(comparison: &DirectoryComparison) -> String {
+    let count = |change: &str| {
+        comparison
+            .files
+            .iter()
+            .filter(|f| f.change == change)
+            .count()
+    };
+    let mut html = String::new();
+    html.push_str(concat!(
+        "<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>目录差异报告</title>\n<style>\n",
+        "body{font-family:sans-serif;margin:2em;color:#222}\n",
+        "ul.tree{list-style:none;padding-left:1.2em}\n",
+        ".added{color:#1a7f37}.deleted{color:#cf222e}.modified{color:#9a6700}.renamed{color:#0969da}\n",
+        "table{border-collapse:collapse}td{padding:2px 8px;white-space:nowrap}\n",
+        ".bar{height:12px}.grow{background:#1a7f37}.shrink{background:#cf222e}\n",
+        "pre{background:#f6f8fa;padding:8px;overflow-x:auto}\n",
+        "pre .add{color:#1a7f37}pre .del{color:#cf222e}pre .hunk{color:#8250df}\n",
+        "</style>\n</head>\n<body>\n<h1>目录差异报告</h1>\n",
+    ));
+    let _ = writeln!(
+        html,
+        "<p>源目录: <code>{}</code><br>目标目录: <code>{}</code></p>",
+        escape(&comparison.source.display().to_string()),
+        escape(&comparison.target.display().to_string())
+    );
+    let _ = writeln!(
+        html,
+        "<p>新增 {} 个文件，删除 {} 个文件，修改 {} 个文件，移动 {} 个文件；总大小 {} → {} ({})</p>",
+        count("added"),
+        count("deleted"),
+        count("modified"),
+        count("renamed"),
+        format_size(comparison.source_size),
+        format_size(comparison.target_size),
+        format_size_delta(comparison.target_size as i64 - comparison.source_size as i64)
+    );
+    if comparison.files.is_empty() {
+        html.push_str("<p>两个目录内容相同。</p>\n</body>\n</html>\n");
+        return html;
+    }
+
+    // 差异树
+    let mut root = TreeNode::default();
+    for file in &comparison.files {
+        let mut node = &mut root;
+        let mut parts: Vec<&str> = file.path.split('/').collect();
+        parts.pop();
+        for part in parts {
+            node = node.dirs.entry(part).or_default();
+        }
+        node.files.push(file);
+    }
+    html.push_str("<h2>差异树</h2>\n");
+    render_tree(&root, &mut html);
+
+    // 大小变化图表 (按变化量从大到小)
+    let mut by_delta: Vec<&ComparedFile> = comparison.files.iter().collect();
+    by_delta.sort_by_key(|f| std::cmp::Reverse(f.size_delta().unsigned_abs()));
+    by_delta.truncate(SIZE_CHART_FILES);
+    let max = by_delta
+        .first()
+        .map_or(1, |f| f.size_delta().unsigned_abs().max(1));
+    html.push_str("<h2>大小变化</h2>\n<table>\n");
+    for file in by_delta {
+        let delta = file.size_delta();
+        let width = (delta.unsigned_abs() * 300 / max).max(1);
+        let _ = writeln!(
+            html,
+            "<tr><td class=\"{}\">{}</td><td><div class=\"bar {}\" style=\"width:{}px\"></div></td><td>{}</td></tr>",
+            file.change,
+            escape(&file.path),
+            if delta < 0 { "shrink" } else { "grow" },
+            width,
+            format_size_delta(delta)
+        );
+    }
+    html.push_str("</table>\n");
+
+    // 文本 diff 片段
+    let diffs: Vec<&ComparedFile> = comparison
+        .files
+        .iter()
+        .filter(|f| f.text_diff.is_some())
+        .collect();
+    if !diffs.is_empty() {
+        html.push_str("<h2>文本差异</h2>\n");
+        for file in diffs {
+            let _ = write!(
+                html,
+                "<details><summary>{}</summary>\n<pre>",
+                escape(&file.path)
+            );
+            for line in file.text_diff.iter().flat_map(|diff| diff.lines()) {
+                let class = if line.starts_with("@@") {
+                    "hunk"
+                } else if line.starts_with('+') {
+                    "add"
+                } else if line.starts_with('-') {
+                    "del"
+                } else {
+                    ""
+                };
+                let _ = writeln!(html, "<span class=\"{}\">{}</span>", class, escape(line));
+            }
+            html.push_str("</pre></details>\n");
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn render_tree(node: &TreeNode, html: &mut String) {
+    html.push_str("<ul class=\"tree\">\n");
+    for (name, child) in &node.dirs {
+        let _ = writeln!(html, "<li>{}/", escape(name));
+        render_tree(child, html);
+        html.push_str("</li>\n");
+    }
+    for file in &node.files {
+        let name = file.path.rsplit('/').next().unwrap_or(&file.path);
+        let symbol = match file.change.as_str() {
+            "added" => "+",
+            "deleted" => "-",
+            "renamed" => ">",
+            _ => "*",
+        };
+        let moved_from = match &file.from {
+            Some(from) => format!(" ← {}", escape(from)),
+            None => String::new(),
+        };
+        let _ = writeln!(
+            html,
+            "<li class=\"{}\">{} {}{} <small>({})</small></li>",
+            file.change,
+            symbol,
+            escape(name),
+            moved_from,
+            format_size_delta(file.size_delta())
+        );
+    }
+    html.push_str("</ul>\n");
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,207 @@
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use super::error::{ArchiveIssue, PatchError, PatchResult};
+use crate::utils::FileKind;
+
+/// 补丁包外层 tar 归档的压缩格式
+///
+/// 应用时按文件头识别，无需事先知道格式；生成时同时登记在 `metadata.toml` 中供查看。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionFormat {
+    #[default]
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl CompressionFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Xz => "xz",
+        }
+    }
+
+    /// 补丁包文件的常用扩展名 (不含开头的点)
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gzip => "tgz",
+            Self::Zstd => "tar.zst",
+            Self::Xz => "tar.xz",
+        }
+    }
+
+    pub fn is_gzip(&self) -> bool {
+        *self == Self::Gzip
+    }
+
+    /// 按文件头识别补丁包的压缩格式
+    pub fn detect(patch_path: &Path) -> PatchResult<Self> {
+        let kind = FileKind::of_file(patch_path)
+            .with_context(|| format!("无法打开补丁包: {:?}", patch_path))?;
+        match kind {
+            FileKind::Gzip => Ok(Self::Gzip),
+            FileKind::Zstd => Ok(Self::Zstd),
+            FileKind::Xz => Ok(Self::Xz),
+            other => {
+                let reason = if fs::metadata(patch_path).is_ok_and(|m| m.len() == 0) {
+                    ArchiveIssue::Empty
+                } else {
+                    ArchiveIssue::NotCompressed {
+                        kind: other.to_string(),
+                    }
+                };
+                Err(PatchError::InvalidArchive {
+                    path: patch_path.to_path_buf(),
+                    reason,
+                })
+            }
+        }
+    }
+
+    /// 该格式可用的压缩级别
+    pub fn level_range(&self) -> RangeInclusive<u32> {
+        match self {
+            Self::Gzip | Self::Xz => 0..=9,
+            Self::Zstd => 1..=22,
+        }
+    }
+
+    /// 未指定级别时使用的默认级别 (与各自的命令行工具相同)
+    pub fn default_level(&self) -> u32 {
+        match self {
+            Self::Gzip | Self::Xz => 6,
+            Self::Zstd => 3,
+        }
+    }
+
+    pub(crate) fn encoder<W: Write>(
+        self,
+        inner: W,
+        options: &CompressionOptions,
+    ) -> Result<Encoder<W>> {
+        let level = options.level.unwrap_or(self.default_level());
+        let range = self.level_range();
+        if !range.contains(&level) {
+            bail!(
+                "{} 的压缩级别须在 {}-{} 之间: {}",
+                self,
+                range.start(),
+                range.end(),
+                level
+            );
+        }
+        let threads = options.threads.max(1) as u32;
+        Ok(match self {
+            Self::Gzip => Encoder::Gzip(GzEncoder::new(inner, Compression::new(level))),
+            Self::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(inner, level as i32)?;
+                if threads > 1 {
+                    encoder.multithread(threads)?;
+                }
+                Encoder::Zstd(encoder)
+            }
+            Self::Xz if threads > 1 => {
+                let stream = liblzma::stream::MtStreamBuilder::new()
+                    .preset(level)
+                    .threads(threads)
+                    .check(liblzma::stream::Check::Crc64)
+                    .encoder()?;
+                Encoder::Xz(liblzma::write::XzEncoder::new_stream(inner, stream))
+            }
+            Self::Xz => Encoder::Xz(liblzma::write::XzEncoder::new(inner, level)),
+        })
+    }
+}
+
+/// 补丁包的压缩级别与线程数，用于在 CPU 时间与补丁体积之间取舍
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionOptions {
+    /// 压缩级别，`None` 时使用格式的默认级别
+    pub level: Option<u32>,
+    /// 压缩线程数 (zstd 与 xz)，0 与 1 均为单线程；gzip 始终单线程
+    pub threads: usize,
+}
+
+impl CompressionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+}
+
+impl fmt::Display for CompressionFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 打开补丁包并按识别出的格式解压，兼容多个分卷首尾相接的补丁包
+pub(crate) fn open_decoder(patch_path: &Path) -> Result<Box<dyn Read + Send>> {
+    let format = CompressionFormat::detect(patch_path)?;
+    let file =
+        File::open(patch_path).with_context(|| format!("无法打开补丁包: {:?}", patch_path))?;
+    let reader = BufReader::new(file);
+    Ok(match format {
+        CompressionFormat::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        CompressionFormat::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+        CompressionFormat::Xz => Box::new(liblzma::read::XzDecoder::new_multi_decoder(reader)),
+    })
+}
+
+/// 各格式的流式压缩器
+pub(crate) enum Encoder<W: Write> {
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    Xz(liblzma::write::XzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// 写完压缩流的结尾，返回内层的输出
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Gzip(encoder) => encoder.finish(),
+            Self::Zstd(encoder) => encoder.finish(),
+            Self::Xz(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+            Self::Xz(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+            Self::Xz(encoder) => encoder.flush(),
+        }
+    }
+}
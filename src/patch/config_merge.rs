@@ -0,0 +1,203 @@
+use anyhow::{Context, Result};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::error::PatchResult;
+
+/// 补丁中存放合并基准 (源目录中的旧版本配置) 的目录
+pub(crate) const MERGE_BASE_DIR: &str = "merge_base";
+
+/// 配置文件的键级合并插件
+///
+/// 玩家改动过的配置文件不直接覆盖，而是以旧版本 (`base`) 为基准做三方合并：
+/// 玩家未改动的键取新版本的值 (更新默认值)，玩家改动或新增的键保留本地的值。
+pub trait ConfigMerger: Send + Sync {
+    /// 插件名称，用于输出
+    fn name(&self) -> &str;
+
+    /// 是否处理该文件 (通常按扩展名判断)
+    fn handles(&self, path: &Path) -> bool;
+
+    /// 合并三个版本的内容，返回写入目标文件的内容
+    fn merge(&self, base: &str, local: &str, new: &str) -> PatchResult<String>;
+}
+
+/// JSON 配置的键级合并 (对象逐层合并，数组与标量整体取值)
+pub struct JsonMerger;
+
+impl ConfigMerger for JsonMerger {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn handles(&self, path: &Path) -> bool {
+        has_extension(path, &["json", "mcmeta"])
+    }
+
+    fn merge(&self, base: &str, local: &str, new: &str) -> PatchResult<String> {
+        let parse = |text: &str, side: &str| -> Result<serde_json::Value> {
+            serde_json::from_str(text).with_context(|| format!("{} 不是有效的 JSON", side))
+        };
+        let merged = merge_json(
+            Some(&parse(base, "旧版本")?),
+            &parse(local, "本地文件")?,
+            &parse(new, "新版本")?,
+        );
+        Ok(serde_json::to_string_pretty(&merged).context("无法输出合并后的 JSON")? + "\n")
+    }
+}
+
+/// TOML 配置的键级合并 (表逐层合并，数组与标量整体取值；注释不保留)
+pub struct TomlMerger;
+
+impl ConfigMerger for TomlMerger {
+    fn name(&self) -> &str {
+        "toml"
+    }
+
+    fn handles(&self, path: &Path) -> bool {
+        has_extension(path, &["toml"])
+    }
+
+    fn merge(&self, base: &str, local: &str, new: &str) -> PatchResult<String> {
+        let parse = |text: &str, side: &str| -> Result<toml::Value> {
+            toml::from_str(text).with_context(|| format!("{} 不是有效的 TOML", side))
+        };
+        let merged = merge_toml(
+            Some(&parse(base, "旧版本")?),
+            &parse(local, "本地文件")?,
+            &parse(new, "新版本")?,
+        );
+        Ok(toml::to_string_pretty(&merged).context("无法输出合并后的 TOML")?)
+    }
+}
+
+/// 已注册的合并插件，默认包含 [`JsonMerger`] 与 [`TomlMerger`]
+#[derive(Clone)]
+pub struct ConfigMergers {
+    mergers: Vec<Arc<dyn ConfigMerger>>,
+}
+
+impl ConfigMergers {
+    /// 不含任何插件
+    pub fn empty() -> Self {
+        Self {
+            mergers: Vec::new(),
+        }
+    }
+
+    /// 注册插件，先注册的优先
+    pub fn register(&mut self, merger: impl ConfigMerger + 'static) {
+        self.mergers.push(Arc::new(merger));
+    }
+
+    /// 追加 `other` 中的所有插件
+    pub fn extend(&mut self, other: &ConfigMergers) {
+        self.mergers.extend(other.mergers.iter().cloned());
+    }
+
+    /// 处理该文件的插件
+    pub fn find(&self, path: &Path) -> Option<&dyn ConfigMerger> {
+        self.mergers
+            .iter()
+            .find(|merger| merger.handles(path))
+            .map(|merger| merger.as_ref())
+    }
+}
+
+impl Default for ConfigMergers {
+    fn default() -> Self {
+        let mut mergers = Self::empty();
+        mergers.register(JsonMerger);
+        mergers.register(TomlMerger);
+        mergers
+    }
+}
+
+impl fmt::Debug for ConfigMergers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.mergers.iter().map(|m| m.name()).collect();
+        write!(f, "ConfigMergers({})", names.join(", "))
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// 单个值的三方合并：只有一侧改动时取改动的一侧，两侧都改动时保留本地的值
+fn merge_leaf<V: Clone + PartialEq>(base: Option<&V>, local: &V, new: &V) -> V {
+    match base {
+        Some(base) if local == base => new.clone(),
+        _ => local.clone(),
+    }
+}
+
+fn merge_json(
+    base: Option<&serde_json::Value>,
+    local: &serde_json::Value,
+    new: &serde_json::Value,
+) -> serde_json::Value {
+    use serde_json::Value;
+    let (Value::Object(local_map), Value::Object(new_map)) = (local, new) else {
+        return merge_leaf(base, local, new);
+    };
+    let base_map = base.and_then(Value::as_object);
+    let base_get = |key: &str| base_map.and_then(|map| map.get(key));
+    let mut merged = serde_json::Map::new();
+    for (key, new_value) in new_map {
+        match local_map.get(key) {
+            Some(local_value) => {
+                merged.insert(
+                    key.clone(),
+                    merge_json(base_get(key), local_value, new_value),
+                );
+            }
+            // 玩家删除了旧版本已有的键
+            None if base_get(key).is_some() => {}
+            None => {
+                merged.insert(key.clone(), new_value.clone());
+            }
+        }
+    }
+    for (key, local_value) in local_map {
+        // 新版本删除了该键，玩家没有改过时一并删除
+        if !new_map.contains_key(key) && base_get(key) != Some(local_value) {
+            merged.insert(key.clone(), local_value.clone());
+        }
+    }
+    Value::Object(merged)
+}
+
+fn merge_toml(base: Option<&toml::Value>, local: &toml::Value, new: &toml::Value) -> toml::Value {
+    use toml::Value;
+    let (Value::Table(local_table), Value::Table(new_table)) = (local, new) else {
+        return merge_leaf(base, local, new);
+    };
+    let base_table = base.and_then(Value::as_table);
+    let base_get = |key: &str| base_table.and_then(|table| table.get(key));
+    let mut merged = toml::Table::new();
+    for (key, new_value) in new_table {
+        match local_table.get(key) {
+            Some(local_value) => {
+                merged.insert(
+                    key.clone(),
+                    merge_toml(base_get(key), local_value, new_value),
+                );
+            }
+            None if base_get(key).is_some() => {}
+            None => {
+                merged.insert(key.clone(), new_value.clone());
+            }
+        }
+    }
+    for (key, local_value) in local_table {
+        if !new_table.contains_key(key) && base_get(key) != Some(local_value) {
+            merged.insert(key.clone(), local_value.clone());
+        }
+    }
+    Value::Table(merged)
+}
@@ -1,157 +1,1422 @@
-use anyhow::Result;
-use flate2::Compression;
-use flate2::write::GzEncoder;
+use anyhow::{Context, Result, bail};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::BufWriter;
-use std::path::Path;
-use tar::Builder;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tar::{Builder, Header};
 use walkdir::WalkDir;
 
-use super::diff::{FileDiff, compare_directories};
-use super::metadata::{Checksums, Metadata, ModifiedChecksum};
-use crate::utils::compute_file_hash;
+use super::apply::is_contained_path;
+use super::base::{BASED_DIR, BasePatch};
+use super::compression::{CompressionFormat, CompressionOptions, Encoder};
+use super::config_merge::MERGE_BASE_DIR;
+use super::delta::{BlockIndex, Delta, DeltaPolicy, IN_MEMORY_MAX, Signature, encode_streaming};
+use super::dictionary::{DICTIONARY_ENTRY, SmallFileDictionary};
+use super::diff::{DirectoryDiff, FileDiff, diff_directories};
+use super::error::PatchResult;
+use super::metadata::{
+    BaseRef, Checksums, Metadata, ModifiedChecksum, MovedChecksum, RenamedChecksum,
+};
+use super::reader::NOTICE_ENTRY;
+use super::report::{CreateReport, Timings};
+use super::snapshot::Snapshot;
+use crate::utils::{
+    FileAttributes, HashManifest, HashResult, HashingReader, IgnoreRules, MemoryLimit,
+    PrefetchedFile, Prefetcher, compute_file_hash, compute_hash, format_size_delta, parallel_map,
+    shard_of, status, walk_files,
+};
+
+/// 生成补丁包的选项
+#[derive(Debug, Clone)]
+pub struct CreateOptions {
+    /// 识别“改名且小改动”文件的相似度阈值 (0.0 ~ 1.0)，为 `None` 时不做识别
+    pub rename_threshold: Option<f64>,
+    /// 快速模式：大小与修改时间相同的文件直接视为未变更，跳过哈希计算
+    pub fast: bool,
+    /// 外部提供的哈希清单，比较目录时直接复用
+    pub hash_manifest: HashManifest,
+    /// 源目录的快照，提供时不再扫描源目录
+    pub source_snapshot: Option<Snapshot>,
+    /// 目标目录的快照，提供时不再扫描目标目录
+    pub target_snapshot: Option<Snapshot>,
+    /// 并行线程数：大于 1 时按顶层子目录分片并行比较与打包
+    pub jobs: usize,
+    /// 用新增/修改的小文件训练 zstd 字典，并以字典压缩这些文件
+    pub zstd_dictionary: bool,
+    /// 基础补丁 (通常是上一个发布的补丁)：内容相同或相近的文件只引用其中的条目
+    pub base_patch: Option<PathBuf>,
+    /// 同样要兼容的其他基线目录 (如相邻的小版本)：其中与源目录不同的文件登记多个原始哈希并完整存放
+    pub alternative_sources: Vec<PathBuf>,
+    /// 内存峰值上限：限制并行线程数，过大的文件不做需要整体读入内存的处理
+    pub max_memory: Option<MemoryLimit>,
+    /// 小于该大小 (字节) 的文件不做增量，直接存放完整文件
+    pub delta_min_size: u64,
+    /// 增量中新数据占文件大小的比例超过该值 (0.0 ~ 1.0) 时回退为存放完整文件
+    pub delta_max_ratio: f64,
+    /// 比较权限与修改时间，内容相同但属性不同的文件报告为 `MetadataOnly`
+    pub track_attributes: bool,
+    /// 把 `MetadataOnly` 文件的属性写入补丁，应用时同步到目标目录
+    pub include_metadata_only: bool,
+    /// 写入元数据的补丁描述
+    pub description: Option<String>,
+    /// 写入元数据的作者
+    pub author: Option<String>,
+    /// 写入元数据的变更说明 (可为多行)
+    pub notes: Option<String>,
+    /// 补丁自带的公告 (Markdown 文本)，存为 `NOTICE.md`，应用前展示给用户
+    pub notice: Option<String>,
+    /// 比较前归一化文本文件的行尾 (CRLF → LF) 与 UTF-8 BOM，只有这些差异的文件视为未变更
+    pub normalize_eol: bool,
+    /// 扫描源目录时忽略的路径
+    pub source_ignore: IgnoreRules,
+    /// 扫描目标目录时忽略的路径
+    pub target_ignore: IgnoreRules,
+    /// 扫描两侧目录时遵循其中的 `.gitignore` (不打包 `target/`、`node_modules/` 等)
+    pub respect_gitignore: bool,
+    /// 整体替换的目录：其中的文件都以新增条目完整存放，应用时删除目标中补丁未包含的文件
+    pub replace_dirs: Vec<String>,
+    /// 在补丁中登记目标目录的全量清单 (所有文件及其哈希)，供 `apply --sync` 删除未登记的文件
+    pub full_manifest: bool,
+    /// 应用时按键合并而非覆盖的配置文件 (如 `config/**/*.json`)，补丁中附带其旧版本作为合并基准
+    pub merge_configs: IgnoreRules,
+    /// 补丁包的压缩格式
+    pub compression: CompressionFormat,
+    /// 补丁包的压缩级别与线程数
+    pub compression_options: CompressionOptions,
+}
+
+impl CreateOptions {
+    pub fn new() -> Self {
+        Self {
+            rename_threshold: Some(0.5),
+            fast: false,
+            hash_manifest: HashManifest::new(),
+            source_snapshot: None,
+            target_snapshot: None,
+            jobs: 1,
+            zstd_dictionary: false,
+            base_patch: None,
+            alternative_sources: Vec::new(),
+            max_memory: None,
+            delta_min_size: 0,
+            delta_max_ratio: 0.5,
+            track_attributes: false,
+            include_metadata_only: false,
+            description: None,
+            author: None,
+            notes: None,
+            notice: None,
+            normalize_eol: false,
+            source_ignore: IgnoreRules::new(),
+            target_ignore: IgnoreRules::new(),
+            respect_gitignore: false,
+            replace_dirs: Vec::new(),
+            full_manifest: false,
+            merge_configs: IgnoreRules::new(),
+            compression: CompressionFormat::Gzip,
+            compression_options: CompressionOptions::new(),
+        }
+    }
+
+    pub fn with_rename_threshold(mut self, threshold: Option<f64>) -> Self {
+        self.rename_threshold = threshold;
+        self
+    }
+
+    pub fn with_fast(mut self, fast: bool) -> Self {
+        self.fast = fast;
+        self
+    }
+
+    pub fn with_hash_manifest(mut self, manifest: HashManifest) -> Self {
+        self.hash_manifest = manifest;
+        self
+    }
+
+    pub fn with_source_snapshot(mut self, snapshot: Snapshot) -> Self {
+        self.source_snapshot = Some(snapshot);
+        self
+    }
+
+    pub fn with_target_snapshot(mut self, snapshot: Snapshot) -> Self {
+        self.target_snapshot = Some(snapshot);
+        self
+    }
+
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    pub fn with_zstd_dictionary(mut self, enabled: bool) -> Self {
+        self.zstd_dictionary = enabled;
+        self
+    }
+
+    pub fn with_base_patch(mut self, base_patch: impl Into<PathBuf>) -> Self {
+        self.base_patch = Some(base_patch.into());
+        self
+    }
+
+    pub fn with_alternative_source(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.alternative_sources.push(dir.into());
+        self
+    }
+
+    pub fn with_max_memory(mut self, limit: MemoryLimit) -> Self {
+        self.max_memory = Some(limit);
+        self
+    }
+
+    /// 考虑内存上限后实际使用的并行线程数
+    pub(crate) fn effective_jobs(&self) -> usize {
+        match self.max_memory {
+            Some(limit) => self.jobs.min(limit.max_jobs()),
+            None => self.jobs,
+        }
+    }
+
+    pub fn with_delta_min_size(mut self, size: u64) -> Self {
+        self.delta_min_size = size;
+        self
+    }
+
+    pub fn with_delta_max_ratio(mut self, ratio: f64) -> Self {
+        self.delta_max_ratio = ratio;
+        self
+    }
+
+    pub fn with_track_attributes(mut self, enabled: bool) -> Self {
+        self.track_attributes = enabled;
+        self
+    }
+
+    pub fn with_include_metadata_only(mut self, enabled: bool) -> Self {
+        self.include_metadata_only = enabled;
+        self
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    pub fn with_notes(mut self, notes: impl Into<String>) -> Self {
+        self.notes = Some(notes.into());
+        self
+    }
+
+    pub fn with_notice(mut self, notice: impl Into<String>) -> Self {
+        self.notice = Some(notice.into());
+        self
+    }
+
+    pub fn with_compression(mut self, format: CompressionFormat) -> Self {
+        self.compression = format;
+        self
+    }
+
+    pub fn with_compression_options(mut self, options: CompressionOptions) -> Self {
+        self.compression_options = options;
+        self
+    }
+
+    pub fn with_normalize_eol(mut self, enabled: bool) -> Self {
+        self.normalize_eol = enabled;
+        self
+    }
+
+    /// 两侧都忽略匹配 `pattern` 的路径
+    pub fn with_ignore(mut self, pattern: &str) -> Self {
+        self.source_ignore.add(pattern);
+        self.target_ignore.add(pattern);
+        self
+    }
+
+    pub fn with_source_ignore(mut self, rules: IgnoreRules) -> Self {
+        self.source_ignore = rules;
+        self
+    }
+
+    pub fn with_target_ignore(mut self, rules: IgnoreRules) -> Self {
+        self.target_ignore = rules;
+        self
+    }
+
+    pub fn with_respect_gitignore(mut self, respect: bool) -> Self {
+        self.respect_gitignore = respect;
+        self
+    }
+
+    /// 开启 `respect_gitignore` 时，把两侧目录的 `.gitignore` 并入各自的忽略规则
+    pub(crate) fn resolve_gitignore(
+        &self,
+        source_dir: &Path,
+        target_dir: &Path,
+    ) -> Result<Cow<'_, Self>> {
+        if !self.respect_gitignore {
+            return Ok(Cow::Borrowed(self));
+        }
+        let mut options = self.clone();
+        options.respect_gitignore = false;
+        options.source_ignore = options.source_ignore.with_gitignore(source_dir)?;
+        options.target_ignore = options.target_ignore.with_gitignore(target_dir)?;
+        Ok(Cow::Owned(options))
+    }
+
+    /// 匹配 `pattern` 的修改文件在应用时与玩家的本地改动按键合并
+    pub fn with_merge_config(mut self, pattern: &str) -> Self {
+        self.merge_configs.add(pattern);
+        self
+    }
+
+    pub fn with_full_manifest(mut self, enabled: bool) -> Self {
+        self.full_manifest = enabled;
+        self
+    }
+
+    /// 声明 `dir` (相对路径) 整体替换为目标目录中的内容
+    pub fn with_replace_dir(mut self, dir: impl Into<String>) -> Self {
+        self.replace_dirs.push(dir.into());
+        self
+    }
+
+    fn delta_policy(&self) -> DeltaPolicy {
+        DeltaPolicy {
+            min_size: self.delta_min_size,
+            max_size: self.max_memory.map_or(IN_MEMORY_MAX, |limit| {
+                limit.max_buffered_file().min(IN_MEMORY_MAX)
+            }),
+            max_literal_ratio: self.delta_max_ratio,
+        }
+    }
+}
+
+impl Default for CreateOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// 生成补丁包
-pub fn create_patch(source_dir: &Path, target_dir: &Path, output: &Path) -> Result<()> {
-    println!("正在比较目录...");
-    let diffs = compare_directories(source_dir, target_dir)?;
+pub fn create_patch(
+    source_dir: &Path,
+    target_dir: &Path,
+    output: &Path,
+) -> PatchResult<CreateReport> {
+    create_patch_with_options(source_dir, target_dir, output, &CreateOptions::default())
+}
+
+/// 按指定选项生成补丁包
+pub fn create_patch_with_options(
+    source_dir: &Path,
+    target_dir: &Path,
+    output: &Path,
+    options: &CreateOptions,
+) -> PatchResult<CreateReport> {
+    let options = &*options.resolve_gitignore(source_dir, target_dir)?;
+    let mut timings = Timings::new();
+    let directory_diff = compare_for_patch(source_dir, target_dir, options, &mut timings)?;
+    Ok(package_patch(
+        source_dir,
+        target_dir,
+        output,
+        options,
+        directory_diff,
+        None,
+        timings,
+    )?)
+}
+
+/// 比较两个目录，得到要打包的差异 (已按选项剔除仅属性不同的文件、展开整体替换的目录)
+pub(crate) fn compare_for_patch(
+    source_dir: &Path,
+    target_dir: &Path,
+    options: &CreateOptions,
+    timings: &mut Timings,
+) -> Result<DirectoryDiff> {
+    status!("正在比较目录...");
+    let stage = Instant::now();
+    let mut directory_diff = diff_directories(source_dir, target_dir, options)?;
+    if !options.include_metadata_only {
+        directory_diff.diffs.retain(|diff| {
+            let FileDiff::MetadataOnly(path) = diff else {
+                return true;
+            };
+            status!("  ~ {} (仅属性不同，未纳入补丁)", path.display());
+            false
+        });
+    }
+    if !directory_diff.diffs.is_empty() && !options.replace_dirs.is_empty() {
+        expand_replace_dirs(&mut directory_diff, target_dir, options)?;
+    }
+    timings.record("compare", stage, directory_diff.scanned_bytes);
+    Ok(directory_diff)
+}
+
+/// 把比较结果打包为补丁包
+///
+/// `shared` 为与其他补丁共用、已单独压缩好的条目，原样拼接在补丁包开头，不再重复压缩。
+pub(crate) fn package_patch(
+    source_dir: &Path,
+    target_dir: &Path,
+    output: &Path,
+    options: &CreateOptions,
+    mut directory_diff: DirectoryDiff,
+    shared: Option<&SharedEntries>,
+    mut timings: Timings,
+) -> Result<CreateReport> {
+    if directory_diff.diffs.is_empty() {
+        status!("两个目录完全相同，无需生成补丁包");
+        return Ok(CreateReport {
+            output: None,
+            checksums: Checksums::new(),
+            timings,
+        });
+    }
+
+    if let Some(shared) = shared {
+        directory_diff
+            .diffs
+            .retain(|diff| !shared.paths.contains(diff.path()));
+    }
+    let alternatives = collect_alternatives(&mut directory_diff, source_dir, target_dir, options)?;
+    let diffs = &directory_diff.diffs;
+
+    let stage = Instant::now();
+    let renames = match options.rename_threshold {
+        Some(threshold) => detect_renames(
+            diffs,
+            source_dir,
+            target_dir,
+            threshold,
+            &options.delta_policy(),
+        )?,
+        None => Vec::new(),
+    };
+    timings.record("renames", stage, 0);
+
+    let stage = Instant::now();
+    let remaining: Vec<&FileDiff> = diffs
+        .iter()
+        .filter(|diff| {
+            !renames
+                .iter()
+                .any(|(from, to)| from == diff.path() || to == diff.path())
+        })
+        .collect();
+    let mut checksums = shared.map_or_else(Checksums::new, |shared| shared.checksums.clone());
+
+    let dictionary = if options.zstd_dictionary {
+        let candidates: Vec<PathBuf> = remaining
+            .iter()
+            .filter(|diff| matches!(diff, FileDiff::Added(_) | FileDiff::Modified(_)))
+            .map(|diff| target_dir.join(diff.path()))
+            .collect();
+        let max_samples = options
+            .max_memory
+            .map_or(u64::MAX, |limit| limit.max_buffered_file());
+        let dictionary = SmallFileDictionary::train(&candidates, max_samples)?;
+        if dictionary.is_none() {
+            status!("  小文件样本不足，未使用 zstd 字典");
+        }
+        dictionary
+    } else {
+        None
+    };
+
+    let base = match &options.base_patch {
+        Some(path) => {
+            let dir = std::env::temp_dir().join(format!("dft_base_{}", std::process::id()));
+            Some(BasePatch::open(path, dir)?)
+        }
+        None => None,
+    };
+
+    status!("正在处理文件差异...");
+    // 直接写入补丁包，文件内容在压缩的同一遍读取中计算哈希；
+    // 不写目录条目，解包时会自动创建文件所在目录
+    let context = PackageContext {
+        source_dir,
+        target_dir,
+        directory_diff: &directory_diff,
+        alternatives: &alternatives,
+        dictionary: dictionary.as_ref(),
+        base: base.as_ref(),
+        delta: options.delta_policy(),
+        merge_configs: &options.merge_configs,
+        compression: options.compression,
+        compression_options: &options.compression_options,
+    };
+    let (mut fragments, mut fragment_bytes) = match shared {
+        Some(shared) => (vec![shared.fragment.clone()], shared.bytes),
+        None => (Vec::new(), 0),
+    };
+    let jobs = options.effective_jobs();
+    let mut writer = if jobs > 1 {
+        let shard_dir = std::env::temp_dir().join(format!("dft_create_{}", std::process::id()));
+        let result = package_shards(&context, &remaining, jobs, &shard_dir, &mut checksums)
+            .and_then(|(shards, bytes)| {
+                fragments.extend(shards);
+                fragment_bytes += bytes;
+                context.writer_after(output, &fragments, fragment_bytes)
+            });
+        let _ = fs::remove_dir_all(&shard_dir);
+        result?
+    } else {
+        let mut writer = context.writer_after(output, &fragments, fragment_bytes)?;
+        package_diffs(&context, &remaining, &mut writer, &mut checksums)?;
+        writer
+    };
+
+    for (from, to) in &renames {
+        // 增量不划算时回退为删除 + 新增
+        if !process_renamed_file(from, to, &context, &mut writer, &mut checksums)? {
+            process_deleted_file(from, &mut checksums);
+            checksums.record_platform(&from.to_string_lossy());
+            process_added_file(to, &context, None, &mut writer, &mut checksums)?;
+        }
+        checksums.record_platform(&to.to_string_lossy());
+    }
+
+    if let Some(base) = &base
+        && !checksums.base_refs.is_empty()
+    {
+        checksums.base_patch = Some(base.hash().clone());
+        status!("  {} 个文件引用基础补丁中的内容", checksums.base_refs.len());
+    }
+    if let Some(dictionary) = &dictionary
+        && !checksums.dictionary_compressed.is_empty()
+    {
+        checksums.dictionary_compressed.sort();
+        writer.append_bytes(Path::new(DICTIONARY_ENTRY), dictionary.as_bytes())?;
+    }
+
+    checksums.replace_dirs = options
+        .replace_dirs
+        .iter()
+        .map(|dir| normalize_replace_dir(dir))
+        .collect();
+    if !options.merge_configs.is_empty() {
+        let mut paths: Vec<&String> = checksums
+            .modified
+            .keys()
+            .filter(|path| options.merge_configs.is_ignored(Path::new(path)))
+            .collect();
+        paths.sort();
+        for path in paths {
+            writer.append_file(
+                &Path::new(MERGE_BASE_DIR).join(path),
+                &source_dir.join(path),
+            )?;
+            checksums.merge_bases.push(path.clone());
+        }
+    }
+    if options.full_manifest {
+        checksums.manifest = full_manifest(&directory_diff, target_dir, options)?;
+        status!("  全量清单: {} 个文件", checksums.manifest.len());
+    }
+    checksums.normalize();
+    checksums.record_encodings();
+    checksums.record_sizes(source_dir, target_dir);
+
+    // 创建元数据
+    let mut metadata = Metadata::new().with_fast_mode(options.fast);
+    metadata.description = options.description.clone();
+    metadata.author = options.author.clone();
+    metadata.notes = options.notes.clone();
+    metadata.compression = options.compression;
+
+    // 写入元数据和校验和文件
+    status!("正在创建补丁包...");
+    if let Some(notice) = &options.notice {
+        writer.append_bytes(Path::new(NOTICE_ENTRY), notice.as_bytes())?;
+    }
+    writer.append_bytes(
+        Path::new("metadata.toml"),
+        toml::to_string_pretty(&metadata)?.as_bytes(),
+    )?;
+    writer.append_bytes(
+        Path::new("checksums.toml"),
+        toml::to_string_pretty(&checksums)?.as_bytes(),
+    )?;
+    let packaged_bytes = writer.bytes_written();
+    writer.finish()?;
+    timings.record("package", stage, packaged_bytes);
+
+    status!("补丁包已生成: {}", output.display());
+    status!("  {}", checksums.summary());
+    status!("  大小变化: {}", format_size_delta(checksums.size_delta()));
+    if options.fast {
+        status!("  (快速模式生成: 未变更文件仅按大小与修改时间判断)");
+    }
+
+    Ok(CreateReport {
+        output: Some(output.to_path_buf()),
+        checksums,
+        timings,
+    })
+}
 
-    if diffs.is_empty() {
-        println!("两个目录完全相同，无需生成补丁包");
-        return Ok(());
+/// 对照其他可兼容的基线目录，收集其中与源目录内容不同的文件在这些基线中的哈希
+///
+/// 源目录与目标目录相同、但在其他基线中不同的文件也登记为修改，使这些基线同样能更新到目标版本。
+fn collect_alternatives(
+    directory_diff: &mut DirectoryDiff,
+    source_dir: &Path,
+    target_dir: &Path,
+    options: &CreateOptions,
+) -> Result<HashMap<PathBuf, Vec<HashResult>>> {
+    let mut alternatives = HashMap::new();
+    if options.alternative_sources.is_empty() {
+        return Ok(alternatives);
+    }
+    let changed: HashSet<&Path> = directory_diff
+        .diffs
+        .iter()
+        .map(|diff| diff.path().as_path())
+        .collect();
+    let mut candidates: Vec<(PathBuf, bool)> = directory_diff
+        .diffs
+        .iter()
+        .filter(|diff| matches!(diff, FileDiff::Modified(_)))
+        .map(|diff| (diff.path().to_path_buf(), true))
+        .collect();
+    for entry in walk_files(target_dir) {
+        let path = entry.path().strip_prefix(target_dir)?;
+        if !changed.contains(path)
+            && !options.target_ignore.is_ignored(path)
+            && source_dir.join(path).is_file()
+        {
+            candidates.push((path.to_path_buf(), false));
+        }
+    }
+
+    let mut added_diffs = Vec::new();
+    for (path, modified) in candidates {
+        let original = directory_diff.source_hash(source_dir, &path)?;
+        let target = directory_diff.target_hash(target_dir, &path)?;
+        let mut hashes: Vec<HashResult> = Vec::new();
+        for dir in &options.alternative_sources {
+            let alternative_path = dir.join(&path);
+            if !alternative_path.is_file() {
+                continue;
+            }
+            let hash = compute_file_hash(&alternative_path)?;
+            if hash != original && hash != target && !hashes.contains(&hash) {
+                hashes.push(hash);
+            }
+        }
+        if hashes.is_empty() {
+            continue;
+        }
+        if !modified {
+            added_diffs.push(FileDiff::Modified(path.clone()));
+        }
+        alternatives.insert(path, hashes);
     }
+    if !added_diffs.is_empty() {
+        status!(
+            "  {} 个未变更的文件在其他基线中不同，登记为修改",
+            added_diffs.len()
+        );
+        directory_diff.diffs.extend(added_diffs);
+        directory_diff.diffs.sort_by(|a, b| a.path().cmp(b.path()));
+    }
+    Ok(alternatives)
+}
 
-    // 创建临时目录
-    let temp_dir = std::env::temp_dir().join(format!("dft_patch_{}", std::process::id()));
-    fs::create_dir_all(&temp_dir)?;
+/// 整体替换的目录中，目标目录里的每个文件 (包括未变更的) 都改为新增条目
+fn expand_replace_dirs(
+    directory_diff: &mut DirectoryDiff,
+    target_dir: &Path,
+    options: &CreateOptions,
+) -> Result<()> {
+    for dir in &options.replace_dirs {
+        let dir = normalize_replace_dir(dir);
+        if !is_contained_path(Path::new(&dir)) {
+            bail!("整体替换的目录必须是目录内的相对路径: {}", dir);
+        }
+        let mut files: BTreeSet<PathBuf> = BTreeSet::new();
+        for entry in walk_files(&target_dir.join(&dir)) {
+            let path = entry.path().strip_prefix(target_dir)?;
+            if !options.target_ignore.is_ignored(path) {
+                files.insert(path.to_path_buf());
+            }
+        }
+        // 移动到该目录中的文件改为完整存放，旧路径照常删除
+        directory_diff.diffs = std::mem::take(&mut directory_diff.diffs)
+            .into_iter()
+            .filter_map(|diff| match diff {
+                FileDiff::Deleted(_) => Some(diff),
+                FileDiff::Renamed { from, to } if files.contains(&to) => {
+                    Some(FileDiff::Deleted(from))
+                }
+                _ => (!files.contains(diff.path())).then_some(diff),
+            })
+            .collect();
+        directory_diff
+            .diffs
+            .extend(files.into_iter().map(FileDiff::Added));
+    }
+    directory_diff.diffs.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(())
+}
 
-    let added_dir = temp_dir.join("added");
-    let deleted_dir = temp_dir.join("deleted");
-    let modified_dir = temp_dir.join("modified");
+/// 目标目录中所有 (未被忽略的) 文件及其哈希
+fn full_manifest(
+    directory_diff: &DirectoryDiff,
+    target_dir: &Path,
+    options: &CreateOptions,
+) -> Result<BTreeMap<String, HashResult>> {
+    let mut manifest = BTreeMap::new();
+    for entry in walk_files(target_dir) {
+        let path = entry.path().strip_prefix(target_dir)?;
+        if options.target_ignore.is_ignored(path) {
+            continue;
+        }
+        let hash = directory_diff.target_hash(target_dir, path)?;
+        manifest.insert(path.to_string_lossy().to_string(), hash);
+    }
+    Ok(manifest)
+}
 
-    fs::create_dir_all(&added_dir)?;
-    fs::create_dir_all(&deleted_dir)?;
-    fs::create_dir_all(&modified_dir)?;
+/// 统一整体替换目录的写法 (`/` 分隔，不带末尾的 `/`)
+fn normalize_replace_dir(dir: &str) -> String {
+    dir.replace('\\', "/").trim_matches('/').to_string()
+}
 
+/// 流式增量临时文件的序号 (分片并行打包时各线程的文件互不冲突)
+static DELTA_SPOOL: AtomicUsize = AtomicUsize::new(0);
+
+/// 打包文件差异所需的上下文
+struct PackageContext<'a> {
+    source_dir: &'a Path,
+    target_dir: &'a Path,
+    directory_diff: &'a DirectoryDiff,
+    /// 兼容多个基线的修改文件在其他基线中的原始哈希
+    alternatives: &'a HashMap<PathBuf, Vec<HashResult>>,
+    dictionary: Option<&'a SmallFileDictionary>,
+    base: Option<&'a BasePatch>,
+    delta: DeltaPolicy,
+    /// 需要附带合并基准的配置文件，应用时要用到完整的新内容，不做增量
+    merge_configs: &'a IgnoreRules,
+    compression: CompressionFormat,
+    compression_options: &'a CompressionOptions,
+}
+
+impl PackageContext<'_> {
+    fn writer(&self, output: &Path) -> Result<PatchWriter> {
+        self.writer_after(output, &[], 0)
+    }
+
+    /// 先原样写入已压缩好的分卷，再接着写入后续条目
+    fn writer_after(
+        &self,
+        output: &Path,
+        fragments: &[PathBuf],
+        fragment_bytes: u64,
+    ) -> Result<PatchWriter> {
+        let mut writer = PatchWriter::create_after(
+            output,
+            self.compression,
+            self.compression_options,
+            fragments,
+            fragment_bytes,
+        )?;
+        if let Some(dictionary) = self.dictionary {
+            writer.use_dictionary(dictionary)?;
+        }
+        Ok(writer)
+    }
+}
+
+/// 多个补丁包共用的条目，已单独压缩为一个分卷
+pub(crate) struct SharedEntries {
+    pub fragment: PathBuf,
+    /// 分卷的未压缩数据量
+    pub bytes: u64,
+    pub checksums: Checksums,
+    pub paths: HashSet<PathBuf>,
+}
+
+/// 把多个补丁包共有的新增、修改文件打包为一个分卷，供各补丁包拼接
+///
+/// 共用的条目不使用 zstd 字典和基础补丁，以保证在各补丁包中含义相同。
+pub(crate) fn package_shared(
+    source_dir: &Path,
+    target_dir: &Path,
+    directory_diff: &DirectoryDiff,
+    paths: HashSet<PathBuf>,
+    fragment: &Path,
+    options: &CreateOptions,
+) -> Result<SharedEntries> {
+    let alternatives = HashMap::new();
+    let context = PackageContext {
+        source_dir,
+        target_dir,
+        directory_diff,
+        alternatives: &alternatives,
+        dictionary: None,
+        base: None,
+        delta: options.delta_policy(),
+        merge_configs: &options.merge_configs,
+        compression: options.compression,
+        compression_options: &options.compression_options,
+    };
+    let diffs: Vec<&FileDiff> = directory_diff
+        .diffs
+        .iter()
+        .filter(|diff| paths.contains(diff.path()))
+        .collect();
     let mut checksums = Checksums::new();
+    let mut writer = context.writer(fragment)?;
+    package_diffs(&context, &diffs, &mut writer, &mut checksums)?;
+    let bytes = writer.bytes_written();
+    writer.finish_fragment()?;
+    Ok(SharedEntries {
+        fragment: fragment.to_path_buf(),
+        bytes,
+        checksums,
+        paths,
+    })
+}
 
-    println!("正在处理文件差异...");
-    for diff in &diffs {
+fn package_diffs(
+    context: &PackageContext,
+    diffs: &[&FileDiff],
+    writer: &mut PatchWriter,
+    checksums: &mut Checksums,
+) -> Result<()> {
+    let contents = diffs
+        .iter()
+        .filter(|diff| matches!(diff, FileDiff::Added(_) | FileDiff::Modified(_)))
+        .map(|diff| context.target_dir.join(diff.path()))
+        .collect();
+    let mut prefetcher = Prefetcher::spawn(contents);
+    for diff in diffs {
         match diff {
             FileDiff::Added(path) => {
-                process_added_file(path, target_dir, &added_dir, &mut checksums)?;
+                let prefetched = prefetcher.next();
+                process_added_file(path, context, prefetched, writer, checksums)?;
             }
             FileDiff::Deleted(path) => {
-                process_deleted_file(path, &mut checksums);
+                process_deleted_file(path, checksums);
             }
             FileDiff::Modified(path) => {
-                process_modified_file(path, source_dir, target_dir, &modified_dir, &mut checksums)?;
+                let prefetched = prefetcher.next();
+                process_modified_file(path, context, prefetched, writer, checksums)?;
+            }
+            FileDiff::MetadataOnly(path) => {
+                let attributes = FileAttributes::read(&context.target_dir.join(path))?;
+                checksums
+                    .attributes
+                    .insert(path.to_string_lossy().to_string(), attributes);
+            }
+            FileDiff::Renamed { from, to } => {
+                process_moved_file(from, to, context, checksums)?;
             }
         }
+        checksums.record_platform(&diff.path().to_string_lossy());
     }
+    Ok(())
+}
 
-    // 创建元数据
-    let metadata = Metadata::new();
-
-    // 写入元数据和校验和文件
-    write_metadata_files(&temp_dir, &metadata, &checksums)?;
-
-    // 创建 tar.gz 包
-    println!("正在创建补丁包...");
-    create_tar_gz(&temp_dir, output)?;
+/// 按顶层子目录分片，并行把各分片打包为独立的压缩分卷，返回按路径排序的分卷文件与未压缩数据量
+fn package_shards(
+    context: &PackageContext,
+    diffs: &[&FileDiff],
+    jobs: usize,
+    shard_dir: &Path,
+    checksums: &mut Checksums,
+) -> Result<(Vec<PathBuf>, u64)> {
+    fs::create_dir_all(shard_dir)?;
 
-    // 清理临时目录
-    fs::remove_dir_all(&temp_dir)?;
+    let mut shards: BTreeMap<PathBuf, Vec<&FileDiff>> = BTreeMap::new();
+    for diff in diffs {
+        shards.entry(shard_of(diff.path())).or_default().push(*diff);
+    }
 
-    println!("补丁包已生成: {}", output.display());
-    println!("  {}", checksums.summary());
+    let shards: Vec<_> = shards.into_values().enumerate().collect();
+    let results = parallel_map(shards, jobs, |(index, shard)| {
+        let fragment = shard_dir.join(format!("shard_{}", index));
+        let mut writer = context.writer(&fragment)?;
+        let mut shard_checksums = Checksums::new();
+        package_diffs(context, &shard, &mut writer, &mut shard_checksums)?;
+        let bytes = writer.bytes_written();
+        writer.finish_fragment()?;
+        Ok((fragment, bytes, shard_checksums))
+    })?;
 
-    Ok(())
+    let mut fragments = Vec::with_capacity(results.len());
+    let mut total_bytes = 0;
+    for (fragment, bytes, shard_checksums) in results {
+        total_bytes += bytes;
+        checksums.added.extend(shard_checksums.added);
+        checksums.modified.extend(shard_checksums.modified);
+        checksums.deleted.extend(shard_checksums.deleted);
+        checksums.platforms.extend(shard_checksums.platforms);
+        checksums
+            .dictionary_compressed
+            .extend(shard_checksums.dictionary_compressed);
+        checksums
+            .delta_modified
+            .extend(shard_checksums.delta_modified);
+        checksums.base_refs.extend(shard_checksums.base_refs);
+        checksums.attributes.extend(shard_checksums.attributes);
+        checksums.moved.extend(shard_checksums.moved);
+        fragments.push(fragment);
+    }
+    Ok((fragments, total_bytes))
 }
 
 fn process_added_file(
     path: &Path,
-    target_dir: &Path,
-    added_dir: &Path,
+    context: &PackageContext,
+    prefetched: Option<PrefetchedFile>,
+    writer: &mut PatchWriter,
     checksums: &mut Checksums,
 ) -> Result<()> {
-    let source = target_dir.join(path);
-    let dest = added_dir.join(path);
-
-    if let Some(parent) = dest.parent() {
-        fs::create_dir_all(parent)?;
-    }
-    fs::copy(&source, &dest)?;
-
-    let hash = compute_file_hash(&source)?;
+    let hash = append_content(context, "added", path, prefetched, writer, checksums)?;
     checksums
         .added
         .insert(path.to_string_lossy().to_string(), hash);
-    println!("  + {}", path.display());
+    status!("  + {}", path.display());
 
     Ok(())
 }
 
 fn process_deleted_file(path: &Path, checksums: &mut Checksums) {
     checksums.deleted.push(path.to_string_lossy().to_string());
-    println!("  - {}", path.display());
+    status!("  - {}", path.display());
+}
+
+/// 内容未变的移动只登记新旧路径，补丁中不存放内容
+fn process_moved_file(
+    from: &Path,
+    to: &Path,
+    context: &PackageContext,
+    checksums: &mut Checksums,
+) -> Result<()> {
+    let hash = context
+        .directory_diff
+        .source_hash(context.source_dir, from)?;
+    checksums.moved.insert(
+        to.to_string_lossy().to_string(),
+        MovedChecksum::new(from.to_string_lossy(), hash),
+    );
+    status!("  > {} -> {} (移动)", from.display(), to.display());
+    Ok(())
 }
 
 fn process_modified_file(
     path: &Path,
-    source_dir: &Path,
-    target_dir: &Path,
-    modified_dir: &Path,
+    context: &PackageContext,
+    prefetched: Option<PrefetchedFile>,
+    writer: &mut PatchWriter,
     checksums: &mut Checksums,
 ) -> Result<()> {
-    let source_file = source_dir.join(path);
-    let target_file = target_dir.join(path);
-    let dest = modified_dir.join(path);
+    let alternatives = context.alternatives.get(path).cloned().unwrap_or_default();
+    // 兼容多个基线的文件完整存放：应用时的旧文件可能来自任一基线，无法套用增量
+    let delta = if alternatives.is_empty() {
+        append_modified_delta(path, context, prefetched.as_ref(), writer)?
+    } else {
+        None
+    };
+    let modified_hash = match delta {
+        Some(hash) => {
+            checksums
+                .delta_modified
+                .push(path.to_string_lossy().to_string());
+            hash
+        }
+        None => append_content(context, "modified", path, prefetched, writer, checksums)?,
+    };
+    let original_hash = context
+        .directory_diff
+        .source_hash(context.source_dir, path)?;
+    checksums.modified.insert(
+        path.to_string_lossy().to_string(),
+        ModifiedChecksum::new(original_hash, modified_hash).with_alternatives(alternatives),
+    );
+    status!("  * {}", path.display());
+
+    Ok(())
+}
 
-    if let Some(parent) = dest.parent() {
-        fs::create_dir_all(parent)?;
+/// 把修改文件相对源目录中旧文件的增量写入补丁，返回新内容的哈希
+///
+/// 能整体读入内存的文件按字节匹配，更大的文件按 rsync 式的分块流式计算。
+/// 文件小于增量下限、增量不够小或不小于完整文件时返回 `None`，改为完整存放；
+/// 基础补丁中有相同内容或同路径的条目时改为引用基础补丁，同样返回 `None`。
+fn append_modified_delta(
+    path: &Path,
+    context: &PackageContext,
+    prefetched: Option<&PrefetchedFile>,
+    writer: &mut PatchWriter,
+) -> Result<Option<HashResult>> {
+    let old_path = context.source_dir.join(path);
+    let new_path = context.target_dir.join(path);
+    let old_size = fs::metadata(&old_path)?.len();
+    let new_size = match prefetched {
+        Some(file) => file.data.len() as u64,
+        None => fs::metadata(&new_path)?.len(),
+    };
+    if context.merge_configs.is_ignored(path) || old_size.min(new_size) < context.delta.min_size {
+        return Ok(None);
     }
+    let hash = context
+        .directory_diff
+        .target_hash(context.target_dir, path)?;
+    let key = path.to_string_lossy();
+    if context
+        .base
+        .is_some_and(|base| base.find_identical(&hash).is_some() || base.contains_path(&key))
+    {
+        return Ok(None);
+    }
+    let name = Path::new("modified").join(path);
 
-    // 对于所有文件，都使用完整替换方式
-    fs::copy(&target_file, &dest)?;
+    if context.delta.accepts_size(old_size) && context.delta.accepts_size(new_size) {
+        let old = fs::read(&old_path)?;
+        let delta = match prefetched {
+            Some(file) => Delta::encode(&old, &file.data),
+            None => Delta::encode(&old, &fs::read(&new_path)?),
+        };
+        if !context.delta.accepts(&delta) || delta.encoded_len() >= new_size {
+            return Ok(None);
+        }
+        let mut encoded = Vec::with_capacity(delta.encoded_len() as usize);
+        delta.write_to(&mut encoded)?;
+        writer.append_bytes(&name, &encoded)?;
+        return Ok(Some(hash));
+    }
 
-    let original_hash = compute_file_hash(&source_file)?;
-    let modified_hash = compute_file_hash(&target_file)?;
-    checksums.modified.insert(
-        path.to_string_lossy().to_string(),
-        ModifiedChecksum::new(original_hash, modified_hash),
+    // 增量先写到临时文件，确认足够小再写入补丁
+    let index = BlockIndex::build(&mut BufReader::new(File::open(&old_path)?), old_size)?;
+    let spool = std::env::temp_dir().join(format!(
+        "dft_delta_{}_{}",
+        std::process::id(),
+        DELTA_SPOOL.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = (|| {
+        let mut out = BufWriter::new(File::create(&spool)?);
+        let delta = encode_streaming(
+            &index,
+            &mut BufReader::new(File::open(&new_path)?),
+            &mut out,
+        )?;
+        out.flush()?;
+        drop(out);
+        if !context
+            .delta
+            .accepts_lengths(delta.literal_len, delta.target_len)
+            || delta.encoded_len >= new_size
+        {
+            return Ok(None);
+        }
+        writer.append_reader(&name, delta.encoded_len, File::open(&spool)?)?;
+        Ok(Some(hash))
+    })();
+    let _ = fs::remove_file(&spool);
+    result
+}
+
+/// 写入新增/修改文件的内容：优先引用基础补丁中的条目，其次字典压缩，否则完整存放
+///
+/// `prefetched` 为预读线程已读入内存的文件内容，没有时从磁盘读取。
+fn append_content(
+    context: &PackageContext,
+    section: &str,
+    path: &Path,
+    prefetched: Option<PrefetchedFile>,
+    writer: &mut PatchWriter,
+    checksums: &mut Checksums,
+) -> Result<HashResult> {
+    let key = path.to_string_lossy().to_string();
+    let source = context.target_dir.join(path);
+
+    if let Some(base) = context.base {
+        let hash = context
+            .directory_diff
+            .target_hash(context.target_dir, path)?;
+        if let Some(blob) = base.find_identical(&hash) {
+            let base_ref = BaseRef {
+                blob: blob.to_string(),
+                delta: false,
+            };
+            checksums.base_refs.insert(key, base_ref);
+            return Ok(hash);
+        }
+        if let Some((blob, delta)) = base.encode_delta(&key, &source, &context.delta)? {
+            let mut encoded = Vec::new();
+            delta.write_to(&mut encoded)?;
+            writer.append_bytes(&Path::new(BASED_DIR).join(path), &encoded)?;
+            checksums
+                .base_refs
+                .insert(key, BaseRef { blob, delta: true });
+            return Ok(hash);
+        }
+    }
+
+    let name = Path::new(section).join(path);
+    let (hash, compressed) = match prefetched {
+        Some(file) => writer.append_prefetched(&name, file)?,
+        None => writer.append_file_compact(&name, &source)?,
+    };
+    if compressed {
+        checksums.dictionary_compressed.push(key);
+    }
+    Ok(hash)
+}
+
+/// 在删除与新增的文件之间按分块相似度配对，识别“改名且小改动”的文件
+fn detect_renames(
+    diffs: &[FileDiff],
+    source_dir: &Path,
+    target_dir: &Path,
+    threshold: f64,
+    policy: &DeltaPolicy,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    // 只有适合做增量的文件才参与识别 (计算签名与增量都要把整个文件读入内存)
+    let fits = |path: &Path| fs::metadata(path).is_ok_and(|m| policy.accepts_size(m.len()));
+
+    let mut deleted = Vec::new();
+    for diff in diffs {
+        if let FileDiff::Deleted(path) = diff {
+            if !fits(&source_dir.join(path)) {
+                continue;
+            }
+            let signature = Signature::new(&fs::read(source_dir.join(path))?);
+            if signature.size() > 0 {
+                deleted.push((path, signature));
+            }
+        }
+    }
+    if deleted.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut candidates = Vec::new();
+    for diff in diffs {
+        let FileDiff::Added(path) = diff else {
+            continue;
+        };
+        if !fits(&target_dir.join(path)) {
+            continue;
+        }
+        let signature = Signature::new(&fs::read(target_dir.join(path))?);
+        for (from, old_signature) in &deleted {
+            // 大小相差过大的文件不可能满足相似度阈值
+            let ratio = signature.size() as f64 / old_signature.size() as f64;
+            if !(0.5..=2.0).contains(&ratio) {
+                continue;
+            }
+            let similarity = old_signature.similarity(&signature);
+            if similarity >= threshold {
+                candidates.push((similarity, *from, path));
+            }
+        }
+    }
+
+    // 优先匹配相似度最高的组合，每个文件只参与一次配对
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut renames: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (_, from, to) in candidates {
+        if renames.iter().any(|(f, t)| f == from || t == to) {
+            continue;
+        }
+        renames.push((from.clone(), to.clone()));
+    }
+
+    Ok(renames)
+}
+
+/// 以增量存放改名文件，增量不满足策略时不写入并返回 `false`
+fn process_renamed_file(
+    from: &Path,
+    to: &Path,
+    context: &PackageContext,
+    writer: &mut PatchWriter,
+    checksums: &mut Checksums,
+) -> Result<bool> {
+    let (source_dir, target_dir) = (context.source_dir, context.target_dir);
+    let directory_diff = context.directory_diff;
+    let base = fs::read(source_dir.join(from))?;
+    let target = fs::read(target_dir.join(to))?;
+    let delta = Delta::encode(&base, &target);
+    if !context.delta.accepts(&delta) {
+        return Ok(false);
+    }
+
+    let mut encoded = Vec::new();
+    delta.write_to(&mut encoded)?;
+    writer.append_bytes(&Path::new("renamed").join(to), &encoded)?;
+
+    let original_hash = directory_diff.source_hash(source_dir, from)?;
+    let modified_hash = match directory_diff.target_hashes.get(to) {
+        Some(hash) => hash.clone(),
+        None => compute_hash(&target),
+    };
+    checksums.renamed.insert(
+        to.to_string_lossy().to_string(),
+        RenamedChecksum::new(from.to_string_lossy(), original_hash, modified_hash),
     );
-    println!("  * {}", path.display());
 
-    Ok(())
+    let reused = 1.0 - delta.literal_len() as f64 / delta.target_len().max(1) as f64;
+    status!(
+        "  > {} -> {} (复用 {:.0}%)",
+        from.display(),
+        to.display(),
+        reused * 100.0
+    );
+
+    Ok(true)
 }
 
-fn write_metadata_files(temp_dir: &Path, metadata: &Metadata, checksums: &Checksums) -> Result<()> {
-    let metadata_content = toml::to_string_pretty(metadata)?;
-    fs::write(temp_dir.join("metadata.toml"), metadata_content)?;
+/// 以流式方式写入补丁包 (按 [`CompressionFormat`] 压缩的 tar)
+///
+/// 条目统一使用 ustar 头；路径放不进 ustar 头时，改用 PAX 扩展头记录完整路径，
+/// 保证 GNU tar、bsdtar 等外部工具都能正确解包。
+pub(crate) struct PatchWriter {
+    builder: Builder<TarSink<Encoder<BufWriter<File>>>>,
+    bytes_written: u64,
+    compressor: Option<zstd::bulk::Compressor<'static>>,
+}
 
-    let checksums_content = toml::to_string_pretty(checksums)?;
-    fs::write(temp_dir.join("checksums.toml"), checksums_content)?;
+impl PatchWriter {
+    /// 以格式的默认级别单线程压缩
+    pub fn create(output: &Path, format: CompressionFormat) -> Result<Self> {
+        Self::create_with(output, format, &CompressionOptions::new())
+    }
 
-    Ok(())
+    pub fn create_with(
+        output: &Path,
+        format: CompressionFormat,
+        options: &CompressionOptions,
+    ) -> Result<Self> {
+        Self::from_file(File::create(output)?, format, options)
+    }
+
+    /// 先依次写入已打包好的同格式分卷 (共含 `fragment_bytes` 字节未压缩数据)，再在其后继续追加条目
+    ///
+    /// 多个 gzip 成员 (zstd 帧、xz 流) 首尾相接仍是合法的压缩流，解压后即为一个完整的 tar 归档。
+    pub fn create_after(
+        output: &Path,
+        format: CompressionFormat,
+        options: &CompressionOptions,
+        fragments: &[PathBuf],
+        fragment_bytes: u64,
+    ) -> Result<Self> {
+        let mut file = File::create(output)?;
+        for fragment in fragments {
+            std::io::copy(&mut File::open(fragment)?, &mut file)?;
+        }
+        let mut writer = Self::from_file(file, format, options)?;
+        writer.bytes_written = fragment_bytes;
+        Ok(writer)
+    }
+
+    fn from_file(
+        file: File,
+        format: CompressionFormat,
+        options: &CompressionOptions,
+    ) -> Result<Self> {
+        let encoder = format.encoder(BufWriter::new(file), options)?;
+        Ok(Self {
+            builder: Builder::new(TarSink {
+                inner: encoder,
+                sealed: false,
+            }),
+            bytes_written: 0,
+            compressor: None,
+        })
+    }
+
+    /// 之后通过 `append_file_compact` 写入的小文件使用该字典压缩
+    pub fn use_dictionary(&mut self, dictionary: &SmallFileDictionary) -> Result<()> {
+        self.compressor = Some(dictionary.compressor()?);
+        Ok(())
+    }
+
+    /// 写入文件；已设置字典且文件足够小、尚未压缩过时以字典压缩后写入，并返回 `true`
+    pub fn append_file_compact(
+        &mut self,
+        name: &Path,
+        source: &Path,
+    ) -> Result<(HashResult, bool)> {
+        let size = fs::metadata(source)
+            .with_context(|| format!("无法打开文件: {:?}", source))?
+            .len();
+        if let Some(compressor) = &mut self.compressor
+            && SmallFileDictionary::accepts(size)
+        {
+            let data = fs::read(source)?;
+            if SmallFileDictionary::worth_compressing(&data) {
+                let compressed = compressor.compress(&data)?;
+                self.append_bytes(name, &compressed)?;
+                return Ok((compute_hash(&data), true));
+            }
+        }
+        Ok((self.append_file(name, source)?, false))
+    }
+
+    /// 写入已读入内存的文件，与 `append_file_compact` 相同地使用字典压缩
+    pub fn append_prefetched(
+        &mut self,
+        name: &Path,
+        file: PrefetchedFile,
+    ) -> Result<(HashResult, bool)> {
+        let hash = compute_hash(&file.data);
+        if let Some(compressor) = &mut self.compressor
+            && SmallFileDictionary::worth_compressing(&file.data)
+        {
+            let compressed = compressor.compress(&file.data)?;
+            self.append_bytes(name, &compressed)?;
+            return Ok((hash, true));
+        }
+        let mut header = Header::new_ustar();
+        header.set_metadata(&file.metadata);
+        header.set_size(file.data.len() as u64);
+        self.append_entry(header, name, file.data.as_slice())?;
+        self.bytes_written += file.data.len() as u64;
+        Ok((hash, false))
+    }
+
+    /// 将磁盘文件写入补丁包，并返回读取过程中计算出的哈希
+    pub fn append_file(&mut self, name: &Path, source: &Path) -> Result<HashResult> {
+        let file = File::open(source).with_context(|| format!("无法打开文件: {:?}", source))?;
+        let metadata = file.metadata()?;
+        let mut header = Header::new_ustar();
+        header.set_metadata(&metadata);
+        let mut reader = HashingReader::new(BufReader::new(file));
+        self.append_entry(header, name, &mut reader)?;
+        self.bytes_written += metadata.len();
+        Ok(reader.finish())
+    }
+
+    pub fn append_bytes(&mut self, name: &Path, data: &[u8]) -> Result<()> {
+        let mut header = Header::new_ustar();
+        header.set_mode(0o644);
+        header.set_size(data.len() as u64);
+        header.set_mtime(now_secs());
+        self.append_entry(header, name, data)?;
+        self.bytes_written += data.len() as u64;
+        Ok(())
+    }
+
+    /// 写入长度为 `size` 的流式数据
+    pub fn append_reader(&mut self, name: &Path, size: u64, data: impl Read) -> Result<()> {
+        let mut header = Header::new_ustar();
+        header.set_mode(0o644);
+        header.set_size(size);
+        header.set_mtime(now_secs());
+        self.append_entry(header, name, data)?;
+        self.bytes_written += size;
+        Ok(())
+    }
+
+    fn append_entry<R: Read>(&mut self, mut header: Header, name: &Path, data: R) -> Result<()> {
+        let path = tar_path(name);
+        if header.set_path(&path).is_err() {
+            self.builder
+                .append_pax_extensions([("path", path.as_bytes())])?;
+            header.set_path(truncate_utf8(&path, PLACEHOLDER_PATH_LEN))?;
+        }
+        header.set_cksum();
+        self.builder.append(&header, data)?;
+        Ok(())
+    }
+
+    /// 已写入补丁包的未压缩数据量
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.builder.into_inner()?.inner.finish()?.flush()?;
+        Ok(())
+    }
+
+    /// 结束一个分卷：不写 tar 结束标记，以便与后续分卷拼接
+    pub fn finish_fragment(mut self) -> Result<()> {
+        self.builder.get_mut().sealed = true;
+        self.builder.into_inner()?.inner.finish()?.flush()?;
+        Ok(())
+    }
 }
 
-pub(crate) fn create_tar_gz(source_dir: &Path, output: &Path) -> Result<()> {
-    let file = File::create(output)?;
-    let encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
-    let mut tar_builder = Builder::new(encoder);
+/// tar 输出端；封口后丢弃写入的数据 (即 tar 的结束标记)
+struct TarSink<W> {
+    inner: W,
+    sealed: bool,
+}
+
+impl<W: Write> Write for TarSink<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.sealed {
+            return Ok(buf.len());
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// PAX 头生效时，ustar 头中仅保留的占位路径长度
+const PLACEHOLDER_PATH_LEN: usize = 99;
+
+/// tar 内统一使用 `/` 作为路径分隔符
+fn tar_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
 
-    for entry in WalkDir::new(source_dir).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        let relative_path = path.strip_prefix(source_dir)?;
+fn truncate_utf8(s: &str, max: usize) -> &str {
+    let mut end = s.len().min(max);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
 
-        if path.is_file() {
-            tar_builder.append_path_with_name(path, relative_path)?;
-        } else if path.is_dir() && path != source_dir {
-            tar_builder.append_dir(relative_path, path)?;
+/// 将目录中的文件按路径顺序打包为补丁包 (不写目录条目)
+pub(crate) fn create_tar_gz(
+    source_dir: &Path,
+    output: &Path,
+    format: CompressionFormat,
+) -> Result<()> {
+    let mut writer = PatchWriter::create(output, format)?;
+
+    for entry in WalkDir::new(source_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        if entry.file_type().is_file() {
+            let relative_path = entry.path().strip_prefix(source_dir)?;
+            writer.append_file(relative_path, entry.path())?;
         }
     }
 
-    tar_builder.finish()?;
-    Ok(())
+    writer.finish()
 }
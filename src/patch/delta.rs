@@ -0,0 +1,715 @@
+use anyhow::{Result, bail};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const MAGIC: &[u8; 4] = b"DFTD";
+pub(crate) const FORMAT_VERSION: u8 = 1;
+
+const OP_END: u8 = 0;
+const OP_COPY: u8 = 1;
+const OP_LITERAL: u8 = 2;
+
+const MIN_CHUNK: usize = 512;
+const MAX_CHUNK: usize = 16 * 1024;
+const CHUNK_MASK: u64 = (1 << 11) - 1;
+
+/// 计算增量时索引基础文件的块大小，也是能识别的最短匹配
+const MATCH_BLOCK: usize = 32;
+const HASH_BASE: u64 = 0x0100_0000_01b3;
+/// 整体读入内存计算增量的文件大小上限，更大的文件使用流式的分块增量
+pub(crate) const IN_MEMORY_MAX: u64 = 64 * 1024 * 1024;
+/// 流式增量中旧文件的最小分块大小
+const STREAM_MIN_BLOCK: usize = 4 * 1024;
+/// 旧文件最多切分的块数，更大的文件按比例增大分块，索引占用的内存因此有上限
+const STREAM_MAX_BLOCKS: u64 = 1 << 18;
+/// 累积的新数据达到该大小时写出一个数据段
+const STREAM_LITERAL_FLUSH: usize = 1024 * 1024;
+/// `HASH_BASE` 的 `MATCH_BLOCK - 1` 次方，滚动时移出最早的字节
+const HASH_BASE_POW: u64 = pow_wrapping(HASH_BASE, MATCH_BLOCK as u32 - 1);
+
+/// 基于内容切分 (gear hash) 的分块边界，插入/删除字节不会影响后续块的划分
+fn chunks(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut hash: u64 = 0;
+
+    for (i, &byte) in data.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        let len = i + 1 - start;
+        if (len >= MIN_CHUNK && hash & CHUNK_MASK == 0) || len >= MAX_CHUNK {
+            result.push((start, len));
+            start = i + 1;
+            hash = 0;
+        }
+    }
+    if start < data.len() {
+        result.push((start, data.len() - start));
+    }
+
+    result
+}
+
+/// 按与 `chunks` 相同的规则切分连续输入的数据，只缓存当前未结束的块
+#[derive(Default)]
+pub(crate) struct Chunker {
+    pending: Vec<u8>,
+    hash: u64,
+}
+
+impl Chunker {
+    /// 输入一段数据，每切出一个完整的块调用一次 `f`
+    pub fn update(&mut self, data: &[u8], mut f: impl FnMut(&[u8])) {
+        for &byte in data {
+            self.pending.push(byte);
+            self.hash = (self.hash << 1).wrapping_add(GEAR[byte as usize]);
+            let len = self.pending.len();
+            if (len >= MIN_CHUNK && self.hash & CHUNK_MASK == 0) || len >= MAX_CHUNK {
+                f(&self.pending);
+                self.pending.clear();
+                self.hash = 0;
+            }
+        }
+    }
+
+    /// 输入结束，剩余的数据作为最后一个块
+    pub fn finish(&mut self, mut f: impl FnMut(&[u8])) {
+        if !self.pending.is_empty() {
+            f(&self.pending);
+            self.pending.clear();
+        }
+        self.hash = 0;
+    }
+}
+
+/// 多项式哈希，可按字节滚动更新
+fn block_hash(block: &[u8]) -> u64 {
+    block.iter().fold(0u64, |hash, &byte| {
+        hash.wrapping_mul(HASH_BASE).wrapping_add(byte as u64)
+    })
+}
+
+fn roll_hash(hash: u64, out: u8, input: u8) -> u64 {
+    hash.wrapping_sub((out as u64).wrapping_mul(HASH_BASE_POW))
+        .wrapping_mul(HASH_BASE)
+        .wrapping_add(input as u64)
+}
+
+const fn pow_wrapping(base: u64, exp: u32) -> u64 {
+    let mut result: u64 = 1;
+    let mut i = 0;
+    while i < exp {
+        result = result.wrapping_mul(base);
+        i += 1;
+    }
+    result
+}
+
+pub(crate) fn chunk_hash(chunk: &[u8]) -> [u8; 32] {
+    Sha256::digest(chunk).into()
+}
+
+/// 文件的分块签名，用于估算两个文件的内容相似度
+pub(crate) struct Signature {
+    chunks: HashMap<[u8; 32], usize>,
+    size: usize,
+}
+
+impl Signature {
+    pub fn new(data: &[u8]) -> Self {
+        let chunks = chunks(data)
+            .into_iter()
+            .map(|(offset, len)| (chunk_hash(&data[offset..offset + len]), len))
+            .collect();
+        Self {
+            chunks,
+            size: data.len(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// `other` 中可以从 `self` 复用的字节比例 (0.0 ~ 1.0)
+    pub fn similarity(&self, other: &Signature) -> f64 {
+        if other.size == 0 {
+            return 0.0;
+        }
+        let shared: usize = other
+            .chunks
+            .iter()
+            .filter(|(hash, _)| self.chunks.contains_key(*hash))
+            .map(|(_, len)| len)
+            .sum();
+        shared as f64 / other.size as f64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum DeltaOp {
+    /// 从基础文件复制 `len` 字节
+    Copy { offset: u64, len: u64 },
+    /// 直接写入的新数据
+    Literal(Vec<u8>),
+}
+
+/// 何时使用增量编码，不满足条件时回退为存放完整文件
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct DeltaPolicy {
+    /// 小于该大小的文件不做增量
+    pub min_size: u64,
+    /// 大于该大小的文件不做增量 (计算增量需要把文件整体读入内存)
+    pub max_size: u64,
+    /// 增量中新数据占目标文件的比例超过该值时回退
+    pub max_literal_ratio: f64,
+}
+
+impl DeltaPolicy {
+    /// 该大小的文件是否值得尝试增量
+    pub fn accepts_size(&self, size: u64) -> bool {
+        (self.min_size..=self.max_size).contains(&size)
+    }
+
+    /// 计算出的增量是否足够小
+    pub fn accepts(&self, delta: &Delta) -> bool {
+        self.accepts_lengths(delta.literal_len(), delta.target_len())
+    }
+
+    /// 新数据为 `literal_len` 字节、还原后为 `target_len` 字节的增量是否足够小
+    pub fn accepts_lengths(&self, literal_len: u64, target_len: u64) -> bool {
+        literal_len as f64 <= target_len as f64 * self.max_literal_ratio
+    }
+}
+
+/// 以“复制基础文件片段 + 新数据”描述的增量
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Delta {
+    ops: Vec<DeltaOp>,
+}
+
+impl Delta {
+    /// 计算从 `base` 生成 `target` 所需的增量
+    ///
+    /// 按 `MATCH_BLOCK` 字节对齐索引基础文件，在目标文件中逐字节滚动查找，
+    /// 命中后向前后逐字节扩展匹配，插入、删除少量字节时只有改动处成为新数据。
+    pub fn encode(base: &[u8], target: &[u8]) -> Self {
+        let mut delta = Delta::default();
+        if base.len() < MATCH_BLOCK || target.len() < MATCH_BLOCK {
+            delta.push_literal(target);
+            return delta;
+        }
+        let mut index: HashMap<u64, usize> = HashMap::with_capacity(base.len() / MATCH_BLOCK);
+        for offset in (0..=base.len() - MATCH_BLOCK).step_by(MATCH_BLOCK) {
+            index
+                .entry(block_hash(&base[offset..offset + MATCH_BLOCK]))
+                .or_insert(offset);
+        }
+
+        let mut literal_start = 0;
+        let mut pos = 0;
+        let mut hash = block_hash(&target[..MATCH_BLOCK]);
+        while pos + MATCH_BLOCK <= target.len() {
+            let found = index.get(&hash).copied().filter(|&offset| {
+                base[offset..offset + MATCH_BLOCK] == target[pos..pos + MATCH_BLOCK]
+            });
+            if let Some(mut offset) = found {
+                // 向前扩展到尚未输出的新数据中
+                let mut start = pos;
+                while start > literal_start && offset > 0 && base[offset - 1] == target[start - 1] {
+                    start -= 1;
+                    offset -= 1;
+                }
+                let len = base[offset..]
+                    .iter()
+                    .zip(&target[start..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                delta.push_literal(&target[literal_start..start]);
+                delta.push_copy(offset as u64, len as u64);
+                pos = start + len;
+                literal_start = pos;
+                if pos + MATCH_BLOCK <= target.len() {
+                    hash = block_hash(&target[pos..pos + MATCH_BLOCK]);
+                }
+                continue;
+            }
+            if pos + MATCH_BLOCK < target.len() {
+                hash = roll_hash(hash, target[pos], target[pos + MATCH_BLOCK]);
+            }
+            pos += 1;
+        }
+        delta.push_literal(&target[literal_start..]);
+        delta
+    }
+
+    fn push_copy(&mut self, offset: u64, len: u64) {
+        if let Some(DeltaOp::Copy {
+            offset: last_offset,
+            len: last_len,
+        }) = self.ops.last_mut()
+            && *last_offset + *last_len == offset
+        {
+            *last_len += len;
+            return;
+        }
+        self.ops.push(DeltaOp::Copy { offset, len });
+    }
+
+    fn push_literal(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        if let Some(DeltaOp::Literal(last)) = self.ops.last_mut() {
+            last.extend_from_slice(data);
+            return;
+        }
+        self.ops.push(DeltaOp::Literal(data.to_vec()));
+    }
+
+    /// 增量还原后的文件长度
+    pub fn target_len(&self) -> u64 {
+        self.ops
+            .iter()
+            .map(|op| match op {
+                DeltaOp::Copy { len, .. } => *len,
+                DeltaOp::Literal(data) => data.len() as u64,
+            })
+            .sum()
+    }
+
+    /// 增量中需要随补丁传输的新数据字节数
+    pub fn literal_len(&self) -> u64 {
+        self.ops
+            .iter()
+            .map(|op| match op {
+                DeltaOp::Copy { .. } => 0,
+                DeltaOp::Literal(data) => data.len() as u64,
+            })
+            .sum()
+    }
+
+    /// `write_to` 写出的字节数
+    pub fn encoded_len(&self) -> u64 {
+        let ops: u64 = self
+            .ops
+            .iter()
+            .map(|op| match op {
+                DeltaOp::Copy { .. } => 17,
+                DeltaOp::Literal(data) => 9 + data.len() as u64,
+            })
+            .sum();
+        MAGIC.len() as u64 + 2 + ops
+    }
+
+    /// 基于基础文件还原目标文件
+    pub fn apply<B, W>(&self, base: &mut B, out: &mut W) -> Result<()>
+    where
+        B: Read + Seek,
+        W: Write,
+    {
+        for op in &self.ops {
+            match op {
+                DeltaOp::Copy { offset, len } => {
+                    base.seek(SeekFrom::Start(*offset))?;
+                    let copied = std::io::copy(&mut base.by_ref().take(*len), out)?;
+                    if copied != *len {
+                        bail!("基础文件长度不足，无法还原增量");
+                    }
+                }
+                DeltaOp::Literal(data) => out.write_all(data)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// 将 `self` (A -> B) 与 `next` (B -> C) 组合为 A -> C 的增量
+    pub fn compose(&self, next: &Delta) -> Delta {
+        // 记录 self 中每个操作在 B 中的起始偏移
+        let mut starts = Vec::with_capacity(self.ops.len());
+        let mut pos = 0u64;
+        for op in &self.ops {
+            starts.push(pos);
+            pos += match op {
+                DeltaOp::Copy { len, .. } => *len,
+                DeltaOp::Literal(data) => data.len() as u64,
+            };
+        }
+
+        let mut composed = Delta::default();
+        for op in &next.ops {
+            match op {
+                DeltaOp::Literal(data) => composed.push_literal(data),
+                DeltaOp::Copy { offset, len } => {
+                    let end = offset + len;
+                    let first = starts.partition_point(|&s| s <= *offset).saturating_sub(1);
+                    for (i, inner) in self.ops.iter().enumerate().skip(first) {
+                        let start = starts[i];
+                        if start >= end {
+                            break;
+                        }
+                        let from = (*offset).max(start) - start;
+                        match inner {
+                            DeltaOp::Copy {
+                                offset: base_offset,
+                                len: inner_len,
+                            } => {
+                                let to = (end - start).min(*inner_len);
+                                composed.push_copy(base_offset + from, to - from);
+                            }
+                            DeltaOp::Literal(data) => {
+                                let to = ((end - start) as usize).min(data.len());
+                                composed.push_literal(&data[from as usize..to]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        composed
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[FORMAT_VERSION])?;
+        for op in &self.ops {
+            match op {
+                DeltaOp::Copy { offset, len } => {
+                    out.write_all(&[OP_COPY])?;
+                    out.write_all(&offset.to_le_bytes())?;
+                    out.write_all(&len.to_le_bytes())?;
+                }
+                DeltaOp::Literal(data) => {
+                    out.write_all(&[OP_LITERAL])?;
+                    out.write_all(&(data.len() as u64).to_le_bytes())?;
+                    out.write_all(data)?;
+                }
+            }
+        }
+        out.write_all(&[OP_END])?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> Result<Self> {
+        read_header(input)?;
+
+        let mut delta = Delta::default();
+        loop {
+            let mut tag = [0u8; 1];
+            input.read_exact(&mut tag)?;
+            match tag[0] {
+                OP_END => break,
+                OP_COPY => {
+                    let offset = read_u64(input)?;
+                    let len = read_u64(input)?;
+                    delta.ops.push(DeltaOp::Copy { offset, len });
+                }
+                OP_LITERAL => {
+                    let len = read_u64(input)?;
+                    let mut data = Vec::new();
+                    input.by_ref().take(len).read_to_end(&mut data)?;
+                    if data.len() as u64 != len {
+                        bail!("增量数据被截断");
+                    }
+                    delta.ops.push(DeltaOp::Literal(data));
+                }
+                other => bail!("未知的增量操作: {}", other),
+            }
+        }
+        Ok(delta)
+    }
+}
+
+/// rsync 式的弱校验和，可按字节滚动更新
+#[derive(Clone, Copy)]
+struct WeakHash {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl WeakHash {
+    fn new(block: &[u8]) -> Self {
+        let mut hash = Self {
+            a: 0,
+            b: 0,
+            len: block.len() as u32,
+        };
+        for (i, &byte) in block.iter().enumerate() {
+            hash.a = hash.a.wrapping_add(byte as u32);
+            hash.b = hash
+                .b
+                .wrapping_add(((block.len() - i) as u32).wrapping_mul(byte as u32));
+        }
+        hash
+    }
+
+    /// 移出窗口开头的 `out`，移入 `input`
+    fn roll(&mut self, out: u8, input: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(input as u32);
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a);
+    }
+
+    fn value(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+/// 旧文件的块签名：按固定大小分块，以弱校验和索引，命中后再以 SHA256 确认
+pub(crate) struct BlockIndex {
+    block_size: usize,
+    blocks: HashMap<u32, Vec<(u64, [u8; 32])>>,
+}
+
+impl BlockIndex {
+    /// 流式读取长度为 `base_len` 的旧文件建立索引，末尾不足一块的数据不参与匹配
+    pub fn build<R: Read>(base: &mut R, base_len: u64) -> Result<Self> {
+        let block_size = (base_len.div_ceil(STREAM_MAX_BLOCKS) as usize).max(STREAM_MIN_BLOCK);
+        let mut blocks: HashMap<u32, Vec<(u64, [u8; 32])>> = HashMap::new();
+        let mut block = vec![0u8; block_size];
+        let mut offset = 0u64;
+        while read_full(base, &mut block)? == block_size {
+            blocks
+                .entry(WeakHash::new(&block).value())
+                .or_default()
+                .push((offset, chunk_hash(&block)));
+            offset += block_size as u64;
+        }
+        Ok(Self { block_size, blocks })
+    }
+
+    fn find(&self, weak: u32, window: &[u8]) -> Option<u64> {
+        let candidates = self.blocks.get(&weak)?;
+        let strong = chunk_hash(window);
+        candidates
+            .iter()
+            .find(|(_, hash)| *hash == strong)
+            .map(|(offset, _)| *offset)
+    }
+}
+
+/// 流式增量的统计
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct StreamedDelta {
+    pub literal_len: u64,
+    pub target_len: u64,
+    pub encoded_len: u64,
+}
+
+/// 以滑动窗口读取目标文件，按 `index` 匹配旧文件中的块，把增量直接编码写出
+///
+/// 内存占用只与块大小和索引有关，与文件大小无关；编码格式与 [`Delta::write_to`] 相同。
+pub(crate) fn encode_streaming<R, W>(
+    index: &BlockIndex,
+    target: &mut R,
+    out: &mut W,
+) -> Result<StreamedDelta>
+where
+    R: Read,
+    W: Write,
+{
+    let block = index.block_size;
+    let mut writer = StreamWriter::new(out)?;
+    let mut buf: Vec<u8> = Vec::with_capacity(2 * block);
+    let mut pos = 0;
+    let mut eof = false;
+    let mut weak: Option<WeakHash> = None;
+    loop {
+        // 保证窗口内有一整块数据
+        if buf.len() - pos < block && !eof {
+            buf.drain(..pos);
+            pos = 0;
+            let filled = buf.len();
+            buf.resize(2 * block, 0);
+            let read = read_full(target, &mut buf[filled..])?;
+            buf.truncate(filled + read);
+            eof = buf.len() < 2 * block;
+        }
+        if buf.len() - pos < block {
+            writer.literal(&buf[pos..])?;
+            break;
+        }
+
+        let window = &buf[pos..pos + block];
+        let hash = *weak.get_or_insert_with(|| WeakHash::new(window));
+        if let Some(offset) = index.find(hash.value(), window) {
+            writer.copy(offset, block as u64)?;
+            pos += block;
+            weak = None;
+            continue;
+        }
+        // 窗口后移一个字节，移出的字节成为新数据
+        writer.literal(&buf[pos..pos + 1])?;
+        weak = weak.filter(|_| pos + block < buf.len()).map(|mut hash| {
+            hash.roll(buf[pos], buf[pos + block]);
+            hash
+        });
+        pos += 1;
+    }
+    writer.finish()
+}
+
+/// 边计算边写出增量，相邻的复制合并、新数据累积到一定大小再写出
+struct StreamWriter<'a, W: Write> {
+    out: &'a mut W,
+    copy: Option<(u64, u64)>,
+    literal: Vec<u8>,
+    stats: StreamedDelta,
+}
+
+impl<'a, W: Write> StreamWriter<'a, W> {
+    fn new(out: &'a mut W) -> Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[FORMAT_VERSION])?;
+        Ok(Self {
+            out,
+            copy: None,
+            literal: Vec::new(),
+            stats: StreamedDelta {
+                encoded_len: MAGIC.len() as u64 + 1,
+                ..Default::default()
+            },
+        })
+    }
+
+    fn copy(&mut self, offset: u64, len: u64) -> Result<()> {
+        self.flush_literal()?;
+        self.stats.target_len += len;
+        match &mut self.copy {
+            Some((last_offset, last_len)) if *last_offset + *last_len == offset => {
+                *last_len += len;
+            }
+            _ => {
+                self.flush_copy()?;
+                self.copy = Some((offset, len));
+            }
+        }
+        Ok(())
+    }
+
+    fn literal(&mut self, data: &[u8]) -> Result<()> {
+        self.flush_copy()?;
+        self.stats.target_len += data.len() as u64;
+        self.stats.literal_len += data.len() as u64;
+        self.literal.extend_from_slice(data);
+        if self.literal.len() >= STREAM_LITERAL_FLUSH {
+            self.flush_literal()?;
+        }
+        Ok(())
+    }
+
+    fn flush_copy(&mut self) -> Result<()> {
+        if let Some((offset, len)) = self.copy.take() {
+            self.out.write_all(&[OP_COPY])?;
+            self.out.write_all(&offset.to_le_bytes())?;
+            self.out.write_all(&len.to_le_bytes())?;
+            self.stats.encoded_len += 17;
+        }
+        Ok(())
+    }
+
+    fn flush_literal(&mut self) -> Result<()> {
+        if !self.literal.is_empty() {
+            self.out.write_all(&[OP_LITERAL])?;
+            self.out
+                .write_all(&(self.literal.len() as u64).to_le_bytes())?;
+            self.out.write_all(&self.literal)?;
+            self.stats.encoded_len += 9 + self.literal.len() as u64;
+            self.literal.clear();
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<StreamedDelta> {
+        self.flush_copy()?;
+        self.flush_literal()?;
+        self.out.write_all(&[OP_END])?;
+        self.stats.encoded_len += 1;
+        Ok(self.stats)
+    }
+}
+
+/// 读满 `buf` 或读到文件末尾，返回读取的字节数
+fn read_full<R: Read>(input: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+/// 边读取编码后的增量边还原目标文件，新数据直接写出而不整体读入内存
+pub fn apply_encoded<R, B, W>(input: &mut R, base: &mut B, out: &mut W) -> Result<()>
+where
+    R: Read,
+    B: Read + Seek,
+    W: Write,
+{
+    read_header(input)?;
+    loop {
+        let mut tag = [0u8; 1];
+        input.read_exact(&mut tag)?;
+        match tag[0] {
+            OP_END => return Ok(()),
+            OP_COPY => {
+                let offset = read_u64(input)?;
+                let len = read_u64(input)?;
+                base.seek(SeekFrom::Start(offset))?;
+                if std::io::copy(&mut base.by_ref().take(len), out)? != len {
+                    bail!("基础文件长度不足，无法还原增量");
+                }
+            }
+            OP_LITERAL => {
+                let len = read_u64(input)?;
+                if std::io::copy(&mut input.by_ref().take(len), out)? != len {
+                    bail!("增量数据被截断");
+                }
+            }
+            other => bail!("未知的增量操作: {}", other),
+        }
+    }
+}
+
+fn read_header<R: Read>(input: &mut R) -> Result<()> {
+    let mut header = [0u8; 5];
+    input.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        bail!("不是有效的增量数据");
+    }
+    if header[4] != FORMAT_VERSION {
+        bail!("不支持的增量格式版本: {}", header[4]);
+    }
+    Ok(())
+}
+
+fn read_u64<R: Read>(input: &mut R) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64 生成固定的伪随机表，保证不同平台分块结果一致
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
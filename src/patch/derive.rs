@@ -0,0 +1,385 @@
+use anyhow::{Result, bail};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use super::compression::CompressionFormat;
+use super::config_merge::MERGE_BASE_DIR;
+use super::create::PatchWriter;
+use super::error::PatchResult;
+use super::merge::{Action, MergeState, prepare_checksums, stream_entries};
+use super::metadata::{Checksums, FileSizes, Metadata, ModifiedChecksum, MovedChecksum};
+use super::reader::{NOTICE_ENTRY, Patch};
+use super::report::{MergeReport, Timings};
+use crate::utils::{HashResult, status};
+
+/// 某个路径在一个版本中的内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    Absent,
+    Present(HashResult),
+    /// 存在于共同的起始版本中，但两个补丁都没有登记其哈希 (如被删除的文件)
+    Unknown,
+}
+
+/// 由同一起始版本出发的两个补丁推导二者目标版本之间的补丁
+///
+/// `older` 为 v1→v2，`newer` 为 v1→v4，生成 v2→v4 的补丁写到 `output`。
+/// 新内容全部取自 `newer` 中的完整文件；需要 v1 原始内容才能推导的文件
+/// (v2 改动过而 v4 未改动、`newer` 中以增量存放的修改与重命名等) 报错列出。
+pub fn derive_patch(older: &Path, newer: &Path, output: &Path) -> PatchResult<MergeReport> {
+    Ok(derive(older, newer, output)?)
+}
+
+fn derive(older: &Path, newer: &Path, output: &Path) -> Result<MergeReport> {
+    status!("正在推导补丁包...");
+    let mut timings = Timings::new();
+
+    let stage = Instant::now();
+    let older_patch = Patch::open(older)?;
+    let newer_patch = Patch::open(newer)?;
+    let from = prepare_checksums(&older_patch)?;
+    let to = prepare_checksums(&newer_patch)?;
+    if !from.base_refs.is_empty() || !to.base_refs.is_empty() {
+        bail!("不支持由引用了基础补丁的补丁包推导，请先用完整补丁包重新生成");
+    }
+    check_common_source(older_patch.metadata(), newer_patch.metadata(), &from, &to)?;
+    let input_bytes = fs::metadata(older)?.len() + fs::metadata(newer)?.len();
+    timings.record("scan", stage, input_bytes);
+
+    let stage = Instant::now();
+    let (checksums, mut plan) = derive_checksums(&from, &to)?;
+    let compression = CompressionFormat::detect(newer)?;
+    let mut writer = PatchWriter::create(output, compression)?;
+    stream_entries(
+        &newer_patch,
+        &to,
+        &mut plan,
+        &mut writer,
+        &mut MergeState::default(),
+        &std::env::temp_dir(),
+    )?;
+    if !plan.is_empty() {
+        let mut missing: Vec<_> = plan.keys().collect();
+        missing.sort();
+        bail!("补丁包中缺少条目: {:?}", missing);
+    }
+
+    // 公告与变更说明一样取较新的补丁
+    if let Some(notice) = newer_patch.notice()? {
+        writer.append_bytes(Path::new(NOTICE_ENTRY), notice.as_bytes())?;
+    }
+    let mut metadata = derived_metadata(older_patch.metadata(), newer_patch.metadata());
+    metadata.compression = compression;
+    writer.append_bytes(
+        Path::new("metadata.toml"),
+        toml::to_string_pretty(&metadata)?.as_bytes(),
+    )?;
+    writer.append_bytes(
+        Path::new("checksums.toml"),
+        toml::to_string_pretty(&checksums)?.as_bytes(),
+    )?;
+    writer.finish()?;
+    timings.record("derive", stage, fs::metadata(output)?.len());
+
+    status!("补丁包推导完成: {}", output.display());
+    status!("  {}", checksums.summary());
+    status!(
+        "  版本范围: {} → {}",
+        metadata.source_version.as_deref().unwrap_or("未知"),
+        metadata.target_version.as_deref().unwrap_or("未知")
+    );
+    Ok(MergeReport {
+        output: output.to_path_buf(),
+        source_version: metadata.source_version,
+        target_version: metadata.target_version,
+        checksums,
+        timings,
+    })
+}
+
+/// 两个补丁须有相同的起始版本：声明的版本号一致，登记的原始哈希不冲突
+fn check_common_source(
+    older: Option<&Metadata>,
+    newer: Option<&Metadata>,
+    from: &Checksums,
+    to: &Checksums,
+) -> Result<()> {
+    let older_source = older.and_then(|m| m.source_version.as_deref());
+    let newer_source = newer.and_then(|m| m.source_version.as_deref());
+    if let (Some(older_source), Some(newer_source)) = (older_source, newer_source)
+        && older_source != newer_source
+    {
+        bail!(
+            "两个补丁的起始版本不同 ({} 与 {})，无法推导",
+            older_source,
+            newer_source
+        );
+    }
+    let mut conflicts: Vec<&str> = from
+        .modified
+        .keys()
+        .chain(from.renamed.values().map(|r| &r.from))
+        .chain(from.moved.values().map(|m| &m.from))
+        .filter(|path| {
+            matches!(
+                (original_hash(from, path), original_hash(to, path)),
+                (Some(a), Some(b)) if a != b
+            )
+        })
+        .map(String::as_str)
+        .collect();
+    if !conflicts.is_empty() {
+        conflicts.sort();
+        bail!("两个补丁登记的起始内容不一致: {:?}", conflicts);
+    }
+    Ok(())
+}
+
+/// 补丁登记的起始版本中该路径的哈希
+fn original_hash<'a>(checksums: &'a Checksums, path: &str) -> Option<&'a HashResult> {
+    checksums
+        .modified
+        .get(path)
+        .map(|m| &m.original)
+        .or_else(|| {
+            checksums
+                .renamed
+                .values()
+                .find(|r| r.from == path)
+                .map(|r| &r.original)
+        })
+        .or_else(|| {
+            checksums
+                .moved
+                .values()
+                .find(|m| m.from == path)
+                .map(|m| &m.hash)
+        })
+}
+
+/// 补丁是否涉及该路径
+fn touches(checksums: &Checksums, path: &str) -> bool {
+    checksums.added.contains_key(path)
+        || checksums.modified.contains_key(path)
+        || checksums.renamed.contains_key(path)
+        || checksums.deleted.iter().any(|p| p == path)
+        || checksums.renamed.values().any(|r| r.from == path)
+        || checksums.moved.contains_key(path)
+        || checksums.moved.values().any(|m| m.from == path)
+}
+
+/// 起始版本中该路径的内容
+fn source_state(from: &Checksums, to: &Checksums, path: &str) -> State {
+    let created = |checksums: &Checksums| {
+        checksums.added.contains_key(path)
+            || checksums.renamed.contains_key(path)
+            || checksums.moved.contains_key(path)
+    };
+    if created(from) || created(to) {
+        return State::Absent;
+    }
+    match original_hash(from, path).or_else(|| original_hash(to, path)) {
+        Some(hash) => State::Present(hash.clone()),
+        None => State::Unknown,
+    }
+}
+
+/// 补丁应用到起始版本后该路径的内容，补丁未涉及时为 `None`
+fn state_after(checksums: &Checksums, path: &str) -> Option<State> {
+    if let Some(hash) = checksums.added.get(path) {
+        return Some(State::Present(hash.clone()));
+    }
+    if let Some(modified) = checksums.modified.get(path) {
+        return Some(State::Present(modified.modified.clone()));
+    }
+    if let Some(renamed) = checksums.renamed.get(path) {
+        return Some(State::Present(renamed.modified.clone()));
+    }
+    if let Some(moved) = checksums.moved.get(path) {
+        return Some(State::Present(moved.hash.clone()));
+    }
+    touches(checksums, path).then_some(State::Absent)
+}
+
+/// 推导 v2→v4 的校验和，并确定需要从较新的补丁中取出的条目
+fn derive_checksums(
+    from: &Checksums,
+    to: &Checksums,
+) -> Result<(Checksums, HashMap<String, Action>)> {
+    let mut derived = Checksums::new();
+    let mut plan = HashMap::new();
+    let mut underivable = Vec::new();
+    let mut copy = |section: &str, path: &str| {
+        let output = format!("{}/{}", section, path);
+        let compressed = section != "renamed" && to.dictionary_compressed.iter().any(|p| p == path);
+        plan.insert(output.clone(), Action::Copy { output, compressed });
+    };
+
+    // 源文件与新路径都未被较早的补丁涉及的重命名原样保留 (增量作用于相同的旧文件)
+    let mut handled = HashSet::new();
+    for (path, renamed) in &to.renamed {
+        if !touches(from, path) && !touches(from, &renamed.from) {
+            derived.renamed.insert(path.clone(), renamed.clone());
+            copy("renamed", path);
+            handled.insert(path.as_str());
+            handled.insert(renamed.from.as_str());
+        }
+    }
+
+    let mut paths = BTreeSet::new();
+    for checksums in [from, to] {
+        paths.extend(checksums.added.keys().map(String::as_str));
+        paths.extend(checksums.modified.keys().map(String::as_str));
+        paths.extend(checksums.renamed.keys().map(String::as_str));
+        paths.extend(checksums.renamed.values().map(|r| r.from.as_str()));
+        paths.extend(checksums.deleted.iter().map(String::as_str));
+        paths.extend(checksums.moved.keys().map(String::as_str));
+        paths.extend(checksums.moved.values().map(|m| m.from.as_str()));
+    }
+    let mut changes = Vec::new();
+    for path in paths.into_iter().filter(|path| !handled.contains(path)) {
+        let source = || source_state(from, to, path);
+        let old = state_after(from, path).unwrap_or_else(source);
+        let new = state_after(to, path).unwrap_or_else(source);
+        if old != new {
+            changes.push((path, old, new));
+        }
+    }
+    // v2 中存在、v4 中不再存在的文件，可就地移动到需要相同内容的新路径
+    let mut movable: HashMap<HashResult, Vec<&str>> = HashMap::new();
+    for (path, old, new) in changes.iter().rev() {
+        if let (State::Present(hash), State::Absent) = (old, new) {
+            movable.entry(hash.clone()).or_default().push(path);
+        }
+    }
+    let mut moved_from = HashSet::new();
+    for (path, old, new) in changes {
+        match new {
+            State::Absent => derived.deleted.push(path.to_string()),
+            State::Present(hash) => {
+                // 新内容须是较新补丁中的完整文件
+                let section = if to.added.contains_key(path) {
+                    "added"
+                } else if let Some(modified) = to.modified.get(path) {
+                    // 增量只能作用于较新补丁的起始内容
+                    if to.delta_modified.iter().any(|p| p == path) {
+                        if old != State::Present(modified.original.clone()) {
+                            underivable.push(path);
+                            continue;
+                        }
+                        derived.delta_modified.push(path.to_string());
+                    }
+                    "modified"
+                } else if let Some(source) = movable.get_mut(&hash).and_then(Vec::pop) {
+                    derived
+                        .moved
+                        .insert(path.to_string(), MovedChecksum::new(source, hash));
+                    moved_from.insert(source);
+                    continue;
+                } else {
+                    underivable.push(path);
+                    continue;
+                };
+                copy(section, path);
+                match old {
+                    State::Present(original) => {
+                        derived
+                            .modified
+                            .insert(path.to_string(), ModifiedChecksum::new(original, hash));
+                    }
+                    State::Absent | State::Unknown => {
+                        derived.added.insert(path.to_string(), hash);
+                    }
+                }
+            }
+            State::Unknown => underivable.push(path),
+        }
+    }
+    if !underivable.is_empty() {
+        bail!(
+            "以下文件需要起始版本的原始内容，无法由这两个补丁推导: {:?}",
+            underivable
+        );
+    }
+    derived
+        .deleted
+        .retain(|path| !moved_from.contains(path.as_str()));
+
+    // 合并基准只在修改条目的原始内容与较新补丁一致时可用
+    for path in derived.modified.keys() {
+        if to.merge_bases.contains(path)
+            && to.modified.get(path).map(|m| &m.original) == Some(&derived.modified[path].original)
+        {
+            derived.merge_bases.push(path.clone());
+            let output = format!("{}/{}", MERGE_BASE_DIR, path);
+            plan.insert(
+                output.clone(),
+                Action::Copy {
+                    output,
+                    compressed: false,
+                },
+            );
+        }
+    }
+
+    let present = |path: &String| {
+        derived.added.contains_key(path)
+            || derived.modified.contains_key(path)
+            || derived.renamed.contains_key(path)
+            || derived.moved.contains_key(path)
+            || derived.deleted.contains(path)
+    };
+    for (path, tag) in from.platforms.iter().chain(&to.platforms) {
+        if present(path) {
+            derived.platforms.insert(path.clone(), tag.clone());
+        }
+    }
+    // 旧大小取 v2 (较早补丁的新大小或起始版本的大小)，新大小取较新补丁登记的值
+    let mut sizes = Vec::new();
+    let changed = derived
+        .added
+        .keys()
+        .chain(derived.modified.keys())
+        .chain(derived.renamed.keys())
+        .chain(derived.moved.keys())
+        .chain(&derived.deleted);
+    for path in changed {
+        let old = match from.sizes.get(path) {
+            Some(sizes) if touches(from, path) => sizes.new,
+            _ => to.sizes.get(path).and_then(|sizes| sizes.old),
+        };
+        let new = to.sizes.get(path).and_then(|sizes| sizes.new);
+        if old.is_some() || new.is_some() {
+            sizes.push((path.clone(), FileSizes::new(old, new)));
+        }
+    }
+    derived.sizes.extend(sizes);
+    let deleted: HashSet<String> = derived.deleted.iter().cloned().collect();
+    derived.attributes = to
+        .attributes
+        .iter()
+        .filter(|(path, _)| !deleted.contains(*path))
+        .map(|(path, attributes)| (path.clone(), *attributes))
+        .collect();
+    derived.manifest = to.manifest.clone();
+    derived.replace_dirs = to.replace_dirs.clone();
+
+    for conflict in derived.normalize() {
+        status!("  ! {}", conflict);
+    }
+    derived.record_encodings();
+    Ok((derived, plan))
+}
+
+/// 推导结果的元数据：起始版本为较早补丁的目标版本，目标版本与说明取较新的补丁
+fn derived_metadata(older: Option<&Metadata>, newer: Option<&Metadata>) -> Metadata {
+    let mut metadata = Metadata::new().with_description("推导补丁包");
+    metadata.source_version = older.and_then(|m| m.target_version.clone());
+    metadata.target_version = newer.and_then(|m| m.target_version.clone());
+    metadata.author = newer.and_then(|m| m.author.clone());
+    metadata.notes = newer.and_then(|m| m.notes.clone());
+    metadata.fast_mode = older.is_some_and(|m| m.fast_mode) || newer.is_some_and(|m| m.fast_mode);
+    metadata
+}
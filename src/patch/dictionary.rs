@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use super::metadata::Checksums;
+use crate::utils::FileKind;
+
+/// 补丁包中保存 zstd 字典的条目名
+pub(crate) const DICTIONARY_ENTRY: &str = "zstd.dict";
+
+/// 不超过该大小的文件才使用字典压缩
+const SMALL_FILE_LIMIT: u64 = 64 * 1024;
+/// 样本太少时训练不出有效的字典
+const MIN_SAMPLES: usize = 8;
+/// 字典大小取样本总量的 1/10，并限制在该范围内
+const DICTIONARY_SIZE: (usize, usize) = (1024, 64 * 1024);
+const COMPRESSION_LEVEL: i32 = 19;
+
+/// 由大量相似小文件训练出的 zstd 字典
+pub(crate) struct SmallFileDictionary {
+    data: Vec<u8>,
+}
+
+impl SmallFileDictionary {
+    /// 从候选文件中挑出小文件训练字典，样本总量不超过 `max_total` 字节；
+    /// 样本不足或训练失败时返回 `None`
+    pub fn train(paths: &[PathBuf], max_total: u64) -> Result<Option<Self>> {
+        let mut samples = Vec::new();
+        let mut total = 0;
+        for path in paths {
+            let size = fs::metadata(path)?.len();
+            if Self::accepts(size) {
+                if total + size > max_total {
+                    break;
+                }
+                let data = fs::read(path)?;
+                if Self::worth_compressing(&data) {
+                    total += size;
+                    samples.push(data);
+                }
+            }
+        }
+        if samples.len() < MIN_SAMPLES {
+            return Ok(None);
+        }
+        let total: usize = samples.iter().map(Vec::len).sum();
+        let size = (total / 10).clamp(DICTIONARY_SIZE.0, DICTIONARY_SIZE.1);
+        Ok(zstd::dict::from_samples(&samples, size)
+            .ok()
+            .map(|data| Self { data }))
+    }
+
+    /// 该大小的文件是否使用字典压缩
+    pub fn accepts(size: u64) -> bool {
+        size <= SMALL_FILE_LIMIT
+    }
+
+    /// 文件内容是否使用字典压缩：足够小，且不是 png、jar 等已压缩的格式 (这些原样存放)
+    pub fn worth_compressing(data: &[u8]) -> bool {
+        Self::accepts(data.len() as u64) && !FileKind::detect(data).is_compressed()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn compressor(&self) -> Result<zstd::bulk::Compressor<'static>> {
+        Ok(zstd::bulk::Compressor::with_dictionary(
+            COMPRESSION_LEVEL,
+            &self.data,
+        )?)
+    }
+}
+
+/// 将解包目录中以字典压缩的条目就地解压还原，并清除对应的登记
+///
+/// 解包后立即调用，之后的应用、合并与展示都只会看到原始文件内容。
+pub(crate) fn expand_dictionary_entries(temp_dir: &Path, checksums: &mut Checksums) -> Result<()> {
+    if checksums.dictionary_compressed.is_empty() {
+        return Ok(());
+    }
+
+    let dictionary_path = temp_dir.join(DICTIONARY_ENTRY);
+    let dictionary = fs::read(&dictionary_path).context("补丁中缺少 zstd 字典")?;
+    for path in checksums.dictionary_compressed.drain(..) {
+        let section = if checksums.added.contains_key(&path) {
+            "added"
+        } else {
+            "modified"
+        };
+        let entry = temp_dir.join(section).join(&path);
+        let mut decoder = zstd::stream::read::Decoder::with_dictionary(
+            BufReader::new(File::open(&entry)?),
+            &dictionary,
+        )?;
+        let mut data = Vec::new();
+        std::io::copy(&mut decoder, &mut data)
+            .with_context(|| format!("无法解压字典压缩的文件: {}", path))?;
+        fs::write(&entry, data)?;
+    }
+    fs::remove_file(dictionary_path)?;
+    Ok(())
+}
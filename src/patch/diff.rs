@@ -1,7 +1,18 @@
 use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::path::{Path, PathBuf};
 
-use crate::utils::scan_directory;
+use super::create::CreateOptions;
+use super::error::PatchResult;
+use super::snapshot::Snapshot;
+use crate::utils::{
+    FileAttributes, FileInfo, HashManifest, HashResult, IgnoreRules, compute_file_hash,
+    compute_hash, is_text_file, scan_directory_sharded, scan_file_stats,
+};
+
+/// 超过该大小的文件不做行尾归一化比较
+const EOL_NORMALIZE_MAX_BYTES: u64 = 16 * 1024 * 1024;
 
 /// 文件差异类型
 #[derive(Debug)]
@@ -9,12 +20,23 @@ pub enum FileDiff {
     Added(PathBuf),
     Deleted(PathBuf),
     Modified(PathBuf),
+    /// 内容相同，仅权限或修改时间不同 (启用属性跟踪时)
+    MetadataOnly(PathBuf),
+    /// 内容未变，只是从 `from` 移动到了 `to`
+    Renamed {
+        from: PathBuf,
+        to: PathBuf,
+    },
 }
 
 impl FileDiff {
     pub fn path(&self) -> &PathBuf {
         match self {
-            FileDiff::Added(p) | FileDiff::Deleted(p) | FileDiff::Modified(p) => p,
+            FileDiff::Added(p)
+            | FileDiff::Deleted(p)
+            | FileDiff::Modified(p)
+            | FileDiff::MetadataOnly(p) => p,
+            FileDiff::Renamed { to, .. } => to,
         }
     }
 
@@ -23,19 +45,287 @@ impl FileDiff {
             FileDiff::Added(_) => "+",
             FileDiff::Deleted(_) => "-",
             FileDiff::Modified(_) => "*",
+            FileDiff::MetadataOnly(_) => "~",
+            FileDiff::Renamed { .. } => ">",
+        }
+    }
+}
+
+/// 目录比较结果，附带比较过程中已经计算出的哈希，避免后续重复读取文件
+pub(crate) struct DirectoryDiff {
+    pub diffs: Vec<FileDiff>,
+    pub source_hashes: HashMap<PathBuf, HashResult>,
+    pub target_hashes: HashMap<PathBuf, HashResult>,
+    /// 两侧参与比较的文件总大小
+    pub scanned_bytes: u64,
+}
+
+impl DirectoryDiff {
+    /// 取得源目录中文件的哈希，比较时未计算的现在计算
+    pub fn source_hash(&self, source_dir: &Path, path: &Path) -> Result<HashResult> {
+        match self.source_hashes.get(path) {
+            Some(hash) => Ok(hash.clone()),
+            None => compute_file_hash(&source_dir.join(path)),
+        }
+    }
+
+    /// 取得目标目录中文件的哈希，比较时未计算的现在计算
+    pub fn target_hash(&self, target_dir: &Path, path: &Path) -> Result<HashResult> {
+        match self.target_hashes.get(path) {
+            Some(hash) => Ok(hash.clone()),
+            None => compute_file_hash(&target_dir.join(path)),
         }
     }
 }
 
 /// 比较两个目录并返回差异
-pub fn compare_directories(source_dir: &Path, target_dir: &Path) -> Result<Vec<FileDiff>> {
-    let source_files = scan_directory(source_dir)?;
-    let target_files = scan_directory(target_dir)?;
+pub fn compare_directories(source_dir: &Path, target_dir: &Path) -> PatchResult<Vec<FileDiff>> {
+    Ok(diff_directories(source_dir, target_dir, &CreateOptions::new())?.diffs)
+}
+
+/// 比较两个目录，内容相同但权限或修改时间不同的文件报告为 [`FileDiff::MetadataOnly`]
+pub fn compare_directories_with_attributes(
+    source_dir: &Path,
+    target_dir: &Path,
+) -> PatchResult<Vec<FileDiff>> {
+    let options = CreateOptions::new().with_track_attributes(true);
+    Ok(diff_directories(source_dir, target_dir, &options)?.diffs)
+}
+
+/// 快速比较两个目录：大小与修改时间都相同的文件视为未变更，仅对可疑文件计算哈希确认
+pub fn compare_directories_fast(
+    source_dir: &Path,
+    target_dir: &Path,
+) -> PatchResult<Vec<FileDiff>> {
+    let options = CreateOptions::new().with_fast(true);
+    Ok(diff_directories(source_dir, target_dir, &options)?.diffs)
+}
+
+/// 按选项比较两个目录 (快速模式、属性比较、两侧各自的忽略规则等)
+pub fn compare_directories_with_options(
+    source_dir: &Path,
+    target_dir: &Path,
+    options: &CreateOptions,
+) -> PatchResult<Vec<FileDiff>> {
+    let options = options.resolve_gitignore(source_dir, target_dir)?;
+    Ok(diff_directories(source_dir, target_dir, &options)?.diffs)
+}
+
+pub(crate) fn diff_directories(
+    source_dir: &Path,
+    target_dir: &Path,
+    options: &CreateOptions,
+) -> Result<DirectoryDiff> {
+    let snapshots = (
+        options.source_snapshot.as_ref(),
+        options.target_snapshot.as_ref(),
+    );
+    let ignore = (&options.source_ignore, &options.target_ignore);
+    let mut result = if options.fast && snapshots == (None, None) {
+        diff_by_stats(source_dir, target_dir, &options.hash_manifest, ignore)?
+    } else {
+        diff_by_hashes(
+            source_dir,
+            target_dir,
+            &options.hash_manifest,
+            snapshots,
+            ignore,
+            options.effective_jobs(),
+        )?
+    };
+    if options.normalize_eol {
+        let mut diffs = Vec::with_capacity(result.diffs.len());
+        for diff in std::mem::take(&mut result.diffs) {
+            if let FileDiff::Modified(path) = &diff
+                && same_ignoring_eol(&source_dir.join(path), &target_dir.join(path))?
+            {
+                continue;
+            }
+            diffs.push(diff);
+        }
+        result.diffs = diffs;
+    }
+    if options.rename_threshold.is_some() {
+        detect_moves(&mut result, source_dir, target_dir)?;
+    }
+    if options.track_attributes {
+        let metadata_only = diff_attributes(source_dir, target_dir, &result.diffs, ignore)?;
+        result.diffs.extend(metadata_only);
+    }
+    // 按路径排序，保证输出与补丁包内条目顺序稳定
+    result.diffs.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(result)
+}
+
+/// 把内容完全相同的一对删除与新增合并为移动 (空文件除外)
+///
+/// 同一内容有多个候选时按路径顺序一一配对。
+fn detect_moves(result: &mut DirectoryDiff, source_dir: &Path, target_dir: &Path) -> Result<()> {
+    let paths = |kind: fn(&FileDiff) -> Option<&PathBuf>| -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = result.diffs.iter().filter_map(kind).cloned().collect();
+        paths.sort();
+        paths
+    };
+    let deleted = paths(|diff| match diff {
+        FileDiff::Deleted(path) => Some(path),
+        _ => None,
+    });
+    let added = paths(|diff| match diff {
+        FileDiff::Added(path) => Some(path),
+        _ => None,
+    });
+    if deleted.is_empty() || added.is_empty() {
+        return Ok(());
+    }
+
+    let empty = compute_hash(&[]);
+    let mut by_hash: HashMap<HashResult, Vec<PathBuf>> = HashMap::new();
+    for path in deleted {
+        let hash = result.source_hash(source_dir, &path)?;
+        if hash != empty {
+            result.source_hashes.insert(path.clone(), hash.clone());
+            by_hash.entry(hash).or_default().push(path);
+        }
+    }
+    let mut moves: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for to in added {
+        let hash = result.target_hash(target_dir, &to)?;
+        result.target_hashes.insert(to.clone(), hash.clone());
+        if let Some(candidates) = by_hash.get_mut(&hash)
+            && !candidates.is_empty()
+        {
+            moves.insert(candidates.remove(0), to);
+        }
+    }
+    if moves.is_empty() {
+        return Ok(());
+    }
+
+    let moved_to: HashSet<&PathBuf> = moves.values().collect();
+    let mut diffs = Vec::with_capacity(result.diffs.len());
+    for diff in std::mem::take(&mut result.diffs) {
+        match diff {
+            FileDiff::Deleted(from) if moves.contains_key(&from) => {
+                let to = moves[&from].clone();
+                diffs.push(FileDiff::Renamed { from, to });
+            }
+            FileDiff::Added(to) if moved_to.contains(&to) => {}
+            diff => diffs.push(diff),
+        }
+    }
+    result.diffs = diffs;
+    Ok(())
+}
+
+/// 两个文本文件归一化行尾与 BOM 后是否相同 (不是文本文件、过大或不在磁盘上时视为不同)
+fn same_ignoring_eol(source: &Path, target: &Path) -> Result<bool> {
+    for path in [source, target] {
+        if !path.is_file()
+            || fs::metadata(path)?.len() > EOL_NORMALIZE_MAX_BYTES
+            || !is_text_file(path)
+        {
+            return Ok(false);
+        }
+    }
+    Ok(normalize_eol(&fs::read(source)?) == normalize_eol(&fs::read(target)?))
+}
+
+/// 去掉开头的 UTF-8 BOM，并把 CRLF 与单独的 CR 换成 LF
+fn normalize_eol(data: &[u8]) -> Vec<u8> {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let mut normalized = Vec::with_capacity(data.len());
+    let mut bytes = data.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'\r' {
+            bytes.next_if_eq(&&b'\n');
+            normalized.push(b'\n');
+        } else {
+            normalized.push(byte);
+        }
+    }
+    normalized
+}
+
+/// 找出两侧都存在、内容未变但属性不同的文件
+fn diff_attributes(
+    source_dir: &Path,
+    target_dir: &Path,
+    diffs: &[FileDiff],
+    ignore: (&IgnoreRules, &IgnoreRules),
+) -> Result<Vec<FileDiff>> {
+    let changed: HashSet<&PathBuf> = diffs.iter().map(FileDiff::path).collect();
+    let source_files = scan_file_stats(source_dir)?;
+    let mut target_files: Vec<PathBuf> = scan_file_stats(target_dir)?
+        .into_keys()
+        .filter(|path| source_files.contains_key(path) && !changed.contains(path))
+        .filter(|path| !ignore.0.is_ignored(path) && !ignore.1.is_ignored(path))
+        .collect();
+    target_files.sort();
+
+    let mut metadata_only = Vec::new();
+    for path in target_files {
+        let source = FileAttributes::read(&source_dir.join(&path))?;
+        let target = FileAttributes::read(&target_dir.join(&path))?;
+        if source != target {
+            metadata_only.push(FileDiff::MetadataOnly(path));
+        }
+    }
+    Ok(metadata_only)
+}
+
+fn diff_by_hashes(
+    source_dir: &Path,
+    target_dir: &Path,
+    manifest: &HashManifest,
+    snapshots: (Option<&Snapshot>, Option<&Snapshot>),
+    ignore: (&IgnoreRules, &IgnoreRules),
+    jobs: usize,
+) -> Result<DirectoryDiff> {
+    // 提供了快照的一侧直接使用快照，不再扫描磁盘
+    let from_snapshot = |snapshot: &Snapshot, ignore: &IgnoreRules| {
+        let mut files = snapshot.files.clone();
+        files.retain(|path, _| !ignore.is_ignored(path));
+        files
+    };
+    let source_files = match snapshots.0 {
+        Some(snapshot) => from_snapshot(snapshot, ignore.0),
+        None => scan_directory_sharded(source_dir, &manifest.source, ignore.0, jobs)?,
+    };
+    let target_files = match snapshots.1 {
+        Some(snapshot) => from_snapshot(snapshot, ignore.1),
+        None => scan_directory_sharded(target_dir, &manifest.target, ignore.1, jobs)?,
+    };
+
+    let diffs = diff_file_maps(&source_files, &target_files);
+    let scanned_bytes = source_files
+        .values()
+        .chain(target_files.values())
+        .map(|info| info.fsize as u64)
+        .sum();
+
+    Ok(DirectoryDiff {
+        diffs,
+        scanned_bytes,
+        source_hashes: source_files
+            .into_iter()
+            .map(|(path, info)| (path, info.hash))
+            .collect(),
+        target_hashes: target_files
+            .into_iter()
+            .map(|(path, info)| (path, info.hash))
+            .collect(),
+    })
+}
 
+/// 按路径与文件信息比较两组文件
+pub(crate) fn diff_file_maps(
+    source_files: &HashMap<PathBuf, FileInfo>,
+    target_files: &HashMap<PathBuf, FileInfo>,
+) -> Vec<FileDiff> {
     let mut diffs = Vec::new();
 
     // 检查新增和修改的文件
-    for (path, target_hash) in &target_files {
+    for (path, target_hash) in target_files {
         if let Some(source_hash) = source_files.get(path) {
             if source_hash != target_hash {
                 diffs.push(FileDiff::Modified(path.clone()));
@@ -52,5 +342,74 @@ pub fn compare_directories(source_dir: &Path, target_dir: &Path) -> Result<Vec<F
         }
     }
 
-    Ok(diffs)
+    diffs
+}
+
+fn diff_by_stats(
+    source_dir: &Path,
+    target_dir: &Path,
+    manifest: &HashManifest,
+    ignore: (&IgnoreRules, &IgnoreRules),
+) -> Result<DirectoryDiff> {
+    let mut source_files = scan_file_stats(source_dir)?;
+    let mut target_files = scan_file_stats(target_dir)?;
+    source_files.retain(|path, _| !ignore.0.is_ignored(path));
+    target_files.retain(|path, _| !ignore.1.is_ignored(path));
+
+    let mut diffs = Vec::new();
+    let mut source_hashes = HashMap::new();
+    let mut target_hashes = HashMap::new();
+
+    // 检查新增和修改的文件
+    for (path, target_stat) in &target_files {
+        match source_files.get(path) {
+            Some(source_stat) if source_stat == target_stat => {}
+            Some(source_stat) if source_stat.size != target_stat.size => {
+                // 大小不同必然已修改
+                diffs.push(FileDiff::Modified(path.clone()));
+            }
+            Some(_) => {
+                // 只有修改时间不同，需要哈希确认
+                let source_hash = known_or_compute(&manifest.source, source_dir, path)?;
+                let target_hash = known_or_compute(&manifest.target, target_dir, path)?;
+                if source_hash != target_hash {
+                    diffs.push(FileDiff::Modified(path.clone()));
+                    source_hashes.insert(path.clone(), source_hash);
+                    target_hashes.insert(path.clone(), target_hash);
+                }
+            }
+            None => diffs.push(FileDiff::Added(path.clone())),
+        }
+    }
+
+    // 检查删除的文件
+    for path in source_files.keys() {
+        if !target_files.contains_key(path) {
+            diffs.push(FileDiff::Deleted(path.clone()));
+        }
+    }
+
+    let scanned_bytes = source_files
+        .values()
+        .chain(target_files.values())
+        .map(|stat| stat.size)
+        .sum();
+
+    Ok(DirectoryDiff {
+        diffs,
+        source_hashes,
+        target_hashes,
+        scanned_bytes,
+    })
+}
+
+fn known_or_compute(
+    known: &HashMap<PathBuf, HashResult>,
+    dir: &Path,
+    path: &Path,
+) -> Result<HashResult> {
+    match known.get(path) {
+        Some(hash) => Ok(hash.clone()),
+        None => compute_file_hash(&dir.join(path)),
+    }
 }
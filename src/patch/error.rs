@@ -0,0 +1,98 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+use super::compression::CompressionFormat;
+use crate::utils::HashResult;
+
+/// 生成、应用、合并与查看补丁包的错误
+///
+/// 库内部仍以 `anyhow` 逐层附加上下文，在 `patch` 模块的公开函数与方法 (含 [`ConfigMerger::merge`])
+/// 处转换为该类型：能识别的错误保留为对应的变体，其余的原样包装在 `Other` 中，
+/// 可通过 `source()` 逐层取得原因。`utils` 中的底层工具函数与供更新器程序实现的 `updater` 框架
+/// 不在此列，仍返回 `anyhow::Result`。
+///
+/// [`ConfigMerger::merge`]: super::ConfigMerger::merge
+#[derive(Debug, Error)]
+pub enum PatchError {
+    /// 本地文件与补丁登记的原始哈希不一致，`reason` 说明为何视为错误
+    #[error("{path} 的校验和不匹配，可能已被修改 (期望 {expected}，实际 {actual}；{reason})")]
+    ChecksumMismatch {
+        path: String,
+        expected: HashResult,
+        actual: HashResult,
+        reason: MismatchReason,
+    },
+    /// 不是补丁包，或补丁包已损坏
+    #[error("不是有效的补丁包 ({reason}): {path:?}")]
+    InvalidArchive { path: PathBuf, reason: ArchiveIssue },
+    /// 补丁包中没有 `checksums.toml`
+    #[error("补丁包缺少 checksums.toml，可能不是由 dft 生成的补丁包")]
+    MissingChecksums,
+    /// 要应用补丁的目标目录不存在
+    #[error("目标目录不存在: {0:?}")]
+    TargetNotFound(PathBuf),
+    /// 读写文件失败
+    #[error("{message}")]
+    Io {
+        message: String,
+        #[source]
+        source: io::Error,
+    },
+    /// 其他错误，保留完整的上下文链
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+/// 校验和不匹配被视为错误的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum MismatchReason {
+    /// 警告策略把 `checksum-mismatch` 设为错误，出错前的条目可能已应用
+    #[error("`checksum-mismatch` 已设为错误")]
+    Denied,
+    /// 严格模式在修改目标目录前核对原始哈希，目标目录未做任何修改
+    #[error("严格模式，未做任何修改")]
+    Strict,
+}
+
+/// 补丁包无法解开的原因
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ArchiveIssue {
+    /// 文件为空，通常是下载或复制未完成
+    #[error("文件为空")]
+    Empty,
+    /// 不是 gzip、zstd 或 xz 压缩文件，`kind` 为按文件头识别出的类型
+    #[error("不是 gzip/zstd/xz 压缩文件，文件头识别为{kind}")]
+    NotCompressed { kind: String },
+    /// 能够解压，但内容不是 tar 归档
+    #[error("是 {format} 压缩文件，但内容不是 tar 归档")]
+    NotTar { format: CompressionFormat },
+    /// 压缩数据或 tar 归档中途损坏，例如文件被截断
+    #[error("数据已损坏: {0}")]
+    Corrupt(String),
+}
+
+pub type PatchResult<T> = std::result::Result<T, PatchError>;
+
+impl From<io::Error> for PatchError {
+    fn from(source: io::Error) -> Self {
+        Self::Io {
+            message: source.to_string(),
+            source,
+        }
+    }
+}
+
+impl From<anyhow::Error> for PatchError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<PatchError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        let message = format!("{:#}", err);
+        match err.downcast::<io::Error>() {
+            Ok(source) => Self::Io { message, source },
+            Err(err) => Self::Other(err),
+        }
+    }
+}
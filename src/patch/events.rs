@@ -0,0 +1,215 @@
+use anyhow::Context;
+use serde::Serialize;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::error::PatchResult;
+use super::report::StageTiming;
+use crate::utils::{now_rfc3339, status};
+
+/// 应用补丁过程中产生的事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PatchEvent {
+    FileRenamed {
+        from: String,
+        to: String,
+    },
+    FileDeleted {
+        path: String,
+        /// 文件被占用，已安排在重启后删除
+        pending_reboot: bool,
+    },
+    FileAdded {
+        path: String,
+        pending_reboot: bool,
+    },
+    FileModified {
+        path: String,
+        pending_reboot: bool,
+    },
+    /// 仅同步了权限与修改时间
+    AttributesUpdated {
+        path: String,
+    },
+    /// 目标文件与补丁记录的原始哈希不一致
+    ChecksumMismatch {
+        path: String,
+    },
+    /// 目标文件已是新内容，未写入
+    FileUnchanged {
+        path: String,
+    },
+    /// 受保护的路径，未修改
+    FileProtected {
+        path: String,
+    },
+    /// 仅适用于其他平台，未修改
+    FileSkipped {
+        path: String,
+        platform: String,
+    },
+    /// 配置文件已与本地改动按键合并 (紧随对应的 `FileModified`)
+    ConfigMerged {
+        path: String,
+        merger: String,
+    },
+    /// 配置文件无法按键合并，改为覆盖
+    ConfigMergeFailed {
+        path: String,
+        error: String,
+    },
+    /// 预演 (或目标目录只读) 时将要重命名的文件，以下 `*Planned` 事件均不代表已修改
+    RenamePlanned {
+        from: String,
+        to: String,
+    },
+    DeletePlanned {
+        path: String,
+    },
+    AddPlanned {
+        path: String,
+    },
+    ModifyPlanned {
+        path: String,
+    },
+    StageCompleted(StageTiming),
+}
+
+/// 事件订阅者，闭包 `Fn(&PatchEvent)` 可直接作为订阅者
+pub trait EventSink: Send + Sync {
+    fn handle(&self, event: &PatchEvent);
+}
+
+impl<F: Fn(&PatchEvent) + Send + Sync> EventSink for F {
+    fn handle(&self, event: &PatchEvent) {
+        self(event)
+    }
+}
+
+/// 把事件分发给所有订阅者
+///
+/// 默认包含把事件打印到控制台的 [`StatusPrinter`] (受静默模式控制)，
+/// 不需要控制台输出时用 [`EventBus::empty`] 代替。
+#[derive(Clone)]
+pub struct EventBus {
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl EventBus {
+    /// 不含任何订阅者的事件总线
+    pub fn empty() -> Self {
+        Self { sinks: Vec::new() }
+    }
+
+    pub fn subscribe(&mut self, sink: impl EventSink + 'static) {
+        self.sinks.push(Arc::new(sink));
+    }
+
+    pub fn emit(&self, event: PatchEvent) {
+        for sink in &self.sinks {
+            sink.handle(&event);
+        }
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let mut bus = Self::empty();
+        bus.subscribe(StatusPrinter);
+        bus
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EventBus({} 个订阅者)", self.sinks.len())
+    }
+}
+
+/// 以 CLI 的格式打印文件变更
+pub struct StatusPrinter;
+
+impl EventSink for StatusPrinter {
+    fn handle(&self, event: &PatchEvent) {
+        let reboot = |pending: bool| if pending { " (重启后生效)" } else { "" };
+        match event {
+            PatchEvent::FileRenamed { from, to } => status!("  > {} -> {}", from, to),
+            PatchEvent::FileDeleted {
+                path,
+                pending_reboot,
+            } => {
+                let suffix = if *pending_reboot {
+                    " (重启后删除)"
+                } else {
+                    ""
+                };
+                status!("  - {}{}", path, suffix)
+            }
+            PatchEvent::FileAdded {
+                path,
+                pending_reboot,
+            } => status!("  + {}{}", path, reboot(*pending_reboot)),
+            PatchEvent::FileModified {
+                path,
+                pending_reboot,
+            } => status!("  * {}{}", path, reboot(*pending_reboot)),
+            PatchEvent::AttributesUpdated { path } => status!("  ~ {} (属性)", path),
+            PatchEvent::ChecksumMismatch { path } => {
+                status!("  ! 警告: {} 的校验和不匹配，可能已被修改", path)
+            }
+            PatchEvent::FileUnchanged { path } => status!("  = {} (内容一致，已跳过)", path),
+            PatchEvent::FileProtected { path } => status!("  = {} (受保护，已跳过)", path),
+            PatchEvent::FileSkipped { path, platform } => {
+                status!("  ~ {} (仅适用于 {}，已跳过)", path, platform)
+            }
+            PatchEvent::ConfigMerged { merger, .. } => {
+                status!("    (已与本地改动按键合并: {})", merger)
+            }
+            PatchEvent::ConfigMergeFailed { path, error } => {
+                status!("  ! {} 无法按键合并，改为覆盖: {}", path, error)
+            }
+            PatchEvent::RenamePlanned { from, to } => status!("  > {} -> {}", from, to),
+            PatchEvent::DeletePlanned { path } => status!("  - {}", path),
+            PatchEvent::AddPlanned { path } => status!("  + {}", path),
+            PatchEvent::ModifyPlanned { path } => status!("  * {}", path),
+            PatchEvent::StageCompleted(_) => {}
+        }
+    }
+}
+
+/// 审计日志：每个事件追加一行带时间戳的 JSON
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> PatchResult<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("无法打开审计日志: {}", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl EventSink for AuditLog {
+    fn handle(&self, event: &PatchEvent) {
+        let mut line = serde_json::json!({ "time": now_rfc3339() });
+        if let (Some(object), Ok(serde_json::Value::Object(fields))) =
+            (line.as_object_mut(), serde_json::to_value(event))
+        {
+            object.extend(fields);
+        }
+        if let Ok(mut file) = self.file.lock() {
+            // 审计日志写入失败不影响补丁应用
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::Result;
+#[cfg(feature = "testing")]
+use std::cell::Cell;
+
+/// 注入到应用流程中的故障
+#[cfg(feature = "testing")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// 返回错误，模拟磁盘写满等可恢复的失败
+    Error,
+    /// panic，模拟进程中途崩溃
+    Panic,
+}
+
+#[cfg(feature = "testing")]
+thread_local! {
+    /// 距离注入点还剩的条目数与要注入的故障
+    static PLAN: Cell<Option<(usize, Fault)>> = const { Cell::new(None) };
+}
+
+/// 故障注入的守卫，离开作用域时撤销尚未触发的故障
+#[cfg(feature = "testing")]
+#[must_use]
+pub struct FaultGuard(());
+
+#[cfg(feature = "testing")]
+impl Drop for FaultGuard {
+    fn drop(&mut self) {
+        PLAN.set(None);
+    }
+}
+
+/// 在当前线程接下来的应用流程中，第 `after` 个条目 (移动、重命名、删除、新增、修改各算一个)
+/// 完成并记入检查点后触发 `fault`，只触发一次
+///
+/// 用于验证中断后的检查点续传与回滚；`after` 为 0 时按 1 处理。新增与修改的文件按检查点间隔
+/// 分批并行放置，同一批的文件在记录前都已写入，需要逐个中断时把检查点间隔设为 1。
+#[cfg(feature = "testing")]
+pub fn inject_fault(after: usize, fault: Fault) -> FaultGuard {
+    PLAN.set(Some((after.max(1), fault)));
+    FaultGuard(())
+}
+
+/// 应用流程中每完成一个条目调用一次，到达注入点时触发故障
+#[cfg(feature = "testing")]
+pub(crate) fn entry_done(kind: &str, path: &str) -> Result<()> {
+    match PLAN.get() {
+        Some((remaining, fault)) if remaining > 1 => PLAN.set(Some((remaining - 1, fault))),
+        Some((_, fault)) => {
+            PLAN.set(None);
+            match fault {
+                Fault::Error => anyhow::bail!("注入的故障: {} {}", kind, path),
+                Fault::Panic => panic!("注入的故障: {} {}", kind, path),
+            }
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "testing"))]
+#[inline]
+pub(crate) fn entry_done(_kind: &str, _path: &str) -> Result<()> {
+    Ok(())
+}
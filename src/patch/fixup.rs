@@ -0,0 +1,237 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::apply::{extract_patch, is_contained_path};
+use super::compression::CompressionFormat;
+use super::create::{PatchWriter, create_tar_gz};
+use super::delta::Delta;
+use super::error::PatchResult;
+use super::report::{FixupReport, Timings};
+use crate::utils::{HashResult, compute_file_hash, scan_directory, status};
+
+/// 修正包中的清单文件
+const MANIFEST_ENTRY: &str = "fixup.toml";
+/// 修正包中存放条目新内容 (完整内容或增量) 的目录
+const ENTRIES_DIR: &str = "entries";
+
+/// 增量中新数据超过条目大小的该比例时，直接存放完整内容
+const MAX_LITERAL_RATIO: f64 = 0.5;
+
+/// 修正包清单：记录针对哪个补丁包，以及需要替换、删除的补丁内条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixupManifest {
+    /// 被修正的补丁包的 SHA256
+    pub base_patch: HashResult,
+    /// 修正后补丁包的条目哈希，用于校验重建结果
+    pub changed: BTreeMap<String, FixupEntry>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixupEntry {
+    pub hash: HashResult,
+    /// 存放的是相对原条目的增量而非完整内容
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub delta: bool,
+}
+
+/// 比较两个补丁包的条目，生成把 `base` 修正为 `fixed` 的修正包
+pub fn create_fixup(base: &Path, fixed: &Path, output: &Path) -> PatchResult<FixupReport> {
+    status!("正在生成补丁修正包...");
+
+    let temp_dir = fixup_temp_dir()?;
+    let result = create_fixup_extracted(base, fixed, output, &temp_dir);
+    fs::remove_dir_all(&temp_dir)?;
+
+    let report = result?;
+    status!("补丁修正包生成完成: {}", output.display());
+    status!("  {}", report.summary());
+    Ok(report)
+}
+
+fn create_fixup_extracted(
+    base: &Path,
+    fixed: &Path,
+    output: &Path,
+    temp_dir: &Path,
+) -> Result<FixupReport> {
+    let mut timings = Timings::new();
+    let base_dir = temp_dir.join("base");
+    let fixed_dir = temp_dir.join("fixed");
+
+    let start = Instant::now();
+    extract_patch(base, &base_dir).with_context(|| format!("无法解包补丁: {:?}", base))?;
+    extract_patch(fixed, &fixed_dir).with_context(|| format!("无法解包补丁: {:?}", fixed))?;
+    let base_entries = entry_hashes(&base_dir)?;
+    let fixed_entries = entry_hashes(&fixed_dir)?;
+    timings.record("extract", start, 0);
+
+    let start = Instant::now();
+    let mut manifest = FixupManifest {
+        base_patch: compute_file_hash(base)?,
+        changed: BTreeMap::new(),
+        removed: base_entries
+            .keys()
+            .filter(|name| !fixed_entries.contains_key(*name))
+            .cloned()
+            .collect(),
+    };
+
+    // 修正包与修正后的补丁使用相同的压缩格式
+    let mut writer = PatchWriter::create(output, CompressionFormat::detect(fixed)?)?;
+    for (name, hash) in &fixed_entries {
+        if base_entries.get(name) == Some(hash) {
+            continue;
+        }
+        let entry_name = Path::new(ENTRIES_DIR).join(name);
+        let fixed_data = fs::read(fixed_dir.join(name))?;
+        let delta = if base_entries.contains_key(name) {
+            Some(Delta::encode(&fs::read(base_dir.join(name))?, &fixed_data))
+                .filter(|d| d.literal_len() as f64 <= fixed_data.len() as f64 * MAX_LITERAL_RATIO)
+        } else {
+            None
+        };
+        match &delta {
+            Some(delta) => {
+                let mut data = Vec::new();
+                delta.write_to(&mut data)?;
+                writer.append_bytes(&entry_name, &data)?;
+            }
+            None => writer.append_bytes(&entry_name, &fixed_data)?,
+        }
+        manifest.changed.insert(
+            name.clone(),
+            FixupEntry {
+                hash: hash.clone(),
+                delta: delta.is_some(),
+            },
+        );
+    }
+    writer.append_bytes(
+        Path::new(MANIFEST_ENTRY),
+        toml::to_string_pretty(&manifest)?.as_bytes(),
+    )?;
+    let bytes = writer.bytes_written();
+    writer.finish()?;
+    timings.record("package", start, bytes);
+
+    Ok(FixupReport {
+        output: output.to_path_buf(),
+        manifest,
+        timings,
+    })
+}
+
+/// 用修正包修正补丁包 `base`，重建后的补丁包写入 `output`
+pub fn apply_fixup(base: &Path, fixup: &Path, output: &Path) -> PatchResult<FixupReport> {
+    status!("正在应用补丁修正包...");
+
+    let temp_dir = fixup_temp_dir()?;
+    let result = apply_fixup_extracted(base, fixup, output, &temp_dir);
+    fs::remove_dir_all(&temp_dir)?;
+
+    let report = result?;
+    status!("补丁包修正完成: {}", output.display());
+    status!("  {}", report.summary());
+    Ok(report)
+}
+
+fn apply_fixup_extracted(
+    base: &Path,
+    fixup: &Path,
+    output: &Path,
+    temp_dir: &Path,
+) -> Result<FixupReport> {
+    let mut timings = Timings::new();
+    let base_dir = temp_dir.join("base");
+    let fixup_dir = temp_dir.join("fixup");
+
+    let start = Instant::now();
+    extract_patch(fixup, &fixup_dir).with_context(|| format!("无法解包修正包: {:?}", fixup))?;
+    let manifest: FixupManifest = toml::from_str(
+        &fs::read_to_string(fixup_dir.join(MANIFEST_ENTRY))
+            .with_context(|| format!("修正包缺少 {}", MANIFEST_ENTRY))?,
+    )
+    .with_context(|| format!("无法解析 {}", MANIFEST_ENTRY))?;
+
+    let base_hash = compute_file_hash(base)?;
+    if base_hash != manifest.base_patch {
+        bail!(
+            "修正包不适用于该补丁包: 需要 {}，实际为 {}",
+            manifest.base_patch,
+            base_hash
+        );
+    }
+    for name in manifest.changed.keys().chain(&manifest.removed) {
+        if !is_contained_path(Path::new(name)) {
+            bail!("修正包登记了非法的条目路径: {}", name);
+        }
+    }
+    extract_patch(base, &base_dir).with_context(|| format!("无法解包补丁: {:?}", base))?;
+    timings.record("extract", start, 0);
+
+    let start = Instant::now();
+    for name in &manifest.removed {
+        let path = base_dir.join(name);
+        if path.is_file() {
+            fs::remove_file(&path)?;
+        }
+    }
+    for (name, entry) in &manifest.changed {
+        let source = fixup_dir.join(ENTRIES_DIR).join(name);
+        let dest = base_dir.join(name);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if entry.delta {
+            let delta = Delta::read_from(&mut BufReader::new(
+                File::open(&source).with_context(|| format!("修正包中缺少条目: {}", name))?,
+            ))?;
+            let mut data = Vec::new();
+            delta.apply(&mut File::open(&dest)?, &mut data)?;
+            let mut out = BufWriter::new(File::create(&dest)?);
+            out.write_all(&data)?;
+            out.flush()?;
+        } else {
+            fs::copy(&source, &dest).with_context(|| format!("修正包中缺少条目: {}", name))?;
+        }
+
+        if compute_file_hash(&dest)? != entry.hash {
+            bail!("修正后的条目校验失败: {}", name);
+        }
+    }
+    timings.record("fixup", start, 0);
+
+    let start = Instant::now();
+    create_tar_gz(&base_dir, output, CompressionFormat::detect(fixup)?)?;
+    timings.record("package", start, fs::metadata(output)?.len());
+
+    Ok(FixupReport {
+        output: output.to_path_buf(),
+        manifest,
+        timings,
+    })
+}
+
+fn fixup_temp_dir() -> Result<PathBuf> {
+    let temp_dir = std::env::temp_dir().join(format!("dft_fixup_{}", std::process::id()));
+    if temp_dir.exists() {
+        fs::remove_dir_all(&temp_dir)?;
+    }
+    fs::create_dir_all(&temp_dir)?;
+    Ok(temp_dir)
+}
+
+/// 解包目录中所有条目 (以 `/` 分隔的路径) 的哈希
+fn entry_hashes(dir: &Path) -> Result<BTreeMap<String, HashResult>> {
+    Ok(scan_directory(dir)?
+        .into_iter()
+        .map(|(path, info)| (path.to_string_lossy().replace('\\', "/"), info.hash))
+        .collect())
+}
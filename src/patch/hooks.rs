@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+use super::report::HookReport;
+use crate::utils::status;
+
+/// 应用前后的外部命令失败时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HookPolicy {
+    /// 前置命令失败时不应用补丁，后置命令失败时返回错误
+    #[default]
+    Abort,
+    /// 记录到报告中并继续
+    Continue,
+}
+
+/// 通过系统 shell 执行 `command`，捕获其输出
+///
+/// 命令的工作目录为目标目录，环境变量 `DFT_TARGET_DIR`、`DFT_PATCH` 分别为目标目录与补丁包路径。
+pub(crate) fn run_hook(
+    stage: &str,
+    command: &str,
+    target_dir: &Path,
+    patch_path: &Path,
+) -> Result<HookReport> {
+    status!("正在执行 {} 命令: {}", stage, command);
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let output = shell
+        .arg(command)
+        .current_dir(target_dir)
+        .env("DFT_TARGET_DIR", target_dir)
+        .env("DFT_PATCH", patch_path)
+        .output()
+        .with_context(|| format!("无法执行 {} 命令: {}", stage, command))?;
+
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    for line in text.lines() {
+        status!("  | {}", line);
+    }
+    let report = HookReport {
+        stage: stage.to_string(),
+        command: command.to_string(),
+        exit_code: output.status.code(),
+        success: output.status.success(),
+        output: text,
+    };
+    if !report.success {
+        status!("  ! {} 命令失败: {}", stage, report.describe_status());
+    }
+    Ok(report)
+}
@@ -0,0 +1,54 @@
+use anyhow::{Result, bail};
+use std::path::Path;
+
+use super::error::PatchResult;
+use super::report::DirectoryInfo;
+use super::state::AppliedState;
+use crate::utils::{directory_fingerprint, scan_directory, status};
+
+/// 统计目录的文件数、总大小与 Merkle 指纹，并读取最近一次应用的补丁信息
+pub fn inspect_directory(dir: &Path) -> PatchResult<DirectoryInfo> {
+    Ok(inspect(dir)?)
+}
+
+fn inspect(dir: &Path) -> Result<DirectoryInfo> {
+    if !dir.is_dir() {
+        bail!("目录不存在: {:?}", dir);
+    }
+
+    let files = scan_directory(dir)?;
+    Ok(DirectoryInfo {
+        file_count: files.len(),
+        total_size: files.values().map(|info| info.fsize as u64).sum(),
+        fingerprint: directory_fingerprint(&files),
+        last_patch: AppliedState::load(dir)?,
+    })
+}
+
+/// 打印目录统计信息
+pub fn show_directory_info(dir: &Path) -> PatchResult<()> {
+    let info = inspect_directory(dir)?;
+
+    status!("目录: {}", dir.display());
+    status!("  文件数: {}", info.file_count);
+    status!("  总大小: {} 字节", info.total_size);
+    status!("  指纹: {}", info.fingerprint);
+
+    match &info.last_patch {
+        Some(state) => {
+            status!("最近应用的补丁:");
+            status!("  文件: {}", state.patch);
+            status!("  SHA256: {}", state.patch_hash);
+            status!("  应用时间: {}", state.applied_at);
+            status!(
+                "  新增: {} 个文件, 删除: {} 个文件, 修改: {} 个文件, 重命名: {} 个文件",
+                state.added,
+                state.deleted,
+                state.modified,
+                state.renamed
+            );
+        }
+        None => status!("未记录已应用的补丁"),
+    }
+    Ok(())
+}
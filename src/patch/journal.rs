@@ -0,0 +1,229 @@
+use anyhow::{Context, Result, bail};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::utils::{STATE_DIR, copy_file, status};
+
+const JOURNAL_DIR: &str = "journal";
+const JOURNAL_FILE: &str = "journal";
+const BACKUP_DIR: &str = "backup";
+
+/// 原子应用时新增与修改文件的暂存目录 (位于 `.dft` 下，与目标目录同一文件系统)
+pub(crate) const STAGING_DIR: &str = "staging";
+
+/// 日志中的一条记录
+enum Entry {
+    /// 原文件已移入 `backup/`，回滚时放回原处
+    Backup(String),
+    /// 原本不存在的文件，回滚时删除
+    Create(String),
+}
+
+/// 原子应用的回滚日志，保存在 `.dft/journal`
+///
+/// 每个将被覆盖、删除或移走的文件在变更前移入 `backup/`，日志中记一行 `backup\t路径`；
+/// 原本不存在的文件记为 `create\t路径`。先写日志再变更，出错或 panic 时按相反顺序撤销，
+/// 成功后删除。进程被终止时日志保留，下次应用前先回滚。
+pub(crate) struct Journal {
+    target_dir: PathBuf,
+    dir: PathBuf,
+    file: Option<File>,
+    entries: Vec<Entry>,
+    /// 已备份原文件的路径，同一路径再次变更时不能覆盖备份
+    backed_up: HashSet<String>,
+}
+
+impl Journal {
+    /// 在目标目录中开始新的日志
+    pub fn open(target_dir: &Path) -> Result<Self> {
+        let dir = target_dir.join(STATE_DIR).join(JOURNAL_DIR);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).with_context(|| format!("无法创建回滚日志: {:?}", dir))?;
+        let file = File::create(dir.join(JOURNAL_FILE))?;
+        Ok(Self {
+            target_dir: target_dir.to_path_buf(),
+            dir,
+            file: Some(file),
+            entries: Vec::new(),
+            backed_up: HashSet::new(),
+        })
+    }
+
+    /// 上次原子应用中途被终止时留下了日志，先回滚到应用前的状态；返回是否做了回滚
+    pub fn recover(target_dir: &Path) -> Result<bool> {
+        let dir = target_dir.join(STATE_DIR).join(JOURNAL_DIR);
+        let path = dir.join(JOURNAL_FILE);
+        if !path.is_file() {
+            return Ok(false);
+        }
+        status!("发现上次未完成的原子应用，正在回滚...");
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("无法读取回滚日志: {:?}", path))?;
+        let mut entries = Vec::new();
+        for line in content.lines() {
+            entries.push(match line.split_once('\t') {
+                Some(("backup", path)) => Entry::Backup(path.to_string()),
+                Some(("create", path)) => Entry::Create(path.to_string()),
+                _ => bail!("回滚日志已损坏: {:?}", line),
+            });
+        }
+        let mut journal = Self {
+            target_dir: target_dir.to_path_buf(),
+            dir,
+            file: None,
+            entries,
+            backed_up: HashSet::new(),
+        };
+        journal.rollback()?;
+        Ok(true)
+    }
+
+    /// 即将覆盖或删除 `path`：原文件移入备份 (之后该路径空出)，不存在时记为新建
+    pub fn preserve(&mut self, path: &str) -> Result<()> {
+        let target_path = self.target_dir.join(path);
+        if target_path.symlink_metadata().is_err() {
+            return self.log(Entry::Create(path.to_string()));
+        }
+        // 本次应用写入过的内容不需要备份，回滚时删除后再放回更早的备份
+        if self.backed_up.contains(path) {
+            self.log(Entry::Create(path.to_string()))?;
+            fs::remove_file(&target_path)?;
+            return Ok(());
+        }
+        let backup = self.backup_path(path);
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.log(Entry::Backup(path.to_string()))?;
+        fs::rename(&target_path, &backup)
+            .with_context(|| format!("无法备份文件: {:?}", target_path))?;
+        self.backed_up.insert(path.to_string());
+        Ok(())
+    }
+
+    /// 即将原地改动 `path` (如权限) 或把它移走：保留一份副本，原文件不动
+    pub fn keep_copy(&mut self, path: &str) -> Result<()> {
+        let target_path = self.target_dir.join(path);
+        if self.backed_up.contains(path) || !target_path.is_file() {
+            return Ok(());
+        }
+        let backup = self.backup_path(path);
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.log(Entry::Backup(path.to_string()))?;
+        // 不能用硬链接：共享 inode 时原地修改的权限与修改时间也会出现在副本上，回滚无法撤销。
+        // copy_file 保留权限，修改时间另行复制
+        let modified = fs::metadata(&target_path)?.modified()?;
+        copy_file(&target_path, &backup)?;
+        File::options()
+            .write(true)
+            .open(&backup)
+            .or_else(|_| File::open(&backup))
+            .and_then(|file| file.set_modified(modified))
+            .with_context(|| format!("无法保留原文件的修改时间: {:?}", target_path))?;
+        self.backed_up.insert(path.to_string());
+        Ok(())
+    }
+
+    /// 应用成功，删除日志、备份与暂存目录
+    pub fn commit(mut self) -> Result<()> {
+        self.file = None;
+        self.entries.clear();
+        self.remove_state()
+    }
+
+    /// 按相反顺序撤销所有记录的变更，然后删除日志
+    fn rollback(&mut self) -> Result<()> {
+        self.file = None;
+        let backed_up: HashSet<String> = self
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Backup(path) => Some(path.clone()),
+                Entry::Create(_) => None,
+            })
+            .collect();
+        let mut touched_dirs = Vec::new();
+        while let Some(entry) = self.entries.pop() {
+            let (Entry::Backup(path) | Entry::Create(path)) = &entry;
+            let target_path = self.target_dir.join(path);
+            // 备份已不存在说明原文件已放回 (上次回滚中途失败)，不能再删除
+            let restored = !self.backup_path(path).exists();
+            let written = match entry {
+                Entry::Backup(_) => !restored,
+                Entry::Create(_) => !(backed_up.contains(path) && restored),
+            };
+            if written && target_path.symlink_metadata().is_ok() {
+                fs::remove_file(&target_path)
+                    .with_context(|| format!("无法撤销写入的文件: {:?}", target_path))?;
+            }
+            if let Entry::Backup(path) = &entry {
+                let backup = self.backup_path(path);
+                if backup.exists() {
+                    if let Some(parent) = target_path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::rename(&backup, &target_path)
+                        .with_context(|| format!("无法恢复原文件: {:?}", target_path))?;
+                }
+            }
+            touched_dirs.extend(target_path.parent().map(Path::to_path_buf));
+        }
+        // 清理应用时新建、回滚后变空的目录
+        touched_dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        for mut dir in touched_dirs {
+            while dir.starts_with(&self.target_dir) && dir != self.target_dir {
+                if fs::remove_dir(&dir).is_err() {
+                    break;
+                }
+                dir.pop();
+            }
+        }
+        self.remove_state()
+    }
+
+    fn log(&mut self, entry: Entry) -> Result<()> {
+        let line = match &entry {
+            Entry::Backup(path) => format!("backup\t{}\n", path),
+            Entry::Create(path) => format!("create\t{}\n", path),
+        };
+        if let Some(file) = &mut self.file {
+            file.write_all(line.as_bytes())
+                .with_context(|| format!("无法写入回滚日志: {:?}", self.dir))?;
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn backup_path(&self, path: &str) -> PathBuf {
+        self.dir.join(BACKUP_DIR).join(path)
+    }
+
+    fn remove_state(&self) -> Result<()> {
+        let _ = fs::remove_dir_all(self.target_dir.join(STATE_DIR).join(STAGING_DIR));
+        fs::remove_dir_all(&self.dir)
+            .with_context(|| format!("无法删除回滚日志: {:?}", self.dir))?;
+        Ok(())
+    }
+}
+
+impl Drop for Journal {
+    /// 应用出错或 panic 时撤销已做的变更
+    fn drop(&mut self) {
+        if self.entries.is_empty() && self.file.is_none() {
+            return;
+        }
+        status!("应用未完成，正在回滚已做的变更...");
+        match self.rollback() {
+            Ok(()) => status!("已回滚，目标目录保持应用前的状态"),
+            Err(e) => status!(
+                "  ! 回滚失败: {:#}；日志保留在 {}，下次应用前会再次尝试",
+                e,
+                self.dir.display()
+            ),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::apply::{
+    check_patch_entries, check_section_conflicts, extract_patch, extract_patch_lenient,
+    load_checksums,
+};
+use super::dictionary::expand_dictionary_entries;
+use super::error::{PatchError, PatchResult};
+use super::metadata::{Checksums, Metadata};
+use super::reader::diagnose_archive;
+use crate::utils::status;
+
+/// 已解包到工作目录并完成解析的补丁包
+///
+/// 加载时会校验补丁中的文件都已在 checksums.toml 中登记，并还原 zstd 字典压缩的条目；
+/// 引用基础补丁的条目需要另外还原。离开作用域时删除工作目录。
+#[derive(Debug)]
+pub struct LoadedPatch {
+    pub metadata: Option<Metadata>,
+    pub checksums: Checksums,
+    workspace: PathBuf,
+    dictionary_compressed: usize,
+    extract_error: Option<String>,
+}
+
+impl LoadedPatch {
+    /// 把补丁包解包到 `workspace` (其中残留的旧内容会先被清除) 并解析
+    pub fn load(patch_path: &Path, workspace: impl Into<PathBuf>) -> PatchResult<Self> {
+        Ok(Self::load_impl(patch_path, workspace.into(), false)?)
+    }
+
+    /// 与 `load` 相同，但解包中途遇到损坏的数据时保留已解出的条目继续加载，
+    /// 错误信息可通过 `extract_error` 取得 (checksums.toml 仍然必须完好)
+    pub fn load_lenient(patch_path: &Path, workspace: impl Into<PathBuf>) -> PatchResult<Self> {
+        Ok(Self::load_impl(patch_path, workspace.into(), true)?)
+    }
+
+    fn load_impl(patch_path: &Path, workspace: PathBuf, lenient: bool) -> Result<Self> {
+        if workspace.exists() {
+            fs::remove_dir_all(&workspace)?;
+        }
+        fs::create_dir_all(&workspace)?;
+
+        // 先持有工作目录，后续步骤失败时同样会被清理
+        let mut patch = Self {
+            metadata: None,
+            checksums: Checksums::new(),
+            workspace,
+            dictionary_compressed: 0,
+            extract_error: None,
+        };
+        if lenient {
+            patch.extract_error = extract_patch_lenient(patch_path, &patch.workspace)?;
+        } else {
+            extract_patch(patch_path, &patch.workspace)?;
+        }
+
+        let metadata_path = patch.workspace.join("metadata.toml");
+        if metadata_path.exists() {
+            patch.metadata = Some(
+                toml::from_str(&fs::read_to_string(&metadata_path)?)
+                    .context("无法解析 metadata.toml")?,
+            );
+        }
+        if let Some(metadata) = &patch.metadata {
+            metadata.check_tool_version()?;
+        }
+        patch.checksums = match (load_checksums(&patch.workspace), &patch.extract_error) {
+            (Err(_), Some(error)) => {
+                return Err(PatchError::InvalidArchive {
+                    path: patch_path.to_path_buf(),
+                    reason: diagnose_archive(patch_path, error.clone()),
+                }
+                .into());
+            }
+            (checksums, _) => checksums?,
+        };
+        patch.checksums.apply_encodings()?;
+        check_section_conflicts(&patch.checksums)?;
+        for conflict in patch.checksums.normalize() {
+            status!("  ! {}", conflict);
+        }
+        check_patch_entries(&patch.workspace, &patch.checksums)?;
+        patch.dictionary_compressed = patch.checksums.dictionary_compressed.len();
+        expand_dictionary_entries(&patch.workspace, &mut patch.checksums)?;
+        Ok(patch)
+    }
+
+    /// 解包后的工作目录
+    pub fn workspace(&self) -> &Path {
+        &self.workspace
+    }
+
+    /// 加载时从 zstd 字典压缩还原的条目数
+    pub fn dictionary_compressed(&self) -> usize {
+        self.dictionary_compressed
+    }
+
+    /// 宽松加载时解包遇到的错误
+    pub fn extract_error(&self) -> Option<&str> {
+        self.extract_error.as_deref()
+    }
+
+    /// 条目在工作目录中的位置，例如 `entry_path("added", "mods/jei.jar")`
+    pub fn entry_path(&self, section: &str, path: &str) -> PathBuf {
+        self.workspace.join(section).join(path)
+    }
+}
+
+impl Drop for LoadedPatch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.workspace);
+    }
+}
@@ -1,64 +1,655 @@
-use anyhow::Result;
-use std::collections::HashSet;
-use std::fs;
-use std::path::Path;
+use anyhow::{Context, Result, bail};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::apply::{check_declared_paths, check_section_conflicts};
+use super::compression::{CompressionFormat, CompressionOptions};
+use super::config_merge::MERGE_BASE_DIR;
+use super::create::PatchWriter;
+use super::delta::Delta;
+use super::dictionary::DICTIONARY_ENTRY;
+use super::error::PatchResult;
+use super::metadata::{Checksums, FileSizes, Metadata, ModifiedChecksum, RenamedChecksum};
+use super::reader::{NOTICE_ENTRY, Patch, TarStream};
+use super::report::{MergeReport, Timings};
+use crate::utils::{parse_rfc3339, status};
+
+/// 合并补丁包的选项
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    /// 严格校验两个补丁首尾相接，不连续时拒绝合并
+    pub strict: bool,
+    /// 合并结果的压缩格式，未指定时沿用第一个补丁的格式
+    pub compression: Option<CompressionFormat>,
+    /// 合并结果的压缩级别与线程数
+    pub compression_options: CompressionOptions,
+}
+
+impl MergeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn with_compression(mut self, format: CompressionFormat) -> Self {
+        self.compression = Some(format);
+        self
+    }
 
-use super::apply::{extract_patch, load_checksums};
-use super::create::create_tar_gz;
-use super::metadata::{Checksums, Metadata, ModifiedChecksum};
+    pub fn with_compression_options(mut self, options: CompressionOptions) -> Self {
+        self.compression_options = options;
+        self
+    }
+}
 
 /// 合并两个补丁包
-pub fn merge_patches(first: &Path, second: &Path, output: &Path) -> Result<()> {
-    println!("正在合并补丁包...");
+pub fn merge_patches(first: &Path, second: &Path, output: &Path) -> PatchResult<MergeReport> {
+    merge_patches_with_options(first, second, output, &MergeOptions::new())
+}
+
+/// 按选项合并两个补丁包
+pub fn merge_patches_with_options(
+    first: &Path,
+    second: &Path,
+    output: &Path,
+    options: &MergeOptions,
+) -> PatchResult<MergeReport> {
+    Ok(merge(first, second, output, options)?)
+}
 
-    // 创建临时目录
+fn merge(
+    first: &Path,
+    second: &Path,
+    output: &Path,
+    options: &MergeOptions,
+) -> Result<MergeReport> {
+    status!("正在合并补丁包...");
+
+    // 创建临时目录 (清除上次失败残留的内容)
     let temp_dir = std::env::temp_dir().join(format!("dft_append_{}", std::process::id()));
-    let first_dir = temp_dir.join("first");
-    let second_dir = temp_dir.join("second");
-    let merged_dir = temp_dir.join("merged");
+    if temp_dir.exists() {
+        fs::remove_dir_all(&temp_dir)?;
+    }
+    fs::create_dir_all(&temp_dir)?;
+
+    // 只在需要还原重命名文件时暂存少量数据，其余条目直接从输入流写入输出
+    let result = merge_streaming(first, second, output, &temp_dir, options);
+
+    // 清理临时目录 (失败时同样清理)
+    fs::remove_dir_all(&temp_dir)?;
+
+    let report = result?;
+    status!("补丁包合并完成: {}", output.display());
+    status!("  {}", report.checksums.summary());
+    status!(
+        "  版本范围: {} → {}",
+        report.source_version.as_deref().unwrap_or("未知"),
+        report.target_version.as_deref().unwrap_or("未知")
+    );
+    Ok(report)
+}
 
-    fs::create_dir_all(&first_dir)?;
-    fs::create_dir_all(&second_dir)?;
-    fs::create_dir_all(&merged_dir)?;
+/// 合并结果中某个条目对应的处理方式 (键为输入补丁中的条目路径)
+pub(super) enum Action {
+    /// 写入合并结果中的该条目，字典压缩的数据先解压
+    Copy { output: String, compressed: bool },
+    /// 第一个补丁中的增量，留待与第二个补丁中的增量组合
+    KeepDelta(String),
+    /// 第二个补丁中增量的基础文件 (来自第一个补丁的新增/修改)，暂存到临时文件
+    SpoolBase { from: String, compressed: bool },
+    /// 第二个补丁中的增量，与第一个补丁中 `from` 的增量组合后写入
+    ComposeDelta { from: String, output: String },
+    /// 第二个补丁中的增量，作用于暂存的基础文件 `from`，还原为完整文件
+    RestoreAdded { from: String, output: String },
+}
 
-    // 解压两个补丁包
-    extract_patch(first, &first_dir)?;
-    extract_patch(second, &second_dir)?;
+/// 两次扫描之间需要保留的数据
+#[derive(Default)]
+pub(super) struct MergeState {
+    deltas: HashMap<String, Delta>,
+    bases: HashMap<String, PathBuf>,
+}
 
-    // 读取两个补丁包的校验和
-    let checksums1 = load_checksums(&first_dir)?;
-    let checksums2 = load_checksums(&second_dir)?;
+fn merge_streaming(
+    first: &Path,
+    second: &Path,
+    output: &Path,
+    temp_dir: &Path,
+    options: &MergeOptions,
+) -> Result<MergeReport> {
+    let mut timings = Timings::new();
+
+    // 只读取两个补丁的校验和，不解包
+    let stage = Instant::now();
+    let first_patch = Patch::open(first)?;
+    let second_patch = Patch::open(second)?;
+    let checksums1 = prepare_checksums(&first_patch)?;
+    let checksums2 = prepare_checksums(&second_patch)?;
+    if !checksums1.base_refs.is_empty() || !checksums2.base_refs.is_empty() {
+        bail!("不支持合并引用了基础补丁的补丁包，请先用完整补丁包重新生成");
+    }
+    check_chain(
+        first_patch.metadata(),
+        second_patch.metadata(),
+        &checksums1,
+        &checksums2,
+        options.strict,
+    )?;
+    check_mergeable_deltas(&checksums1, &checksums2)?;
+    let input_bytes = fs::metadata(first)?.len() + fs::metadata(second)?.len();
+    timings.record("scan", stage, input_bytes);
 
-    // 合并校验和
+    // 合并校验和，并据此确定需要从两个补丁中取出的条目
+    let stage = Instant::now();
     let merged_checksums = merge_checksums(&checksums1, &checksums2);
+    let (mut first_plan, mut second_plan) =
+        plan_merged_entries(&checksums1, &checksums2, &merged_checksums);
+
+    // 依次流式读取两个补丁，需要的条目直接写入输出归档
+    let compression = match options.compression {
+        Some(format) => format,
+        None => CompressionFormat::detect(first)?,
+    };
+    let mut writer = PatchWriter::create_with(output, compression, &options.compression_options)?;
+    let mut state = MergeState::default();
+    stream_entries(
+        &first_patch,
+        &checksums1,
+        &mut first_plan,
+        &mut writer,
+        &mut state,
+        temp_dir,
+    )?;
+    stream_entries(
+        &second_patch,
+        &checksums2,
+        &mut second_plan,
+        &mut writer,
+        &mut state,
+        temp_dir,
+    )?;
+    let mut missing: Vec<_> = first_plan.keys().chain(second_plan.keys()).collect();
+    if !missing.is_empty() {
+        missing.sort();
+        bail!("补丁包中缺少条目: {:?}", missing);
+    }
 
-    // 创建合并后的目录结构
-    setup_merged_directories(&merged_dir)?;
+    // 两个补丁的公告按先后顺序拼接，元数据与校验和最后写入
+    let notices: Vec<String> = [first_patch.notice()?, second_patch.notice()?]
+        .into_iter()
+        .flatten()
+        .map(|notice| notice.trim_end().to_string())
+        .collect();
+    if !notices.is_empty() {
+        writer.append_bytes(Path::new(NOTICE_ENTRY), notices.join("\n\n").as_bytes())?;
+    }
+    let mut metadata = merged_metadata(first_patch.metadata(), second_patch.metadata());
+    metadata.compression = compression;
+    writer.append_bytes(
+        Path::new("metadata.toml"),
+        toml::to_string_pretty(&metadata)?.as_bytes(),
+    )?;
+    writer.append_bytes(
+        Path::new("checksums.toml"),
+        toml::to_string_pretty(&merged_checksums)?.as_bytes(),
+    )?;
+    writer.finish()?;
+    timings.record("merge", stage, fs::metadata(output)?.len());
+
+    Ok(MergeReport {
+        output: output.to_path_buf(),
+        source_version: metadata.source_version,
+        target_version: metadata.target_version,
+        checksums: merged_checksums,
+        timings,
+    })
+}
 
-    // 复制文件
-    copy_merged_files(&first_dir, &second_dir, &merged_dir, &merged_checksums)?;
+/// 检查第二个补丁是否紧接在第一个补丁之后：版本号首尾相接，
+/// 且第二个补丁修改或重命名的文件的原始哈希与第一个补丁产生的内容一致
+///
+/// 严格模式下任何不一致都拒绝合并，否则只输出警告。
+fn check_chain(
+    first: Option<&Metadata>,
+    second: Option<&Metadata>,
+    checksums1: &Checksums,
+    checksums2: &Checksums,
+    strict: bool,
+) -> Result<()> {
+    let mut problems = Vec::new();
+    let first_target = first.and_then(|m| m.target_version.as_deref());
+    let second_source = second.and_then(|m| m.source_version.as_deref());
+    match (first_target, second_source) {
+        (Some(target), Some(source)) if target != source => problems.push(format!(
+            "第一个补丁的目标版本为 {}，第二个补丁的起始版本为 {}",
+            target, source
+        )),
+        (Some(_), Some(_)) => {}
+        _ if strict => problems.push("补丁未声明起始或目标版本，无法校验版本链".to_string()),
+        _ => {}
+    }
+
+    // 第一个补丁产生的文件内容
+    let produced = |path: &str| {
+        checksums1
+            .added
+            .get(path)
+            .or_else(|| checksums1.modified.get(path).map(|m| &m.modified))
+            .or_else(|| checksums1.renamed.get(path).map(|r| &r.modified))
+            .or_else(|| checksums1.moved.get(path).map(|m| &m.hash))
+    };
+    let removed: HashSet<&str> = checksums1
+        .deleted
+        .iter()
+        .map(String::as_str)
+        .chain(checksums1.renamed.values().map(|r| r.from.as_str()))
+        .chain(checksums1.moved.values().map(|m| m.from.as_str()))
+        .filter(|path| produced(path).is_none())
+        .collect();
+    let originals = checksums2
+        .modified
+        .iter()
+        .map(|(path, m)| (path.as_str(), &m.original))
+        .chain(
+            checksums2
+                .renamed
+                .values()
+                .map(|r| (r.from.as_str(), &r.original)),
+        )
+        .chain(
+            checksums2
+                .moved
+                .values()
+                .map(|m| (m.from.as_str(), &m.hash)),
+        );
+    let mut mismatched: Vec<_> = originals
+        .filter(|(path, original)| {
+            removed.contains(path) || produced(path).is_some_and(|hash| hash != *original)
+        })
+        .map(|(path, _)| path)
+        .collect();
+    mismatched.sort();
+    if !mismatched.is_empty() {
+        problems.push(format!(
+            "第二个补丁依赖的文件与第一个补丁的结果不一致: {:?}",
+            mismatched
+        ));
+    }
 
-    // 创建元数据
-    let metadata = Metadata::new().with_description("合并补丁包");
+    if strict && !problems.is_empty() {
+        bail!("补丁链不连续: {}", problems.join("；"));
+    }
+    for problem in problems {
+        status!("  ! 警告: {}", problem);
+    }
+    Ok(())
+}
 
-    // 写入元数据和校验和
-    write_merged_metadata(&merged_dir, &metadata, &merged_checksums)?;
+/// 第二个补丁中的增量必须能作用于第一个补丁中的完整内容或可组合的增量
+fn check_mergeable_deltas(checksums1: &Checksums, checksums2: &Checksums) -> Result<()> {
+    let mut unsupported: Vec<&String> = checksums2
+        .renamed
+        .values()
+        .map(|r| &r.from)
+        .filter(|from| checksums1.delta_modified.contains(from))
+        .chain(
+            checksums2
+                .delta_modified
+                .iter()
+                .filter(|path| checksums1.renamed.contains_key(*path)),
+        )
+        .collect();
+    if !unsupported.is_empty() {
+        unsupported.sort();
+        bail!(
+            "以下文件的增量依赖另一个补丁中以增量存放的内容，无法合并，请用完整补丁包重新生成: {:?}",
+            unsupported
+        );
+    }
+    Ok(())
+}
 
-    // 创建 tar.gz 包
-    create_tar_gz(&merged_dir, output)?;
+/// 合并结果的元数据：起始版本取第一个补丁，目标版本取第二个补丁，创建时间取较新者，
+/// 保证链式升级信息不丢失
+fn merged_metadata(first: Option<&Metadata>, second: Option<&Metadata>) -> Metadata {
+    let mut metadata = Metadata::new().with_description("合并补丁包");
+    metadata.source_version = first.and_then(|m| m.source_version.clone());
+    metadata.target_version = second.and_then(|m| m.target_version.clone());
+    metadata.author = second.and_then(|m| m.author.clone());
+    // 两个补丁的变更说明按先后顺序拼接
+    let notes: Vec<&str> = first
+        .into_iter()
+        .chain(second)
+        .filter_map(|m| m.notes.as_deref())
+        .map(str::trim_end)
+        .collect();
+    if !notes.is_empty() {
+        metadata.notes = Some(notes.join("\n\n"));
+    }
+    metadata.fast_mode = first.is_some_and(|m| m.fast_mode) || second.is_some_and(|m| m.fast_mode);
+
+    let newest = first
+        .into_iter()
+        .chain(second)
+        .filter_map(|m| Some((parse_rfc3339(&m.created_at)?, &m.created_at)))
+        .max();
+    if let Some((_, created_at)) = newest {
+        metadata.created_at = created_at.clone();
+    }
+    metadata
+}
 
-    // 清理临时目录
-    fs::remove_dir_all(&temp_dir)?;
+/// 与加载补丁时相同：按显式编码校正登记、规范化并检查路径
+pub(super) fn prepare_checksums(patch: &Patch) -> Result<Checksums> {
+    let mut checksums = patch.checksums().clone();
+    checksums.apply_encodings()?;
+    check_section_conflicts(&checksums)?;
+    checksums.normalize();
+    check_declared_paths(&checksums)?;
+    Ok(checksums)
+}
+
+/// 确定合并结果中每个条目来自哪个补丁的哪个条目
+fn plan_merged_entries(
+    checksums1: &Checksums,
+    checksums2: &Checksums,
+    merged: &Checksums,
+) -> (HashMap<String, Action>, HashMap<String, Action>) {
+    let mut first = HashMap::new();
+    let mut second = HashMap::new();
+    let copy = |checksums: &Checksums, path: &str, output: String| Action::Copy {
+        output,
+        compressed: checksums.dictionary_compressed.iter().any(|p| p == path),
+    };
+
+    for path in merged.added.keys() {
+        let output = format!("added/{}", path);
+        if checksums2.added.contains_key(path) {
+            second.insert(output.clone(), copy(checksums2, path, output));
+        } else if checksums2.delta_modified.contains(path) {
+            // 第二个补丁以增量修改第一个补丁新增的文件，还原为完整文件
+            let base = format!("added/{}", path);
+            first.insert(
+                base.clone(),
+                Action::SpoolBase {
+                    from: base.clone(),
+                    compressed: checksums1.dictionary_compressed.contains(path),
+                },
+            );
+            second.insert(
+                format!("modified/{}", path),
+                Action::RestoreAdded { from: base, output },
+            );
+        } else if checksums2.modified.contains_key(path) {
+            second.insert(format!("modified/{}", path), copy(checksums2, path, output));
+        } else if let Some(moved) = checksums2.moved.get(path) {
+            // 第二个补丁移动了第一个补丁的新增/修改文件，取其完整内容
+            let section = if checksums1.added.contains_key(&moved.from) {
+                "added"
+            } else {
+                "modified"
+            };
+            first.insert(
+                format!("{}/{}", section, moved.from),
+                copy(checksums1, &moved.from, output),
+            );
+        } else if let Some(renamed) = checksums2.renamed.get(path) {
+            // 第二个补丁以第一个补丁的新增/修改文件为源重命名，还原为完整文件
+            let section = if checksums1.added.contains_key(&renamed.from) {
+                "added"
+            } else {
+                "modified"
+            };
+            first.insert(
+                format!("{}/{}", section, renamed.from),
+                Action::SpoolBase {
+                    from: renamed.from.clone(),
+                    compressed: checksums1.dictionary_compressed.contains(&renamed.from),
+                },
+            );
+            second.insert(
+                format!("renamed/{}", path),
+                Action::RestoreAdded {
+                    from: renamed.from.clone(),
+                    output,
+                },
+            );
+        } else {
+            first.insert(output.clone(), copy(checksums1, path, output));
+        }
+    }
+
+    for path in merged.modified.keys() {
+        let output = format!("modified/{}", path);
+        if checksums2.delta_modified.contains(path) && checksums1.modified.contains_key(path) {
+            // 两个补丁都修改了该文件，第二个补丁中的增量作用于第一个补丁中的内容
+            if checksums1.delta_modified.contains(path) {
+                first.insert(output.clone(), Action::KeepDelta(output.clone()));
+                second.insert(
+                    output.clone(),
+                    Action::ComposeDelta {
+                        from: output.clone(),
+                        output,
+                    },
+                );
+            } else {
+                first.insert(
+                    output.clone(),
+                    Action::SpoolBase {
+                        from: output.clone(),
+                        compressed: checksums1.dictionary_compressed.contains(path),
+                    },
+                );
+                second.insert(
+                    output.clone(),
+                    Action::RestoreAdded {
+                        from: output.clone(),
+                        output,
+                    },
+                );
+            }
+        } else if checksums2.modified.contains_key(path) {
+            second.insert(output.clone(), copy(checksums2, path, output));
+        } else {
+            first.insert(output.clone(), copy(checksums1, path, output));
+        }
+    }
+
+    for path in &merged.merge_bases {
+        let output = format!("{}/{}", MERGE_BASE_DIR, path);
+        let plan = if checksums1.modified.contains_key(path) {
+            &mut first
+        } else {
+            &mut second
+        };
+        plan.insert(
+            output.clone(),
+            Action::Copy {
+                output,
+                compressed: false,
+            },
+        );
+    }
+
+    for path in merged.renamed.keys() {
+        let output = format!("renamed/{}", path);
+        match checksums2.renamed.get(path) {
+            Some(renamed) if checksums1.renamed.contains_key(&renamed.from) => {
+                // 连续两次重命名，组合两个增量
+                first.insert(
+                    format!("renamed/{}", renamed.from),
+                    Action::KeepDelta(renamed.from.clone()),
+                );
+                second.insert(
+                    output.clone(),
+                    Action::ComposeDelta {
+                        from: renamed.from.clone(),
+                        output,
+                    },
+                );
+            }
+            Some(_) => {
+                second.insert(
+                    output.clone(),
+                    Action::Copy {
+                        output,
+                        compressed: false,
+                    },
+                );
+            }
+            // 第一个补丁移动后又被第二个补丁以增量修改，增量同样适用于移动前的文件
+            None if checksums1.moved.contains_key(path) => {
+                second.insert(
+                    format!("modified/{}", path),
+                    Action::Copy {
+                        output,
+                        compressed: false,
+                    },
+                );
+            }
+            // 第二个补丁移动了第一个补丁中以增量存放的文件
+            None if checksums2.moved.contains_key(path) => {
+                let from = &checksums2.moved[path].from;
+                let section = if checksums1.renamed.contains_key(from) {
+                    "renamed"
+                } else {
+                    "modified"
+                };
+                first.insert(
+                    format!("{}/{}", section, from),
+                    Action::Copy {
+                        output,
+                        compressed: false,
+                    },
+                );
+            }
+            None => {
+                first.insert(
+                    output.clone(),
+                    Action::Copy {
+                        output,
+                        compressed: false,
+                    },
+                );
+            }
+        }
+    }
 
-    println!("补丁包合并完成: {}", output.display());
-    println!("  {}", merged_checksums.summary());
+    (first, second)
+}
 
+/// 顺序扫描补丁包，按计划处理需要的条目 (处理过的条目从计划中移除)
+pub(super) fn stream_entries(
+    patch: &Patch,
+    checksums: &Checksums,
+    plan: &mut HashMap<String, Action>,
+    writer: &mut PatchWriter,
+    state: &mut MergeState,
+    temp_dir: &Path,
+) -> Result<()> {
+    if plan.is_empty() {
+        return Ok(());
+    }
+    let dictionary = if checksums.dictionary_compressed.is_empty() {
+        None
+    } else {
+        let mut data = Vec::new();
+        patch
+            .read_entry(DICTIONARY_ENTRY)
+            .context("补丁中缺少 zstd 字典")?
+            .read_to_end(&mut data)?;
+        Some(data)
+    };
+    let expand = |data: &mut dyn Read, path: &str| -> Result<Vec<u8>> {
+        let dictionary = dictionary.as_deref().context("补丁中缺少 zstd 字典")?;
+        let mut decoder =
+            zstd::stream::read::Decoder::with_dictionary(BufReader::new(data), dictionary)?;
+        let mut expanded = Vec::new();
+        decoder
+            .read_to_end(&mut expanded)
+            .with_context(|| format!("无法解压字典压缩的文件: {}", path))?;
+        Ok(expanded)
+    };
+
+    let mut stream = TarStream::open(patch.path())?;
+    while let Some(entry) = stream.next_entry()? {
+        let Some(action) = plan.remove(&entry.path) else {
+            stream.skip_data(entry.size)?;
+            continue;
+        };
+        stream.with_data(entry.size, |data| {
+            match action {
+                Action::Copy {
+                    output,
+                    compressed: false,
+                } => {
+                    writer.append_reader(Path::new(&output), entry.size, data)?;
+                }
+                Action::Copy {
+                    output,
+                    compressed: true,
+                } => {
+                    let expanded = expand(data, &entry.path)?;
+                    writer.append_bytes(Path::new(&output), &expanded)?;
+                }
+                Action::KeepDelta(from) => {
+                    state
+                        .deltas
+                        .insert(from, Delta::read_from(&mut BufReader::new(data))?);
+                }
+                Action::SpoolBase { from, compressed } => {
+                    let spool = temp_dir.join(format!("base_{}", state.bases.len()));
+                    if compressed {
+                        fs::write(&spool, expand(data, &entry.path)?)?;
+                    } else {
+                        io::copy(data, &mut File::create(&spool)?)?;
+                    }
+                    state.bases.insert(from, spool);
+                }
+                Action::ComposeDelta { from, output } => {
+                    let first = state
+                        .deltas
+                        .remove(&from)
+                        .context("缺少第一个补丁中的增量")?;
+                    let mut composed = Vec::new();
+                    first
+                        .compose(&Delta::read_from(&mut BufReader::new(data))?)
+                        .write_to(&mut composed)?;
+                    writer.append_bytes(Path::new(&output), &composed)?;
+                }
+                Action::RestoreAdded { from, output } => {
+                    let base = state.bases.get(&from).context("缺少重命名的源文件")?;
+                    let restored = temp_dir.join("restored");
+                    let mut out = BufWriter::new(File::create(&restored)?);
+                    Delta::read_from(&mut BufReader::new(data))?
+                        .apply(&mut File::open(base)?, &mut out)?;
+                    out.flush()?;
+                    drop(out);
+                    writer.append_file(Path::new(&output), &restored)?;
+                }
+            }
+            Ok(())
+        })?;
+    }
     Ok(())
 }
 
 fn merge_checksums(checksums1: &Checksums, checksums2: &Checksums) -> Checksums {
     let mut merged = Checksums::new();
-    let second_deleted: HashSet<_> = checksums2.deleted.iter().collect();
+    // 第二个补丁中被重命名走的文件，对第一个补丁而言等同于被删除
+    let second_deleted: HashSet<_> = checksums2
+        .deleted
+        .iter()
+        .chain(checksums2.renamed.values().map(|r| &r.from))
+        .chain(checksums2.moved.values().map(|m| &m.from))
+        .collect();
 
     // 处理第一个补丁的新增文件
     merge_added_files(&mut merged, checksums1, checksums2, &second_deleted);
@@ -76,6 +667,110 @@ fn merge_checksums(checksums1: &Checksums, checksums2: &Checksums) -> Checksums
     // 处理删除文件
     merge_deleted_files(&mut merged, checksums1, checksums2);
 
+    // 处理重命名文件
+    merge_renamed_files(&mut merged, checksums1, checksums2);
+
+    // 处理移动文件
+    merge_moved_files(&mut merged, checksums1, checksums2);
+
+    // 保留仍在合并结果中的条目的平台声明
+    for (path, tag) in checksums1.platforms.iter().chain(&checksums2.platforms) {
+        if merged.added.contains_key(path)
+            || merged.modified.contains_key(path)
+            || merged.deleted.contains(path)
+        {
+            merged.platforms.insert(path.clone(), tag.clone());
+        }
+    }
+
+    // 属性以后一个补丁为准；第二个补丁改动了内容的文件，属性随新内容一起由其决定
+    for (path, attributes) in checksums1.attributes.iter().chain(&checksums2.attributes) {
+        if !second_deleted.contains(path) {
+            merged.attributes.insert(path.clone(), *attributes);
+        }
+    }
+    for path in checksums2.modified.keys().chain(checksums2.added.keys()) {
+        if !checksums2.attributes.contains_key(path) {
+            merged.attributes.remove(path);
+        }
+    }
+
+    // 大小变化：旧大小取第一个补丁登记的值，新大小取第二个补丁登记的值
+    merged.sizes = checksums1.sizes.clone();
+    for (path, sizes) in &checksums2.sizes {
+        let from = match (checksums2.renamed.get(path), checksums2.moved.get(path)) {
+            (Some(renamed), _) => &renamed.from,
+            (_, Some(moved)) => &moved.from,
+            _ => path,
+        };
+        let old = checksums1
+            .sizes
+            .get(from)
+            .map_or(sizes.old, |first| first.old);
+        merged
+            .sizes
+            .insert(path.clone(), FileSizes::new(old, sizes.new));
+    }
+    let deleted: HashSet<&String> = merged.deleted.iter().collect();
+    let present = |path: &String| {
+        merged.added.contains_key(path)
+            || merged.modified.contains_key(path)
+            || merged.renamed.contains_key(path)
+            || merged.moved.contains_key(path)
+            || deleted.contains(path)
+    };
+    let sizes = std::mem::take(&mut merged.sizes);
+    merged.sizes = sizes
+        .into_iter()
+        .filter(|(path, _)| present(path))
+        .collect();
+
+    // 全量清单描述最终状态，以第二个补丁为准
+    merged.manifest = checksums2.manifest.clone();
+
+    // 合并基准须来自登记该文件原始哈希的补丁
+    merged.merge_bases = merged
+        .modified
+        .keys()
+        .filter(|path| {
+            let owner = if checksums1.modified.contains_key(*path) {
+                checksums1
+            } else {
+                checksums2
+            };
+            owner.merge_bases.contains(path)
+        })
+        .cloned()
+        .collect();
+
+    // 第二个补丁的增量作用于第一个补丁的完整内容时已还原为完整文件，其余增量保持为增量
+    merged.delta_modified = merged
+        .modified
+        .keys()
+        .filter(|path| {
+            let first_delta = checksums1.delta_modified.contains(path);
+            if checksums2.modified.contains_key(*path) {
+                checksums2.delta_modified.contains(path)
+                    && (first_delta || !checksums1.modified.contains_key(*path))
+            } else {
+                first_delta
+            }
+        })
+        .cloned()
+        .collect();
+
+    // 整体替换的目录取两个补丁的并集
+    merged.replace_dirs = checksums1
+        .replace_dirs
+        .iter()
+        .chain(&checksums2.replace_dirs)
+        .cloned()
+        .collect();
+
+    for conflict in merged.normalize() {
+        status!("  ! {}", conflict);
+    }
+    merged.record_encodings();
     merged
 }
 
@@ -120,9 +815,11 @@ fn merge_modified_files(
         }
         if let Some(second_checksum) = checksums2.modified.get(path) {
             // 两次都被修改，合并为一次修改
+            // 第一个补丁中兼容多个基线的文件是完整存放的，合并结果同样兼容这些基线
             merged.modified.insert(
                 path.clone(),
-                ModifiedChecksum::new(checksum.original.clone(), second_checksum.modified.clone()),
+                ModifiedChecksum::new(checksum.original.clone(), second_checksum.modified.clone())
+                    .with_alternatives(checksum.alternatives.clone()),
             );
         } else {
             merged.modified.insert(path.clone(), checksum.clone());
@@ -130,7 +827,11 @@ fn merge_modified_files(
     }
 
     for (path, checksum) in &checksums2.modified {
-        if !merged.modified.contains_key(path) && !merged.added.contains_key(path) {
+        if !merged.modified.contains_key(path)
+            && !merged.added.contains_key(path)
+            && !checksums1.renamed.contains_key(path)
+            && !checksums1.moved.contains_key(path)
+        {
             merged.modified.insert(path.clone(), checksum.clone());
         }
     }
@@ -138,7 +839,10 @@ fn merge_modified_files(
 
 fn merge_deleted_files(merged: &mut Checksums, checksums1: &Checksums, checksums2: &Checksums) {
     for path in &checksums1.deleted {
-        if checksums2.added.contains_key(path) {
+        if checksums2.added.contains_key(path)
+            || checksums2.renamed.contains_key(path)
+            || checksums2.moved.contains_key(path)
+        {
             // 删除后又添加，简化处理为添加
             continue;
         }
@@ -148,83 +852,134 @@ fn merge_deleted_files(merged: &mut Checksums, checksums1: &Checksums, checksums
     }
 
     for path in &checksums2.deleted {
-        if !merged.deleted.contains(path) && !checksums1.added.contains_key(path) {
+        if !merged.deleted.contains(path)
+            && !checksums1.added.contains_key(path)
+            && !checksums1.renamed.contains_key(path)
+            && !checksums1.moved.contains_key(path)
+        {
             merged.deleted.push(path.clone());
         }
     }
 }
 
-fn setup_merged_directories(merged_dir: &Path) -> Result<()> {
-    fs::create_dir_all(merged_dir.join("added"))?;
-    fs::create_dir_all(merged_dir.join("modified"))?;
-    fs::create_dir_all(merged_dir.join("deleted"))?;
-    Ok(())
-}
-
-fn copy_merged_files(
-    first_dir: &Path,
-    second_dir: &Path,
-    merged_dir: &Path,
-    checksums: &Checksums,
-) -> Result<()> {
-    let merged_added = merged_dir.join("added");
-    let merged_modified = merged_dir.join("modified");
-
-    // 复制新增文件
-    for path in checksums.added.keys() {
-        let source = find_added_source(first_dir, second_dir, path);
-        let dest = merged_added.join(path);
+fn merge_renamed_files(merged: &mut Checksums, checksums1: &Checksums, checksums2: &Checksums) {
+    for (path, renamed) in &checksums1.renamed {
+        let second_rename = checksums2.renamed.iter().find(|(_, r)| &r.from == path);
+        if let Some((next_path, next)) = second_rename {
+            // 连续两次重命名，合并为一次 (增量在写入文件时组合)
+            merged.renamed.insert(
+                next_path.clone(),
+                RenamedChecksum::new(
+                    renamed.from.clone(),
+                    renamed.original.clone(),
+                    next.modified.clone(),
+                ),
+            );
+        } else if let Some(second_modified) = checksums2.modified.get(path) {
+            // 重命名后又被修改，以第二个补丁中的完整文件作为新增
+            merged
+                .added
+                .insert(path.clone(), second_modified.modified.clone());
+            push_deleted(merged, &renamed.from);
+        } else if checksums2.deleted.contains(path) {
+            push_deleted(merged, &renamed.from);
+        } else if let Some((next_path, _)) = checksums2.moved.iter().find(|(_, m)| &m.from == path)
+        {
+            // 重命名后又被移动，合并为一次重命名
+            merged.renamed.insert(next_path.clone(), renamed.clone());
+        } else {
+            merged.renamed.insert(path.clone(), renamed.clone());
+        }
+    }
 
-        if let Some(parent) = dest.parent() {
-            fs::create_dir_all(parent)?;
+    for (path, renamed) in &checksums2.renamed {
+        if checksums1.renamed.contains_key(&renamed.from) {
+            continue;
         }
-        if source.exists() {
-            fs::copy(&source, &dest)?;
+        if checksums1.added.contains_key(&renamed.from)
+            || checksums1.modified.contains_key(&renamed.from)
+        {
+            // 源文件来自第一个补丁，合并时还原为完整的新增文件
+            merged.added.insert(path.clone(), renamed.modified.clone());
+        } else if let Some(moved) = checksums1.moved.get(&renamed.from) {
+            // 移动后又被重命名，增量同样适用于移动前的文件
+            merged.renamed.insert(
+                path.clone(),
+                RenamedChecksum::new(
+                    moved.from.clone(),
+                    moved.hash.clone(),
+                    renamed.modified.clone(),
+                ),
+            );
+        } else {
+            merged.renamed.insert(path.clone(), renamed.clone());
         }
     }
+}
 
-    // 复制修改文件
-    for path in checksums.modified.keys() {
-        let source = if second_dir.join("modified").join(path).exists() {
-            second_dir.join("modified").join(path)
+fn merge_moved_files(merged: &mut Checksums, checksums1: &Checksums, checksums2: &Checksums) {
+    for (path, moved) in &checksums1.moved {
+        let second_move = checksums2.moved.iter().find(|(_, m)| &m.from == path);
+        if let Some((next_path, _)) = second_move {
+            // 连续两次移动，合并为一次
+            merged.moved.insert(next_path.clone(), moved.clone());
+        } else if checksums2.renamed.values().any(|r| &r.from == path) {
+            // 已在重命名中处理
+        } else if let Some(second_modified) = checksums2.modified.get(path) {
+            if checksums2.delta_modified.contains(path) {
+                // 移动后又以增量修改，合并为一次重命名
+                merged.renamed.insert(
+                    path.clone(),
+                    RenamedChecksum::new(
+                        moved.from.clone(),
+                        moved.hash.clone(),
+                        second_modified.modified.clone(),
+                    ),
+                );
+            } else {
+                merged
+                    .added
+                    .insert(path.clone(), second_modified.modified.clone());
+                push_deleted(merged, &moved.from);
+            }
+        } else if checksums2.deleted.contains(path) {
+            push_deleted(merged, &moved.from);
         } else {
-            first_dir.join("modified").join(path)
-        };
+            merged.moved.insert(path.clone(), moved.clone());
+        }
+    }
 
-        let dest = merged_modified.join(path);
-        if let Some(parent) = dest.parent() {
-            fs::create_dir_all(parent)?;
+    for (path, moved) in &checksums2.moved {
+        if checksums1.moved.contains_key(&moved.from)
+            || checksums1.renamed.contains_key(&moved.from)
+        {
+            continue;
         }
-        if source.exists() {
-            fs::copy(&source, &dest)?;
+        if checksums1.added.contains_key(&moved.from) {
+            merged.added.insert(path.clone(), moved.hash.clone());
+        } else if let Some(first_modified) = checksums1.modified.get(&moved.from) {
+            if checksums1.delta_modified.contains(&moved.from) {
+                // 第一个补丁中的增量作用于移动前的旧文件，合并为一次重命名
+                merged.renamed.insert(
+                    path.clone(),
+                    RenamedChecksum::new(
+                        moved.from.clone(),
+                        first_modified.original.clone(),
+                        moved.hash.clone(),
+                    ),
+                );
+                merged.deleted.retain(|p| p != &moved.from);
+            } else {
+                merged.added.insert(path.clone(), moved.hash.clone());
+            }
+        } else {
+            merged.moved.insert(path.clone(), moved.clone());
         }
     }
-
-    Ok(())
 }
 
-fn find_added_source(first_dir: &Path, second_dir: &Path, path: &str) -> std::path::PathBuf {
-    if second_dir.join("added").join(path).exists() {
-        second_dir.join("added").join(path)
-    } else if second_dir.join("modified").join(path).exists() {
-        second_dir.join("modified").join(path)
-    } else {
-        first_dir.join("added").join(path)
+fn push_deleted(merged: &mut Checksums, path: &str) {
+    if !merged.added.contains_key(path) && !merged.deleted.iter().any(|p| p == path) {
+        merged.deleted.push(path.to_string());
     }
 }
-
-fn write_merged_metadata(
-    merged_dir: &Path,
-    metadata: &Metadata,
-    checksums: &Checksums,
-) -> Result<()> {
-    fs::write(
-        merged_dir.join("metadata.toml"),
-        toml::to_string_pretty(metadata)?,
-    )?;
-    fs::write(
-        merged_dir.join("checksums.toml"),
-        toml::to_string_pretty(checksums)?,
-    )?;
-    Ok(())
-}
@@ -1,26 +1,57 @@
+use anyhow::{Result, anyhow, bail};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
 
-use crate::utils::HashResult;
+use super::compression::CompressionFormat;
+use super::delta::FORMAT_VERSION as DELTA_FORMAT_VERSION;
+use super::dictionary::DICTIONARY_ENTRY;
+use super::error::PatchResult;
+use super::platform::Platform;
+use crate::utils::{FileAttributes, HashResult, now_rfc3339};
+
+/// 当前工具的版本，生成的补丁以此作为最低版本要求
+pub const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 /// 补丁包元数据
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Metadata {
     pub version: String,
     pub created_at: String,
     pub source_version: Option<String>,
     pub target_version: Option<String>,
     pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// 变更说明 (可为多行)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// 是否以快速模式 (按大小与修改时间判断未变更文件) 生成
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fast_mode: bool,
+    /// 补丁包的压缩格式 (应用时按文件头识别，此处仅供查看)
+    #[serde(default, skip_serializing_if = "CompressionFormat::is_gzip")]
+    pub compression: CompressionFormat,
+    /// 能正确处理该补丁的最低工具版本，旧版本读取时提示升级而不是产生错误的结果
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_tool_version: Option<String>,
 }
 
 impl Metadata {
     pub fn new() -> Self {
         Self {
             version: "1.0".to_string(),
-            created_at: chrono::Utc::now().to_rfc3339(),
+            created_at: now_rfc3339(),
             source_version: None,
             target_version: None,
             description: None,
+            author: None,
+            notes: None,
+            fast_mode: false,
+            compression: CompressionFormat::Gzip,
+            min_tool_version: Some(TOOL_VERSION.to_string()),
         }
     }
 
@@ -28,6 +59,53 @@ impl Metadata {
         self.description = Some(description.into());
         self
     }
+
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    pub fn with_notes(mut self, notes: impl Into<String>) -> Self {
+        self.notes = Some(notes.into());
+        self
+    }
+
+    pub fn with_fast_mode(mut self, fast_mode: bool) -> Self {
+        self.fast_mode = fast_mode;
+        self
+    }
+
+    /// 检查当前工具是否满足补丁的最低版本要求
+    pub fn check_tool_version(&self) -> PatchResult<()> {
+        let Some(required) = &self.min_tool_version else {
+            return Ok(());
+        };
+        let Some(required_version) = parse_version(required) else {
+            return Err(anyhow!("无法识别补丁要求的最低工具版本: {}", required).into());
+        };
+        if parse_version(TOOL_VERSION).is_some_and(|current| current < required_version) {
+            return Err(anyhow!(
+                "该补丁需要 dft {} 或更高版本 (当前为 {})，请升级后再试",
+                required,
+                TOOL_VERSION
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// 解析 `主.次.修订` 形式的版本号，忽略预发布与构建后缀，缺少的部分视为 0
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.trim().split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
 }
 
 impl Default for Metadata {
@@ -37,11 +115,52 @@ impl Default for Metadata {
 }
 
 /// 文件校验和信息
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Checksums {
     pub added: HashMap<String, HashResult>,
     pub modified: HashMap<String, ModifiedChecksum>,
     pub deleted: Vec<String>,
+    /// 重命名 (可能伴随少量修改) 的文件 (新路径 -> 重命名信息)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub renamed: HashMap<String, RenamedChecksum>,
+    /// 内容未变、只是换了路径的文件 (新路径 -> 旧路径与哈希)，补丁中不存放内容，应用时就地移动
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub moved: BTreeMap<String, MovedChecksum>,
+    /// 仅适用于特定平台的条目 (路径 -> 平台标签)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub platforms: HashMap<String, String>,
+    /// 以补丁内 zstd 字典压缩存放的新增/修改文件
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dictionary_compressed: Vec<String>,
+    /// 以相对目标目录中旧文件的增量存放的修改文件
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delta_modified: Vec<String>,
+    /// 引用的基础补丁的哈希，`base_refs` 中的文件内容需从该补丁取得
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_patch: Option<HashResult>,
+    /// 内容引用基础补丁的新增/修改文件 (路径 -> 引用信息)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub base_refs: HashMap<String, BaseRef>,
+    /// 新增/修改/重命名条目在补丁内的编码方式 (路径 -> 编码)，
+    /// 旧版本生成的补丁没有该字段，按上面的登记推断
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub encodings: HashMap<String, EntryEncoding>,
+    /// 内容未变、只需同步权限与修改时间的文件 (路径 -> 属性)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub attributes: HashMap<String, FileAttributes>,
+    /// 变更文件在源目录与目标目录中的大小 (路径 -> 大小，重命名按新路径登记)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sizes: BTreeMap<String, FileSizes>,
+    /// 整体替换的目录 (`/` 分隔)：应用时删除其中补丁未包含的文件
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replace_dirs: Vec<String>,
+    /// 全量清单：应用后目标目录应有的全部文件 (路径 -> 哈希)，`apply --sync` 据此删除未登记的文件
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub manifest: BTreeMap<String, HashResult>,
+    /// 应用时按键合并而非覆盖的修改文件，补丁中附带其旧版本 (`merge_base/`) 作为合并基准
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub merge_bases: Vec<String>,
 }
 
 impl Checksums {
@@ -50,28 +169,398 @@ impl Checksums {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.added.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+        self.added.is_empty()
+            && self.modified.is_empty()
+            && self.deleted.is_empty()
+            && self.renamed.is_empty()
+            && self.moved.is_empty()
+            && self.attributes.is_empty()
+    }
+
+    /// 路径是否位于整体替换的目录中
+    pub fn in_replace_dir(&self, path: &str) -> bool {
+        self.replace_dirs
+            .iter()
+            .any(|dir| Path::new(path).starts_with(dir))
+    }
+
+    /// 记录条目的适用平台 (若路径位于平台目录下)
+    pub fn record_platform(&mut self, path: &str) {
+        if let Some(platform) = Platform::detect(Path::new(path)) {
+            self.platforms
+                .insert(path.to_string(), platform.to_string());
+        }
+    }
+
+    /// 判断条目是否适用于给定平台，未声明平台的条目适用于所有平台
+    ///
+    /// 无法识别的平台标签在加载补丁时即被拒绝，这里不再视为适用于所有平台。
+    pub fn applies_to(&self, path: &str, platform: &Platform) -> bool {
+        match self.platforms.get(path) {
+            Some(tag) => Platform::parse(tag).is_some_and(|required| required.matches(platform)),
+            None => true,
+        }
+    }
+
+    /// 条目的编码方式：优先使用显式记录，否则按其余登记推断
+    pub fn encoding_of(&self, path: &str) -> Option<EntryEncoding> {
+        self.encodings
+            .get(path)
+            .cloned()
+            .or_else(|| self.inferred_encoding(path))
+    }
+
+    fn inferred_encoding(&self, path: &str) -> Option<EntryEncoding> {
+        if self.renamed.contains_key(path) || self.delta_modified.iter().any(|p| p == path) {
+            return Some(EntryEncoding::Delta {
+                version: DELTA_FORMAT_VERSION,
+            });
+        }
+        if !self.added.contains_key(path) && !self.modified.contains_key(path) {
+            return None;
+        }
+        Some(if let Some(base_ref) = self.base_refs.get(path) {
+            EntryEncoding::BaseRef {
+                delta: base_ref.delta,
+            }
+        } else if self.dictionary_compressed.iter().any(|p| p == path) {
+            EntryEncoding::ZstdDict {
+                dictionary: DICTIONARY_ENTRY.to_string(),
+            }
+        } else {
+            EntryEncoding::Store
+        })
+    }
+
+    /// 为所有新增/修改/重命名条目显式记录编码方式 (写出补丁包前调用)
+    pub fn record_encodings(&mut self) {
+        let paths: Vec<String> = self
+            .added
+            .keys()
+            .chain(self.modified.keys())
+            .chain(self.renamed.keys())
+            .cloned()
+            .collect();
+        self.encodings = paths
+            .into_iter()
+            .filter_map(|path| {
+                let encoding = self.inferred_encoding(&path)?;
+                Some((path, encoding))
+            })
+            .collect();
+    }
+
+    /// 按显式记录的编码方式校正其余登记，之后各登记即为权威信息，显式记录被清空
+    ///
+    /// 遇到当前版本不支持的编码或与条目类型矛盾的编码时报错，而不是错误地解码。
+    pub(crate) fn apply_encodings(&mut self) -> Result<()> {
+        for (path, encoding) in std::mem::take(&mut self.encodings) {
+            let is_content = self.added.contains_key(&path) || self.modified.contains_key(&path);
+            let is_renamed = self.renamed.contains_key(&path);
+            match encoding {
+                _ if !is_content && !is_renamed => {}
+                EntryEncoding::Store if is_content => {
+                    self.dictionary_compressed.retain(|p| *p != path);
+                    self.delta_modified.retain(|p| *p != path);
+                    self.base_refs.remove(&path);
+                }
+                EntryEncoding::ZstdDict { dictionary }
+                    if is_content && dictionary == DICTIONARY_ENTRY =>
+                {
+                    if !self.dictionary_compressed.contains(&path) {
+                        self.dictionary_compressed.push(path);
+                    }
+                }
+                EntryEncoding::Delta { version }
+                    if is_renamed || self.modified.contains_key(&path) =>
+                {
+                    if version != DELTA_FORMAT_VERSION {
+                        bail!("条目 {} 使用了不支持的增量格式版本: {}", path, version);
+                    }
+                    if !is_renamed && !self.delta_modified.contains(&path) {
+                        self.delta_modified.push(path);
+                    }
+                }
+                EntryEncoding::BaseRef { .. } if is_content => {
+                    if !self.base_refs.contains_key(&path) {
+                        bail!("条目 {} 标记为引用基础补丁，但缺少引用信息", path);
+                    }
+                }
+                EntryEncoding::Unknown => {
+                    bail!("条目 {} 使用了当前版本不支持的编码，请升级 dft", path)
+                }
+                encoding => bail!("条目 {} 的编码方式与条目类型不符: {:?}", path, encoding),
+            }
+        }
+        Ok(())
+    }
+
+    /// 规范化登记：去除重复项，裁决同一路径同时出现在多个类别中的冲突，并稳定排序列表
+    ///
+    /// 按文件的最终状态裁决：同时登记为新增与修改时按新增处理 (补丁中有完整内容)；
+    /// 删除的路径同时是新增、修改或重命名的目标时，文件最终存在，撤销删除。
+    /// 返回被裁决的冲突说明。
+    pub fn normalize(&mut self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+
+        let mut both: Vec<String> = self
+            .added
+            .keys()
+            .filter(|path| self.modified.contains_key(*path))
+            .cloned()
+            .collect();
+        both.sort();
+        for path in both {
+            self.modified.remove(&path);
+            conflicts.push(format!("{} 同时登记为新增与修改，按新增处理", path));
+        }
+
+        let mut seen = HashSet::new();
+        self.deleted.retain(|path| seen.insert(path.clone()));
+        self.deleted.retain(|path| {
+            let exists = self.added.contains_key(path)
+                || self.modified.contains_key(path)
+                || self.renamed.contains_key(path)
+                || self.moved.contains_key(path);
+            if exists {
+                conflicts.push(format!("{} 同时登记为删除与新增/修改，撤销删除", path));
+            }
+            !exists
+        });
+        self.deleted.sort();
+
+        self.dictionary_compressed.sort();
+        self.dictionary_compressed.dedup();
+        let modified = &self.modified;
+        self.delta_modified
+            .retain(|path| modified.contains_key(path));
+        self.delta_modified.sort();
+        self.delta_modified.dedup();
+        self.replace_dirs.sort();
+        self.replace_dirs.dedup();
+        self.merge_bases.retain(|path| modified.contains_key(path));
+        self.merge_bases.sort();
+        self.merge_bases.dedup();
+        let deleted = &self.deleted;
+        self.attributes.retain(|path, _| !deleted.contains(path));
+        conflicts
+    }
+
+    /// 按源目录与目标目录中的文件登记各变更条目的大小 (文件不存在时留空)
+    pub(crate) fn record_sizes(&mut self, source_dir: &Path, target_dir: &Path) {
+        let size = |dir: &Path, path: &str| fs::metadata(dir.join(path)).ok().map(|m| m.len());
+        let mut sizes = BTreeMap::new();
+        for path in self.added.keys() {
+            // 整体替换的目录中未变更的文件同样登记为新增，源目录中也有
+            let sizes_of = FileSizes::new(size(source_dir, path), size(target_dir, path));
+            sizes.insert(path.clone(), sizes_of);
+        }
+        for path in self.modified.keys() {
+            let sizes_of = FileSizes::new(size(source_dir, path), size(target_dir, path));
+            sizes.insert(path.clone(), sizes_of);
+        }
+        for path in &self.deleted {
+            sizes.insert(path.clone(), FileSizes::new(size(source_dir, path), None));
+        }
+        for (to, renamed) in &self.renamed {
+            let sizes_of = FileSizes::new(size(source_dir, &renamed.from), size(target_dir, to));
+            sizes.insert(to.clone(), sizes_of);
+        }
+        for (to, moved) in &self.moved {
+            let sizes_of = FileSizes::new(size(source_dir, &moved.from), size(target_dir, to));
+            sizes.insert(to.clone(), sizes_of);
+        }
+        self.sizes = sizes;
+    }
+
+    /// 登记的大小变化总量 (字节)
+    pub fn size_delta(&self) -> i64 {
+        self.sizes.values().map(FileSizes::delta).sum()
     }
 
     pub fn summary(&self) -> String {
         format!(
-            "新增: {} 个文件, 删除: {} 个文件, 修改: {} 个文件",
+            "新增: {} 个文件, 删除: {} 个文件, 修改: {} 个文件, 重命名: {} 个文件",
             self.added.len(),
             self.deleted.len(),
-            self.modified.len()
+            self.modified.len(),
+            self.renamed.len() + self.moved.len()
         )
     }
 }
 
+/// 文件变更前后的大小，不存在的一侧为 `None`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FileSizes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new: Option<u64>,
+}
+
+impl FileSizes {
+    pub fn new(old: Option<u64>, new: Option<u64>) -> Self {
+        Self { old, new }
+    }
+
+    /// 大小的增减 (字节)
+    pub fn delta(&self) -> i64 {
+        self.new.unwrap_or(0) as i64 - self.old.unwrap_or(0) as i64
+    }
+}
+
 /// 修改文件的校验和
+///
+/// 兼容多个基线的补丁中，`original` 在 checksums.toml 里写为哈希数组：
+/// 第一个为主基线，其余为 `alternatives`。这样的文件总是完整存放，不做增量。
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "ModifiedChecksumRepr", into = "ModifiedChecksumRepr")]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ModifiedChecksum {
     pub original: HashResult,
+    /// 其他可兼容基线中该文件的哈希，本地文件与任一原始哈希一致即可应用
+    pub alternatives: Vec<HashResult>,
     pub modified: HashResult,
 }
 
 impl ModifiedChecksum {
     pub fn new(original: HashResult, modified: HashResult) -> Self {
-        Self { original, modified }
+        Self {
+            original,
+            alternatives: Vec::new(),
+            modified,
+        }
+    }
+
+    pub fn with_alternatives(mut self, alternatives: Vec<HashResult>) -> Self {
+        self.alternatives = alternatives;
+        self
+    }
+
+    /// 主基线与其他基线的原始哈希
+    pub fn originals(&self) -> impl Iterator<Item = &HashResult> {
+        std::iter::once(&self.original).chain(&self.alternatives)
     }
+
+    /// 本地文件的哈希是否与任一原始哈希一致
+    pub fn matches_original(&self, hash: &HashResult) -> bool {
+        self.originals().any(|original| original == hash)
+    }
+}
+
+/// `ModifiedChecksum` 在 checksums.toml 中的形式
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+struct ModifiedChecksumRepr {
+    original: OriginalHashes,
+    modified: HashResult,
+}
+
+/// 单个原始哈希，或多个基线的原始哈希
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+enum OriginalHashes {
+    One(HashResult),
+    Many(Vec<HashResult>),
+}
+
+impl TryFrom<ModifiedChecksumRepr> for ModifiedChecksum {
+    type Error = String;
+
+    fn try_from(repr: ModifiedChecksumRepr) -> Result<Self, Self::Error> {
+        let (original, alternatives) = match repr.original {
+            OriginalHashes::One(original) => (original, Vec::new()),
+            OriginalHashes::Many(mut originals) => {
+                if originals.is_empty() {
+                    return Err("original 不能为空数组".to_string());
+                }
+                let original = originals.remove(0);
+                (original, originals)
+            }
+        };
+        Ok(Self::new(original, repr.modified).with_alternatives(alternatives))
+    }
+}
+
+impl From<ModifiedChecksum> for ModifiedChecksumRepr {
+    fn from(checksum: ModifiedChecksum) -> Self {
+        let original = if checksum.alternatives.is_empty() {
+            OriginalHashes::One(checksum.original)
+        } else {
+            let mut originals = vec![checksum.original];
+            originals.extend(checksum.alternatives);
+            OriginalHashes::Many(originals)
+        };
+        Self {
+            original,
+            modified: checksum.modified,
+        }
+    }
+}
+
+/// 重命名文件的校验和，`original` 为旧路径文件的哈希，`modified` 为新路径文件的哈希
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RenamedChecksum {
+    pub from: String,
+    pub original: HashResult,
+    pub modified: HashResult,
+}
+
+impl RenamedChecksum {
+    pub fn new(from: impl Into<String>, original: HashResult, modified: HashResult) -> Self {
+        Self {
+            from: from.into(),
+            original,
+            modified,
+        }
+    }
+}
+
+/// 移动的文件：`from` 为旧路径，`hash` 为移动前后相同的内容哈希
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MovedChecksum {
+    pub from: String,
+    pub hash: HashResult,
+}
+
+impl MovedChecksum {
+    pub fn new(from: impl Into<String>, hash: HashResult) -> Self {
+        Self {
+            from: from.into(),
+            hash,
+        }
+    }
+}
+
+/// 对基础补丁中条目的引用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BaseRef {
+    /// 基础补丁中的条目，例如 `modified/mods/foo.jar`
+    pub blob: String,
+    /// 为 `true` 时补丁内 `based/<路径>` 存放相对该条目的增量，否则内容与该条目完全相同
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub delta: bool,
+}
+
+/// 条目在补丁内的编码方式，apply 据此选择解码方式
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EntryEncoding {
+    /// 原样存放
+    Store,
+    /// 以补丁内的 zstd 字典 (`dictionary` 为字典条目名) 压缩存放
+    ZstdDict { dictionary: String },
+    /// 相对旧文件的增量 (`renamed/` 下为重命名前的文件，`modified/` 下为同路径的文件)
+    Delta { version: u8 },
+    /// 引用基础补丁中的条目，`delta` 为 `true` 时 `based/` 下存放相对该条目的增量
+    BaseRef { delta: bool },
+    /// 更新版本引入、当前版本无法解码的编码
+    #[serde(other)]
+    Unknown,
 }
@@ -0,0 +1,257 @@
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::mem::discriminant;
+use std::path::{Path, PathBuf};
+
+use super::create::{
+    CreateOptions, SharedEntries, compare_for_patch, package_patch, package_shared,
+};
+use super::diff::{DirectoryDiff, FileDiff};
+use super::error::PatchResult;
+use super::report::{MultiPatchReport, PlatformPatch, Timings};
+use crate::utils::{compute_file_hash, status};
+
+/// 多平台补丁的清单文件名，写在输出目录中
+pub const MULTI_MANIFEST: &str = "manifest.json";
+
+/// `diff-multi` 的配置文件 (TOML)
+///
+/// ```toml
+/// output_dir = "dist"
+///
+/// [[platform]]
+/// name = "windows"
+/// source = "v1/windows"
+/// target = "v2/windows"
+/// ```
+///
+/// 相对路径都相对于配置文件所在的目录。
+#[derive(Debug, Clone, Deserialize)]
+pub struct MultiSpec {
+    /// 补丁包与清单的输出目录，默认为配置文件所在的目录
+    #[serde(default)]
+    pub output_dir: PathBuf,
+    #[serde(rename = "platform")]
+    pub platforms: Vec<PlatformSpec>,
+}
+
+/// 一个平台的源目录与目标目录，补丁包命名为 `<name>.<扩展名>`
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlatformSpec {
+    pub name: String,
+    pub source: PathBuf,
+    pub target: PathBuf,
+}
+
+impl MultiSpec {
+    pub fn load(path: &Path) -> PatchResult<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("无法读取配置文件: {:?}", path))?;
+        let mut spec: Self =
+            toml::from_str(&content).with_context(|| format!("无法解析配置文件: {:?}", path))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        spec.output_dir = base.join(&spec.output_dir);
+        for platform in &mut spec.platforms {
+            platform.source = base.join(&platform.source);
+            platform.target = base.join(&platform.target);
+        }
+        Ok(spec)
+    }
+}
+
+/// 比较完成、等待打包的一个平台
+struct ComparedPlatform<'a> {
+    spec: &'a PlatformSpec,
+    options: CreateOptions,
+    directory_diff: DirectoryDiff,
+    timings: Timings,
+}
+
+/// 按配置为每个平台生成补丁包，并在输出目录写入统一的清单 [`MULTI_MANIFEST`]
+///
+/// 所有平台中内容相同的新增文件 (以及源、目标内容都相同的修改文件) 只压缩一次，
+/// 作为公共分卷拼接进每个补丁包；各补丁包仍可独立应用。
+pub fn create_multi_patches(
+    spec: &MultiSpec,
+    options: &CreateOptions,
+) -> PatchResult<MultiPatchReport> {
+    Ok(create_multi(spec, options)?)
+}
+
+fn create_multi(spec: &MultiSpec, options: &CreateOptions) -> Result<MultiPatchReport> {
+    if spec.platforms.is_empty() {
+        bail!("配置文件中没有任何平台");
+    }
+    let mut names = HashSet::new();
+    for platform in &spec.platforms {
+        if platform.name.is_empty() || platform.name.contains(['/', '\\']) {
+            bail!("无效的平台名称: {:?}", platform.name);
+        }
+        if !names.insert(platform.name.as_str()) {
+            bail!("平台名称重复: {}", platform.name);
+        }
+        for dir in [&platform.source, &platform.target] {
+            if !dir.is_dir() {
+                bail!("平台 {} 的目录不存在: {:?}", platform.name, dir);
+            }
+        }
+    }
+    fs::create_dir_all(&spec.output_dir)
+        .with_context(|| format!("无法创建输出目录: {:?}", spec.output_dir))?;
+
+    let mut compared = Vec::with_capacity(spec.platforms.len());
+    for platform in &spec.platforms {
+        status!("=== 平台 {} ===", platform.name);
+        let options = options
+            .resolve_gitignore(&platform.source, &platform.target)?
+            .into_owned();
+        let mut timings = Timings::new();
+        let directory_diff =
+            compare_for_patch(&platform.source, &platform.target, &options, &mut timings)?;
+        compared.push(ComparedPlatform {
+            spec: platform,
+            options,
+            directory_diff,
+            timings,
+        });
+    }
+
+    let work_dir = std::env::temp_dir().join(format!("dft_multi_{}", std::process::id()));
+    fs::create_dir_all(&work_dir)?;
+    let result = package_platforms(spec, compared, &work_dir);
+    let _ = fs::remove_dir_all(&work_dir);
+    let report = result?;
+
+    let manifest = spec.output_dir.join(MULTI_MANIFEST);
+    fs::write(&manifest, serde_json::to_string_pretty(&report)?)
+        .with_context(|| format!("无法写入清单: {:?}", manifest))?;
+    status!("清单已写入: {}", manifest.display());
+    Ok(report)
+}
+
+fn package_platforms(
+    spec: &MultiSpec,
+    compared: Vec<ComparedPlatform>,
+    work_dir: &Path,
+) -> Result<MultiPatchReport> {
+    let paths = shared_paths(&compared)?;
+    let shared = match compared.first() {
+        Some(first) if !paths.is_empty() => {
+            status!("正在打包 {} 个各平台共用的文件...", paths.len());
+            Some(package_shared(
+                &first.spec.source,
+                &first.spec.target,
+                &first.directory_diff,
+                paths,
+                &work_dir.join("shared"),
+                &first.options,
+            )?)
+        }
+        _ => None,
+    };
+    let mut shared_files: Vec<String> = shared
+        .iter()
+        .flat_map(|shared: &SharedEntries| &shared.paths)
+        .map(|path| path.to_string_lossy().replace('\\', "/"))
+        .collect();
+    shared_files.sort();
+
+    let mut patches = Vec::with_capacity(compared.len());
+    for platform in compared {
+        let spec_entry = platform.spec;
+        status!("=== 平台 {} ===", spec_entry.name);
+        let file = format!(
+            "{}.{}",
+            spec_entry.name,
+            platform.options.compression.extension()
+        );
+        let report = package_patch(
+            &spec_entry.source,
+            &spec_entry.target,
+            &spec.output_dir.join(&file),
+            &platform.options,
+            platform.directory_diff,
+            shared.as_ref(),
+            platform.timings,
+        )?;
+        let checksums = &report.checksums;
+        let (size, hash) = match &report.output {
+            Some(output) => (
+                fs::metadata(output)?.len(),
+                Some(compute_file_hash(output)?),
+            ),
+            None => (0, None),
+        };
+        patches.push(PlatformPatch {
+            name: spec_entry.name.clone(),
+            file: report.output.is_some().then_some(file),
+            size,
+            hash,
+            added: checksums.added.len(),
+            modified: checksums.modified.len(),
+            deleted: checksums.deleted.len(),
+            renamed: checksums.renamed.len() + checksums.moved.len(),
+        });
+    }
+    Ok(MultiPatchReport {
+        output_dir: spec.output_dir.clone(),
+        shared_files,
+        patches,
+    })
+}
+
+/// 所有平台中都以相同方式变更、且内容相同的新增与修改文件
+fn shared_paths(compared: &[ComparedPlatform]) -> Result<HashSet<PathBuf>> {
+    let mut shared = HashSet::new();
+    let Some((first, rest)) = compared.split_first() else {
+        return Ok(shared);
+    };
+    if rest.is_empty() {
+        return Ok(shared);
+    }
+    let others: Vec<HashMap<&PathBuf, &FileDiff>> = rest
+        .iter()
+        .map(|platform| {
+            platform
+                .directory_diff
+                .diffs
+                .iter()
+                .map(|diff| (diff.path(), diff))
+                .collect()
+        })
+        .collect();
+
+    'diffs: for diff in &first.directory_diff.diffs {
+        let (FileDiff::Added(path) | FileDiff::Modified(path)) = diff else {
+            continue;
+        };
+        let modified = matches!(diff, FileDiff::Modified(_));
+        let hashes = |platform: &ComparedPlatform| -> Result<_> {
+            let diff = &platform.directory_diff;
+            let source = if modified {
+                Some(diff.source_hash(&platform.spec.source, path)?)
+            } else {
+                None
+            };
+            Ok((source, diff.target_hash(&platform.spec.target, path)?))
+        };
+        for diffs in &others {
+            if !diffs
+                .get(path)
+                .is_some_and(|other| discriminant(*other) == discriminant(diff))
+            {
+                continue 'diffs;
+            }
+        }
+        let expected = hashes(first)?;
+        for platform in rest {
+            if hashes(platform)? != expected {
+                continue 'diffs;
+            }
+        }
+        shared.insert(path.clone());
+    }
+    Ok(shared)
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::path::{Component, Path};
+
+const KNOWN_OS: &[&str] = &["windows", "linux", "macos"];
+const KNOWN_ARCH: &[&str] = &["x86", "x86_64", "aarch64", "arm", "riscv64"];
+/// 常见的架构别名 -> Rust 的架构名 (`std::env::consts::ARCH`)
+const ARCH_ALIASES: &[(&str, &str)] = &[("amd64", "x86_64"), ("arm64", "aarch64")];
+
+/// 条目适用的平台 (操作系统与可选的 CPU 架构)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub os: String,
+    pub arch: Option<String>,
+}
+
+impl Platform {
+    /// 当前运行平台
+    pub fn current() -> Self {
+        Self {
+            os: std::env::consts::OS.to_string(),
+            arch: Some(std::env::consts::ARCH.to_string()),
+        }
+    }
+
+    /// 解析平台标签，例如 `windows`、`linux-x86_64`、`macos-arm64` (别名归一为 `aarch64`)
+    pub fn parse(tag: &str) -> Option<Self> {
+        let (os, arch) = match tag.split_once('-') {
+            Some((os, arch)) => (os, Some(arch)),
+            None => (tag, None),
+        };
+
+        if !KNOWN_OS.contains(&os) {
+            return None;
+        }
+        let arch = arch.map(|arch| {
+            ARCH_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == arch)
+                .map_or(arch, |(_, canonical)| canonical)
+        });
+        if let Some(arch) = arch
+            && !KNOWN_ARCH.contains(&arch)
+        {
+            return None;
+        }
+
+        Some(Self {
+            os: os.to_string(),
+            arch: arch.map(str::to_string),
+        })
+    }
+
+    /// 根据路径的第一级目录名识别平台，例如 `windows-x86_64/lwjgl.dll`
+    pub fn detect(path: &Path) -> Option<Self> {
+        match path.components().next()? {
+            Component::Normal(first) if path.components().count() > 1 => {
+                Self::parse(first.to_str()?)
+            }
+            _ => None,
+        }
+    }
+
+    /// 判断该平台标签是否适用于另一平台 (未声明架构时匹配任意架构)
+    pub fn matches(&self, other: &Platform) -> bool {
+        self.os == other.os
+            && match (&self.arch, &other.arch) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.arch {
+            Some(arch) => write!(f, "{}-{}", self.os, arch),
+            None => write!(f, "{}", self.os),
+        }
+    }
+}
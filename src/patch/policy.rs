@@ -0,0 +1,119 @@
+use anyhow::{Result, bail};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use super::report::ApplyReport;
+
+/// 应用补丁时可能出现的警告类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    /// 要修改或重命名的文件与补丁记录的原始哈希不一致 (`checksum-mismatch`)
+    ChecksumMismatch,
+    /// 要修改、重命名或同步属性的文件在目标目录中不存在 (`missing-target`)
+    MissingTarget,
+    /// 要新增的文件在目标目录中已存在，将被覆盖 (`extra-file`)
+    ExtraFile,
+    /// 已有文件与补丁中的名字只有大小写不同 (`case-mismatch`)
+    CaseMismatch,
+}
+
+impl WarningKind {
+    pub const ALL: [WarningKind; 4] = [
+        Self::ChecksumMismatch,
+        Self::MissingTarget,
+        Self::ExtraFile,
+        Self::CaseMismatch,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ChecksumMismatch => "checksum-mismatch",
+            Self::MissingTarget => "missing-target",
+            Self::ExtraFile => "extra-file",
+            Self::CaseMismatch => "case-mismatch",
+        }
+    }
+}
+
+impl FromStr for WarningKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        match Self::ALL.into_iter().find(|kind| kind.name() == s) {
+            Some(kind) => Ok(kind),
+            None => bail!(
+                "未知的警告类型: {} (可选: {})",
+                s,
+                Self::ALL.map(|kind| kind.name()).join(", ")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 某类警告的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WarningAction {
+    /// 不记录也不输出
+    Ignore,
+    /// 记录到报告并继续 (默认)
+    #[default]
+    Warn,
+    /// 立即中止应用
+    Error,
+}
+
+/// 按警告类型决定忽略、警告还是报错
+#[derive(Debug, Clone, Default)]
+pub struct WarningPolicy {
+    actions: HashMap<WarningKind, WarningAction>,
+}
+
+impl WarningPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_action(mut self, kind: WarningKind, action: WarningAction) -> Self {
+        self.actions.insert(kind, action);
+        self
+    }
+
+    /// 把该类警告升级为错误
+    pub fn deny(self, kind: WarningKind) -> Self {
+        self.with_action(kind, WarningAction::Error)
+    }
+
+    /// 忽略该类警告
+    pub fn allow(self, kind: WarningKind) -> Self {
+        self.with_action(kind, WarningAction::Ignore)
+    }
+
+    pub fn action(&self, kind: WarningKind) -> WarningAction {
+        self.actions.get(&kind).copied().unwrap_or_default()
+    }
+
+    /// 按策略处理一条警告：记录到报告时返回 `true`，被忽略时返回 `false`，被禁止时返回错误
+    pub(crate) fn raise(
+        &self,
+        kind: WarningKind,
+        message: String,
+        report: &mut ApplyReport,
+    ) -> Result<bool> {
+        match self.action(kind) {
+            WarningAction::Ignore => Ok(false),
+            WarningAction::Warn => {
+                report.warnings.push(message);
+                Ok(true)
+            }
+            WarningAction::Error => bail!("{} (`{}` 已设为错误)", message, kind),
+        }
+    }
+}
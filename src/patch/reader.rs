@@ -0,0 +1,272 @@
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tar::{EntryType, Header, PaxExtensions};
+
+use super::compression::{CompressionFormat, open_decoder};
+use super::error::{ArchiveIssue, PatchError, PatchResult};
+use super::metadata::{Checksums, Metadata};
+
+const BLOCK_SIZE: u64 = 512;
+
+/// 补丁自带的公告 (更新说明)，应用前展示给用户
+pub(crate) const NOTICE_ENTRY: &str = "NOTICE.md";
+
+/// 补丁包中的一个条目
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PatchEntry {
+    /// 补丁内的路径，例如 `added/mods/jei.jar`、`checksums.toml`
+    pub path: String,
+    pub size: u64,
+}
+
+/// 只读打开的补丁包，可以枚举条目并按需流式读取单个条目，无需解包到目录
+///
+/// 读取到的是补丁内存放的原始数据：`renamed/`、`based/` 下是增量，
+/// 登记在 `dictionary_compressed` 中的条目是 zstd 字典压缩后的数据。
+#[derive(Debug, Clone)]
+pub struct Patch {
+    path: PathBuf,
+    entries: Vec<PatchEntry>,
+    metadata: Option<Metadata>,
+    checksums: Checksums,
+}
+
+impl Patch {
+    /// 打开补丁包并读取条目清单、元数据与校验和
+    pub fn open(path: &Path) -> PatchResult<Self> {
+        Ok(Self::read(path)?)
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let mut stream = TarStream::open(path)?;
+        let mut entries = Vec::new();
+        let mut metadata: Option<Metadata> = None;
+        let mut checksums = None;
+
+        while let Some(entry) = stream.next_entry()? {
+            match entry.path.as_str() {
+                "metadata.toml" => {
+                    let content = String::from_utf8(stream.read_data(entry.size)?)?;
+                    metadata = Some(toml::from_str(&content).context("无法解析 metadata.toml")?);
+                }
+                "checksums.toml" => {
+                    let content = String::from_utf8(stream.read_data(entry.size)?)?;
+                    checksums = Some(toml::from_str(&content).context("无法解析 checksums.toml")?);
+                }
+                _ => stream.skip_data(entry.size)?,
+            }
+            entries.push(entry);
+        }
+        if let Some(metadata) = &metadata {
+            metadata.check_tool_version()?;
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+            metadata,
+            checksums: checksums.ok_or(PatchError::MissingChecksums)?,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 按补丁内的存放顺序枚举条目
+    pub fn entries(&self) -> impl Iterator<Item = &PatchEntry> {
+        self.entries.iter()
+    }
+
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    pub fn checksums(&self) -> &Checksums {
+        &self.checksums
+    }
+
+    /// 补丁自带的公告，没有时为 `None`
+    pub fn notice(&self) -> PatchResult<Option<String>> {
+        if !self.entries.iter().any(|e| e.path == NOTICE_ENTRY) {
+            return Ok(None);
+        }
+        let mut notice = String::new();
+        self.read_entry(NOTICE_ENTRY)?
+            .read_to_string(&mut notice)
+            .context("公告不是有效的 UTF-8 文本")?;
+        Ok(Some(notice))
+    }
+
+    /// 流式读取一个条目的内容 (每次调用都会从头扫描补丁包直到该条目)
+    pub fn read_entry(&self, path: &str) -> PatchResult<impl Read + use<>> {
+        Ok(self.open_entry(path)?)
+    }
+
+    fn open_entry(&self, path: &str) -> Result<impl Read + use<>> {
+        if !self.entries.iter().any(|e| e.path == path) {
+            bail!("补丁包中不存在条目: {}", path);
+        }
+
+        let mut stream = TarStream::open(&self.path)?;
+        while let Some(entry) = stream.next_entry()? {
+            if entry.path == path {
+                return Ok(stream.reader.take(entry.size));
+            }
+            stream.skip_data(entry.size)?;
+        }
+        bail!("补丁包在读取条目前结束: {}", path)
+    }
+}
+
+/// 顺序读取补丁包 (gzip/zstd/xz 压缩的 tar) 中的文件条目，支持 PAX 与 GNU 长路径
+pub(crate) struct TarStream {
+    reader: Box<dyn Read + Send>,
+    path: PathBuf,
+    /// 已读到第一个 tar 头
+    started: bool,
+}
+
+impl TarStream {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            reader: open_decoder(path)?,
+            path: path.to_path_buf(),
+            started: false,
+        })
+    }
+
+    /// 读取下一个文件条目的头部，之后需要调用 `read_data`/`skip_data` 消费其数据
+    pub fn next_entry(&mut self) -> Result<Option<PatchEntry>> {
+        let mut long_path = None;
+        loop {
+            let mut block = [0u8; BLOCK_SIZE as usize];
+            let read = self.reader.read_exact(&mut block);
+            if !self.started {
+                self.started = true;
+                if read.is_err() || !is_tar_header(&block) {
+                    let error = read.err().map(|e| e.to_string()).unwrap_or_default();
+                    return Err(PatchError::InvalidArchive {
+                        path: self.path.clone(),
+                        reason: diagnose_archive(&self.path, error),
+                    }
+                    .into());
+                }
+            }
+            match read {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+            if block.iter().all(|&b| b == 0) {
+                return Ok(None);
+            }
+
+            let header = Header::from_byte_slice(&block);
+            let size = header.entry_size()?;
+            match header.entry_type() {
+                EntryType::XHeader => {
+                    let data = self.read_data(size)?;
+                    for extension in PaxExtensions::new(&data) {
+                        let extension = extension?;
+                        if extension.key() == Ok("path") {
+                            long_path = Some(extension.value()?.to_string());
+                        }
+                    }
+                }
+                EntryType::GNULongName => {
+                    let data = self.read_data(size)?;
+                    let name = data.split(|&b| b == 0).next().unwrap_or_default();
+                    long_path = Some(String::from_utf8_lossy(name).to_string());
+                }
+                EntryType::Regular | EntryType::Continuous => {
+                    let path = match long_path.take() {
+                        Some(path) => path,
+                        None => header.path()?.to_string_lossy().replace('\\', "/"),
+                    };
+                    return Ok(Some(PatchEntry { path, size }));
+                }
+                _ => self.skip_data(size)?,
+            }
+        }
+    }
+
+    pub fn read_data(&mut self, size: u64) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(size as usize);
+        (&mut self.reader).take(size).read_to_end(&mut data)?;
+        if data.len() as u64 != size {
+            bail!("补丁包数据被截断");
+        }
+        self.skip_padding(size)?;
+        Ok(data)
+    }
+
+    pub fn skip_data(&mut self, size: u64) -> Result<()> {
+        let skipped = io::copy(&mut (&mut self.reader).take(size), &mut io::sink())?;
+        if skipped != size {
+            bail!("补丁包数据被截断");
+        }
+        self.skip_padding(size)
+    }
+
+    /// 以流的形式把当前条目的数据交给 `f`，`f` 未读完的部分会被跳过
+    pub fn with_data<T>(
+        &mut self,
+        size: u64,
+        f: impl FnOnce(&mut dyn Read) -> Result<T>,
+    ) -> Result<T> {
+        let mut data = (&mut self.reader).take(size);
+        let result = f(&mut data)?;
+        io::copy(&mut data, &mut io::sink())?;
+        if data.limit() != 0 {
+            bail!("补丁包数据被截断");
+        }
+        self.skip_padding(size)?;
+        Ok(result)
+    }
+
+    fn skip_padding(&mut self, size: u64) -> Result<()> {
+        let padding = (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+        io::copy(&mut (&mut self.reader).take(padding), &mut io::sink())?;
+        Ok(())
+    }
+}
+
+/// 是否为校验和正确的 tar 头，或表示归档结束的全零块
+fn is_tar_header(block: &[u8; BLOCK_SIZE as usize]) -> bool {
+    if block.iter().all(|&b| b == 0) {
+        return true;
+    }
+    let sum: u32 = block
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u32)
+        .sum();
+    Header::from_byte_slice(block)
+        .cksum()
+        .is_ok_and(|cksum| cksum == sum)
+}
+
+/// 补丁包无法解开时找出原因：解压开头就出错或内容不像 tar 归档时给出对应的分类，
+/// 否则视为中途损坏，`error` 为解包时遇到的错误
+pub(crate) fn diagnose_archive(patch_path: &Path, error: String) -> ArchiveIssue {
+    let format = match CompressionFormat::detect(patch_path) {
+        Ok(format) => format,
+        Err(e) => match e {
+            PatchError::InvalidArchive { reason, .. } => return reason,
+            other => return ArchiveIssue::Corrupt(other.to_string()),
+        },
+    };
+    let mut block = Vec::with_capacity(BLOCK_SIZE as usize);
+    let read = open_decoder(patch_path)
+        .and_then(|reader| Ok(reader.take(BLOCK_SIZE).read_to_end(&mut block)?));
+    match read {
+        Err(e) => ArchiveIssue::Corrupt(format!("{:#}", e)),
+        Ok(_) => match block.as_slice().try_into() {
+            Ok(block) if is_tar_header(block) => ArchiveIssue::Corrupt(error),
+            _ => ArchiveIssue::NotTar { format },
+        },
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::{Context, Result, anyhow, bail};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use super::apply::ApplyOptions;
+use super::error::PatchResult;
+use super::policy::{WarningAction, WarningKind};
+use crate::utils::status;
+
+/// 覆盖使用的 ssh 程序的环境变量 (默认 `ssh`)
+pub const SSH_PROGRAM_ENV: &str = "DFT_SSH";
+
+/// ssh 远程目标目录，格式为 `[user@]host:/path`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteTarget {
+    pub host: String,
+    pub path: String,
+}
+
+impl RemoteTarget {
+    pub fn parse(spec: &str) -> PatchResult<Self> {
+        match spec.split_once(':') {
+            Some((host, path)) if !host.is_empty() && !path.is_empty() => Ok(Self {
+                host: host.to_string(),
+                path: path.to_string(),
+            }),
+            _ => Err(anyhow!("远程目标格式应为 user@host:/path: {}", spec).into()),
+        }
+    }
+}
+
+/// 通过 ssh 把补丁流送到远端，由远端的 dft (`remote_dft`) 从标准输入读取并应用
+///
+/// 远端的输出直接转发到本地，远端应用失败时返回错误。
+pub fn apply_patch_remote(
+    target: &RemoteTarget,
+    patch_path: &Path,
+    options: &ApplyOptions,
+    remote_dft: &str,
+) -> PatchResult<()> {
+    Ok(apply_remote(target, patch_path, options, remote_dft)?)
+}
+
+fn apply_remote(
+    target: &RemoteTarget,
+    patch_path: &Path,
+    options: &ApplyOptions,
+    remote_dft: &str,
+) -> Result<()> {
+    if options.base_patch.is_some() {
+        bail!("远程应用不支持引用基础补丁的补丁包");
+    }
+    if options.sync {
+        bail!("远程应用不支持同步模式");
+    }
+    if !options.protected.is_empty() {
+        bail!("远程应用不支持保护路径");
+    }
+    if options.atomic {
+        bail!("远程应用不支持原子应用");
+    }
+    if options.rollback.is_some() {
+        bail!("远程应用不支持生成回滚补丁");
+    }
+    if options.pre_command.is_some() || options.post_command.is_some() {
+        bail!("远程应用不支持前置/后置命令");
+    }
+
+    let mut command = format!(
+        "{} apply {} --patch -",
+        shell_quote(remote_dft),
+        shell_quote(&target.path)
+    );
+    if options.strict {
+        command.push_str(" --strict");
+    }
+    if options.skip_corrupt {
+        command.push_str(" --skip-corrupt");
+    }
+    if options.dry_run {
+        command.push_str(" --dry-run");
+    }
+    if options.replace_on_reboot {
+        command.push_str(" --replace-on-reboot");
+    }
+    if options.resume {
+        command.push_str(" --resume");
+    }
+    if options.checkpoint_interval != ApplyOptions::default().checkpoint_interval {
+        command.push_str(&format!(
+            " --checkpoint-every {}",
+            options.checkpoint_interval
+        ));
+    }
+    if let Some(verify) = options.verify {
+        command.push_str(&format!(" --verify {}", shell_quote(&verify.to_string())));
+    }
+    for kind in WarningKind::ALL {
+        match options.warning_policy.action(kind) {
+            WarningAction::Ignore => command.push_str(&format!(" --allow {}", kind)),
+            WarningAction::Error => command.push_str(&format!(" --deny {}", kind)),
+            WarningAction::Warn => {}
+        }
+    }
+    if let Some(restrict_to) = &options.restrict_to {
+        command.push_str(" --restrict-to ");
+        command.push_str(&shell_quote(&restrict_to.to_string_lossy()));
+    }
+
+    let ssh = std::env::var(SSH_PROGRAM_ENV).unwrap_or_else(|_| "ssh".to_string());
+    status!(
+        "正在通过 {} 应用到 {}:{} ...",
+        ssh,
+        target.host,
+        target.path
+    );
+    let mut child = Command::new(&ssh)
+        .arg(&target.host)
+        .arg(&command)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("无法启动 {}", ssh))?;
+
+    let mut stdin = child.stdin.take().context("无法写入 ssh 标准输入")?;
+    let sent = io::copy(&mut File::open(patch_path)?, &mut stdin);
+    drop(stdin);
+    let exit = child.wait()?;
+    if !exit.success() {
+        bail!("远程应用失败 ({})", exit);
+    }
+    sent.context("向远端发送补丁包失败")?;
+    Ok(())
+}
+
+/// 按 POSIX shell 规则用单引号包裹参数
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
@@ -0,0 +1,651 @@
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::error::PatchResult;
+use super::fixup::FixupManifest;
+use super::metadata::{Checksums, FileSizes, Metadata};
+use super::state::AppliedState;
+use crate::utils::modname::ModUpgrade;
+use crate::utils::{HashResult, format_size_delta};
+
+/// JSON 报告的格式版本，结构发生不兼容变化时递增
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// 生成补丁包的结果
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CreateReport {
+    /// 生成的补丁包路径，两个目录完全相同时为 `None`
+    pub output: Option<PathBuf>,
+    pub checksums: Checksums,
+    pub timings: Timings,
+}
+
+/// 应用补丁包的结果
+#[derive(Debug, Clone, Default, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ApplyReport {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub deleted: Vec<String>,
+    pub renamed: Vec<RenamedEntry>,
+    /// 只同步了权限与修改时间的文件
+    pub attributes: Vec<String>,
+    /// 因平台不匹配等原因未应用的条目
+    pub skipped: Vec<String>,
+    /// 从删除与新增的文件中识别出的 mod 升级
+    pub upgrades: Vec<ModUpgrade>,
+    /// 因损坏而跳过的条目 (`skip_corrupt` 模式)
+    pub corrupt: Vec<String>,
+    /// 位于受保护路径、未被触碰的条目
+    pub protected: Vec<String>,
+    /// 与玩家的本地改动按键合并 (而非覆盖) 的配置文件
+    pub merged: Vec<String>,
+    /// 目标中已是补丁新内容、未重复写入的条目
+    pub unchanged: Vec<String>,
+    /// 目标目录只读，未做任何修改，各列表为需要的变更
+    pub read_only: bool,
+    /// 试运行 (`dry_run`)，未做任何修改，各列表为将要进行的变更
+    pub dry_run: bool,
+    /// 文件被占用，已安排在重启后替换或删除的条目 (`replace_on_reboot` 模式)
+    pub pending_reboot: Vec<String>,
+    /// 写出的回滚补丁 (启用 `rollback` 时)
+    pub rollback: Option<PathBuf>,
+    /// 应用后的复验结果 (启用 `verify` 时)
+    pub verification: Option<VerificationReport>,
+    /// 应用前后执行的外部命令 (`pre_command`、`post_command`)
+    pub hooks: Vec<HookReport>,
+    /// 各变更文件应用前后的大小 (重命名按新路径登记)
+    pub size_changes: BTreeMap<String, FileSizes>,
+    /// 修改的文本文件的行级增删 (其余修改的文件按二进制看待，只有大小变化)
+    pub line_changes: BTreeMap<String, LineChanges>,
+    /// 登记了多个原始哈希的修改文件实际匹配的基线 (路径 -> 本地文件的原始哈希)
+    pub baselines: BTreeMap<String, HashResult>,
+    pub warnings: Vec<String>,
+    pub timings: Timings,
+}
+
+/// 文本文件新增与删除的行数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LineChanges {
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl fmt::Display for LineChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} -{} 行", self.added, self.removed)
+    }
+}
+
+/// 一条外部命令的执行结果
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HookReport {
+    /// `pre` 或 `post`
+    pub stage: String,
+    pub command: String,
+    /// 退出码，被信号终止时为空
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    /// 标准输出与标准错误
+    pub output: String,
+}
+
+impl HookReport {
+    pub fn describe_status(&self) -> String {
+        match self.exit_code {
+            Some(code) => format!("退出码 {}", code),
+            None => "被信号终止".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RenamedEntry {
+    pub from: String,
+    pub to: String,
+}
+
+impl ApplyReport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "新增: {} 个文件, 删除: {} 个文件, 修改: {} 个文件, 重命名: {} 个文件, 跳过: {} 个文件",
+            self.added.len(),
+            self.deleted.len(),
+            self.modified.len(),
+            self.renamed.len(),
+            self.skipped.len()
+        );
+        if !self.unchanged.is_empty() {
+            summary.push_str(&format!(", 已是最新: {} 个文件", self.unchanged.len()));
+        }
+        summary
+    }
+
+    /// 应用前后的总大小变化 (字节)
+    pub fn size_delta(&self) -> i64 {
+        self.size_changes.values().map(FileSizes::delta).sum()
+    }
+
+    /// 修改文件的变更摘要：文本文件为行级增删，二进制文件为大小变化
+    pub fn describe_modification(&self, path: &str) -> String {
+        match self.line_changes.get(path) {
+            Some(lines) => format!("文本 {}", lines),
+            None => {
+                let delta = self.size_changes.get(path).map_or(0, FileSizes::delta);
+                format!("二进制 {}", format_size_delta(delta))
+            }
+        }
+    }
+
+    /// 供 CI 读取的结果摘要
+    pub fn run_summary(&self) -> RunSummary {
+        RunSummary {
+            command: "apply".to_string(),
+            ok: true,
+            added: self.added.len(),
+            modified: self.modified.len(),
+            deleted: self.deleted.len(),
+            renamed: self.renamed.len(),
+            skipped: self.skipped.len(),
+            has_warnings: !self.warnings.is_empty(),
+            warnings: self.warnings.clone(),
+            timings: self.timings.clone(),
+            error: None,
+        }
+    }
+}
+
+/// 应用后复验写入文件的结果
+#[derive(Debug, Clone, Default, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct VerificationReport {
+    /// 复验模式 (`full` 或 `sample:N%`)
+    pub mode: String,
+    /// 可复验的文件总数
+    pub total: usize,
+    /// 实际复验的文件数
+    pub checked: usize,
+    pub checked_bytes: u64,
+    /// 内容与补丁登记的哈希不一致的文件
+    pub mismatched: Vec<String>,
+}
+
+impl VerificationReport {
+    pub fn is_valid(&self) -> bool {
+        self.mismatched.is_empty()
+    }
+}
+
+/// 合并补丁包的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeReport {
+    pub output: PathBuf,
+    /// 合并结果的起始版本 (取自第一个补丁)
+    pub source_version: Option<String>,
+    /// 合并结果的目标版本 (取自第二个补丁)
+    pub target_version: Option<String>,
+    pub checksums: Checksums,
+    pub timings: Timings,
+}
+
+impl MergeReport {
+    /// 供 CI 读取的结果摘要
+    pub fn run_summary(&self) -> RunSummary {
+        RunSummary {
+            command: "append".to_string(),
+            ok: true,
+            added: self.checksums.added.len(),
+            modified: self.checksums.modified.len(),
+            deleted: self.checksums.deleted.len(),
+            renamed: self.checksums.renamed.len() + self.checksums.moved.len(),
+            timings: self.timings.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 往返校验 (`verify_roundtrip`) 的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundtripReport {
+    /// 生成的补丁包大小，两个目录相同时为 0
+    pub patch_bytes: u64,
+    /// 应用补丁的结果，两个目录相同时为 `None`
+    pub apply: Option<ApplyReport>,
+    /// 应用后与目标目录仍不一致的文件 (`+`/`-`/`*` 加路径)
+    pub differences: Vec<String>,
+    pub timings: Timings,
+}
+
+impl RoundtripReport {
+    pub fn is_ok(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// 命令结束时写给 CI 流水线的摘要 (`--summary-file`)：变更统计、耗时与是否有警告
+#[derive(Debug, Clone, Default, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RunSummary {
+    pub command: String,
+    pub ok: bool,
+    pub added: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    pub renamed: usize,
+    pub skipped: usize,
+    pub has_warnings: bool,
+    pub warnings: Vec<String>,
+    pub timings: Timings,
+    /// 命令失败时的错误信息
+    pub error: Option<String>,
+}
+
+impl RunSummary {
+    /// 命令失败时的摘要
+    pub fn failed(command: &str, error: &impl fmt::Display) -> Self {
+        Self {
+            command: command.to_string(),
+            error: Some(format!("{:#}", error)),
+            ..Default::default()
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> PatchResult<()> {
+        let content = serde_json::to_string_pretty(self).context("无法序列化运行摘要")?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// 生成或应用补丁修正包的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct FixupReport {
+    /// 生成的修正包或重建后的补丁包路径
+    pub output: PathBuf,
+    pub manifest: FixupManifest,
+    pub timings: Timings,
+}
+
+impl FixupReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "更新: {} 个条目 (其中增量 {} 个), 删除: {} 个条目",
+            self.manifest.changed.len(),
+            self.manifest.changed.values().filter(|e| e.delta).count(),
+            self.manifest.removed.len()
+        )
+    }
+}
+
+/// 补丁包完整性校验的结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    /// 已核对哈希的条目数
+    pub checked: usize,
+    /// 内容与登记的哈希不一致的条目
+    pub corrupt: Vec<String>,
+    /// 已登记但补丁中缺少的条目
+    pub missing: Vec<String>,
+    /// 引用基础补丁或以增量存放、无法单独校验的条目
+    pub unverified: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.corrupt.is_empty() && self.missing.is_empty()
+    }
+
+    /// 问题条目的简要说明
+    pub fn problems(&self) -> String {
+        self.corrupt
+            .iter()
+            .map(|p| format!("{} (哈希不一致)", p))
+            .chain(self.missing.iter().map(|p| format!("{} (缺失)", p)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// 补丁包内容分析的结果 (`dft analyze`)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PatchAnalysis {
+    /// 补丁包文件大小
+    pub patch_size: u64,
+    /// 分析的数据条目数 (不含元数据与校验和)
+    pub entries: usize,
+    /// 条目解压后的总字节数
+    pub content_bytes: u64,
+    /// 其中已以增量存放的条目的字节数
+    pub delta_bytes: u64,
+    /// 按内容切分的块数
+    pub chunks: usize,
+    /// 与前面出现过的块内容相同的块数
+    pub duplicate_chunks: usize,
+    /// 重复块的总字节数，去重后可省去
+    pub duplicate_bytes: u64,
+    /// 大部分内容与前面的条目重复、适合改为增量的条目
+    pub delta_candidates: Vec<String>,
+    /// 适合改为增量的条目的总字节数
+    pub delta_candidate_bytes: u64,
+    /// 各压缩算法下条目数据的预估体积
+    pub compression: Vec<CompressionEstimate>,
+}
+
+impl PatchAnalysis {
+    /// 重复块占全部内容的比例
+    pub fn duplicate_ratio(&self) -> f64 {
+        self.duplicate_bytes as f64 / self.content_bytes.max(1) as f64
+    }
+
+    /// 适合改为增量的条目占全部内容的比例
+    pub fn delta_candidate_ratio(&self) -> f64 {
+        self.delta_candidate_bytes as f64 / self.content_bytes.max(1) as f64
+    }
+}
+
+/// 某个压缩算法与级别下的预估体积
+#[derive(Debug, Clone, Serialize)]
+pub struct CompressionEstimate {
+    pub algorithm: String,
+    pub level: i32,
+    pub size: u64,
+}
+
+/// 批量应用中单个补丁的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Applied,
+    Failed,
+    /// 前面的补丁失败后未应用
+    NotRun,
+}
+
+impl fmt::Display for BatchStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BatchStatus::Applied => "成功",
+            BatchStatus::Failed => "失败",
+            BatchStatus::NotRun => "未应用",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchEntry {
+    pub patch: PathBuf,
+    pub status: BatchStatus,
+    pub report: Option<ApplyReport>,
+    pub error: Option<String>,
+}
+
+impl BatchEntry {
+    pub(crate) fn new(patch: PathBuf, status: BatchStatus) -> Self {
+        Self {
+            patch,
+            status,
+            report: None,
+            error: None,
+        }
+    }
+}
+
+/// 批量应用补丁包的结果，按应用顺序排列
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchReport {
+    pub entries: Vec<BatchEntry>,
+}
+
+impl BatchReport {
+    pub fn failed(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| e.status == BatchStatus::Failed)
+            .count()
+    }
+
+    /// 每个补丁一行的结果表格
+    pub fn table(&self) -> String {
+        let name = |entry: &BatchEntry| {
+            entry
+                .patch
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        };
+        let width = self
+            .entries
+            .iter()
+            .map(|e| name(e).chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(4);
+
+        let mut table = format!("{:<width$}  结果    说明", "补丁");
+        for entry in &self.entries {
+            let detail = match (&entry.report, &entry.error) {
+                (Some(report), _) => report.summary(),
+                (None, Some(error)) => error.clone(),
+                (None, None) => String::new(),
+            };
+            table.push_str(&format!(
+                "\n{:<width$}  {}  {}",
+                name(entry),
+                entry.status,
+                detail
+            ));
+        }
+        table
+    }
+}
+
+/// 单个签名的校验结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    Valid,
+    /// 签名与补丁包内容不符
+    Invalid,
+    /// 签名者的公钥不在受信任列表中
+    Untrusted,
+}
+
+impl fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SignatureStatus::Valid => "✓ 有效",
+            SignatureStatus::Invalid => "✗ 无效",
+            SignatureStatus::Untrusted => "? 不受信任",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SignatureCheck {
+    pub signer: String,
+    pub public_key: String,
+    pub status: SignatureStatus,
+}
+
+/// 校验补丁包签名块的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct SignatureReport {
+    pub patch_sha256: HashResult,
+    /// 至少需要的有效签名数
+    pub threshold: usize,
+    pub signers: Vec<SignatureCheck>,
+}
+
+impl SignatureReport {
+    /// 签名有效的不同受信任公钥数
+    pub fn valid_signers(&self) -> usize {
+        let mut keys: Vec<&str> = self
+            .signers
+            .iter()
+            .filter(|s| s.status == SignatureStatus::Valid)
+            .map(|s| s.public_key.as_str())
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys.len()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.valid_signers() >= self.threshold
+    }
+}
+
+/// 补丁包的元数据与条目清单
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchInfo {
+    pub metadata: Option<Metadata>,
+    pub checksums: Checksums,
+}
+
+/// 目录统计信息
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryInfo {
+    pub file_count: usize,
+    pub total_size: u64,
+    /// 目录内容的 Merkle 指纹
+    pub fingerprint: HashResult,
+    /// 最近一次应用的补丁，未记录时为 `None`
+    pub last_patch: Option<AppliedState>,
+}
+
+/// 两个目录中一个文件的差异
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparedFile {
+    pub path: String,
+    /// `added`、`deleted`、`modified` 或 `renamed` (内容未变的移动)
+    pub change: String,
+    /// 移动前的路径
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// 源目录中的大小，新增文件为 `None`
+    pub old_size: Option<u64>,
+    /// 目标目录中的大小，删除文件为 `None`
+    pub new_size: Option<u64>,
+    /// 文本文件修改时的统一格式 diff 片段
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_diff: Option<String>,
+}
+
+impl ComparedFile {
+    /// 大小变化 (字节)
+    pub fn size_delta(&self) -> i64 {
+        self.new_size.unwrap_or(0) as i64 - self.old_size.unwrap_or(0) as i64
+    }
+}
+
+/// 任意两个目录的比较结果 (`inspect-dir`)
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryComparison {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub source_size: u64,
+    pub target_size: u64,
+    /// 按路径排序的差异文件
+    pub files: Vec<ComparedFile>,
+}
+
+/// 一次为多个平台生成补丁包的结果 (`diff-multi`)，同时写为输出目录中的 `manifest.json`
+#[derive(Debug, Clone, Serialize)]
+pub struct MultiPatchReport {
+    pub output_dir: PathBuf,
+    /// 各平台共用、只压缩一次的文件
+    pub shared_files: Vec<String>,
+    pub patches: Vec<PlatformPatch>,
+}
+
+/// 一个平台的补丁包
+#[derive(Debug, Clone, Serialize)]
+pub struct PlatformPatch {
+    pub name: String,
+    /// 补丁包文件名 (相对于输出目录)，两个目录完全相同时为 `None`
+    pub file: Option<String>,
+    pub size: u64,
+    pub hash: Option<HashResult>,
+    pub added: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    pub renamed: usize,
+}
+
+/// 各阶段耗时与处理的数据量
+#[derive(Debug, Clone, Default, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Timings {
+    pub stages: Vec<StageTiming>,
+    pub total_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct StageTiming {
+    pub name: String,
+    pub duration_ms: f64,
+    /// 该阶段读取或写入的字节数
+    pub bytes: u64,
+}
+
+impl StageTiming {
+    /// 吞吐率 (MB/s)，耗时过短时不计算
+    pub fn throughput(&self) -> Option<f64> {
+        (self.duration_ms >= 1.0 && self.bytes > 0)
+            .then(|| self.bytes as f64 / 1_048_576.0 / (self.duration_ms / 1000.0))
+    }
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录从 `start` 开始到现在的阶段耗时
+    pub fn record(&mut self, name: &str, start: Instant, bytes: u64) {
+        let duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+        self.total_ms += duration_ms;
+        self.stages.push(StageTiming {
+            name: name.to_string(),
+            duration_ms,
+            bytes,
+        });
+    }
+
+    /// 合并另一组阶段统计 (例如嵌套调用的结果)
+    pub fn extend(&mut self, other: Timings) {
+        self.total_ms += other.total_ms;
+        self.stages.extend(other.stages);
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "阶段耗时:")?;
+        for stage in &self.stages {
+            write!(
+                f,
+                "  {:<12} {:>10.1} ms {:>10.2} MB",
+                stage.name,
+                stage.duration_ms,
+                stage.bytes as f64 / 1_048_576.0
+            )?;
+            if let Some(throughput) = stage.throughput() {
+                write!(f, " {:>10.1} MB/s", throughput)?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "  {:<12} {:>10.1} ms", "total", self.total_ms)
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 把应用补丁时的所有写入与删除限制在某个目录之内 (`--restrict-to`)
+///
+/// 每次操作前解析路径上已存在的部分 (包括符号链接)，最终位置逃出限制目录、
+/// 或要写入的文件本身是符号链接时立即失败。未设置限制目录时不做任何检查。
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PathGuard {
+    root: Option<PathBuf>,
+}
+
+impl PathGuard {
+    pub fn new(root: Option<&Path>) -> Result<Self> {
+        let root = match root {
+            Some(root) => Some(
+                root.canonicalize()
+                    .with_context(|| format!("限制目录不存在: {}", root.display()))?,
+            ),
+            None => None,
+        };
+        Ok(Self { root })
+    }
+
+    /// 在写入、删除 `path` 或在其下创建目录之前调用
+    pub fn check(&self, path: &Path) -> Result<()> {
+        let Some(root) = &self.root else {
+            return Ok(());
+        };
+
+        if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink()) {
+            bail!("拒绝通过符号链接写入: {}", path.display());
+        }
+        // 尚不存在的部分之后才会创建，不可能是符号链接，只需解析已存在的最深一级
+        let existing = path
+            .ancestors()
+            .find(|ancestor| fs::symlink_metadata(ancestor).is_ok())
+            .unwrap_or(path);
+        let resolved = existing
+            .canonicalize()
+            .with_context(|| format!("无法解析路径: {}", existing.display()))?;
+        if !resolved.starts_with(root) {
+            bail!(
+                "写入路径逃出限制目录 {}: {} (实际位置 {})",
+                root.display(),
+                path.display(),
+                resolved.display()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::apply::{ApplyOptions, apply_patch_with_options};
+use super::compression::CompressionFormat;
+use super::create::PatchWriter;
+use super::error::PatchResult;
+use super::metadata::{Checksums, Metadata, ModifiedChecksum};
+use super::report::ApplyReport;
+use crate::utils::{FileAttributes, HashResult, STATE_DIR, compute_file_hash, copy_file, status};
+
+/// 应用时保存原文件副本的目录 (位于 `.dft` 下)
+const CAPTURE_DIR: &str = "rollback";
+
+/// 文件在应用前的内容
+struct Original {
+    hash: HashResult,
+    attributes: FileAttributes,
+}
+
+/// 应用补丁时记录被改动文件的原始内容，应用完成后写成撤销这次应用的回滚补丁
+///
+/// 每个路径只在第一次被覆盖、删除或移走前记录一次：存在的文件复制到 `.dft/rollback`，
+/// 不存在的记为新建。离开作用域时删除副本。
+pub(crate) struct RollbackCapture {
+    target_dir: PathBuf,
+    dir: PathBuf,
+    /// 路径 -> 应用前的内容，原本不存在的为 `None`
+    originals: BTreeMap<String, Option<Original>>,
+}
+
+impl RollbackCapture {
+    pub fn open(target_dir: &Path) -> Result<Self> {
+        let dir = target_dir.join(STATE_DIR).join(CAPTURE_DIR);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).with_context(|| format!("无法创建回滚副本目录: {:?}", dir))?;
+        Ok(Self {
+            target_dir: target_dir.to_path_buf(),
+            dir,
+            originals: BTreeMap::new(),
+        })
+    }
+
+    /// 即将改动 `path`：首次改动时保存原文件的副本
+    pub fn capture(&mut self, path: &str) -> Result<()> {
+        if self.originals.contains_key(path) {
+            return Ok(());
+        }
+        let target_path = self.target_dir.join(path);
+        let original = if target_path.is_file() {
+            let copy = self.dir.join(path);
+            if let Some(parent) = copy.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            copy_file(&target_path, &copy)
+                .with_context(|| format!("无法保存原文件: {:?}", target_path))?;
+            Some(Original {
+                hash: compute_file_hash(&copy)?,
+                attributes: FileAttributes::read(&target_path)?,
+            })
+        } else {
+            None
+        };
+        self.originals.insert(path.to_string(), original);
+        Ok(())
+    }
+
+    /// 对比记录的原始内容与应用后的目标目录，把回滚补丁写到 `output`
+    ///
+    /// 新建的文件登记为删除，删除的文件连同原内容登记为新增，内容变了的登记为修改
+    /// (原始哈希为应用后的内容)，只有属性变了的登记为属性同步。
+    pub fn write(
+        &self,
+        output: &Path,
+        applied: Option<&Metadata>,
+        compression: CompressionFormat,
+    ) -> Result<Checksums> {
+        let mut checksums = Checksums::new();
+        let mut writer = PatchWriter::create(output, compression)
+            .with_context(|| format!("无法创建回滚补丁: {:?}", output))?;
+        for (path, original) in &self.originals {
+            let target_path = self.target_dir.join(path);
+            let current = if target_path.is_file() {
+                Some(compute_file_hash(&target_path)?)
+            } else {
+                None
+            };
+            let copy = self.dir.join(path);
+            match (original, current) {
+                (None, None) => {}
+                (None, Some(_)) => checksums.deleted.push(path.clone()),
+                (Some(original), None) => {
+                    writer.append_file(&Path::new("added").join(path), &copy)?;
+                    checksums.added.insert(path.clone(), original.hash.clone());
+                }
+                (Some(original), Some(current)) if current != original.hash => {
+                    writer.append_file(&Path::new("modified").join(path), &copy)?;
+                    let checksum = ModifiedChecksum::new(current, original.hash.clone());
+                    checksums.modified.insert(path.clone(), checksum);
+                }
+                (Some(original), Some(_)) => {
+                    if FileAttributes::read(&target_path)? != original.attributes {
+                        checksums
+                            .attributes
+                            .insert(path.clone(), original.attributes);
+                    }
+                }
+            }
+        }
+
+        let mut metadata = Metadata::new().with_description("回滚补丁");
+        metadata.source_version = applied.and_then(|m| m.target_version.clone());
+        metadata.target_version = applied.and_then(|m| m.source_version.clone());
+        metadata.compression = compression;
+        writer.append_bytes(
+            Path::new("metadata.toml"),
+            toml::to_string_pretty(&metadata)?.as_bytes(),
+        )?;
+        writer.append_bytes(
+            Path::new("checksums.toml"),
+            toml::to_string_pretty(&checksums)?.as_bytes(),
+        )?;
+        writer.finish()?;
+        Ok(checksums)
+    }
+}
+
+impl Drop for RollbackCapture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// 用应用时生成的回滚补丁撤销那次应用
+///
+/// 以严格、原子的方式应用：应用后又被改动过的文件与回滚补丁登记的哈希不一致时不做任何修改，
+/// 中途失败时恢复到撤销前的状态。
+pub fn revert_patch(target_dir: &Path, rollback_path: &Path) -> PatchResult<ApplyReport> {
+    status!("正在撤销补丁...");
+    let options = ApplyOptions::new().with_strict(true).with_atomic(true);
+    apply_patch_with_options(target_dir, rollback_path, &options)
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use super::apply::{ApplyOptions, apply_patch_into};
+use super::create::{CreateOptions, create_patch_with_options};
+use super::diff::compare_directories;
+use super::error::PatchResult;
+use super::report::RoundtripReport;
+use crate::utils::status;
+
+/// 自检补丁的正确性：由 `a`、`b` 生成补丁，应用到 `a` 的副本后与 `b` 比较
+///
+/// `a`、`b` 本身不会被修改，补丁与副本放在临时目录中，结束后删除。
+pub fn verify_roundtrip(a: &Path, b: &Path) -> PatchResult<RoundtripReport> {
+    verify_roundtrip_with_options(a, b, &CreateOptions::default())
+}
+
+/// 按指定的生成选项自检补丁的正确性
+pub fn verify_roundtrip_with_options(
+    a: &Path,
+    b: &Path,
+    options: &CreateOptions,
+) -> PatchResult<RoundtripReport> {
+    let work_dir = std::env::temp_dir().join(format!("dft_roundtrip_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&work_dir);
+    fs::create_dir_all(&work_dir)?;
+    let result = roundtrip_in(a, b, options, &work_dir);
+    let _ = fs::remove_dir_all(&work_dir);
+    Ok(result?)
+}
+
+fn roundtrip_in(
+    a: &Path,
+    b: &Path,
+    options: &CreateOptions,
+    work_dir: &Path,
+) -> Result<RoundtripReport> {
+    let patch = work_dir.join("roundtrip.tgz");
+    let created = create_patch_with_options(a, b, &patch, options)?;
+    let mut timings = created.timings;
+
+    let (applied_dir, apply) = match created.output {
+        Some(patch) => {
+            let output = work_dir.join("applied");
+            let report = apply_patch_into(a, &output, &patch, &ApplyOptions::default())?;
+            timings.extend(report.timings.clone());
+            (output, Some(report))
+        }
+        // 两个目录相同，没有补丁可应用，直接比较原目录
+        None => (a.to_path_buf(), None),
+    };
+    let patch_bytes = fs::metadata(&patch).map_or(0, |m| m.len());
+
+    let stage = Instant::now();
+    let differences: Vec<String> = compare_directories(&applied_dir, b)?
+        .iter()
+        .map(|diff| format!("{} {}", diff.symbol(), diff.path().display()))
+        .collect();
+    timings.record("roundtrip", stage, 0);
+
+    let report = RoundtripReport {
+        patch_bytes,
+        apply,
+        differences,
+        timings,
+    };
+    if report.is_ok() {
+        status!("往返校验通过: 应用补丁后与目标目录一致");
+    } else {
+        status!("往返校验失败: {} 处不一致", report.differences.len());
+        for difference in &report.differences {
+            status!("  {}", difference);
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,40 @@
+use schemars::{JsonSchema, schema_for};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use super::metadata::{Checksums, Metadata};
+use super::report::{ApplyReport, CreateReport, RunSummary};
+
+/// 可导出 schema 的格式：名称 -> 说明
+pub const SCHEMA_NAMES: &[(&str, &str)] = &[
+    ("metadata", "补丁包内的 metadata.toml"),
+    ("checksums", "补丁包内的 checksums.toml"),
+    ("create-report", "dft diff --json 的结果"),
+    ("apply-report", "dft apply --json 的结果"),
+    ("run-summary", "--summary-file 写入的摘要"),
+];
+
+/// 指定格式的 JSON Schema，名称见 [`SCHEMA_NAMES`]
+pub fn json_schema(name: &str) -> Option<Value> {
+    let schema = match name {
+        "metadata" => schema_value::<Metadata>(),
+        "checksums" => schema_value::<Checksums>(),
+        "create-report" => schema_value::<CreateReport>(),
+        "apply-report" => schema_value::<ApplyReport>(),
+        "run-summary" => schema_value::<RunSummary>(),
+        _ => return None,
+    };
+    Some(schema)
+}
+
+/// 所有格式的 JSON Schema (名称 -> schema)
+pub fn json_schemas() -> BTreeMap<&'static str, Value> {
+    SCHEMA_NAMES
+        .iter()
+        .filter_map(|(name, _)| Some((*name, json_schema(name)?)))
+        .collect()
+}
+
+fn schema_value<T: JsonSchema>() -> Value {
+    schema_for!(T).to_value()
+}
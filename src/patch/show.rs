@@ -1,89 +1,299 @@
 use anyhow::Result;
-use std::fs;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
 
-use super::apply::{extract_patch, load_checksums};
-use super::metadata::Metadata;
-use crate::utils::is_text_file;
+use super::error::PatchResult;
+use super::loaded::LoadedPatch;
+use super::metadata::{Checksums, Metadata};
+use super::reader::Patch;
+use super::report::PatchInfo;
+use crate::utils::modname::find_upgrades;
+use crate::utils::{FileKind, format_size_delta, is_text_file, status};
+
+/// 文本预览最多读取的字节数，避免把超大的文本文件整个读入内存
+const PREVIEW_MAX_BYTES: u64 = 64 * 1024;
+/// 预览中单行最多显示的字符数
+const PREVIEW_LINE_CHARS: usize = 200;
+
+/// 显示补丁包内容时的选项
+#[derive(Debug, Clone)]
+pub struct ShowOptions {
+    /// 修改的文本文件预览的行数，为 0 时不显示预览
+    pub preview_lines: usize,
+}
+
+impl Default for ShowOptions {
+    fn default() -> Self {
+        Self { preview_lines: 20 }
+    }
+}
+
+impl ShowOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_preview_lines(mut self, lines: usize) -> Self {
+        self.preview_lines = lines;
+        self
+    }
+}
 
 /// 显示补丁包内容
-pub fn show_patch(patch_path: &Path) -> Result<()> {
-    println!("补丁包: {}\n", patch_path.display());
+pub fn show_patch(patch_path: &Path) -> PatchResult<()> {
+    show_patch_with_options(patch_path, &ShowOptions::default())
+}
 
-    // 创建临时目录
-    let temp_dir = std::env::temp_dir().join(format!("dft_show_{}", std::process::id()));
-    fs::create_dir_all(&temp_dir)?;
+/// 按指定选项显示补丁包内容
+pub fn show_patch_with_options(patch_path: &Path, options: &ShowOptions) -> PatchResult<()> {
+    Ok(show(patch_path, options)?)
+}
+
+fn show(patch_path: &Path, options: &ShowOptions) -> Result<()> {
+    status!("补丁包: {}\n", patch_path.display());
 
-    // 解压补丁包
-    extract_patch(patch_path, &temp_dir)?;
+    // 解压补丁包 (临时目录在结束或失败时自动清理)
+    let temp_dir = std::env::temp_dir().join(format!("dft_show_{}", std::process::id()));
+    let patch = LoadedPatch::load(patch_path, temp_dir)?;
+    let checksums = &patch.checksums;
 
     // 显示元数据
-    show_metadata(&temp_dir)?;
+    if let Some(metadata) = &patch.metadata {
+        show_metadata(metadata);
+    }
 
-    // 读取并显示校验和信息
-    let checksums = load_checksums(&temp_dir)?;
+    if !checksums.sizes.is_empty() {
+        status!(
+            "大小变化: {} ({} 个文件)",
+            format_size_delta(checksums.size_delta()),
+            checksums.sizes.len()
+        );
+    }
+    if patch.dictionary_compressed() > 0 {
+        status!("zstd 字典压缩: {} 个文件", patch.dictionary_compressed());
+    }
+    if !checksums.manifest.is_empty() {
+        status!(
+            "全量清单: {} 个文件 (可用 apply --sync 同步)",
+            checksums.manifest.len()
+        );
+    }
+    if !checksums.merge_bases.is_empty() {
+        status!(
+            "按键合并的配置: {} 个文件 (玩家改动过时与新版本合并而非覆盖)",
+            checksums.merge_bases.len()
+        );
+    }
+    if !checksums.replace_dirs.is_empty() {
+        status!(
+            "整体替换的目录: {} (应用时删除其中补丁未包含的文件)",
+            checksums.replace_dirs.join(", ")
+        );
+    }
+    if let Some(base) = &checksums.base_patch {
+        status!(
+            "引用基础补丁: {} 个文件 (基础补丁 SHA256: {})",
+            checksums.base_refs.len(),
+            base
+        );
+    }
+
+    // 同一 mod 的“删除旧版本 + 新增新版本”合并显示为升级
+    let upgrades = find_upgrades(
+        checksums.deleted.iter().map(String::as_str),
+        checksums.added.keys().map(String::as_str),
+    );
+    let folded: HashSet<&str> = upgrades
+        .iter()
+        .flat_map(|u| [u.from.as_str(), u.to.as_str()])
+        .collect();
 
     // 显示新增文件
-    if !checksums.added.is_empty() {
-        println!("=== 新增文件 ({}) ===", checksums.added.len());
-        for path in checksums.added.keys() {
-            println!("  + {}", path);
+    let added: Vec<&String> = checksums
+        .added
+        .keys()
+        .filter(|p| !folded.contains(p.as_str()))
+        .collect();
+    if !added.is_empty() {
+        status!("=== 新增文件 ({}) ===", added.len());
+        for path in added {
+            status!(
+                "  + {}{}{}{}",
+                path,
+                kind_suffix(&patch.entry_path("added", path)),
+                size_suffix(checksums, path),
+                platform_suffix(checksums, path)
+            );
         }
-        println!();
+        status!();
     }
 
     // 显示删除文件
-    if !checksums.deleted.is_empty() {
-        println!("=== 删除文件 ({}) ===", checksums.deleted.len());
-        for path in &checksums.deleted {
-            println!("  - {}", path);
+    let deleted: Vec<&String> = checksums
+        .deleted
+        .iter()
+        .filter(|p| !folded.contains(p.as_str()))
+        .collect();
+    if !deleted.is_empty() {
+        status!("=== 删除文件 ({}) ===", deleted.len());
+        for path in deleted {
+            status!(
+                "  - {}{}{}",
+                path,
+                size_suffix(checksums, path),
+                platform_suffix(checksums, path)
+            );
+        }
+        status!();
+    }
+
+    // 显示 mod 升级
+    if !upgrades.is_empty() {
+        status!("=== mod 升级 ({}) ===", upgrades.len());
+        for upgrade in &upgrades {
+            status!("  {}{}", upgrade, platform_suffix(checksums, &upgrade.to));
+        }
+        status!();
+    }
+
+    // 显示重命名文件
+    if !checksums.renamed.is_empty() {
+        status!("=== 重命名文件 ({}) ===", checksums.renamed.len());
+        for (to, renamed) in &checksums.renamed {
+            status!(
+                "  > {} -> {}{}{}",
+                renamed.from,
+                to,
+                size_suffix(checksums, to),
+                platform_suffix(checksums, to)
+            );
+        }
+        status!();
+    }
+
+    // 显示移动文件
+    if !checksums.moved.is_empty() {
+        status!("=== 移动文件 ({}) ===", checksums.moved.len());
+        for (to, moved) in &checksums.moved {
+            status!(
+                "  > {} -> {}{}",
+                moved.from,
+                to,
+                platform_suffix(checksums, to)
+            );
         }
-        println!();
+        status!();
     }
 
     // 显示修改文件
     if !checksums.modified.is_empty() {
-        println!("=== 修改文件 ({}) ===", checksums.modified.len());
+        status!("=== 修改文件 ({}) ===", checksums.modified.len());
         for path in checksums.modified.keys() {
-            println!("  * {}", path);
-            show_text_file_preview(&temp_dir, path)?;
+            // 增量存放的条目不是完整文件，无法识别类型或预览
+            let is_delta = checksums.delta_modified.contains(path);
+            status!(
+                "  * {}{}{}{}",
+                path,
+                if is_delta {
+                    " [增量]".to_string()
+                } else {
+                    kind_suffix(&patch.entry_path("modified", path))
+                },
+                size_suffix(checksums, path),
+                platform_suffix(checksums, path)
+            );
+            if options.preview_lines > 0 && !is_delta {
+                show_text_file_preview(&patch.entry_path("modified", path), options.preview_lines)?;
+            }
         }
     }
 
-    // 清理临时目录
-    fs::remove_dir_all(&temp_dir)?;
-
     Ok(())
 }
 
-fn show_metadata(temp_dir: &Path) -> Result<()> {
-    let metadata_path = temp_dir.join("metadata.toml");
-    if metadata_path.exists() {
-        let metadata_content = fs::read_to_string(&metadata_path)?;
-        let metadata: Metadata = toml::from_str(&metadata_content)?;
-        println!("=== 元数据 ===");
-        println!("版本: {}", metadata.version);
-        println!("创建时间: {}", metadata.created_at);
-        if let Some(desc) = &metadata.description {
-            println!("描述: {}", desc);
+/// 读取补丁包的元数据与条目清单
+pub fn inspect_patch(patch_path: &Path) -> PatchResult<PatchInfo> {
+    let patch = Patch::open(patch_path)?;
+    Ok(PatchInfo {
+        metadata: patch.metadata().cloned(),
+        checksums: patch.checksums().clone(),
+    })
+}
+
+fn platform_suffix(checksums: &Checksums, path: &str) -> String {
+    match checksums.platforms.get(path) {
+        Some(tag) => format!(" [{}]", tag),
+        None => String::new(),
+    }
+}
+
+fn show_metadata(metadata: &Metadata) {
+    status!("=== 元数据 ===");
+    status!("版本: {}", metadata.version);
+    status!("创建时间: {}", metadata.created_at);
+    status!("压缩格式: {}", metadata.compression);
+    if let Some(desc) = &metadata.description {
+        status!("描述: {}", desc);
+    }
+    if let Some(author) = &metadata.author {
+        status!("作者: {}", author);
+    }
+    if let Some(notes) = &metadata.notes {
+        status!("变更说明:");
+        for line in notes.lines() {
+            status!("  {}", line);
         }
-        println!();
     }
-    Ok(())
+    if metadata.fast_mode {
+        status!("生成模式: 快速 (按大小与修改时间判断未变更文件)");
+    }
+    status!();
+}
+
+/// 补丁登记的大小变化，如 ` (+1.2 KiB)`；旧版本生成的补丁没有登记
+fn size_suffix(checksums: &Checksums, path: &str) -> String {
+    match checksums.sizes.get(path) {
+        Some(sizes) => format!(" ({})", format_size_delta(sizes.delta())),
+        None => String::new(),
+    }
 }
 
-fn show_text_file_preview(temp_dir: &Path, path: &str) -> Result<()> {
-    let modified_file = temp_dir.join("modified").join(path);
-    if modified_file.exists() && is_text_file(&modified_file) {
-        let content = fs::read_to_string(&modified_file)?;
-        println!("    --- 新内容 ---");
-        for line in content.lines().take(20) {
-            println!("    | {}", line);
+/// 按文件头识别的类型，如 ` [PNG 图像]`
+fn kind_suffix(path: &Path) -> String {
+    match FileKind::of_file(path) {
+        Ok(kind) => format!(" [{}]", kind),
+        Err(_) => String::new(),
+    }
+}
+
+fn show_text_file_preview(modified_file: &Path, lines: usize) -> Result<()> {
+    if !modified_file.exists() || !is_text_file(modified_file) {
+        return Ok(());
+    }
+
+    // 只流式读取文件开头的一部分，按行截取
+    let mut reader = BufReader::new(File::open(modified_file)?.take(PREVIEW_MAX_BYTES));
+    let mut line = Vec::new();
+    let mut shown = 0;
+    status!("    --- 新内容 ---");
+    while shown < lines {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
         }
-        if content.lines().count() > 20 {
-            println!("    | ... (更多内容省略)");
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+        match text.char_indices().nth(PREVIEW_LINE_CHARS) {
+            Some((end, _)) => status!("    | {} ...", &text[..end]),
+            None => status!("    | {}", text),
         }
-        println!();
+        shown += 1;
+    }
+    if !reader.fill_buf()?.is_empty() || fs::metadata(modified_file)?.len() > PREVIEW_MAX_BYTES {
+        status!("    | ... (更多内容省略)");
     }
+    status!();
     Ok(())
 }
@@ -0,0 +1,197 @@
+use anyhow::{Context, Result, bail};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::error::PatchResult;
+use super::report::{SignatureCheck, SignatureReport, SignatureStatus};
+use crate::utils::{HashResult, compute_file_hash, now_rfc3339, status};
+
+/// 签名内容的前缀，避免签名被挪作他用
+const SIGNED_MESSAGE_PREFIX: &str = "dft-patch-signature-v1\n";
+
+/// 补丁包旁的签名块 (`<补丁包>.sig`)，可包含多个签名者的签名
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SignatureBlock {
+    /// 签名时补丁包的 SHA256
+    pub patch_sha256: Option<HashResult>,
+    #[serde(default)]
+    pub signatures: Vec<PatchSignature>,
+}
+
+/// 一个签名者对补丁包的 ed25519 签名
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchSignature {
+    pub signer: String,
+    /// 公钥 (hex)
+    pub public_key: String,
+    /// 签名 (hex)
+    pub signature: String,
+    pub signed_at: String,
+}
+
+impl SignatureBlock {
+    pub fn load(path: &Path) -> PatchResult<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("无法读取签名块: {:?}", path))?;
+        Ok(toml::from_str(&content).context("无法解析签名块")?)
+    }
+
+    pub fn save(&self, path: &Path) -> PatchResult<()> {
+        fs::write(
+            path,
+            toml::to_string_pretty(self).context("无法序列化签名块")?,
+        )?;
+        Ok(())
+    }
+}
+
+/// 补丁包默认的签名块路径
+pub fn signature_path(patch_path: &Path) -> PathBuf {
+    let mut path = patch_path.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+/// 生成 ed25519 密钥对，私钥与公钥分别以 hex 文本写入 `secret_path`、`public_path`
+pub fn generate_signing_key(secret_path: &Path, public_path: &Path) -> PatchResult<()> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| anyhow::anyhow!("无法获取随机数: {}", e))?;
+    let key = SigningKey::from_bytes(&seed);
+    fs::write(secret_path, format!("{}\n", hex::encode(key.to_bytes())))?;
+    fs::write(
+        public_path,
+        format!("{}\n", hex::encode(key.verifying_key().to_bytes())),
+    )?;
+    status!(
+        "已生成密钥: {} (私钥，请妥善保管), {} (公钥)",
+        secret_path.display(),
+        public_path.display()
+    );
+    Ok(())
+}
+
+/// 以 `key_path` 中的私钥为补丁包签名，追加到签名块中 (同一公钥的旧签名会被替换)
+pub fn sign_patch(patch_path: &Path, key_path: &Path, signer: &str) -> PatchResult<PathBuf> {
+    let key = SigningKey::from_bytes(&read_key(key_path)?);
+    let hash = compute_file_hash(patch_path)?;
+
+    let block_path = signature_path(patch_path);
+    let mut block = if block_path.exists() {
+        SignatureBlock::load(&block_path)?
+    } else {
+        SignatureBlock::default()
+    };
+    // 补丁包已变化时旧签名全部失效
+    if block.patch_sha256.as_ref() != Some(&hash) {
+        block.signatures.clear();
+    }
+
+    let public_key = hex::encode(key.verifying_key().to_bytes());
+    block.signatures.retain(|s| s.public_key != public_key);
+    block.signatures.push(PatchSignature {
+        signer: signer.to_string(),
+        public_key,
+        signature: hex::encode(key.sign(signed_message(&hash).as_bytes()).to_bytes()),
+        signed_at: now_rfc3339(),
+    });
+    block.patch_sha256 = Some(hash);
+    block.save(&block_path)?;
+
+    status!(
+        "已签名: {} ({} 个签名)",
+        block_path.display(),
+        block.signatures.len()
+    );
+    Ok(block_path)
+}
+
+/// 用受信任的公钥校验补丁包的签名块，至少 `threshold` 个受信任签名者的签名有效时通过
+pub fn verify_patch_signatures(
+    patch_path: &Path,
+    trusted_keys: &[PathBuf],
+    threshold: usize,
+) -> PatchResult<SignatureReport> {
+    Ok(verify_signatures(patch_path, trusted_keys, threshold)?)
+}
+
+fn verify_signatures(
+    patch_path: &Path,
+    trusted_keys: &[PathBuf],
+    threshold: usize,
+) -> Result<SignatureReport> {
+    if threshold == 0 {
+        bail!("签名阈值至少为 1");
+    }
+    let mut trusted = Vec::new();
+    for path in trusted_keys {
+        trusted.push(hex::encode(read_key(path)?));
+    }
+
+    let hash = compute_file_hash(patch_path)?;
+    let block = SignatureBlock::load(&signature_path(patch_path))?;
+    let message = signed_message(&hash);
+
+    let mut report = SignatureReport {
+        patch_sha256: hash,
+        threshold,
+        signers: Vec::new(),
+    };
+    for signature in &block.signatures {
+        let status = if !trusted.contains(&signature.public_key) {
+            SignatureStatus::Untrusted
+        } else if verify_signature(signature, message.as_bytes()) {
+            SignatureStatus::Valid
+        } else {
+            SignatureStatus::Invalid
+        };
+        report.signers.push(SignatureCheck {
+            signer: signature.signer.clone(),
+            public_key: signature.public_key.clone(),
+            status,
+        });
+    }
+
+    for check in &report.signers {
+        status!("  {} {} ({})", check.status, check.signer, check.public_key);
+    }
+    status!(
+        "有效签名: {} / 需要 {}",
+        report.valid_signers(),
+        report.threshold
+    );
+    Ok(report)
+}
+
+fn signed_message(hash: &HashResult) -> String {
+    format!("{}{}", SIGNED_MESSAGE_PREFIX, hash.to_hex())
+}
+
+fn verify_signature(signature: &PatchSignature, message: &[u8]) -> bool {
+    let Ok(public_key) = decode_32(&signature.public_key) else {
+        return false;
+    };
+    let Ok(key) = VerifyingKey::from_bytes(&public_key) else {
+        return false;
+    };
+    let Some(bytes) = hex::decode(&signature.signature)
+        .ok()
+        .and_then(|b| <[u8; 64]>::try_from(b).ok())
+    else {
+        return false;
+    };
+    key.verify(message, &Signature::from_bytes(&bytes)).is_ok()
+}
+
+/// 读取 hex 文本形式的 32 字节密钥 (私钥种子或公钥)
+fn read_key(path: &Path) -> Result<[u8; 32]> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("无法读取密钥文件: {:?}", path))?;
+    decode_32(content.trim()).with_context(|| format!("无效的密钥文件: {:?}", path))
+}
+
+fn decode_32(s: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(s)?;
+    <[u8; 32]>::try_from(bytes).map_err(|_| anyhow::anyhow!("密钥长度应为 32 字节"))
+}
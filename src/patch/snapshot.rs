@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+
+use super::diff::{FileDiff, diff_file_maps};
+use super::error::PatchResult;
+use crate::utils::{
+    FileInfo, HashResult, STATE_DIR, compute_file_hash, scan_directory, walk_files,
+};
+
+/// 目录快照：相对路径到文件哈希与大小的映射，可脱离磁盘目录保存与比较
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub files: HashMap<PathBuf, FileInfo>,
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 扫描目录生成快照
+    pub fn scan(dir: &Path) -> PatchResult<Self> {
+        Ok(Self {
+            files: scan_directory(dir)?,
+        })
+    }
+
+    pub fn insert(&mut self, path: impl Into<PathBuf>, hash: HashResult, fsize: usize) {
+        self.files.insert(path.into(), FileInfo { hash, fsize });
+    }
+
+    /// 从 JSON 文件读取快照
+    pub fn load(path: &Path) -> PatchResult<Self> {
+        let file = File::open(path).with_context(|| format!("无法打开快照文件: {:?}", path))?;
+        Ok(serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("快照文件格式错误: {:?}", path))?)
+    }
+
+    /// 保存为 JSON 文件 (先写临时文件再替换，读取方不会看到写了一半的快照)
+    pub fn save(&self, path: &Path) -> PatchResult<()> {
+        let temp = path.with_extension("dft_tmp");
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        serde_json::to_writer(&mut writer, self).context("无法序列化快照")?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temp, path).with_context(|| format!("无法写入快照文件: {:?}", path))?;
+        Ok(())
+    }
+
+    /// 按发生变化的路径增量更新快照，返回更新的条目数
+    ///
+    /// `paths` 为 `dir` 下的路径：文件被重新计算哈希，目录整体重新扫描，
+    /// 已不存在的路径连同其下的所有条目一并移除。
+    pub fn refresh<I, P>(&mut self, dir: &Path, paths: I) -> PatchResult<usize>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        Ok(self.refresh_paths(dir, paths)?)
+    }
+
+    fn refresh_paths<I, P>(&mut self, dir: &Path, paths: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut updated = 0;
+        for path in paths {
+            let path = path.as_ref();
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            if relative.components().next() == Some(Component::Normal(STATE_DIR.as_ref())) {
+                continue;
+            }
+
+            if path.is_file() {
+                let hash = compute_file_hash(path)?;
+                let fsize = path.metadata()?.len() as usize;
+                self.insert(relative, hash, fsize);
+                updated += 1;
+                continue;
+            }
+
+            // 目录或已删除的路径：先移除旧条目，目录再重新扫描
+            let before = self.files.len();
+            self.files.retain(|file, _| !file.starts_with(relative));
+            updated += before - self.files.len();
+            if path.is_dir() {
+                for entry in walk_files(path) {
+                    let hash = compute_file_hash(entry.path())?;
+                    let fsize = entry.metadata()?.len() as usize;
+                    self.insert(entry.path().strip_prefix(dir)?, hash, fsize);
+                    updated += 1;
+                }
+            }
+        }
+        Ok(updated)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl From<HashMap<PathBuf, FileInfo>> for Snapshot {
+    fn from(files: HashMap<PathBuf, FileInfo>) -> Self {
+        Self { files }
+    }
+}
+
+/// 比较两份快照并返回差异，无需访问磁盘目录
+pub fn compare_snapshots(source: &Snapshot, target: &Snapshot) -> Vec<FileDiff> {
+    diff_file_maps(&source.files, &target.files)
+}
@@ -0,0 +1,63 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use super::error::PatchResult;
+use super::report::ApplyReport;
+use crate::utils::{HashResult, STATE_DIR, now_rfc3339};
+
+const STATE_FILE: &str = "state";
+
+/// 目录最近一次应用的补丁信息，保存在 `.dft/state`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedState {
+    /// 补丁包文件名
+    pub patch: String,
+    pub patch_hash: HashResult,
+    pub applied_at: String,
+    pub added: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    #[serde(default)]
+    pub renamed: usize,
+}
+
+impl AppliedState {
+    pub fn new(patch_path: &Path, patch_hash: HashResult, report: &ApplyReport) -> Self {
+        Self {
+            patch: patch_path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            patch_hash,
+            applied_at: now_rfc3339(),
+            added: report.added.len(),
+            modified: report.modified.len(),
+            deleted: report.deleted.len(),
+            renamed: report.renamed.len(),
+        }
+    }
+
+    /// 读取目录的状态文件，不存在时返回 `None`
+    pub fn load(dir: &Path) -> PatchResult<Option<Self>> {
+        let path = dir.join(STATE_DIR).join(STATE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("无法读取状态文件: {:?}", path))?;
+        let state =
+            toml::from_str(&content).with_context(|| format!("状态文件格式错误: {:?}", path))?;
+        Ok(Some(state))
+    }
+
+    pub fn save(&self, dir: &Path) -> PatchResult<()> {
+        let state_dir = dir.join(STATE_DIR);
+        fs::create_dir_all(&state_dir)?;
+        let content = toml::to_string_pretty(self).context("无法序列化状态文件")?;
+        fs::write(state_dir.join(STATE_FILE), content)
+            .with_context(|| format!("无法写入状态文件: {:?}", state_dir))?;
+        Ok(())
+    }
+}
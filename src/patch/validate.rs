@@ -0,0 +1,73 @@
+use anyhow::Result;
+use std::path::Path;
+
+use super::error::PatchResult;
+use super::loaded::LoadedPatch;
+use super::report::ValidationReport;
+use crate::utils::{compute_file_hash, status};
+
+/// 校验补丁包完整性：逐个核对新增/修改条目的哈希与 checksums.toml 的登记
+pub fn validate_patch(patch_path: &Path) -> PatchResult<ValidationReport> {
+    Ok(validate(patch_path)?)
+}
+
+fn validate(patch_path: &Path) -> Result<ValidationReport> {
+    status!("正在校验补丁包...");
+
+    let temp_dir = std::env::temp_dir().join(format!("dft_validate_{}", std::process::id()));
+    let patch = LoadedPatch::load(patch_path, temp_dir)?;
+    let report = verify_entries(&patch)?;
+
+    if report.is_valid() {
+        status!("补丁包校验通过: {} 个条目", report.checked);
+    } else {
+        for path in &report.corrupt {
+            status!("  ! 哈希不一致: {}", path);
+        }
+        for path in &report.missing {
+            status!("  ! 缺少条目: {}", path);
+        }
+    }
+    if !report.unverified.is_empty() {
+        status!(
+            "  {} 个条目引用基础补丁或以增量存放，需在应用时校验",
+            report.unverified.len()
+        );
+    }
+    Ok(report)
+}
+
+/// 核对已加载补丁中新增/修改条目的哈希，引用基础补丁或以增量存放的条目记为未校验
+pub(crate) fn verify_entries(patch: &LoadedPatch) -> Result<ValidationReport> {
+    let checksums = &patch.checksums;
+    let mut entries: Vec<(&str, &String, _)> = checksums
+        .added
+        .iter()
+        .map(|(path, hash)| ("added", path, hash))
+        .chain(
+            checksums
+                .modified
+                .iter()
+                .map(|(path, checksum)| ("modified", path, &checksum.modified)),
+        )
+        .collect();
+    entries.sort_by(|a, b| a.1.cmp(b.1));
+
+    let mut report = ValidationReport::default();
+    for (section, path, expected) in entries {
+        if checksums.base_refs.contains_key(path) || checksums.delta_modified.contains(path) {
+            report.unverified.push(path.clone());
+            continue;
+        }
+        let entry = patch.entry_path(section, path);
+        if !entry.is_file() {
+            report.missing.push(path.clone());
+            continue;
+        }
+        report.checked += 1;
+        if compute_file_hash(&entry)? != *expected {
+            report.corrupt.push(path.clone());
+        }
+    }
+    Ok(report)
+}
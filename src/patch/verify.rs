@@ -0,0 +1,113 @@
+use anyhow::{Context, Result, bail};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::metadata::Checksums;
+use super::report::{ApplyReport, VerificationReport};
+use crate::utils::{compute_file_hash, status};
+
+/// 应用完成后复验写入文件的方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VerifyMode {
+    /// 复验所有写入的文件
+    Full,
+    /// 随机抽取一定比例 (0 ~ 100%) 的文件复验，至少一个
+    Sample(f64),
+}
+
+impl FromStr for VerifyMode {
+    type Err = anyhow::Error;
+
+    /// 解析 `full` 或 `sample:5%`
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s == "full" {
+            return Ok(Self::Full);
+        }
+        let Some(percent) = s.strip_prefix("sample:") else {
+            bail!("无效的复验模式: {} (应为 full 或 sample:<百分比>)", s);
+        };
+        let percent: f64 = percent
+            .trim_end_matches('%')
+            .parse()
+            .with_context(|| format!("无效的抽样比例: {}", s))?;
+        if !(percent > 0.0 && percent <= 100.0) {
+            bail!("抽样比例应在 (0, 100] 之间: {}", s);
+        }
+        Ok(Self::Sample(percent))
+    }
+}
+
+impl fmt::Display for VerifyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "full"),
+            Self::Sample(percent) => write!(f, "sample:{}%", percent),
+        }
+    }
+}
+
+/// 重新计算本次写入 (新增、修改、重命名) 的文件哈希，与补丁登记的结果核对
+///
+/// 已安排在重启后替换的文件不参与复验。
+pub(crate) fn verify_applied(
+    target_dir: &Path,
+    checksums: &Checksums,
+    report: &ApplyReport,
+    mode: VerifyMode,
+) -> Result<VerificationReport> {
+    let mut candidates: Vec<(&str, _)> = report
+        .added
+        .iter()
+        .filter_map(|path| Some((path.as_str(), checksums.added.get(path)?)))
+        .chain(
+            report
+                .modified
+                .iter()
+                .filter_map(|path| Some((path.as_str(), &checksums.modified.get(path)?.modified))),
+        )
+        .chain(report.renamed.iter().filter_map(|entry| {
+            let hash = match checksums.moved.get(&entry.to) {
+                Some(moved) => &moved.hash,
+                None => &checksums.renamed.get(&entry.to)?.modified,
+            };
+            Some((entry.to.as_str(), hash))
+        }))
+        .filter(|(path, _)| !report.pending_reboot.iter().any(|p| p == path))
+        .collect();
+    let total = candidates.len();
+
+    if let VerifyMode::Sample(percent) = mode {
+        // 每次运行使用不同的随机种子，抽中的文件不可预测
+        let random = RandomState::new();
+        candidates.sort_by_cached_key(|(path, _)| random.hash_one(path));
+        let count = ((total as f64 * percent / 100.0).ceil() as usize).clamp(1, total.max(1));
+        candidates.truncate(count);
+    }
+    candidates.sort_by_key(|(path, _)| *path);
+
+    status!(
+        "正在复验写入的文件 ({}，{} / {})...",
+        mode,
+        candidates.len(),
+        total
+    );
+    let mut verification = VerificationReport {
+        mode: mode.to_string(),
+        total,
+        ..Default::default()
+    };
+    for (path, expected) in candidates {
+        let file = target_dir.join(path);
+        verification.checked += 1;
+        verification.checked_bytes += file.metadata().map(|m| m.len()).unwrap_or(0);
+        if !file.is_file() || compute_file_hash(&file)? != *expected {
+            status!("  ! {} 与补丁登记的哈希不一致", path);
+            verification.mismatched.push(path.to_string());
+        }
+    }
+    Ok(verification)
+}
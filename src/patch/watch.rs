@@ -0,0 +1,97 @@
+use anyhow::{Context, Result};
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use super::error::PatchResult;
+use super::snapshot::Snapshot;
+use crate::utils::status;
+
+/// 同一批变更的合并窗口：事件停止到达这么久后才更新快照
+const DEBOUNCE: Duration = Duration::from_millis(300);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 长驻监听目录，借助文件系统事件 (inotify/FSEvents/ReadDirectoryChangesW) 增量维护快照文件
+///
+/// 启动时先全量扫描一次；此后只重新计算发生变化的文件，每批变更后原子地写回 `snapshot_path`，
+/// 供 `dft diff --source-snapshot/--target-snapshot` 直接使用。`stop` 置位后返回。
+pub fn watch_snapshot(dir: &Path, snapshot_path: &Path, stop: &AtomicBool) -> PatchResult<()> {
+    Ok(watch(dir, snapshot_path, stop)?)
+}
+
+fn watch(dir: &Path, snapshot_path: &Path, stop: &AtomicBool) -> Result<()> {
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("目录不存在: {:?}", dir))?;
+
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&dir, RecursiveMode::Recursive)?;
+
+    let mut snapshot = Snapshot::scan(&dir)?;
+    snapshot.save(snapshot_path)?;
+    status!(
+        "快照已建立: {} 个文件 -> {}",
+        snapshot.len(),
+        snapshot_path.display()
+    );
+
+    // 快照文件本身可能位于被监听的目录中，忽略它 (及其临时文件) 引起的事件
+    let snapshot_file = snapshot_path.canonicalize()?;
+    let ignored = [
+        snapshot_file.clone(),
+        snapshot_file.with_extension("dft_tmp"),
+    ];
+
+    while !stop.load(Ordering::Relaxed) {
+        let first = match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        let mut changed = HashSet::new();
+        let mut rescan = false;
+        let mut pending = Some(first);
+        while let Some(event) = pending {
+            match event {
+                // 计算哈希时读取文件也会产生访问事件，必须忽略，否则会不停自我触发
+                Ok(event) if event.kind.is_access() => {}
+                Ok(event) if !event.need_rescan() => {
+                    // 目录自身的修改事件 (如 mtime 变化) 无需整体重扫，其中的文件会各自产生事件
+                    let is_modify = matches!(event.kind, EventKind::Modify(kind) if !matches!(kind, ModifyKind::Name(_)));
+                    changed.extend(
+                        event
+                            .paths
+                            .into_iter()
+                            .filter(|path| !(is_modify && path.is_dir())),
+                    );
+                }
+                // 事件队列溢出等情况无法得知具体变化，退回全量扫描
+                _ => rescan = true,
+            }
+            pending = rx.recv_timeout(DEBOUNCE).ok();
+        }
+        changed.retain(|path: &PathBuf| !ignored.contains(path));
+
+        if rescan {
+            snapshot = Snapshot::scan(&dir)?;
+            status!("快照已全量重建: {} 个文件", snapshot.len());
+        } else if changed.is_empty() {
+            continue;
+        } else {
+            let updated = snapshot.refresh(&dir, &changed)?;
+            status!(
+                "快照已更新: {} 个条目，共 {} 个文件",
+                updated,
+                snapshot.len()
+            );
+        }
+        snapshot.save(snapshot_path)?;
+    }
+    Ok(())
+}
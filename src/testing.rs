@@ -0,0 +1,151 @@
+//! 集成测试辅助工具 (需启用 `testing` feature)
+//!
+//! 声明式构造目录树、生成固定种子的随机二进制内容、一步得到补丁 fixture，
+//! 以及断言两个目录内容等价；[`inject_fault`] 在应用流程的第 N 个条目后注入错误或 panic。
+//!
+//! ```no_run
+//! use bin_diff_tool::testing::{DirTree, assert_dirs_equal, patch_fixture};
+//!
+//! let v1 = DirTree::new().file("config.toml", "a = 1").random_file("data.bin", 4096, 1);
+//! let v2 = DirTree::new().file("config.toml", "a = 2").random_file("data.bin", 4096, 1);
+//! let fixture = patch_fixture(&v1, &v2).unwrap();
+//! bin_diff_tool::apply_patch(&fixture.source, &fixture.patch).unwrap();
+//! assert_dirs_equal(&fixture.source, &fixture.target);
+//! ```
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+pub use crate::patch::fault::{Fault, FaultGuard, inject_fault};
+use crate::patch::{FileDiff, compare_directories, create_patch};
+
+/// 目录树中的一项
+#[derive(Debug, Clone)]
+enum Node {
+    File(PathBuf, Vec<u8>),
+    Dir(PathBuf),
+}
+
+/// 声明式描述的目录树，用 [`DirTree::write_to`] 或 [`DirTree::build`] 落盘
+#[derive(Debug, Clone, Default)]
+pub struct DirTree {
+    nodes: Vec<Node>,
+}
+
+impl DirTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加文件，所在目录自动创建
+    pub fn file(mut self, path: impl Into<PathBuf>, content: impl AsRef<[u8]>) -> Self {
+        self.nodes
+            .push(Node::File(path.into(), content.as_ref().to_vec()));
+        self
+    }
+
+    /// 添加以 `seed` 生成的 `len` 字节随机内容的文件
+    pub fn random_file(self, path: impl Into<PathBuf>, len: usize, seed: u64) -> Self {
+        self.file(path, random_bytes(len, seed))
+    }
+
+    /// 添加空目录
+    pub fn dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.nodes.push(Node::Dir(path.into()));
+        self
+    }
+
+    /// 在 `root` 下创建目录树
+    pub fn write_to(&self, root: &Path) -> Result<()> {
+        for node in &self.nodes {
+            match node {
+                Node::File(path, content) => {
+                    let path = root.join(path);
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(&path, content)
+                        .with_context(|| format!("无法写入文件: {:?}", path))?;
+                }
+                Node::Dir(path) => fs::create_dir_all(root.join(path))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// 在新的临时目录中创建目录树，临时目录随返回值删除
+    pub fn build(&self) -> Result<TempDir> {
+        let dir = TempDir::new()?;
+        self.write_to(dir.path())?;
+        Ok(dir)
+    }
+}
+
+/// 以 `seed` 生成 `len` 字节的伪随机数据，相同参数得到相同内容
+pub fn random_bytes(len: usize, seed: u64) -> Vec<u8> {
+    // xorshift 的状态不能为 0
+    let mut state = seed.max(1);
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+/// 源目录、目标目录与两者之间的补丁包，均位于同一个临时目录中，随 fixture 一起删除
+pub struct PatchFixture {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub patch: PathBuf,
+    root: TempDir,
+}
+
+impl PatchFixture {
+    /// fixture 所在的临时目录，可用来放置其他文件
+    pub fn root(&self) -> &Path {
+        self.root.path()
+    }
+}
+
+/// 由两个目录树生成补丁 fixture
+pub fn patch_fixture(source: &DirTree, target: &DirTree) -> Result<PatchFixture> {
+    let root = TempDir::new()?;
+    let source_dir = root.path().join("source");
+    let target_dir = root.path().join("target");
+    fs::create_dir_all(&source_dir)?;
+    fs::create_dir_all(&target_dir)?;
+    source.write_to(&source_dir)?;
+    target.write_to(&target_dir)?;
+    let patch = root.path().join("patch.tgz");
+    create_patch(&source_dir, &target_dir, &patch)?;
+    Ok(PatchFixture {
+        source: source_dir,
+        target: target_dir,
+        patch,
+        root,
+    })
+}
+
+/// 断言两个目录中的文件 (路径与内容) 完全相同，不同时列出全部差异
+#[track_caller]
+pub fn assert_dirs_equal(left: &Path, right: &Path) {
+    let diffs = compare_directories(left, right)
+        .unwrap_or_else(|e| panic!("无法比较目录 {:?} 与 {:?}: {:#}", left, right, e));
+    if !diffs.is_empty() {
+        let listing: Vec<String> = diffs
+            .iter()
+            .map(|diff: &FileDiff| format!("  {} {}", diff.symbol(), diff.path().display()))
+            .collect();
+        panic!(
+            "目录不相同: {:?} 与 {:?}\n{}",
+            left,
+            right,
+            listing.join("\n")
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! 通用的补丁更新器框架
+//!
+//! 把“收集补丁 → 按版本链排序 → 合并 → 应用 → 结束提示”的流程抽出来，
+//! 具体的更新器只需提供目标目录的定位方式 ([`TargetLocator`]，或注册若干 [`TargetDetector`])
+//! 与界面回调 ([`UpdaterUi`])。
+//! `mc_updater` 即基于此实现。
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::patch::{
+    ApplyOptions, ApplyReport, Patch, apply_patch_with_options, merge_patches, order_patch_chain,
+};
+use crate::utils::{show_paged, status, write_status_log};
+
+/// 定位要更新的目标目录
+pub trait TargetLocator {
+    fn locate(&self) -> Result<PathBuf>;
+}
+
+/// 固定的目标目录
+impl TargetLocator for PathBuf {
+    fn locate(&self) -> Result<PathBuf> {
+        if !self.is_dir() {
+            bail!("目标目录不存在: {}", self.display());
+        }
+        Ok(self.clone())
+    }
+}
+
+/// 目标探测器：按某种规则 (已知路径、注册表、启动器配置等) 寻找安装目录
+pub trait TargetDetector {
+    /// 探测器名称，用于找不到目标时的提示
+    fn name(&self) -> &str;
+    /// 找到时返回目标目录，本探测器不适用时返回 `None`
+    fn detect(&self) -> Result<Option<PathBuf>>;
+}
+
+/// 依次检查若干已知路径，返回第一个存在的目录
+#[derive(Debug, Clone)]
+pub struct KnownPaths {
+    pub name: String,
+    pub candidates: Vec<PathBuf>,
+}
+
+impl TargetDetector for KnownPaths {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn detect(&self) -> Result<Option<PathBuf>> {
+        Ok(self.candidates.iter().find(|path| path.is_dir()).cloned())
+    }
+}
+
+/// 已注册的目标探测器，按注册顺序尝试，采用第一个找到的目录
+#[derive(Default)]
+pub struct TargetDetectors {
+    detectors: Vec<Box<dyn TargetDetector>>,
+}
+
+impl TargetDetectors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_detector(mut self, detector: impl TargetDetector + 'static) -> Self {
+        self.register(detector);
+        self
+    }
+
+    pub fn register(&mut self, detector: impl TargetDetector + 'static) {
+        self.detectors.push(Box::new(detector));
+    }
+}
+
+impl TargetLocator for TargetDetectors {
+    fn locate(&self) -> Result<PathBuf> {
+        let mut tried = Vec::new();
+        for detector in &self.detectors {
+            // 单个探测器出错不影响其他探测器，只记录在最终的错误信息中
+            match detector.detect() {
+                Ok(Some(path)) => {
+                    status!("目标目录: {} (由 {} 找到)", path.display(), detector.name());
+                    return Ok(path);
+                }
+                Ok(None) => tried.push(detector.name().to_string()),
+                Err(e) => tried.push(format!("{} ({:#})", detector.name(), e)),
+            }
+        }
+        if tried.is_empty() {
+            bail!("没有注册目标探测器");
+        }
+        bail!("未找到目标目录，已尝试: {}", tried.join(", "))
+    }
+}
+
+/// 更新过程中的界面回调，均有空的默认实现
+pub trait UpdaterUi {
+    /// 已确定目标目录
+    fn on_target(&mut self, _target: &Path) {}
+    /// 补丁已按应用顺序排好
+    fn on_ordered(&mut self, _patches: &[PathBuf]) {}
+    /// 应用前展示补丁自带的公告 (图形界面可用弹窗显示)
+    fn on_notice(&mut self, _notice: &str) {}
+    /// 补丁应用完成
+    fn on_applied(&mut self, _report: &ApplyReport) {}
+    /// 更新失败
+    fn on_error(&mut self, _error: &anyhow::Error) {}
+    /// 无论成功与否，流程结束时调用 (例如等待按键再退出)
+    fn on_finish(&mut self) {}
+}
+
+/// 借用的界面，便于调用方在更新结束后读取其中记录的状态
+impl<T: UpdaterUi + ?Sized> UpdaterUi for &mut T {
+    fn on_target(&mut self, target: &Path) {
+        (**self).on_target(target)
+    }
+    fn on_ordered(&mut self, patches: &[PathBuf]) {
+        (**self).on_ordered(patches)
+    }
+    fn on_notice(&mut self, notice: &str) {
+        (**self).on_notice(notice)
+    }
+    fn on_applied(&mut self, report: &ApplyReport) {
+        (**self).on_applied(report)
+    }
+    fn on_error(&mut self, error: &anyhow::Error) {
+        (**self).on_error(error)
+    }
+    fn on_finish(&mut self) {
+        (**self).on_finish()
+    }
+}
+
+/// 在终端分页显示补丁公告、输出错误，可选在结束时等待回车 (适合拖放补丁包启动的场景)
+///
+/// 标准输入不是终端 (脚本、管道、计划任务) 时不等待。
+#[derive(Debug, Clone, Default)]
+pub struct ConsoleUi {
+    pub pause_on_exit: bool,
+}
+
+impl UpdaterUi for ConsoleUi {
+    fn on_notice(&mut self, notice: &str) {
+        show_paged(&format!("补丁公告:\n{}", notice.trim_end()));
+    }
+
+    fn on_error(&mut self, error: &anyhow::Error) {
+        write_status_log(&format!("错误: {:#}", error));
+        eprintln!("错误: {:#}", error);
+    }
+
+    fn on_finish(&mut self) {
+        if self.pause_on_exit && io::stdin().is_terminal() {
+            print!("按回车退出...");
+            let _ = io::stdout().flush();
+            let _ = io::stdin().read(&mut [0u8]);
+        }
+    }
+}
+
+/// 补丁更新器
+pub struct Updater<L, U> {
+    locator: L,
+    ui: U,
+    apply_options: ApplyOptions,
+}
+
+impl<L: TargetLocator, U: UpdaterUi> Updater<L, U> {
+    pub fn new(locator: L, ui: U) -> Self {
+        Self {
+            locator,
+            ui,
+            apply_options: ApplyOptions::default(),
+        }
+    }
+
+    pub fn with_apply_options(mut self, options: ApplyOptions) -> Self {
+        self.apply_options = options;
+        self
+    }
+
+    /// 把 `patches` 按版本链 (其次创建时间) 排序、合并后应用到目标目录
+    pub fn run(&mut self, patches: &[PathBuf]) -> Result<ApplyReport> {
+        let result = self.update(patches);
+        match &result {
+            Ok(report) => self.ui.on_applied(report),
+            Err(e) => self.ui.on_error(e),
+        }
+        self.ui.on_finish();
+        result
+    }
+
+    fn update(&mut self, patches: &[PathBuf]) -> Result<ApplyReport> {
+        let target = self.locator.locate()?;
+        self.ui.on_target(&target);
+
+        if patches.is_empty() {
+            bail!("没有提供补丁包");
+        }
+        for patch in patches {
+            if !patch.is_file() {
+                bail!("补丁文件未找到: {}", patch.display());
+            }
+        }
+        let mut ordered = Vec::new();
+        for (path, metadata) in order_patch_chain(patches.to_vec()) {
+            metadata.with_context(|| format!("无法读取补丁包: {}", path.display()))?;
+            ordered.push(path);
+        }
+        self.ui.on_ordered(&ordered);
+
+        let merge_dir = std::env::temp_dir().join(format!("dft_updater_{}", std::process::id()));
+        fs::create_dir_all(&merge_dir)?;
+        let result = merge_chain(&ordered, &merge_dir).and_then(|merged| {
+            if let Some(notice) = Patch::open(&merged)?.notice()? {
+                self.ui.on_notice(&notice);
+            }
+            apply_patch_with_options(&target, &merged, &self.apply_options)
+                .with_context(|| format!("应用补丁失败: {}", merged.display()))
+        });
+        let _ = fs::remove_dir_all(&merge_dir);
+        result
+    }
+}
+
+/// 依次合并排好序的补丁，返回最终的补丁包 (只有一个补丁时直接返回它)
+fn merge_chain(patches: &[PathBuf], temp_dir: &Path) -> Result<PathBuf> {
+    let (first, rest) = patches.split_first().context("没有提供补丁包")?;
+    let mut current = first.clone();
+    for (i, patch) in rest.iter().enumerate() {
+        let output = temp_dir.join(format!("merge_{}.tgz", i));
+        merge_patches(&current, patch, &output)?;
+        current = output;
+    }
+    Ok(current)
+}
@@ -1,5 +1,49 @@
+mod cache;
+mod copy;
+#[cfg(unix)]
+mod dirfd;
+#[cfg(feature = "network")]
+mod download;
 mod fs;
 mod hash;
+mod ignore_rules;
+mod magic;
+mod manifest;
+mod memory;
+pub mod modname;
+mod output;
+mod parallel;
+mod prefetch;
+mod reboot;
+mod time;
 
-pub use fs::{FileInfo, is_text_file, scan_directory};
-pub use hash::{HashResult, compute_file_hash};
+#[cfg(feature = "network")]
+pub use cache::fetch_cached;
+pub use cache::{CACHE_DIR_ENV, DEFAULT_CACHE_SIZE, PatchCache, cache_dir, is_url};
+pub use copy::{CopyMethod, LinkMode, copy_file, link_file};
+#[cfg(feature = "network")]
+pub use download::{DownloadOptions, download_file};
+pub use fs::{
+    FileAttributes, FileInfo, FileStat, STATE_DIR, directory_fingerprint, hash_files, is_text_file,
+    scan_directory, scan_directory_with_hashes, scan_file_stats,
+};
+pub(crate) use fs::{scan_directory_sharded, shard_of, walk_files};
+pub use hash::{
+    HashAlgorithm, HashResult, HashingReader, Sha256Stream, compute_file_hash,
+    compute_file_hash_with, compute_hash, hash_reader, hash_reader_with,
+};
+pub use ignore_rules::IgnoreRules;
+pub use magic::FileKind;
+pub use manifest::HashManifest;
+pub use memory::MemoryLimit;
+pub(crate) use output::status;
+#[doc(hidden)]
+pub use output::write_status;
+pub use output::{
+    format_size, format_size_delta, is_quiet, is_status_to_stderr, set_quiet, set_status_log,
+    set_status_to_stderr, show_paged, write_status_log,
+};
+pub(crate) use parallel::parallel_map;
+pub(crate) use prefetch::{PrefetchedFile, Prefetcher};
+pub(crate) use reboot::{is_file_in_use, move_on_reboot};
+pub(crate) use time::{log_timestamp, now_rfc3339, parse_rfc3339};
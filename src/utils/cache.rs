@@ -0,0 +1,194 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[cfg(feature = "network")]
+use super::download::{DownloadOptions, download_file};
+use super::hash::{HashResult, compute_file_hash};
+use super::status;
+
+/// 指定缓存目录的环境变量
+pub const CACHE_DIR_ENV: &str = "DFT_CACHE_DIR";
+
+/// 缓存默认的大小上限
+pub const DEFAULT_CACHE_SIZE: u64 = 1 << 30;
+
+/// 本地缓存目录：`DFT_CACHE_DIR`，否则为 `$XDG_CACHE_HOME/dft`、`~/.cache/dft`
+/// (Windows 下为 `%LOCALAPPDATA%\dft`)
+pub fn cache_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os(CACHE_DIR_ENV) {
+        return Ok(PathBuf::from(dir));
+    }
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .context("无法确定缓存目录，请设置 DFT_CACHE_DIR")?;
+    Ok(base.join("dft"))
+}
+
+/// 参数是否为 http(s) URL
+pub fn is_url(value: &str) -> bool {
+    value.starts_with("http://") || value.starts_with("https://")
+}
+
+/// 按内容 SHA256 寻址的本地补丁缓存
+///
+/// 文件存放在 `objects/<sha256>`，`urls/` 记录下载过的 URL 对应的哈希。
+/// 每次命中都会刷新文件的修改时间，总大小超过上限时按修改时间从旧到新清理 (LRU)。
+#[derive(Debug, Clone)]
+pub struct PatchCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl PatchCache {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        for sub in ["objects", "urls"] {
+            fs::create_dir_all(dir.join(sub))
+                .with_context(|| format!("无法创建缓存目录: {:?}", dir))?;
+        }
+        Ok(Self {
+            dir,
+            max_size: DEFAULT_CACHE_SIZE,
+        })
+    }
+
+    /// 打开默认位置 ([`cache_dir`]) 的缓存
+    pub fn open_default() -> Result<Self> {
+        Self::open(cache_dir()?)
+    }
+
+    pub fn with_max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// 取得内容为 `hash` 的缓存文件
+    pub fn get(&self, hash: &HashResult) -> Option<PathBuf> {
+        let path = self.object_path(hash);
+        if !path.is_file() {
+            return None;
+        }
+        touch(&path);
+        Some(path)
+    }
+
+    /// 把文件复制进缓存，返回其哈希与缓存中的路径
+    pub fn insert(&self, path: &Path) -> Result<(HashResult, PathBuf)> {
+        let hash = compute_file_hash(path)?;
+        let object = self.object_path(&hash);
+        if !object.is_file() {
+            let part = object.with_extension("part");
+            fs::copy(path, &part).with_context(|| format!("无法写入缓存: {:?}", part))?;
+            fs::rename(&part, &object)?;
+        }
+        touch(&object);
+        self.evict(Some(&object))?;
+        Ok((hash, object))
+    }
+
+    /// 取得 `url` 的内容：`expected` 给定且已缓存，或该 URL 下载过且内容仍在缓存中时不再下载
+    ///
+    /// 下载的内容与 `expected` 不一致时报错，不会放入缓存。
+    #[cfg(feature = "network")]
+    pub fn fetch(
+        &self,
+        url: &str,
+        expected: Option<&HashResult>,
+        options: &DownloadOptions,
+    ) -> Result<PathBuf> {
+        let url_record = self.dir.join("urls").join(url_key(url));
+        let known = match expected {
+            Some(hash) => Some(hash.clone()),
+            None => fs::read_to_string(&url_record)
+                .ok()
+                .and_then(|hex| HashResult::from_hex(hex.trim()).ok()),
+        };
+        if let Some(path) = known.as_ref().and_then(|hash| self.get(hash)) {
+            status!("使用缓存: {}", path.display());
+            return Ok(path);
+        }
+
+        let part = self
+            .dir
+            .join("objects")
+            .join(format!("{}.download", url_key(url)));
+        download_file(url, &part, options)?;
+        let result = self.insert(&part);
+        let _ = fs::remove_file(&part);
+        let (hash, path) = result?;
+        if let Some(expected) = expected
+            && &hash != expected
+        {
+            let _ = fs::remove_file(&path);
+            anyhow::bail!("下载内容的 SHA256 不一致: 期望 {}，实际 {}", expected, hash);
+        }
+        fs::write(&url_record, hash.to_hex())?;
+        Ok(path)
+    }
+
+    /// 缓存文件的总大小
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.objects()?.iter().map(|(_, size, _)| size).sum())
+    }
+
+    /// 总大小超过上限时，从最久未使用的文件开始删除 (`keep` 除外)
+    pub fn evict(&self, keep: Option<&Path>) -> Result<()> {
+        let mut objects = self.objects()?;
+        let mut total: u64 = objects.iter().map(|(_, size, _)| size).sum();
+        objects.sort_by_key(|(_, _, used)| *used);
+        for (path, size, _) in objects {
+            if total <= self.max_size {
+                break;
+            }
+            if Some(path.as_path()) == keep {
+                continue;
+            }
+            fs::remove_file(&path)?;
+            status!("  清理缓存: {}", path.display());
+            total -= size;
+        }
+        Ok(())
+    }
+
+    fn object_path(&self, hash: &HashResult) -> PathBuf {
+        self.dir.join("objects").join(hash.to_hex())
+    }
+
+    /// 缓存中的文件、大小与最近使用时间
+    fn objects(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let mut objects = Vec::new();
+        for entry in fs::read_dir(self.dir.join("objects"))? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let is_object = entry.file_name().len() == 64;
+            if metadata.is_file() && is_object {
+                let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                objects.push((entry.path(), metadata.len(), used));
+            }
+        }
+        Ok(objects)
+    }
+}
+
+/// 从默认缓存取得 `url` 的内容并返回本地路径，同一 URL 已下载过时直接复用
+#[cfg(feature = "network")]
+pub fn fetch_cached(url: &str, options: &DownloadOptions) -> Result<PathBuf> {
+    PatchCache::open_default()?.fetch(url, None, options)
+}
+
+#[cfg(feature = "network")]
+fn url_key(url: &str) -> String {
+    super::hash::compute_hash(url.as_bytes()).to_hex()[..16].to_string()
+}
+
+/// 刷新最近使用时间，失败时不影响使用
+fn touch(path: &Path) {
+    let _ = File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+}
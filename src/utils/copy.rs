@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::{fs::File, io, os::unix::fs::PermissionsExt};
+
+#[cfg(unix)]
+use super::dirfd::DirHandle;
+
+/// 文件复制实际使用的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyMethod {
+    /// 写时复制克隆 (btrfs/xfs reflink、APFS clonefile、ReFS block clone)
+    Reflink,
+    /// 硬链接，与源文件共享同一份数据
+    HardLink,
+    /// 内核加速的数据复制 (Linux copy_file_range、macOS fcopyfile 等，由标准库选择)
+    Copy,
+}
+
+/// 未变更文件放入新目录的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkMode {
+    /// 复制数据 (仍会优先尝试 reflink)
+    #[default]
+    Copy,
+    /// 硬链接，失败时回退到复制
+    Hard,
+    /// 写时复制克隆，失败时回退到复制
+    Reflink,
+}
+
+/// 复制文件：优先尝试 reflink 克隆，不支持时回退到普通复制
+///
+/// 目标文件若已存在，会先写入同目录下的临时文件再整体替换，
+/// 因此不会改写与目标共享数据的硬链接。
+pub fn copy_file(source: &Path, dest: &Path) -> Result<CopyMethod> {
+    #[cfg(unix)]
+    if let (Some(parent), Some(name)) = (dest.parent(), dest.file_name()) {
+        return copy_file_at(source, parent, name);
+    }
+
+    if !dest.exists() {
+        return copy_new_file(source, dest);
+    }
+
+    let temp = temp_sibling(dest);
+    let method = copy_new_file(source, &temp)?;
+    if let Err(e) = fs::rename(&temp, dest) {
+        let _ = fs::remove_file(&temp);
+        return Err(e).with_context(|| format!("无法替换文件: {:?}", dest));
+    }
+    Ok(method)
+}
+
+/// 按指定方式把文件放入新位置，链接失败 (如跨文件系统) 时回退到复制
+pub fn link_file(source: &Path, dest: &Path, mode: LinkMode) -> Result<CopyMethod> {
+    match mode {
+        LinkMode::Hard if fs::hard_link(source, dest).is_ok() => Ok(CopyMethod::HardLink),
+        LinkMode::Reflink if reflink_copy::reflink(source, dest).is_ok() => Ok(CopyMethod::Reflink),
+        _ => copy_file(source, dest),
+    }
+}
+
+fn copy_new_file(source: &Path, dest: &Path) -> Result<CopyMethod> {
+    if reflink_copy::reflink(source, dest).is_ok() {
+        return Ok(CopyMethod::Reflink);
+    }
+
+    fs::copy(source, dest).with_context(|| format!("无法复制文件: {:?} -> {:?}", source, dest))?;
+    Ok(CopyMethod::Copy)
+}
+
+fn temp_sibling(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default();
+    path.with_file_name(temp_name(name))
+}
+
+fn temp_name(name: &OsStr) -> OsString {
+    format!(".{}.dft_tmp", name.to_string_lossy()).into()
+}
+
+/// Unix 上通过目标所在目录的句柄写入：新文件以 `O_EXCL | O_NOFOLLOW` 创建，
+/// 再在同一句柄内改名替换，目标位置在检查之后被换成符号链接也不会写到别处
+#[cfg(unix)]
+fn copy_file_at(source: &Path, parent: &Path, name: &OsStr) -> Result<CopyMethod> {
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    let dir = DirHandle::open(parent).with_context(|| format!("无法打开目录: {:?}", parent))?;
+    let dest = parent.join(name);
+    if !dir.exists(name) {
+        return copy_into(source, &dir, name)
+            .with_context(|| format!("无法复制文件: {:?} -> {:?}", source, dest));
+    }
+
+    let temp = temp_name(name);
+    let _ = dir.remove_file(&temp); // 上次失败残留的临时文件
+    let result = copy_into(source, &dir, &temp)
+        .with_context(|| format!("无法复制文件: {:?} -> {:?}", source, dest))
+        .and_then(|method| {
+            dir.rename(&temp, name)
+                .with_context(|| format!("无法替换文件: {:?}", dest))?;
+            Ok(method)
+        });
+    if result.is_err() {
+        let _ = dir.remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn copy_into(source: &Path, dir: &DirHandle, name: &OsStr) -> Result<CopyMethod> {
+    let mut input = File::open(source)?;
+    let permissions = input.metadata()?.permissions();
+    let mut output = dir.create_file(name, permissions.mode() & 0o7777)?;
+    let method = if clone_file(&input, &output) {
+        CopyMethod::Reflink
+    } else {
+        io::copy(&mut input, &mut output)?;
+        CopyMethod::Copy
+    };
+    // 创建时的权限受 umask 影响，与 fs::copy 一样按源文件重新设置
+    output.set_permissions(permissions)?;
+    Ok(method)
+}
+
+/// 在两个已打开的文件之间做写时复制克隆 (Linux FICLONE)
+#[cfg(target_os = "linux")]
+fn clone_file(source: &File, dest: &File) -> bool {
+    use std::os::fd::AsRawFd;
+    // SAFETY: 两个描述符在调用期间都有效
+    unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) == 0 }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn clone_file(_source: &File, _dest: &File) -> bool {
+    false
+}
@@ -0,0 +1,240 @@
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 目录中一个条目的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntryKind {
+    File,
+    Dir,
+    /// 符号链接、设备文件等，遍历时跳过
+    Other,
+}
+
+/// 已打开的目录句柄 (Unix)
+///
+/// 目录下的文件都通过 `openat` 等相对于句柄的调用访问，内核不必为每个文件重新解析
+/// 整条路径；打开子目录与文件时一律带 `O_NOFOLLOW`，检查与使用之间路径中途被换成
+/// 符号链接也不会跟随出去。
+pub(crate) struct DirHandle {
+    fd: OwnedFd,
+}
+
+impl DirHandle {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let path = c_string(path.as_os_str())?;
+        // SAFETY: path 是以 0 结尾的字符串
+        let fd = unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        Self::from_raw(fd)
+    }
+
+    /// 打开直接子目录，子目录是符号链接时失败
+    pub fn open_dir(&self, name: &OsStr) -> io::Result<Self> {
+        let fd = self.openat(
+            name,
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW,
+            0,
+        )?;
+        Ok(Self { fd })
+    }
+
+    /// 以只读方式打开目录下的文件，文件是符号链接时失败
+    pub fn open_file(&self, name: &OsStr) -> io::Result<File> {
+        let fd = self.openat(name, libc::O_RDONLY | libc::O_NOFOLLOW, 0)?;
+        Ok(File::from(fd))
+    }
+
+    /// 新建文件，已存在 (包括同名的符号链接) 时失败
+    pub fn create_file(&self, name: &OsStr, mode: u32) -> io::Result<File> {
+        let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW;
+        let fd = self.openat(name, flags, mode)?;
+        Ok(File::from(fd))
+    }
+
+    /// 在同一目录内改名，目标已存在时整体替换
+    pub fn rename(&self, from: &OsStr, to: &OsStr) -> io::Result<()> {
+        let (from, to) = (c_string(from)?, c_string(to)?);
+        let fd = self.fd.as_raw_fd();
+        // SAFETY: fd 有效，两个名字都是以 0 结尾的字符串
+        let result = unsafe { libc::renameat(fd, from.as_ptr(), fd, to.as_ptr()) };
+        check(result)
+    }
+
+    pub fn remove_file(&self, name: &OsStr) -> io::Result<()> {
+        let name = c_string(name)?;
+        // SAFETY: fd 有效，name 是以 0 结尾的字符串
+        check(unsafe { libc::unlinkat(self.fd.as_raw_fd(), name.as_ptr(), 0) })
+    }
+
+    /// 目录下的条目是否存在 (不跟随符号链接)
+    pub fn exists(&self, name: &OsStr) -> bool {
+        self.kind_of(name).is_ok()
+    }
+
+    /// 列出目录下的条目 (不含 `.` 与 `..`)
+    pub fn entries(&self) -> io::Result<Vec<(OsString, EntryKind)>> {
+        // fdopendir 接管传入的描述符，因此交给它一个副本
+        let dup = self.try_clone()?;
+        // SAFETY: 描述符有效且为目录；成功后由 closedir 负责关闭
+        let dir = unsafe { libc::fdopendir(dup.fd.as_raw_fd()) };
+        if dir.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        std::mem::forget(dup);
+        // 副本与原描述符共享读取位置，从头开始读
+        // SAFETY: dir 有效
+        unsafe { libc::rewinddir(dir) };
+
+        let mut entries = Vec::new();
+        loop {
+            // SAFETY: dir 在 closedir 之前一直有效
+            let entry = unsafe { libc::readdir(dir) };
+            if entry.is_null() {
+                break;
+            }
+            // SAFETY: d_name 是 readdir 返回的以 0 结尾的文件名
+            let name = unsafe { std::ffi::CStr::from_ptr((*entry).d_name.as_ptr()) };
+            let name = OsString::from_vec(name.to_bytes().to_vec());
+            if name == "." || name == ".." {
+                continue;
+            }
+            // SAFETY: entry 非空
+            let kind = match unsafe { (*entry).d_type } {
+                libc::DT_REG => EntryKind::File,
+                libc::DT_DIR => EntryKind::Dir,
+                libc::DT_UNKNOWN => self.kind_of(&name).unwrap_or(EntryKind::Other),
+                _ => EntryKind::Other,
+            };
+            entries.push((name, kind));
+        }
+        // SAFETY: dir 由 fdopendir 返回，只关闭一次
+        unsafe { libc::closedir(dir) };
+        Ok(entries)
+    }
+
+    /// 目录下条目的大小与修改时间 (不跟随符号链接)
+    pub fn size_and_modified(&self, name: &OsStr) -> io::Result<(u64, SystemTime)> {
+        let stat = self.lstat(name)?;
+        let modified = UNIX_EPOCH
+            + Duration::from_secs(stat.st_mtime.max(0) as u64)
+            + Duration::from_nanos(stat.st_mtime_nsec.max(0) as u64);
+        Ok((stat.st_size as u64, modified))
+    }
+
+    fn kind_of(&self, name: &OsStr) -> io::Result<EntryKind> {
+        let mode = self.lstat(name)?.st_mode & libc::S_IFMT;
+        Ok(match mode {
+            libc::S_IFREG => EntryKind::File,
+            libc::S_IFDIR => EntryKind::Dir,
+            _ => EntryKind::Other,
+        })
+    }
+
+    fn lstat(&self, name: &OsStr) -> io::Result<libc::stat> {
+        let c_name = c_string(name)?;
+        let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+        // SAFETY: fd 有效，c_name 以 0 结尾，stat 指向足够大的缓冲区
+        let result = unsafe {
+            libc::fstatat(
+                self.fd.as_raw_fd(),
+                c_name.as_ptr(),
+                stat.as_mut_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        check(result)?;
+        // SAFETY: fstatat 成功时已填充 stat
+        Ok(unsafe { stat.assume_init() })
+    }
+
+    fn openat(&self, name: &OsStr, flags: libc::c_int, mode: u32) -> io::Result<OwnedFd> {
+        let c_name = c_string(name)?;
+        // SAFETY: fd 有效，c_name 是以 0 结尾的字符串
+        let fd = unsafe {
+            libc::openat(
+                self.fd.as_raw_fd(),
+                c_name.as_ptr(),
+                flags | libc::O_CLOEXEC,
+                mode as libc::c_uint,
+            )
+        };
+        Ok(Self::from_raw(fd)?.fd)
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            fd: self.fd.try_clone()?,
+        })
+    }
+
+    fn from_raw(fd: libc::c_int) -> io::Result<Self> {
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd 是刚打开的有效描述符，由 OwnedFd 独占
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+}
+
+/// 通过目录句柄遍历 `root` 下的所有普通文件，对每个文件以 (相对路径, 所在目录, 文件名) 调用 `visit`
+///
+/// 不跟随符号链接，跳过顶层的 `skip_top` 目录与无法打开的子目录。
+/// 深度优先递归，同时打开的目录句柄数不超过目录深度。
+pub(crate) fn visit_files<F>(root: &Path, skip_top: &str, mut visit: F) -> anyhow::Result<()>
+where
+    F: FnMut(&Path, &DirHandle, &OsStr) -> anyhow::Result<()>,
+{
+    let root_dir = DirHandle::open(root)?;
+    visit_dir(&root_dir, &PathBuf::new(), Some(skip_top), &mut visit)
+}
+
+fn visit_dir<F>(
+    dir: &DirHandle,
+    relative_dir: &Path,
+    skip: Option<&str>,
+    visit: &mut F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(&Path, &DirHandle, &OsStr) -> anyhow::Result<()>,
+{
+    let Ok(entries) = dir.entries() else {
+        return Ok(());
+    };
+    for (name, kind) in entries {
+        match kind {
+            EntryKind::File => visit(&relative_dir.join(&name), dir, &name)?,
+            EntryKind::Dir if skip.is_some_and(|skip| name == skip) => {}
+            EntryKind::Dir => {
+                if let Ok(child) = dir.open_dir(&name) {
+                    visit_dir(&child, &relative_dir.join(&name), None, visit)?;
+                }
+            }
+            EntryKind::Other => {}
+        }
+    }
+    Ok(())
+}
+
+fn c_string(name: &OsStr) -> io::Result<CString> {
+    CString::new(name.as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "路径中含有 NUL 字符"))
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{Context, Result, bail};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::status;
+
+/// 两次重试之间最长的等待时间
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// 下载选项
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// 遇到可重试错误 (连接失败、429、5xx) 时的最大重试次数
+    pub max_retries: u32,
+    /// 整个下载 (含所有重试与等待) 的时限
+    pub timeout: Option<Duration>,
+    /// 第一次重试前的等待时间，之后每次翻倍
+    pub initial_backoff: Duration,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            timeout: None,
+            initial_backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl DownloadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+}
+
+/// 单次尝试的结果
+enum Attempt {
+    Done(u64),
+    /// 可重试的失败，附带服务器要求的等待时间 (`Retry-After`)
+    Retry(String, Option<Duration>),
+}
+
+/// 下载 `url` 到 `dest`，返回写入的字节数
+///
+/// 先写到 `dest` 旁的 `.part` 临时文件，完整下载后再改名，失败时不会留下半截文件。
+pub fn download_file(url: &str, dest: &Path, options: &DownloadOptions) -> Result<u64> {
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let part = dest.with_extension("part");
+    let mut backoff = options.initial_backoff;
+
+    for attempt in 1.. {
+        let remaining = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => Some(remaining),
+                _ => bail!("下载超时: {}", url),
+            },
+            None => None,
+        };
+
+        status!("下载 {} (第 {} 次尝试)", url, attempt);
+        let result = try_download(url, &part, remaining);
+        let (error, retry_after) = match result {
+            Ok(Attempt::Done(bytes)) => {
+                fs::rename(&part, dest)?;
+                status!("  完成: {} 字节", bytes);
+                return Ok(bytes);
+            }
+            Ok(Attempt::Retry(error, retry_after)) => (error, retry_after),
+            Err(e) => {
+                let _ = fs::remove_file(&part);
+                return Err(e);
+            }
+        };
+        let _ = fs::remove_file(&part);
+
+        if attempt > options.max_retries {
+            bail!("下载失败 (已尝试 {} 次): {}", attempt, error);
+        }
+        let wait = retry_after.unwrap_or(backoff).min(MAX_BACKOFF);
+        if let Some(deadline) = deadline
+            && Instant::now() + wait >= deadline
+        {
+            bail!("下载超时 (已尝试 {} 次): {}", attempt, error);
+        }
+        status!("  失败: {}，{:.1} 秒后重试", error, wait.as_secs_f64());
+        std::thread::sleep(wait);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+    unreachable!()
+}
+
+fn try_download(url: &str, part: &Path, timeout: Option<Duration>) -> Result<Attempt> {
+    let mut agent = ureq::AgentBuilder::new();
+    if let Some(timeout) = timeout {
+        agent = agent.timeout(timeout);
+    }
+
+    let response = match agent.build().get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) if code == 429 || code >= 500 => {
+            let retry_after = response
+                .header("Retry-After")
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs);
+            return Ok(Attempt::Retry(format!("HTTP {}", code), retry_after));
+        }
+        Err(ureq::Error::Status(code, _)) => bail!("下载失败: HTTP {} ({})", code, url),
+        Err(ureq::Error::Transport(e)) => return Ok(Attempt::Retry(e.to_string(), None)),
+    };
+
+    let mut file = File::create(part).with_context(|| format!("无法创建文件: {:?}", part))?;
+    // 传输中途断开同样可以重试
+    match io::copy(&mut response.into_reader(), &mut file) {
+        Ok(bytes) => Ok(Attempt::Done(bytes)),
+        Err(e) => Ok(Attempt::Retry(e.to_string(), None)),
+    }
+}
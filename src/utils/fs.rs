@@ -1,13 +1,23 @@
-use anyhow::{Context, Result};
-use std::collections::HashMap;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::time::SystemTime;
+use walkdir::{DirEntry, WalkDir};
 
-use super::hash::{HashResult, compute_file_hash};
+use super::hash::{
+    HashAlgorithm, HashResult, compute_file_hash, compute_file_hash_with, compute_hash,
+    hash_reader_with,
+};
+use super::ignore_rules::IgnoreRules;
+use super::magic::FileKind;
+use super::parallel::parallel_map;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// 目录中存放 dft 自身状态的子目录，扫描与比较时忽略
+pub const STATE_DIR: &str = ".dft";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FileInfo {
     pub hash: HashResult,
     pub fsize: usize,
@@ -15,53 +25,291 @@ pub struct FileInfo {
 
 /// 获取目录下所有文件的相对路径和哈希值
 pub fn scan_directory(dir: &Path) -> Result<HashMap<PathBuf, FileInfo>> {
+    scan_directory_with_hashes(dir, &HashMap::new())
+}
+
+/// 获取目录下所有文件的相对路径和哈希值，`known` 中已有的哈希直接复用而不读取文件
+pub fn scan_directory_with_hashes(
+    dir: &Path,
+    known: &HashMap<PathBuf, HashResult>,
+) -> Result<HashMap<PathBuf, FileInfo>> {
     let mut files = HashMap::new();
 
     if !dir.exists() {
         return Ok(files);
     }
 
-    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
-        if !entry.file_type().is_file() {
-            continue;
+    for_each_file(dir, |relative_path, file| {
+        let hash = match known.get(&relative_path) {
+            Some(hash) => hash.clone(),
+            None => hash_reader_with(&file, HashAlgorithm::Sha256)
+                .with_context(|| format!("无法读取文件: {:?}", dir.join(&relative_path)))?,
+        };
+        let fsize = file.metadata()?.len() as usize;
+        files.insert(relative_path, FileInfo { hash, fsize });
+        Ok(())
+    })?;
+
+    Ok(files)
+}
+
+/// 按顶层子目录分片，用 `jobs` 个线程并行计算目录下所有文件的哈希，跳过 `ignore` 匹配的文件
+pub(crate) fn scan_directory_sharded(
+    dir: &Path,
+    known: &HashMap<PathBuf, HashResult>,
+    ignore: &IgnoreRules,
+    jobs: usize,
+) -> Result<HashMap<PathBuf, FileInfo>> {
+    let mut shards: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    if dir.exists() {
+        for entry in walk_files(dir) {
+            let relative_path = entry.path().strip_prefix(dir)?.to_path_buf();
+            if ignore.is_ignored(&relative_path) {
+                continue;
+            }
+            shards
+                .entry(shard_of(&relative_path))
+                .or_default()
+                .push(relative_path);
+        }
+    }
+
+    let scanned = parallel_map(shards.into_values().collect(), jobs, |paths| {
+        paths
+            .into_iter()
+            .map(|relative_path| {
+                let path = dir.join(&relative_path);
+                let hash = match known.get(&relative_path) {
+                    Some(hash) => hash.clone(),
+                    None => compute_file_hash(&path)?,
+                };
+                let fsize = path.metadata()?.len() as usize;
+                Ok((relative_path, FileInfo { hash, fsize }))
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+    Ok(scanned.into_iter().flatten().collect())
+}
+
+/// 文件所属的分片：顶层子目录名，顶层文件归入空路径
+pub(crate) fn shard_of(relative_path: &Path) -> PathBuf {
+    let mut components = relative_path.components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => PathBuf::from(first.as_os_str()),
+        _ => PathBuf::new(),
+    }
+}
+
+/// 遍历目录下的所有文件，以相对路径与只读打开的文件调用 `visit`，跳过顶层的 `.dft` 状态目录
+///
+/// Unix 上通过目录句柄 (openat) 逐级打开，不必为每个文件重新解析整条路径，
+/// 也不会跟随遍历过程中被换成符号链接的目录；其他平台按完整路径打开。
+fn for_each_file(dir: &Path, mut visit: impl FnMut(PathBuf, File) -> Result<()>) -> Result<()> {
+    #[cfg(unix)]
+    {
+        super::dirfd::visit_files(dir, STATE_DIR, |relative_path, parent, name| {
+            let file = parent
+                .open_file(name)
+                .with_context(|| format!("无法打开文件: {:?}", dir.join(relative_path)))?;
+            visit(relative_path.to_path_buf(), file)
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        for entry in walk_files(dir) {
+            let path = entry.path();
+            let relative_path = path
+                .strip_prefix(dir)
+                .with_context(|| format!("无法获取相对路径: {:?}", path))?
+                .to_path_buf();
+            let file = File::open(path).with_context(|| format!("无法打开文件: {:?}", path))?;
+            visit(relative_path, file)?;
+        }
+        Ok(())
+    }
+}
+
+/// 遍历目录下的所有文件，跳过顶层的 `.dft` 状态目录
+pub(crate) fn walk_files(dir: &Path) -> impl Iterator<Item = DirEntry> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|e| !(e.depth() == 1 && e.file_name() == STATE_DIR))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+}
+
+/// 目录内容的 Merkle 指纹：叶子为 `路径 + 文件哈希`，按路径排序后两两合并
+///
+/// 只要任一文件的路径或内容不同，指纹就不同。
+pub fn directory_fingerprint(files: &HashMap<PathBuf, FileInfo>) -> HashResult {
+    let mut leaves: Vec<(String, &HashResult)> = files
+        .iter()
+        .map(|(path, info)| (path.to_string_lossy().replace('\\', "/"), &info.hash))
+        .collect();
+    leaves.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut level: Vec<HashResult> = leaves
+        .into_iter()
+        .map(|(path, hash)| {
+            let mut data = path.into_bytes();
+            data.push(0);
+            data.extend_from_slice(&hash.hash);
+            compute_hash(&data)
+        })
+        .collect();
+    if level.is_empty() {
+        return compute_hash(&[]);
+    }
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => compute_hash(&[left.hash, right.hash].concat()),
+                [single] => single.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level.remove(0)
+}
+
+/// 文件的大小与修改时间，用于快速模式下跳过哈希计算
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// 计算单个文件或整个目录下所有文件的哈希 (路径 -> 哈希)
+///
+/// 目录按顶层子目录分片，`jobs` 大于 1 时并行计算；单个文件以文件名为键。
+pub fn hash_files(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    jobs: usize,
+) -> Result<BTreeMap<String, HashResult>> {
+    if path.is_file() {
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        let hash = compute_file_hash_with(path, algorithm)?;
+        return Ok(BTreeMap::from([(name.to_string_lossy().to_string(), hash)]));
+    }
+    if !path.is_dir() {
+        bail!("路径不存在: {:?}", path);
+    }
+
+    let mut shards: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for entry in walk_files(path) {
+        let relative_path = entry.path().strip_prefix(path)?.to_path_buf();
+        shards
+            .entry(shard_of(&relative_path))
+            .or_default()
+            .push(relative_path);
+    }
+
+    let hashed = parallel_map(shards.into_values().collect(), jobs, |paths| {
+        paths
+            .into_iter()
+            .map(|relative_path| {
+                let hash = compute_file_hash_with(&path.join(&relative_path), algorithm)?;
+                Ok((relative_path.to_string_lossy().to_string(), hash))
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+    Ok(hashed.into_iter().flatten().collect())
+}
+
+/// 内容之外的文件属性：unix 权限位与修改时间 (Unix 时间戳，秒)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FileAttributes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<i64>,
+}
+
+impl FileAttributes {
+    pub fn read(path: &Path) -> Result<Self> {
+        let metadata =
+            std::fs::metadata(path).with_context(|| format!("无法读取文件属性: {:?}", path))?;
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs() as i64);
+        Ok(Self { mode, modified })
+    }
+
+    /// 把属性写到 `path` 上，当前平台不支持的属性忽略
+    pub fn apply_to(&self, path: &Path) -> Result<()> {
+        // 先改时间再改权限，避免权限改为只读后无法打开文件
+        if let Some(secs) = self.modified {
+            let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs.max(0) as u64);
+            File::options()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_modified(time))
+                .with_context(|| format!("无法设置修改时间: {:?}", path))?;
         }
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                .with_context(|| format!("无法设置权限: {:?}", path))?;
+        }
+        Ok(())
+    }
+}
+
+/// 获取目录下所有文件的相对路径、大小与修改时间 (不计算哈希)
+pub fn scan_file_stats(dir: &Path) -> Result<HashMap<PathBuf, FileStat>> {
+    let mut files = HashMap::new();
+
+    if !dir.exists() {
+        return Ok(files);
+    }
+
+    #[cfg(unix)]
+    super::dirfd::visit_files(dir, STATE_DIR, |relative_path, parent, name| {
+        let (size, modified) = parent.size_and_modified(name)?;
+        let stat = FileStat {
+            size,
+            modified: Some(modified),
+        };
+        files.insert(relative_path.to_path_buf(), stat);
+        Ok(())
+    })?;
 
+    #[cfg(not(unix))]
+    for entry in walk_files(dir) {
         let path = entry.path();
         let relative_path = path
             .strip_prefix(dir)
             .with_context(|| format!("无法获取相对路径: {:?}", path))?
             .to_path_buf();
 
-        let hash = compute_file_hash(path)?;
-        let fsize = path.metadata()?.len() as usize;
-        files.insert(relative_path, FileInfo { hash, fsize });
+        let metadata = entry.metadata()?;
+        files.insert(
+            relative_path,
+            FileStat {
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            },
+        );
     }
 
     Ok(files)
 }
 
-/// 判断文件是否为文本文件
+/// 判断文件是否为文本文件 (按文件头识别，见 [`FileKind`])
 pub fn is_text_file(path: &Path) -> bool {
-    const TEXT_EXTENSIONS: &[&str] = &[
-        "txt", "md", "json", "xml", "html", "css", "js", "ts", "py", "rs", "go", "java", "c", "h",
-        "cpp", "hpp", "toml", "yaml", "yml", "ini", "cfg", "conf", "sh", "bat", "ps1", "sql",
-    ];
-
-    if let Some(ext) = path.extension()
-        && let Some(ext_str) = ext.to_str()
-    {
-        return TEXT_EXTENSIONS.contains(&ext_str.to_lowercase().as_str());
-    }
-
-    // 尝试读取文件头部判断
-    if let Ok(file) = File::open(path) {
-        let mut reader = BufReader::new(file);
-        let mut buffer = [0u8; 512];
-        if let Ok(bytes_read) = reader.read(&mut buffer) {
-            // 检查是否包含空字节，如果包含则可能是二进制文件
-            return !buffer[..bytes_read].contains(&0);
-        }
-    }
-
-    false
+    FileKind::of_file(path).is_ok_and(|kind| kind.is_text())
 }
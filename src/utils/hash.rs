@@ -3,11 +3,11 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sha2::{Digest, Sha256};
 use std::fmt;
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{self, BufReader, Read, Write};
 use std::path::Path;
 use std::str::FromStr;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct HashResult {
     pub hash: [u8; 32],
 }
@@ -80,11 +80,79 @@ impl<'de> Deserialize<'de> for HashResult {
     }
 }
 
+/// 以 64 位小写十六进制字符串表示
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for HashResult {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "HashResult".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "string",
+            "pattern": "^[0-9a-f]{64}$",
+        })
+    }
+}
+
+/// 计算内存数据的 SHA256 校验和
+pub fn compute_hash(data: &[u8]) -> HashResult {
+    HashResult {
+        hash: Sha256::digest(data).into(),
+    }
+}
+
 /// 计算文件的 SHA256 校验和
 pub fn compute_file_hash(path: &Path) -> Result<HashResult> {
+    compute_file_hash_with(path, HashAlgorithm::Sha256)
+}
+
+/// 哈希算法，两者的摘要都是 32 字节
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(Self::Sha256),
+            "blake3" => Ok(Self::Blake3),
+            _ => bail!("不支持的哈希算法: {} (可选 sha256、blake3)", s),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sha256 => write!(f, "sha256"),
+            Self::Blake3 => write!(f, "blake3"),
+        }
+    }
+}
+
+/// 以指定算法计算文件的校验和
+pub fn compute_file_hash_with(path: &Path, algorithm: HashAlgorithm) -> Result<HashResult> {
     let file = File::open(path).with_context(|| format!("无法打开文件: {:?}", path))?;
-    let mut reader = BufReader::new(file);
-    let mut hasher = Sha256::new();
+    Ok(hash_reader_with(file, algorithm)?)
+}
+
+/// 读完 `reader` 并计算其内容的 SHA256，例如边下载边计算补丁的哈希
+pub fn hash_reader(reader: impl Read) -> io::Result<HashResult> {
+    hash_reader_with(reader, HashAlgorithm::Sha256)
+}
+
+/// 读完 `reader` 并以指定算法计算其内容的哈希
+pub fn hash_reader_with(reader: impl Read, algorithm: HashAlgorithm) -> io::Result<HashResult> {
+    let mut reader = BufReader::new(reader);
+    let mut sha256 = Sha256::new();
+    let mut blake3 = blake3::Hasher::new();
     let mut buffer = [0u8; 8192];
 
     loop {
@@ -92,10 +160,92 @@ pub fn compute_file_hash(path: &Path) -> Result<HashResult> {
         if bytes_read == 0 {
             break;
         }
-        hasher.update(&buffer[..bytes_read]);
+        match algorithm {
+            HashAlgorithm::Sha256 => sha256.update(&buffer[..bytes_read]),
+            HashAlgorithm::Blake3 => {
+                blake3.update(&buffer[..bytes_read]);
+            }
+        }
     }
 
-    Ok(HashResult {
-        hash: hasher.finalize().into(),
-    })
+    let hash = match algorithm {
+        HashAlgorithm::Sha256 => sha256.finalize().into(),
+        HashAlgorithm::Blake3 => blake3.finalize().into(),
+    };
+    Ok(HashResult { hash })
+}
+
+/// 增量计算 SHA256：数据分块到达时逐块送入 (如下载时每收到一块就写入)，最后取得哈希
+///
+/// 实现了 [`Write`]，可以与文件一起作为 `io::copy` 的目标，落盘后不必再读一遍。
+#[derive(Debug, Clone, Default)]
+pub struct Sha256Stream {
+    hasher: Sha256,
+    len: u64,
+}
+
+impl Sha256Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+        self.len += data.len() as u64;
+    }
+
+    /// 已送入的字节数
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 返回已送入全部数据的哈希值
+    pub fn finish(self) -> HashResult {
+        HashResult {
+            hash: self.hasher.finalize().into(),
+        }
+    }
+}
+
+impl Write for Sha256Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 在读取数据的同时计算 SHA256，用于在复制/压缩的同一遍读取中得到哈希
+pub struct HashingReader<R> {
+    inner: R,
+    stream: Sha256Stream,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            stream: Sha256Stream::new(),
+        }
+    }
+
+    /// 返回已读取全部数据的哈希值
+    pub fn finish(self) -> HashResult {
+        self.stream.finish()
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.stream.update(&buf[..bytes_read]);
+        Ok(bytes_read)
+    }
 }
@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// 一条忽略规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    /// 按 `/` 拆开的各段，`**` 匹配任意层目录
+    parts: Vec<String>,
+    /// 含 `/` 的规则相对于目录根部匹配，否则匹配任意层的同名条目
+    anchored: bool,
+    /// 以 `/` 结尾的规则只匹配目录
+    dir_only: bool,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim().replace('\\', "/");
+        let dir_only = pattern.ends_with('/');
+        let trimmed = pattern.trim_end_matches('/');
+        let anchored = trimmed.contains('/');
+        let parts: Vec<String> = trimmed
+            .trim_start_matches('/')
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .map(str::to_string)
+            .collect();
+        if parts.is_empty() {
+            return None;
+        }
+        Some(Self {
+            parts,
+            anchored,
+            dir_only,
+        })
+    }
+
+    fn matches(&self, components: &[&str]) -> bool {
+        // 规则匹配某个祖先目录时，其下的所有文件都被忽略；只匹配目录的规则不能匹配文件本身
+        let max_len = if self.dir_only {
+            components.len() - 1
+        } else {
+            components.len()
+        };
+        let starts: Vec<usize> = if self.anchored {
+            vec![0]
+        } else {
+            (0..components.len()).collect()
+        };
+        starts.into_iter().any(|start| {
+            (start + 1..=max_len).any(|end| match_parts(&self.parts, &components[start..end]))
+        })
+    }
+}
+
+/// 目录扫描与比较时忽略的路径规则 (`logs/`、`*.tmp`、`config/local/*.toml` 等)
+///
+/// 规则语法与 `.gitignore` 相近：`*`、`?` 匹配一段路径中的任意字符，`**` 匹配任意层目录；
+/// 以 `/` 结尾的规则只匹配目录 (及其下所有文件)；不含 `/` 的规则匹配任意层的同名文件或目录，
+/// 含 `/` 的规则相对于目录根部匹配。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IgnoreRules {
+    patterns: Vec<Pattern>,
+    gitignore: Option<GitignoreStack>,
+}
+
+impl IgnoreRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_pattern(mut self, pattern: &str) -> Self {
+        self.add(pattern);
+        self
+    }
+
+    pub fn add(&mut self, pattern: &str) {
+        self.patterns.extend(Pattern::parse(pattern));
+    }
+
+    /// 从规则文件读取，每行一条，忽略空行与 `#` 开头的注释
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("无法读取忽略规则文件: {}", path.display()))?;
+        let mut rules = Self::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                rules.add(line);
+            }
+        }
+        Ok(rules)
+    }
+
+    /// 追加目录中 `.gitignore` 的规则 (按 git 的规则栈：各级 `.gitignore`、`.git/info/exclude`
+    /// 与全局 `core.excludesFile`，深层的规则优先)，同时忽略 `.git/` 目录本身
+    ///
+    /// 目录不存在时不追加任何规则。
+    pub fn with_gitignore(mut self, root: &Path) -> Result<Self> {
+        self.gitignore = Some(GitignoreStack::load(root)?);
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.gitignore.is_none()
+    }
+
+    /// 相对路径是否被忽略
+    pub fn is_ignored(&self, relative_path: &Path) -> bool {
+        if let Some(gitignore) = &self.gitignore
+            && gitignore.is_ignored(relative_path)
+        {
+            return true;
+        }
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let path = relative_path.to_string_lossy().replace('\\', "/");
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        !components.is_empty() && self.patterns.iter().any(|p| p.matches(&components))
+    }
+}
+
+/// 一个目录树中所有生效的 `.gitignore`，浅层在前
+#[derive(Clone)]
+struct GitignoreStack {
+    root: PathBuf,
+    layers: Vec<Arc<Gitignore>>,
+}
+
+impl GitignoreStack {
+    fn load(root: &Path) -> Result<Self> {
+        let root = std::path::absolute(root)?;
+        let mut layers = Vec::new();
+        if root.is_dir() {
+            let (global, _) = GitignoreBuilder::new(&root).build_global();
+            layers.push(Arc::new(global));
+            // 借用 ignore 的遍历跳过已被忽略的目录，其中的 `.gitignore` 不生效
+            let walker = ignore::WalkBuilder::new(&root)
+                .standard_filters(false)
+                .git_ignore(true)
+                .git_global(true)
+                .git_exclude(true)
+                .require_git(false)
+                .parents(false)
+                .filter_entry(|entry| entry.file_name() != ".git")
+                .build();
+            for entry in walker {
+                let entry = entry.with_context(|| format!("无法扫描目录: {}", root.display()))?;
+                if !entry.file_type().is_some_and(|kind| kind.is_dir()) {
+                    continue;
+                }
+                let dir = entry.path();
+                let mut builder = GitignoreBuilder::new(dir);
+                if dir == root {
+                    builder.add(root.join(".git").join("info").join("exclude"));
+                }
+                let gitignore = dir.join(".gitignore");
+                if gitignore.is_file()
+                    && let Some(e) = builder.add(&gitignore)
+                {
+                    return Err(e).with_context(|| format!("无法读取 {}", gitignore.display()));
+                }
+                let layer = builder
+                    .build()
+                    .with_context(|| format!("无法解析 {}", gitignore.display()))?;
+                if !layer.is_empty() {
+                    layers.push(Arc::new(layer));
+                }
+            }
+            layers.sort_by_key(|layer| layer.path().components().count());
+        }
+        Ok(Self { root, layers })
+    }
+
+    fn is_ignored(&self, relative_path: &Path) -> bool {
+        if relative_path.components().next() == Some(Component::Normal(".git".as_ref())) {
+            return true;
+        }
+        let path = self.root.join(relative_path);
+        for layer in self.layers.iter().rev() {
+            if !path.starts_with(layer.path()) {
+                continue;
+            }
+            match layer.matched_path_or_any_parents(&path, false) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+impl fmt::Debug for GitignoreStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GitignoreStack({})", self.root.display())
+    }
+}
+
+impl PartialEq for GitignoreStack {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root
+    }
+}
+
+impl Eq for GitignoreStack {}
+
+/// 规则的各段与路径的各段逐一匹配，`**` 可匹配零到多段
+fn match_parts(parts: &[String], components: &[&str]) -> bool {
+    match parts.split_first() {
+        None => components.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=components.len()).any(|skip| match_parts(rest, &components[skip..]))
+        }
+        Some((first, rest)) => match components.split_first() {
+            Some((component, remaining)) => {
+                let pattern: Vec<char> = first.chars().collect();
+                let text: Vec<char> = component.chars().collect();
+                match_glob(&pattern, &text) && match_parts(rest, remaining)
+            }
+            None => false,
+        },
+    }
+}
+
+/// 单段路径的通配符匹配：`*` 匹配任意多个字符，`?` 匹配一个字符
+fn match_glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| match_glob(rest, &text[skip..])),
+        Some(('?', rest)) => !text.is_empty() && match_glob(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && match_glob(rest, &text[1..]),
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// 识别文件类型时读取的文件头长度
+const HEADER_LEN: usize = 512;
+
+/// 按文件头魔数识别的文件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Zip,
+    /// 第一个条目位于 `META-INF/` 下的 zip (Java 归档)
+    Jar,
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+    SevenZip,
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Pdf,
+    /// Linux 等系统的可执行文件与共享库
+    Elf,
+    /// Windows 可执行文件与 DLL
+    Pe,
+    MachO,
+    Wasm,
+    /// Java 字节码
+    JavaClass,
+    /// SQLite 数据库
+    Sqlite,
+    /// 带 BOM 或不含空字节的内容
+    Text,
+    /// 未识别的二进制内容
+    Binary,
+}
+
+impl FileKind {
+    /// 按文件头识别类型，`header` 为文件开头的若干字节
+    pub fn detect(header: &[u8]) -> Self {
+        const SIGNATURES: &[(&[u8], FileKind)] = &[
+            (b"\x89PNG\r\n\x1a\n", FileKind::Png),
+            (b"\xff\xd8\xff", FileKind::Jpeg),
+            (b"GIF87a", FileKind::Gif),
+            (b"GIF89a", FileKind::Gif),
+            (b"\x1f\x8b", FileKind::Gzip),
+            (b"\x28\xb5\x2f\xfd", FileKind::Zstd),
+            (b"\xfd7zXZ\x00", FileKind::Xz),
+            (b"BZh", FileKind::Bzip2),
+            (b"7z\xbc\xaf\x27\x1c", FileKind::SevenZip),
+            (b"%PDF-", FileKind::Pdf),
+            (b"\x7fELF", FileKind::Elf),
+            (b"\xcf\xfa\xed\xfe", FileKind::MachO),
+            (b"\xce\xfa\xed\xfe", FileKind::MachO),
+            (b"\xca\xfe\xba\xbe", FileKind::JavaClass),
+            (b"\x00asm", FileKind::Wasm),
+            (b"SQLite format 3\x00", FileKind::Sqlite),
+        ];
+        if let Some((_, kind)) = SIGNATURES
+            .iter()
+            .find(|(magic, _)| header.starts_with(magic))
+        {
+            return *kind;
+        }
+        if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            return if zip_first_entry(header).is_some_and(|name| name.starts_with(b"META-INF/")) {
+                Self::Jar
+            } else {
+                Self::Zip
+            };
+        }
+        if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP") {
+            return Self::Webp;
+        }
+        if header.starts_with(b"MZ") && is_pe(header) {
+            return Self::Pe;
+        }
+        // UTF-8 / UTF-16 BOM
+        if header.starts_with(b"\xef\xbb\xbf")
+            || header.starts_with(b"\xff\xfe")
+            || header.starts_with(b"\xfe\xff")
+        {
+            return Self::Text;
+        }
+        if header.contains(&0) {
+            Self::Binary
+        } else {
+            Self::Text
+        }
+    }
+
+    /// 读取文件开头识别类型
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        File::open(path)?
+            .take(HEADER_LEN as u64)
+            .read_to_end(&mut header)?;
+        Ok(Self::detect(&header))
+    }
+
+    pub fn is_text(&self) -> bool {
+        *self == Self::Text
+    }
+
+    /// 内容已经压缩过，再次压缩几乎没有收益
+    pub fn is_compressed(&self) -> bool {
+        matches!(
+            self,
+            Self::Zip
+                | Self::Jar
+                | Self::Gzip
+                | Self::Zstd
+                | Self::Xz
+                | Self::Bzip2
+                | Self::SevenZip
+                | Self::Png
+                | Self::Jpeg
+                | Self::Gif
+                | Self::Webp
+        )
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Zip => "ZIP 压缩包",
+            Self::Jar => "JAR 包",
+            Self::Gzip => "gzip 压缩文件",
+            Self::Zstd => "zstd 压缩文件",
+            Self::Xz => "xz 压缩文件",
+            Self::Bzip2 => "bzip2 压缩文件",
+            Self::SevenZip => "7z 压缩包",
+            Self::Png => "PNG 图像",
+            Self::Jpeg => "JPEG 图像",
+            Self::Gif => "GIF 图像",
+            Self::Webp => "WebP 图像",
+            Self::Pdf => "PDF 文档",
+            Self::Elf => "ELF 可执行文件",
+            Self::Pe => "PE 可执行文件",
+            Self::MachO => "Mach-O 可执行文件",
+            Self::Wasm => "WebAssembly 模块",
+            Self::JavaClass => "Java 字节码",
+            Self::Sqlite => "SQLite 数据库",
+            Self::Text => "文本",
+            Self::Binary => "二进制",
+        }
+    }
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// zip 本地文件头中第一个条目的文件名
+fn zip_first_entry(header: &[u8]) -> Option<&[u8]> {
+    let name_len = u16::from_le_bytes(header.get(26..28)?.try_into().ok()?) as usize;
+    header.get(30..30 + name_len)
+}
+
+/// `MZ` 头的 `e_lfanew` 处是否为 `PE\0\0` 签名 (在读取的文件头范围内才能确认)
+fn is_pe(header: &[u8]) -> bool {
+    let Some(offset) = header.get(0x3c..0x40) else {
+        return false;
+    };
+    let offset = u32::from_le_bytes(offset.try_into().unwrap_or_default()) as usize;
+    header.get(offset..offset.saturating_add(4)) == Some(b"PE\0\0")
+}
@@ -0,0 +1,39 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::hash::HashResult;
+
+/// 外部提供的 SHA256 清单，生成补丁时直接复用其中的哈希而不重新计算
+///
+/// 清单为 JSON 格式，`source`/`target` 分别对应旧版本与新版本目录，
+/// 键为相对于目录的路径 (使用 `/` 分隔)，值为十六进制哈希：
+///
+/// ```json
+/// {
+///   "source": { "mods/jei.jar": "b94d27b9..." },
+///   "target": { "mods/jei.jar": "7d865e95..." }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HashManifest {
+    #[serde(default)]
+    pub source: HashMap<PathBuf, HashResult>,
+    #[serde(default)]
+    pub target: HashMap<PathBuf, HashResult>,
+}
+
+impl HashManifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 从 JSON 文件加载清单
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("无法读取哈希清单: {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("无法解析哈希清单: {:?}", path))
+    }
+}
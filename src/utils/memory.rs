@@ -0,0 +1,66 @@
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+/// 每个并行任务预留的内存 (读写缓冲区、压缩器状态等)
+const MEMORY_PER_JOB: u64 = 64 * 1024 * 1024;
+
+/// 内存峰值上限，例如 `256MB`
+///
+/// 设置后会收紧并行线程数，并跳过需要把整个文件读入内存的优化
+/// (改名识别、基础补丁增量、字典训练样本)，以速度和补丁大小换取稳定运行。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLimit(u64);
+
+impl MemoryLimit {
+    pub fn new(bytes: u64) -> Self {
+        Self(bytes)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.0
+    }
+
+    /// 在该上限下允许的最大并行线程数
+    pub fn max_jobs(&self) -> usize {
+        (self.0 / MEMORY_PER_JOB).max(1) as usize
+    }
+
+    /// 允许整体读入内存处理的单个文件大小上限 (需要同时容纳新旧两份及中间结果)
+    pub fn max_buffered_file(&self) -> u64 {
+        self.0 / 4
+    }
+}
+
+impl FromStr for MemoryLimit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: u64 = number
+            .parse()
+            .with_context(|| format!("无效的内存大小: {}", s))?;
+        let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "K" | "KB" | "KIB" => 1 << 10,
+            "M" | "MB" | "MIB" => 1 << 20,
+            "G" | "GB" | "GIB" => 1 << 30,
+            _ => bail!("无效的内存大小单位: {}", s),
+        };
+        let bytes = number
+            .checked_mul(multiplier)
+            .with_context(|| format!("内存大小过大: {}", s))?;
+        if bytes == 0 {
+            bail!("内存上限不能为 0");
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl fmt::Display for MemoryLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} MiB", self.0 >> 20)
+    }
+}
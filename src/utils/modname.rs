@@ -0,0 +1,169 @@
+//! 从 mod 文件名中猜测 mod 名称与版本号
+//!
+//! 文件名没有统一规范，这里只按常见写法做启发式解析，例如
+//! `jei-1.20.1-15.2.0.27.jar`、`fabric-api-0.92.0+1.20.1.jar`、
+//! `Xaeros_Minimap_23.9.7_Forge_1.20.jar`。
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// 会被当作包装而去掉的扩展名
+const EXTENSIONS: &[&str] = &["jar", "zip", "disabled"];
+
+/// 从文件名解析出的 mod 名称与版本
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModName {
+    pub name: String,
+    pub version: String,
+    /// 文件名中同时出现的游戏版本 (如 `1.20.1`)
+    pub game_version: Option<String>,
+}
+
+impl ModName {
+    /// 解析文件名 (可带目录)，找不到版本号时返回 `None`
+    pub fn parse(file_name: &str) -> Option<Self> {
+        let file_name = file_name.rsplit(['/', '\\']).next().unwrap_or(file_name);
+        let mut stem = file_name;
+        while let Some((rest, ext)) = stem.rsplit_once('.')
+            && EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+        {
+            stem = rest;
+        }
+
+        // 按分隔符切分为 (起始偏移, 片段)
+        let mut tokens = Vec::new();
+        let mut start = 0;
+        for (i, c) in stem.char_indices() {
+            if matches!(c, '-' | '_' | '+' | ' ') {
+                tokens.push((start, &stem[start..i]));
+                start = i + 1;
+            }
+        }
+        tokens.push((start, &stem[start..]));
+
+        // 名称至少占一个片段 (有些 mod 名本身以数字开头，如 `3dskinlayers`)
+        let first_version = tokens.iter().skip(1).position(|(_, t)| is_version(t))? + 1;
+        let name = stem[..tokens[first_version].0]
+            .trim_end_matches(['-', '_', '+', ' '])
+            .to_string();
+        if name.is_empty() {
+            return None;
+        }
+
+        let versions: Vec<&str> = tokens[first_version..]
+            .iter()
+            .map(|(_, t)| *t)
+            .filter(|t| is_version(t))
+            .collect();
+        let game_version = versions
+            .iter()
+            .find(|t| is_game_version(t))
+            .filter(|_| versions.len() > 1)
+            .map(|t| t.trim_start_matches("mc").to_string());
+        let version = versions
+            .iter()
+            .find(|t| game_version.is_none() || !is_game_version(t))
+            .map(|t| t.trim_start_matches('v').to_string())?;
+
+        Some(Self {
+            name,
+            version,
+            game_version,
+        })
+    }
+
+    /// 用于配对同一 mod 的键 (忽略大小写与分隔符差异)
+    fn key(&self) -> String {
+        self.name
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+}
+
+/// 同一 mod 从一个版本的文件换成另一个版本的文件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ModUpgrade {
+    pub name: String,
+    /// 被删除的旧文件路径
+    pub from: String,
+    /// 新增的新文件路径
+    pub to: String,
+    pub from_version: String,
+    pub to_version: String,
+}
+
+impl fmt::Display for ModUpgrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "↑ {} {} -> {}",
+            self.name, self.from_version, self.to_version
+        )
+    }
+}
+
+/// 把“删除旧版本文件 + 新增新版本文件”配对为升级
+///
+/// 只在同一目录下、同名 mod 恰好删除一个并新增一个时配对，避免误判。
+pub fn find_upgrades<'a>(
+    deleted: impl IntoIterator<Item = &'a str>,
+    added: impl IntoIterator<Item = &'a str>,
+) -> Vec<ModUpgrade> {
+    type Candidates<'a> = HashMap<(&'a str, String), Vec<(&'a str, ModName)>>;
+
+    fn group<'a>(paths: impl IntoIterator<Item = &'a str>) -> Candidates<'a> {
+        let mut groups: Candidates = HashMap::new();
+        for path in paths {
+            if let Some(parsed) = ModName::parse(path) {
+                let dir = path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+                groups
+                    .entry((dir, parsed.key()))
+                    .or_default()
+                    .push((path, parsed));
+            }
+        }
+        groups
+    }
+
+    let added = group(added);
+    let mut upgrades: Vec<ModUpgrade> = group(deleted)
+        .into_iter()
+        .filter_map(|(key, old)| {
+            let new = added.get(&key)?;
+            let ([(from, old)], [(to, new)]) = (old.as_slice(), new.as_slice()) else {
+                return None;
+            };
+            Some(ModUpgrade {
+                name: new.name.clone(),
+                from: from.to_string(),
+                to: to.to_string(),
+                from_version: old.version.clone(),
+                to_version: new.version.clone(),
+            })
+        })
+        .collect();
+    upgrades.sort_by(|a, b| a.to.cmp(&b.to));
+    upgrades
+}
+
+/// 以数字开头 (可带 `v`/`mc` 前缀) 的片段视为版本号
+fn is_version(token: &str) -> bool {
+    let token = token
+        .strip_prefix("mc")
+        .or_else(|| token.strip_prefix('v'))
+        .unwrap_or(token);
+    token.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// 形如 `1.20`、`1.20.1`、`mc1.19.2` 的游戏版本号
+fn is_game_version(token: &str) -> bool {
+    let token = token.strip_prefix("mc").unwrap_or(token);
+    let parts: Vec<&str> = token.split('.').collect();
+    matches!(parts.as_slice(), ["1", minor] | ["1", minor, _]
+        if minor.parse::<u32>().is_ok_and(|m| (7..=30).contains(&m)))
+        && parts.iter().all(|p| p.parse::<u32>().is_ok())
+}
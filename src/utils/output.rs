@@ -0,0 +1,145 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+static STATUS_LOG: Mutex<Option<File>> = Mutex::new(None);
+
+/// 设置是否静默库内的进度输出 (例如 CLI 以 JSON 输出结果时)
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// 当前是否静默进度输出
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// 设置进度输出是否改为写到标准错误 (例如补丁包写到标准输出时)
+pub fn set_status_to_stderr(to_stderr: bool) {
+    TO_STDERR.store(to_stderr, Ordering::Relaxed);
+}
+
+/// 进度输出当前是否写到标准错误
+pub fn is_status_to_stderr() -> bool {
+    TO_STDERR.load(Ordering::Relaxed)
+}
+
+/// 把进度输出同时追加到日志文件 (带时间戳，不受静默模式影响)；`None` 关闭日志
+pub fn set_status_log(path: Option<&Path>) -> io::Result<()> {
+    let file = match path {
+        Some(path) => {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            Some(OpenOptions::new().create(true).append(true).open(path)?)
+        }
+        None => None,
+    };
+    *STATUS_LOG.lock().unwrap_or_else(|e| e.into_inner()) = file;
+    Ok(())
+}
+
+/// 只写入日志文件的一行 (例如最终的错误信息)，未设置日志时不做任何事
+pub fn write_status_log(message: &str) {
+    let mut log = STATUS_LOG.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(file) = log.as_mut() {
+        let time = super::log_timestamp();
+        for line in message.lines() {
+            let _ = writeln!(file, "[{}] {}", time, line);
+        }
+        if message.is_empty() {
+            let _ = writeln!(file);
+        }
+    }
+}
+
+/// `status!` 的实现：写入日志，非静默时再输出到终端
+#[doc(hidden)]
+pub fn write_status(args: fmt::Arguments) {
+    let message = args.to_string();
+    write_status_log(&message);
+    if !is_quiet() {
+        if is_status_to_stderr() {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+}
+
+/// 展示较长的文本 (如补丁公告)：标准输出是终端时交给分页器 (`$PAGER`，默认 `less`/`more`)，
+/// 否则与进度输出一样直接打印；同样写入日志，静默模式下不输出
+pub fn show_paged(text: &str) {
+    write_status_log(text);
+    if is_quiet() {
+        return;
+    }
+    if is_status_to_stderr() {
+        eprintln!("{}", text);
+    } else if !io::stdout().is_terminal() || run_pager(text).is_err() {
+        println!("{}", text);
+    }
+}
+
+fn run_pager(text: &str) -> io::Result<()> {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "more" } else { "less -FRX" }.to_string());
+    let mut args = pager.split_whitespace();
+    let program = args.next().unwrap_or_default();
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // 用户提前退出分页器时写入会失败，忽略即可
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// 以 B/KiB/MiB/GiB 显示字节数
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// 带正负号显示字节数的增减
+pub fn format_size_delta(bytes: i64) -> String {
+    let sign = match bytes.signum() {
+        1 => "+",
+        -1 => "-",
+        _ => "",
+    };
+    format!("{}{}", sign, format_size(bytes.unsigned_abs()))
+}
+
+/// 打印进度信息，静默模式下不输出；设置了日志文件时同时写入日志
+macro_rules! status {
+    () => {
+        $crate::utils::write_status(format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::utils::write_status(format_args!($($arg)*))
+    };
+}
+
+pub(crate) use status;
@@ -0,0 +1,55 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, sync_channel};
+
+/// 不超过该大小的文件由后台线程整体预读
+const SMALL_FILE_LIMIT: u64 = 64 * 1024;
+
+/// 预读队列的深度 (最多占用 `PREFETCH_DEPTH * SMALL_FILE_LIMIT` 字节内存)
+const PREFETCH_DEPTH: usize = 256;
+
+/// 已读入内存的小文件
+pub(crate) struct PrefetchedFile {
+    pub data: Vec<u8>,
+    pub metadata: fs::Metadata,
+}
+
+/// 由后台线程按顺序预读一组文件中的小文件
+///
+/// 数万个小文件时逐个 open/read 的等待占主导，预读线程与打包、压缩并行进行。
+/// 调用方按同样的顺序对每个文件调用一次 [`Prefetcher::next`]。
+pub(crate) struct Prefetcher {
+    receiver: Receiver<Option<PrefetchedFile>>,
+}
+
+impl Prefetcher {
+    pub fn spawn(paths: Vec<PathBuf>) -> Self {
+        let (sender, receiver) = sync_channel(PREFETCH_DEPTH);
+        // 消费方提前结束时发送失败，线程随之退出
+        std::thread::spawn(move || {
+            for path in paths {
+                if sender.send(read_small(&path)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { receiver }
+    }
+
+    /// 下一个文件的内容；文件较大或读取失败时为 `None`，由调用方自行读取 (并报告错误)
+    pub fn next(&mut self) -> Option<PrefetchedFile> {
+        self.receiver.recv().ok().flatten()
+    }
+}
+
+fn read_small(path: &Path) -> Option<PrefetchedFile> {
+    let mut file = File::open(path).ok()?;
+    let metadata = file.metadata().ok()?;
+    if !metadata.is_file() || metadata.len() > SMALL_FILE_LIMIT {
+        return None;
+    }
+    let mut data = Vec::with_capacity(metadata.len() as usize);
+    file.read_to_end(&mut data).ok()?;
+    Some(PrefetchedFile { data, metadata })
+}
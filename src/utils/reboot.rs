@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use std::io;
+use std::path::Path;
+
+/// 错误是否因为文件正被占用 (Windows 上运行中的程序、已加载的 dll)
+pub fn is_file_in_use(err: &io::Error) -> bool {
+    // ERROR_ACCESS_DENIED、ERROR_SHARING_VIOLATION、ERROR_LOCK_VIOLATION
+    cfg!(windows) && matches!(err.raw_os_error(), Some(5 | 32 | 33))
+}
+
+/// 安排在下次重启时把 `source` 移动为 `dest`，`dest` 为 `None` 时删除 `source`
+///
+/// 使用 `MoveFileExW(MOVEFILE_DELAY_UNTIL_REBOOT)`，需要管理员权限，
+/// 且 `source` 与 `dest` 必须位于同一卷上。
+#[cfg(windows)]
+pub fn move_on_reboot(source: &Path, dest: Option<&Path>) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    const MOVEFILE_REPLACE_EXISTING: u32 = 0x1;
+    const MOVEFILE_DELAY_UNTIL_REBOOT: u32 = 0x4;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn MoveFileExW(existing: *const u16, new: *const u16, flags: u32) -> i32;
+    }
+
+    // 延迟操作在启动早期执行，必须使用绝对路径
+    let wide = |path: &Path| -> Result<Vec<u16>> {
+        Ok(std::path::absolute(path)?
+            .as_os_str()
+            .encode_wide()
+            .chain(Some(0))
+            .collect())
+    };
+    let source_wide = wide(source)?;
+    let dest_wide = dest.map(wide).transpose()?;
+    let dest_ptr = dest_wide
+        .as_ref()
+        .map_or(std::ptr::null(), |dest| dest.as_ptr());
+
+    // SAFETY: 两个指针都指向以 0 结尾的 UTF-16 字符串 (或为空)，调用期间保持有效
+    let ok = unsafe {
+        MoveFileExW(
+            source_wide.as_ptr(),
+            dest_ptr,
+            MOVEFILE_REPLACE_EXISTING | MOVEFILE_DELAY_UNTIL_REBOOT,
+        )
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("无法安排重启后替换: {:?}", source));
+    }
+    Ok(())
+}
+
+/// 安排在下次重启时把 `source` 移动为 `dest`，仅 Windows 支持
+#[cfg(not(windows))]
+pub fn move_on_reboot(source: &Path, _dest: Option<&Path>) -> Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+        .with_context(|| format!("仅 Windows 支持重启后替换: {:?}", source))
+}
@@ -1,10 +1,27 @@
 use anyhow::Result;
+use bin_diff_tool::FileDiff;
 use bin_diff_tool::patch::{
-    apply_patch, compare_directories, create_patch, merge_patches, show_patch,
+    ApplyOptions, ArchiveIssue, AuditLog, BatchOptions, BatchPolicy, BatchStatus, ChangelogFormat,
+    Checksums, CompressionFormat, CompressionOptions, CreateOptions, EntryEncoding, EventBus,
+    FileSizes, HookPolicy, LoadedPatch, MergeOptions, MismatchReason, ModifiedChecksum, MultiSpec,
+    Patch, PatchError, PatchEvent, Platform, ShowOptions, Snapshot, TOOL_VERSION, VerifyMode,
+    WarningKind, WarningPolicy, analyze_patch, apply_batch, apply_fixup, apply_patch,
+    apply_patch_into, apply_patch_with_options, compare_directories, compare_directories_fast,
+    compare_directories_with_attributes, compare_directories_with_options, compare_snapshots,
+    create_fixup, create_multi_patches, create_patch, create_patch_with_options, derive_patch,
+    inspect_directory, inspect_patch, merge_patches, merge_patches_with_options, patch_changelog,
+    revert_patch, show_patch, show_patch_with_options, validate_patch, verify_roundtrip,
+    write_html_report,
+};
+use bin_diff_tool::utils::modname::{ModName, find_upgrades};
+use bin_diff_tool::utils::{
+    FileAttributes, FileKind, HashAlgorithm, HashManifest, IgnoreRules, LinkMode, MemoryLimit,
+    PatchCache, Sha256Stream, compute_file_hash, compute_hash, hash_files, hash_reader,
+    is_text_file, scan_directory, scan_file_stats,
 };
-use bin_diff_tool::utils::{compute_file_hash, is_text_file, scan_directory};
 use std::collections::HashSet;
 use std::fs;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Mutex, OnceLock};
 use tempfile::TempDir;
@@ -41,6 +58,21 @@ fn copy_dir(source: &Path, dest: &Path) {
     }
 }
 
+/// Unpacks `patch`, lets `edit` tamper with the entries and packs the result into `output`.
+fn repack_patch(patch: &Path, output: &Path, edit: impl FnOnce(&Path)) -> Result<()> {
+    let unpacked = TempDir::new()?;
+    let file = fs::File::open(patch)?;
+    tar::Archive::new(flate2::read::MultiGzDecoder::new(file)).unpack(unpacked.path())?;
+    edit(unpacked.path());
+
+    let encoder =
+        flate2::write::GzEncoder::new(fs::File::create(output)?, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder.append_dir_all(".", unpacked.path())?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
 #[test]
 fn compute_file_hash_matches_expected_value() -> Result<()> {
     let dir = TempDir::new()?;
@@ -239,3 +271,4330 @@ fn merge_patches_panics_with_invalid_inputs() {
 
     merge_patches(&first, &second, &output).unwrap();
 }
+
+#[test]
+fn apply_patch_skips_entries_for_other_platforms() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("native.tgz");
+
+    let current = Platform::current().to_string();
+    let other = if std::env::consts::OS == "windows" {
+        "linux-x86_64"
+    } else {
+        "windows-x86_64"
+    };
+
+    write_file(source.path(), "common.txt", b"same");
+    write_file(target.path(), "common.txt", b"same");
+    write_file(target.path(), &format!("{}/native.lib", current), b"ours");
+    write_file(target.path(), &format!("{}/native.lib", other), b"theirs");
+
+    create_patch(source.path(), target.path(), &output)?;
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    apply_patch(apply_dir.path(), &output)?;
+
+    assert!(apply_dir.path().join(&current).join("native.lib").exists());
+    assert!(!apply_dir.path().join(other).join("native.lib").exists());
+    Ok(())
+}
+
+fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[test]
+fn renamed_file_with_small_change_is_stored_as_delta() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("rename.tgz");
+
+    let original = pseudo_random_bytes(256 * 1024, 42);
+    let mut changed = original.clone();
+    changed.splice(100_000..100_010, b"inserted bytes".iter().copied());
+
+    write_file(source.path(), "libs/mod-1.0.jar", &original);
+    write_file(target.path(), "mods/mod-1.1.jar", &changed);
+
+    create_patch(source.path(), target.path(), &output)?;
+    assert!(fs::metadata(&output)?.len() < 64 * 1024);
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    apply_patch(apply_dir.path(), &output)?;
+
+    let expected = scan_directory(target.path())?;
+    let actual = scan_directory(apply_dir.path())?;
+    assert_eq!(expected, actual);
+    Ok(())
+}
+
+#[test]
+fn modified_file_is_stored_as_delta_and_merged_patches_compose() -> Result<()> {
+    let _guard = patch_lock();
+
+    let v0 = TempDir::new()?;
+    let v1 = TempDir::new()?;
+    let v2 = TempDir::new()?;
+    let mut data = pseudo_random_bytes(1024 * 1024, 21);
+    write_file(v0.path(), "world/region.mca", &data);
+    data.splice(300_000..300_001, b"a few inserted bytes".iter().copied());
+    write_file(v1.path(), "world/region.mca", &data);
+    data[700_000..700_016].copy_from_slice(b"patched in v2!!!");
+    write_file(v2.path(), "world/region.mca", &data);
+
+    let patch_dir = TempDir::new()?;
+    let first = patch_dir.path().join("v1.tgz");
+    let second = patch_dir.path().join("v2.tgz");
+    let merged = patch_dir.path().join("merged.tgz");
+    let report = create_patch(v0.path(), v1.path(), &first)?;
+    assert_eq!(report.checksums.delta_modified, vec!["world/region.mca"]);
+    assert!(matches!(
+        report.checksums.encodings["world/region.mca"],
+        EntryEncoding::Delta { .. }
+    ));
+    assert!(fs::metadata(&first)?.len() < 4 * 1024);
+    create_patch(v1.path(), v2.path(), &second)?;
+    merge_patches(&first, &second, &merged)?;
+
+    for (patch, base, expected) in [(&first, &v0, &v1), (&merged, &v0, &v2)] {
+        let apply_dir = TempDir::new()?;
+        copy_dir(base.path(), apply_dir.path());
+        apply_patch(apply_dir.path(), patch)?;
+        assert!(compare_directories(apply_dir.path(), expected.path())?.is_empty());
+    }
+
+    // The delta cannot be restored on top of a locally changed file
+    let apply_dir = TempDir::new()?;
+    copy_dir(v1.path(), apply_dir.path());
+    write_file(apply_dir.path(), "world/region.mca", b"local edits");
+    assert!(apply_patch(apply_dir.path(), &second).is_err());
+    Ok(())
+}
+
+#[test]
+fn merge_patches_composes_consecutive_renames() -> Result<()> {
+    let _guard = patch_lock();
+
+    let base = TempDir::new()?;
+    let mid = TempDir::new()?;
+    let final_dir = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let patch_one = patch_dir.path().join("one.tgz");
+    let patch_two = patch_dir.path().join("two.tgz");
+    let merged_patch = patch_dir.path().join("merged.tgz");
+
+    let v1 = pseudo_random_bytes(128 * 1024, 7);
+    let mut v2 = v1.clone();
+    v2.splice(10_000..10_000, b"first change".iter().copied());
+    let mut v3 = v2.clone();
+    v3.splice(90_000..90_100, b"second change".iter().copied());
+
+    write_file(base.path(), "a.bin", &v1);
+    write_file(mid.path(), "b.bin", &v2);
+    write_file(final_dir.path(), "c/c.bin", &v3);
+
+    create_patch(base.path(), mid.path(), &patch_one)?;
+    create_patch(mid.path(), final_dir.path(), &patch_two)?;
+    merge_patches(&patch_one, &patch_two, &merged_patch)?;
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(base.path(), apply_dir.path());
+    apply_patch(apply_dir.path(), &merged_patch)?;
+
+    let expected = scan_directory(final_dir.path())?;
+    let actual = scan_directory(apply_dir.path())?;
+    assert_eq!(expected, actual);
+    Ok(())
+}
+
+#[test]
+fn fast_compare_trusts_size_and_mtime() -> Result<()> {
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+
+    let stamp = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    for (root, contents) in [(source.path(), b"aaaa"), (target.path(), b"bbbb")] {
+        let path = write_file(root, "same_stat.txt", contents);
+        fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(stamp)?;
+    }
+    write_file(source.path(), "grown.txt", b"v1");
+    write_file(target.path(), "grown.txt", b"v1 longer");
+
+    let fast: Vec<String> = compare_directories_fast(source.path(), target.path())?
+        .iter()
+        .map(|d| d.path().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(fast, vec!["grown.txt".to_string()]);
+
+    // A full comparison still notices the content change.
+    assert_eq!(compare_directories(source.path(), target.path())?.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn apply_patch_into_links_unchanged_files_and_keeps_base() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("patch.tgz");
+
+    write_file(source.path(), "keep.txt", b"same");
+    write_file(source.path(), "change.txt", b"v1");
+    write_file(target.path(), "keep.txt", b"same");
+    write_file(target.path(), "change.txt", b"v2");
+
+    create_patch(source.path(), target.path(), &output)?;
+
+    let base = TempDir::new()?;
+    copy_dir(source.path(), base.path());
+    let new_version = TempDir::new()?;
+    let new_dir = new_version.path().join("v2");
+    apply_patch_into(
+        base.path(),
+        &new_dir,
+        &output,
+        &ApplyOptions::new().with_link_unchanged(LinkMode::Hard),
+    )?;
+
+    assert_eq!(scan_directory(&new_dir)?, scan_directory(target.path())?);
+    assert_eq!(scan_directory(base.path())?, scan_directory(source.path())?);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert_eq!(
+            fs::metadata(base.path().join("keep.txt"))?.ino(),
+            fs::metadata(new_dir.join("keep.txt"))?.ino()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn create_patch_reuses_hashes_from_manifest() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("patch.tgz");
+
+    write_file(source.path(), "trusted.txt", b"old");
+    write_file(target.path(), "trusted.txt", b"new");
+    write_file(target.path(), "added.txt", b"added");
+
+    // The manifest claims both versions are identical, so no hashing happens
+    // and the content change is deliberately not detected.
+    let fake = "00".repeat(32);
+    let manifest = write_file(
+        patch_dir.path(),
+        "manifest.json",
+        format!(
+            r#"{{"source": {{"trusted.txt": "{fake}"}}, "target": {{"trusted.txt": "{fake}"}}}}"#
+        )
+        .as_bytes(),
+    );
+    let options = CreateOptions::new().with_hash_manifest(HashManifest::load(&manifest)?);
+    create_patch_with_options(source.path(), target.path(), &output, &options)?;
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    apply_patch(apply_dir.path(), &output)?;
+
+    assert_eq!(fs::read(apply_dir.path().join("trusted.txt"))?, b"old");
+    assert!(apply_dir.path().join("added.txt").exists());
+    Ok(())
+}
+
+#[test]
+fn compare_snapshots_works_without_directories() -> Result<()> {
+    let dir = TempDir::new()?;
+    write_file(dir.path(), "a.txt", b"one");
+    write_file(dir.path(), "b.txt", b"two");
+    let on_disk = Snapshot::scan(dir.path())?;
+
+    // Round-trip through JSON as a server holding only manifests would.
+    let stored: Snapshot = serde_json::from_str(&serde_json::to_string(&on_disk)?)?;
+    assert_eq!(stored, on_disk);
+
+    let mut next = stored.clone();
+    next.files.remove(Path::new("a.txt"));
+    next.insert(
+        "b.txt",
+        compute_file_hash(&write_file(dir.path(), "b2", b"2"))?,
+        1,
+    );
+    next.insert(
+        "c.txt",
+        compute_file_hash(&write_file(dir.path(), "c", b"3"))?,
+        1,
+    );
+
+    let diff_set: HashSet<(String, String)> = compare_snapshots(&stored, &next)
+        .into_iter()
+        .map(|d| {
+            (
+                d.symbol().to_string(),
+                d.path().to_string_lossy().to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(diff_set.len(), 3);
+    assert!(diff_set.contains(&("-".to_string(), "a.txt".to_string())));
+    assert!(diff_set.contains(&("*".to_string(), "b.txt".to_string())));
+    assert!(diff_set.contains(&("+".to_string(), "c.txt".to_string())));
+    Ok(())
+}
+
+#[test]
+fn reports_describe_applied_changes() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("patch.tgz");
+
+    write_file(source.path(), "remove.txt", b"old");
+    write_file(source.path(), "change.txt", b"v1");
+    write_file(target.path(), "change.txt", b"v2");
+    write_file(target.path(), "new.txt", b"new");
+
+    let created = create_patch(source.path(), target.path(), &output)?;
+    assert_eq!(created.output.as_deref(), Some(output.as_path()));
+    assert_eq!(created.checksums.added.len(), 1);
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let report = apply_patch(apply_dir.path(), &output)?;
+
+    assert_eq!(report.added, vec!["new.txt".to_string()]);
+    assert_eq!(report.modified, vec!["change.txt".to_string()]);
+    assert_eq!(report.deleted, vec!["remove.txt".to_string()]);
+    assert!(report.warnings.is_empty());
+
+    let json = serde_json::to_value(&report)?;
+    assert_eq!(json["added"][0], "new.txt");
+    Ok(())
+}
+
+#[test]
+fn long_paths_are_stored_with_pax_headers() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("patch.tgz");
+
+    let long_path = format!(
+        "{}/{}.jar",
+        "nested_directory_name".repeat(4),
+        "x".repeat(120)
+    );
+    write_file(target.path(), &long_path, b"long path contents");
+
+    create_patch(source.path(), target.path(), &output)?;
+
+    // The archive must describe the path with a PAX header, not a GNU long name entry.
+    let file = fs::File::open(&output)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    let mut found = false;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        assert_ne!(entry.header().entry_type(), tar::EntryType::GNULongName);
+        if entry.path()?.to_string_lossy() == format!("added/{}", long_path) {
+            assert!(entry.pax_extensions()?.is_some());
+            found = true;
+        }
+    }
+    assert!(found);
+
+    let apply_dir = TempDir::new()?;
+    apply_patch(apply_dir.path(), &output)?;
+    assert_eq!(
+        fs::read(apply_dir.path().join(&long_path))?,
+        b"long path contents"
+    );
+    Ok(())
+}
+
+#[test]
+fn patch_archive_has_sorted_file_entries_only() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("patch.tgz");
+
+    for name in ["z.txt", "a/b/c.txt", "m/n.txt", "a/a.txt"] {
+        write_file(target.path(), name, name.as_bytes());
+    }
+    create_patch(source.path(), target.path(), &output)?;
+
+    let file = fs::File::open(&output)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    let mut added = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        assert_ne!(entry.header().entry_type(), tar::EntryType::Directory);
+        let path = entry.path()?.to_string_lossy().to_string();
+        if let Some(name) = path.strip_prefix("added/") {
+            added.push(name.to_string());
+        }
+    }
+    assert_eq!(added, ["a/a.txt", "a/b/c.txt", "m/n.txt", "z.txt"]);
+    Ok(())
+}
+
+#[test]
+fn apply_rejects_files_missing_from_checksums() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("patch.tgz");
+
+    write_file(target.path(), "listed.txt", b"listed");
+    create_patch(source.path(), target.path(), &output)?;
+
+    // Repack the patch with an extra file that checksums.toml does not know about.
+    let tampered = patch_dir.path().join("tampered.tgz");
+    repack_patch(&output, &tampered, |unpacked| {
+        write_file(unpacked, "added/unlisted.txt", b"sneaky");
+    })?;
+
+    let apply_dir = TempDir::new()?;
+    assert!(apply_patch(apply_dir.path(), &tampered).is_err());
+    assert!(!apply_dir.path().join("unlisted.txt").exists());
+    assert!(!apply_dir.path().join("listed.txt").exists());
+    Ok(())
+}
+
+#[test]
+fn info_reports_fingerprint_and_last_applied_patch() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("update.tgz");
+
+    write_file(source.path(), "a.txt", b"one");
+    write_file(target.path(), "a.txt", b"two");
+    write_file(target.path(), "dir/b.bin", &[1, 2, 3]);
+    create_patch(source.path(), target.path(), &output)?;
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    assert!(inspect_directory(apply_dir.path())?.last_patch.is_none());
+    apply_patch(apply_dir.path(), &output)?;
+
+    // The .dft state directory is excluded, so the result matches the target exactly.
+    let applied = inspect_directory(apply_dir.path())?;
+    let expected = inspect_directory(target.path())?;
+    assert_eq!(applied.file_count, 2);
+    assert_eq!(applied.total_size, 6);
+    assert_eq!(applied.fingerprint, expected.fingerprint);
+    assert_ne!(
+        applied.fingerprint,
+        inspect_directory(source.path())?.fingerprint
+    );
+
+    let state = applied.last_patch.expect("state should be recorded");
+    assert_eq!(state.patch, "update.tgz");
+    assert_eq!(state.patch_hash, compute_file_hash(&output)?);
+    assert_eq!((state.added, state.modified), (1, 1));
+    assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn snapshot_refresh_tracks_incremental_changes() -> Result<()> {
+    let _guard = patch_lock();
+
+    let dir = TempDir::new()?;
+    write_file(dir.path(), "keep.txt", b"keep");
+    write_file(dir.path(), "edit.txt", b"before");
+    write_file(dir.path(), "gone/a.txt", b"a");
+    write_file(dir.path(), "gone/b.txt", b"b");
+    let mut snapshot = Snapshot::scan(dir.path())?;
+
+    write_file(dir.path(), "edit.txt", b"after");
+    fs::remove_dir_all(dir.path().join("gone"))?;
+    write_file(dir.path(), "new/deep/c.txt", b"c");
+    let changed = [
+        dir.path().join("edit.txt"),
+        dir.path().join("gone"),
+        dir.path().join("new"),
+    ];
+    snapshot.refresh(dir.path(), &changed)?;
+    assert_eq!(snapshot, Snapshot::scan(dir.path())?);
+
+    // A saved snapshot can stand in for scanning the target directory.
+    let snapshot_file = TempDir::new()?;
+    let snapshot_path = snapshot_file.path().join("snapshot.json");
+    snapshot.save(&snapshot_path)?;
+
+    let source = TempDir::new()?;
+    write_file(source.path(), "keep.txt", b"keep");
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("patch.tgz");
+    let options = CreateOptions::new().with_target_snapshot(Snapshot::load(&snapshot_path)?);
+    let report = create_patch_with_options(source.path(), dir.path(), &output, &options)?;
+    assert_eq!(report.checksums.added.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn parallel_create_produces_equivalent_patch() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    for shard in ["mods", "config", "libraries"] {
+        for i in 0..5 {
+            let name = format!("{}/file_{}.bin", shard, i);
+            write_file(source.path(), &name, &pseudo_random_bytes(2048, i));
+            write_file(target.path(), &name, &pseudo_random_bytes(2048, i + 100));
+        }
+        write_file(
+            target.path(),
+            &format!("{}/new.txt", shard),
+            shard.as_bytes(),
+        );
+    }
+    write_file(source.path(), "root.txt", b"old root");
+    write_file(target.path(), "root.txt", b"new root");
+
+    let patch_dir = TempDir::new()?;
+    let output = patch_dir.path().join("parallel.tgz");
+    let options = CreateOptions::new()
+        .with_rename_threshold(None)
+        .with_jobs(4);
+    let report = create_patch_with_options(source.path(), target.path(), &output, &options)?;
+    assert_eq!(report.checksums.added.len(), 3);
+    assert_eq!(report.checksums.modified.len(), 16);
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    apply_patch(apply_dir.path(), &output)?;
+    assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn zstd_dictionary_compresses_similar_small_files() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    for i in 0..64 {
+        let config = format!(
+            "[general]\nname = \"mod_{i}\"\nenabled = true\nversion = \"1.{i}.0\"\n\n\
+             [render]\ndistance = {}\nshadows = \"high\"\nparticles = \"all\"\n",
+            i % 16
+        );
+        write_file(
+            target.path(),
+            &format!("config/mod_{}.toml", i),
+            config.as_bytes(),
+        );
+    }
+    write_file(source.path(), "config/mod_0.toml", b"old");
+
+    let patch_dir = TempDir::new()?;
+    let plain = patch_dir.path().join("plain.tgz");
+    let compact = patch_dir.path().join("compact.tgz");
+    create_patch(source.path(), target.path(), &plain)?;
+    let options = CreateOptions::new().with_zstd_dictionary(true);
+    let report = create_patch_with_options(source.path(), target.path(), &compact, &options)?;
+    assert_eq!(report.checksums.dictionary_compressed.len(), 64);
+
+    for patch in [&plain, &compact] {
+        let apply_dir = TempDir::new()?;
+        copy_dir(source.path(), apply_dir.path());
+        apply_patch(apply_dir.path(), patch)?;
+        assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+    }
+    Ok(())
+}
+
+#[test]
+fn base_patch_references_shrink_chained_patch() -> Result<()> {
+    let _guard = patch_lock();
+
+    let v0 = TempDir::new()?;
+    let v1 = TempDir::new()?;
+    let v2 = TempDir::new()?;
+    write_file(v0.path(), "keep.txt", b"keep");
+    copy_dir(v0.path(), v1.path());
+    let library = pseudo_random_bytes(256 * 1024, 7);
+    let mut model = pseudo_random_bytes(128 * 1024, 11);
+    write_file(v1.path(), "lib/library.jar", &library);
+    write_file(v1.path(), "assets/model.bin", &model);
+
+    // v2 moves the library into a new folder (copied content) and tweaks the model
+    copy_dir(v1.path(), v2.path());
+    write_file(v2.path(), "mods/library.jar", &library);
+    model[1000..1016].copy_from_slice(b"patched in v2!!!");
+    write_file(v2.path(), "assets/model.bin", &model);
+
+    let patch_dir = TempDir::new()?;
+    let first = patch_dir.path().join("v1.tgz");
+    let plain = patch_dir.path().join("v2_plain.tgz");
+    let chained = patch_dir.path().join("v2_chained.tgz");
+    create_patch(v0.path(), v1.path(), &first)?;
+    let options = CreateOptions::new().with_rename_threshold(None);
+    create_patch_with_options(v1.path(), v2.path(), &plain, &options)?;
+    let report = create_patch_with_options(
+        v1.path(),
+        v2.path(),
+        &chained,
+        &options.clone().with_base_patch(&first),
+    )?;
+    assert_eq!(report.checksums.base_refs.len(), 2);
+    assert!(fs::metadata(&chained)?.len() * 4 < fs::metadata(&plain)?.len());
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(v1.path(), apply_dir.path());
+    assert!(apply_patch(apply_dir.path(), &chained).is_err());
+    let options = ApplyOptions::new().with_base_patch(&first);
+    apply_patch_with_options(apply_dir.path(), &chained, &options)?;
+    assert!(compare_directories(apply_dir.path(), v2.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn fixup_rebuilds_corrected_patch_from_small_fix() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "mods/old.jar", b"old");
+    write_file(
+        target.path(),
+        "mods/big.jar",
+        &pseudo_random_bytes(128 * 1024, 3),
+    );
+    write_file(target.path(), "config/broken.toml", b"value = 1\n");
+
+    let patch_dir = TempDir::new()?;
+    let released = patch_dir.path().join("released.tgz");
+    let corrected = patch_dir.path().join("corrected.tgz");
+    let fix = patch_dir.path().join("fix.dftfix");
+    let rebuilt = patch_dir.path().join("rebuilt.tgz");
+    create_patch(source.path(), target.path(), &released)?;
+
+    // Only one small config file changes in the corrected release
+    write_file(target.path(), "config/broken.toml", b"value = 2\n");
+    create_patch(source.path(), target.path(), &corrected)?;
+
+    let report = create_fixup(&released, &corrected, &fix)?;
+    assert!(report.manifest.removed.is_empty());
+    assert!(
+        report
+            .manifest
+            .changed
+            .contains_key("added/config/broken.toml")
+    );
+    assert!(!report.manifest.changed.contains_key("added/mods/big.jar"));
+    assert!(fs::metadata(&fix)?.len() * 10 < fs::metadata(&released)?.len());
+
+    apply_fixup(&released, &fix, &rebuilt)?;
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    apply_patch(apply_dir.path(), &rebuilt)?;
+    assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+
+    // The fix is bound to the exact patch it was made for
+    assert!(apply_fixup(&corrected, &fix, &rebuilt).is_err());
+    Ok(())
+}
+
+#[test]
+fn show_patch_previews_huge_text_files_without_loading_them() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("huge.tgz");
+
+    // A single-line 8 MiB JSON document
+    let mut json = b"{\"items\": [".to_vec();
+    json.extend(std::iter::repeat_n(b"1, ".as_slice(), 2_800_000).flatten());
+    json.extend_from_slice(b"1]}");
+    write_file(source.path(), "data.json", b"{}");
+    write_file(target.path(), "data.json", &json);
+    create_patch(source.path(), target.path(), &patch)?;
+
+    show_patch_with_options(&patch, &ShowOptions::new().with_preview_lines(3))?;
+    show_patch_with_options(&patch, &ShowOptions::new().with_preview_lines(0))?;
+    Ok(())
+}
+
+#[test]
+fn changelog_groups_changes_by_directory() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "mods/jei-1.20.1-15.2.0.27.jar", b"old jei");
+    write_file(target.path(), "mods/sodium-fabric-0.5.8.jar", b"sodium");
+    write_file(target.path(), "config/sodium.json", b"{}");
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("changelog.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let changelog = patch_changelog(&patch, ChangelogFormat::Markdown)?;
+    let mods = changelog.find("## mods").unwrap();
+    assert!(changelog.find("## config").unwrap() < mods);
+    assert!(changelog[mods..].contains("### 新增\n\n- sodium-fabric (0.5.8)"));
+    assert!(changelog[mods..].contains("### 删除\n\n- jei (15.2.0.27)"));
+    assert!(changelog.contains("- `sodium.json`"));
+    Ok(())
+}
+
+#[test]
+fn mod_names_and_upgrades_are_guessed_from_file_names() {
+    let parsed = ModName::parse("mods/jei-1.20.1-15.2.0.27.jar").unwrap();
+    assert_eq!(parsed.name, "jei");
+    assert_eq!(parsed.version, "15.2.0.27");
+    assert_eq!(parsed.game_version.as_deref(), Some("1.20.1"));
+
+    let cases = [
+        ("fabric-api-0.92.0+1.20.1.jar", "fabric-api", "0.92.0"),
+        ("sodium-fabric-mc1.20.1-0.5.8.jar", "sodium-fabric", "0.5.8"),
+        (
+            "Xaeros_Minimap_23.9.7_Forge_1.20.jar",
+            "Xaeros_Minimap",
+            "23.9.7",
+        ),
+        (
+            "3dskinlayers-forge-1.6.2.jar",
+            "3dskinlayers-forge",
+            "1.6.2",
+        ),
+        ("create-1.20.1-0.5.1.f.jar", "create", "0.5.1.f"),
+    ];
+    for (file, name, version) in cases {
+        let parsed = ModName::parse(file).unwrap();
+        assert_eq!(
+            (parsed.name.as_str(), parsed.version.as_str()),
+            (name, version)
+        );
+    }
+    assert!(ModName::parse("options.txt").is_none());
+
+    let upgrades = find_upgrades(
+        ["mods/jei-1.20.1-15.2.0.27.jar", "mods/old-lib-1.0.jar"],
+        ["mods/jei-1.20.1-15.3.1.jar", "config/old-lib-2.0.jar"],
+    );
+    assert_eq!(upgrades.len(), 1);
+    assert_eq!(upgrades[0].name, "jei");
+    assert_eq!(upgrades[0].from_version, "15.2.0.27");
+    assert_eq!(upgrades[0].to_version, "15.3.1");
+}
+
+#[test]
+fn apply_report_pairs_replaced_mod_jars_as_upgrades() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "mods/jei-1.20.1-15.2.0.27.jar", b"jei 15.2");
+    write_file(source.path(), "mods/journeymap-1.20.1-5.9.7.jar", b"map");
+    write_file(target.path(), "mods/jei-1.20.1-15.3.1.jar", b"jei 15.3");
+    write_file(target.path(), "mods/journeymap-1.20.1-5.9.7.jar", b"map");
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("upgrade.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let report = apply_patch(apply_dir.path(), &patch)?;
+    assert_eq!(report.upgrades.len(), 1);
+    assert_eq!(report.upgrades[0].to_string(), "↑ jei 15.2.0.27 -> 15.3.1");
+    Ok(())
+}
+
+#[test]
+fn patch_handle_lists_and_streams_entries() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let long_name = format!("{}/data.bin", "nested".repeat(20));
+    write_file(target.path(), &long_name, b"long path content");
+    write_file(target.path(), "a/readme.txt", b"readme");
+    write_file(target.path(), "b/other.txt", b"other");
+
+    let patch_dir = TempDir::new()?;
+    let patch_path = patch_dir.path().join("handle.tgz");
+    let options = CreateOptions::new().with_jobs(2);
+    create_patch_with_options(source.path(), target.path(), &patch_path, &options)?;
+
+    let patch = Patch::open(&patch_path)?;
+    assert_eq!(patch.checksums().added.len(), 3);
+    assert!(patch.metadata().is_some());
+    let paths: Vec<&str> = patch.entries().map(|e| e.path.as_str()).collect();
+    assert!(paths.contains(&"checksums.toml"));
+    assert!(paths.contains(&format!("added/{}", long_name).as_str()));
+
+    let mut content = String::new();
+    patch
+        .read_entry(&format!("added/{}", long_name))?
+        .read_to_string(&mut content)?;
+    assert_eq!(content, "long path content");
+    assert!(patch.read_entry("added/missing.txt").is_err());
+    Ok(())
+}
+
+#[test]
+fn loaded_patch_exposes_parsed_patch_and_cleans_up() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "mods/a.jar", b"jar");
+
+    let patch_dir = TempDir::new()?;
+    let patch_path = patch_dir.path().join("loaded.tgz");
+    create_patch(source.path(), target.path(), &patch_path)?;
+
+    let workspace = patch_dir.path().join("workspace");
+    let patch = LoadedPatch::load(&patch_path, &workspace)?;
+    assert!(patch.metadata.is_some());
+    assert_eq!(patch.checksums.added.len(), 1);
+    assert_eq!(fs::read(patch.entry_path("added", "mods/a.jar"))?, b"jar");
+    assert_eq!(
+        fs::read(patch.entry_path("modified", "config.toml"))?,
+        b"new"
+    );
+    drop(patch);
+    assert!(!workspace.exists());
+    Ok(())
+}
+
+#[test]
+fn validate_and_strict_apply_detect_tampered_entries() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "mods/a.jar", b"original jar");
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+    let report = validate_patch(&patch)?;
+    assert!(report.is_valid());
+    assert_eq!(report.checked, 2);
+
+    let tampered = patch_dir.path().join("tampered.tgz");
+    repack_patch(&patch, &tampered, |unpacked| {
+        write_file(unpacked, "added/mods/a.jar", b"tampered jar");
+    })?;
+    let report = validate_patch(&tampered)?;
+    assert_eq!(report.corrupt, vec!["mods/a.jar".to_string()]);
+
+    // Strict mode refuses before touching the target directory
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let options = ApplyOptions::new().with_strict(true);
+    assert!(apply_patch_with_options(apply_dir.path(), &tampered, &options).is_err());
+    assert_eq!(fs::read(apply_dir.path().join("config.toml"))?, b"old");
+    assert!(!apply_dir.path().join("mods/a.jar").exists());
+    Ok(())
+}
+
+#[test]
+fn analyze_reports_duplicate_blocks_and_compression_estimates() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let library = pseudo_random_bytes(256 * 1024, 5);
+    write_file(target.path(), "mods/a.jar", &library);
+    write_file(target.path(), "mods/b.jar", &library);
+    write_file(
+        target.path(),
+        "config/readme.txt",
+        &b"hello world\n".repeat(1000),
+    );
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+    let analysis = analyze_patch(&patch)?;
+    assert_eq!(analysis.entries, 3);
+    assert_eq!(analysis.content_bytes, 2 * 256 * 1024 + 12_000);
+    // The second copy of the library is made of blocks already seen in the first
+    assert!(analysis.duplicate_bytes >= 256 * 1024);
+    assert_eq!(analysis.delta_candidates, vec!["added/mods/b.jar"]);
+    assert_eq!(analysis.delta_candidate_bytes, 256 * 1024);
+
+    let algorithms: Vec<(&str, i32)> = analysis
+        .compression
+        .iter()
+        .map(|e| (e.algorithm.as_str(), e.level))
+        .collect();
+    assert_eq!(
+        algorithms,
+        vec![("gzip", 6), ("gzip", 9), ("zstd", 3), ("zstd", 19)]
+    );
+    // Random data does not compress, the repeated text does
+    assert!(analysis.compression.iter().all(|e| e.size > 256 * 1024));
+    assert!(
+        analysis
+            .compression
+            .iter()
+            .all(|e| e.size < analysis.content_bytes)
+    );
+    Ok(())
+}
+
+#[test]
+fn skip_corrupt_applies_intact_entries_and_lists_damaged_ones() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "mods/a.jar", b"jar a");
+    write_file(target.path(), "mods/b.jar", b"jar b");
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    // Damage one entry so that its content no longer matches checksums.toml
+    let damaged = patch_dir.path().join("damaged.tgz");
+    repack_patch(&patch, &damaged, |unpacked| {
+        write_file(unpacked, "added/mods/b.jar", b"garbage");
+        fs::remove_file(unpacked.join("modified/config.toml")).unwrap();
+    })?;
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let options = ApplyOptions::new().with_skip_corrupt(true);
+    let report = apply_patch_with_options(apply_dir.path(), &damaged, &options)?;
+    assert_eq!(report.corrupt, vec!["config.toml", "mods/b.jar"]);
+    assert_eq!(fs::read(apply_dir.path().join("config.toml"))?, b"old");
+    assert_eq!(fs::read(apply_dir.path().join("mods/a.jar"))?, b"jar a");
+    assert!(!apply_dir.path().join("mods/b.jar").exists());
+    Ok(())
+}
+
+#[test]
+fn resume_skips_entries_completed_before_interruption() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(source.path(), "obsolete.txt", b"gone");
+    write_file(target.path(), "config.toml", b"new");
+    for name in ["a.jar", "b.jar", "c.jar"] {
+        write_file(target.path(), &format!("mods/{}", name), name.as_bytes());
+    }
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    // A directory in place of the last added file makes the apply fail midway
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    fs::create_dir_all(apply_dir.path().join("mods/c.jar/blocker"))?;
+    let options = ApplyOptions::new().with_checkpoint_interval(1);
+    assert!(apply_patch_with_options(apply_dir.path(), &patch, &options).is_err());
+    let checkpoint = apply_dir.path().join(".dft/checkpoint");
+    assert!(checkpoint.is_file());
+
+    // Entries recorded in the checkpoint are not written again on resume
+    fs::remove_dir_all(apply_dir.path().join("mods/c.jar"))?;
+    write_file(apply_dir.path(), "mods/a.jar", b"touched");
+    let report = apply_patch_with_options(apply_dir.path(), &patch, &options.with_resume(true))?;
+    assert_eq!(report.added.len(), 3);
+    assert_eq!(report.deleted, vec!["obsolete.txt"]);
+    assert_eq!(fs::read(apply_dir.path().join("mods/a.jar"))?, b"touched");
+    assert_eq!(fs::read(apply_dir.path().join("mods/c.jar"))?, b"c.jar");
+    assert_eq!(fs::read(apply_dir.path().join("config.toml"))?, b"new");
+    assert!(!checkpoint.exists());
+    Ok(())
+}
+
+#[test]
+fn replace_dir_removes_files_not_in_patch() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    for root in [source.path(), target.path()] {
+        write_file(root, "config/foo/keep.toml", b"keep");
+        write_file(root, "config/other.toml", b"other");
+    }
+    write_file(source.path(), "config/foo/old.toml", b"old");
+    write_file(target.path(), "config/foo/new.toml", b"new");
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    let options = CreateOptions::new().with_replace_dir("config/foo/");
+    let report = create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+    assert_eq!(report.checksums.replace_dirs, vec!["config/foo"]);
+    assert!(report.checksums.added.contains_key("config/foo/keep.toml"));
+
+    // Local edits and extra files inside the replaced directory are discarded
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    write_file(apply_dir.path(), "config/foo/keep.toml", b"local edit");
+    write_file(apply_dir.path(), "config/foo/local/extra.toml", b"extra");
+    write_file(apply_dir.path(), "config/local.toml", b"untouched");
+    let report = apply_patch(apply_dir.path(), &patch)?;
+    assert!(report.warnings.is_empty());
+    assert!(
+        report
+            .deleted
+            .iter()
+            .any(|path| path.ends_with("extra.toml"))
+    );
+    assert_eq!(
+        fs::read(apply_dir.path().join("config/foo/keep.toml"))?,
+        b"keep"
+    );
+    assert!(!apply_dir.path().join("config/foo/local").exists());
+    assert_eq!(
+        fs::read(apply_dir.path().join("config/local.toml"))?,
+        b"untouched"
+    );
+    fs::remove_file(apply_dir.path().join("config/local.toml"))?;
+    assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn sync_removes_unlisted_files_except_kept_paths() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "mods/a.jar", b"a1");
+    write_file(target.path(), "mods/a.jar", b"a2");
+    write_file(target.path(), "mods/b.jar", b"b");
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    let plain = patch_dir.path().join("plain.tgz");
+    let options = CreateOptions::new().with_full_manifest(true);
+    create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+    create_patch(source.path(), target.path(), &plain)?;
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    write_file(apply_dir.path(), "mods/cheat.jar", b"cheat");
+    write_file(apply_dir.path(), "saves/world/level.dat", b"world");
+    let options = ApplyOptions::new().with_sync(true).with_sync_keep("saves/");
+
+    // Syncing requires the full manifest recorded at create time
+    assert!(apply_patch_with_options(apply_dir.path(), &plain, &options).is_err());
+
+    let report = apply_patch_with_options(apply_dir.path(), &patch, &options)?;
+    assert!(
+        report
+            .deleted
+            .iter()
+            .any(|path| path.ends_with("cheat.jar"))
+    );
+    assert!(!apply_dir.path().join("mods/cheat.jar").exists());
+    assert!(apply_dir.path().join("saves/world/level.dat").is_file());
+    fs::remove_dir_all(apply_dir.path().join("saves"))?;
+    assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn protected_paths_are_never_touched() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "options.txt", b"fov:70");
+    write_file(source.path(), "saves/world/level.dat", b"old world");
+    write_file(source.path(), "mods/a.jar", b"a1");
+    write_file(target.path(), "options.txt", b"fov:90");
+    write_file(target.path(), "mods/a.jar", b"a2");
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let options = ApplyOptions::new()
+        .with_protected("options.txt")
+        .with_protected("saves/**");
+    let report = apply_patch_with_options(apply_dir.path(), &patch, &options)?;
+    assert_eq!(report.protected.len(), 2);
+    assert_eq!(fs::read(apply_dir.path().join("options.txt"))?, b"fov:70");
+    assert_eq!(
+        fs::read(apply_dir.path().join("saves/world/level.dat"))?,
+        b"old world"
+    );
+    assert_eq!(fs::read(apply_dir.path().join("mods/a.jar"))?, b"a2");
+    Ok(())
+}
+
+#[test]
+fn merge_config_keeps_player_settings() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(
+        source.path(),
+        "config/game.json",
+        br#"{"fov": 70, "volume": 100, "render": {"distance": 8}}"#,
+    );
+    write_file(
+        target.path(),
+        "config/game.json",
+        br#"{"fov": 70, "volume": 80, "render": {"distance": 12}, "shaders": false}"#,
+    );
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    let options = CreateOptions::new().with_merge_config("config/*.json");
+    create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+
+    // The player changed fov and added a key; untouched defaults are updated
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    write_file(
+        apply_dir.path(),
+        "config/game.json",
+        br#"{"fov": 110, "volume": 100, "render": {"distance": 8}, "lang": "zh_cn"}"#,
+    );
+    let report = apply_patch(apply_dir.path(), &patch)?;
+    assert_eq!(report.merged.len(), 1);
+    assert!(report.warnings.is_empty());
+    let merged: serde_json::Value =
+        serde_json::from_slice(&fs::read(apply_dir.path().join("config/game.json"))?)?;
+    assert_eq!(
+        merged,
+        serde_json::json!({
+            "fov": 110,
+            "volume": 80,
+            "render": {"distance": 12},
+            "shaders": false,
+            "lang": "zh_cn"
+        })
+    );
+
+    // An untouched file is simply replaced
+    let clean_dir = TempDir::new()?;
+    copy_dir(source.path(), clean_dir.path());
+    let report = apply_patch(clean_dir.path(), &patch)?;
+    assert!(report.merged.is_empty());
+    assert!(compare_directories(clean_dir.path(), target.path())?.is_empty());
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "cli",
+    feature = "network",
+    feature = "signing",
+    feature = "watch",
+    feature = "schema"
+))]
+#[test]
+fn cli_streams_patch_through_stdout_and_stdin() -> Result<()> {
+    use std::process::{Command, Stdio};
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "mods/a.jar", b"jar");
+
+    let diff = Command::new(env!("CARGO_BIN_EXE_dft"))
+        .args(["diff", "-o", "-"])
+        .args([source.path(), target.path()])
+        .output()?;
+    assert!(diff.status.success());
+    // Progress goes to stderr so stdout only carries the gzip stream
+    assert_eq!(&diff.stdout[..2], &[0x1f, 0x8b]);
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let mut apply = Command::new(env!("CARGO_BIN_EXE_dft"))
+        .args(["apply", "--patch", "-"])
+        .arg(apply_dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    std::io::Write::write_all(&mut apply.stdin.take().unwrap(), &diff.stdout)?;
+    assert!(apply.wait()?.success());
+    assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "cli",
+    feature = "network",
+    feature = "signing",
+    feature = "watch",
+    feature = "schema"
+))]
+#[test]
+fn cli_quiet_prints_nothing_but_errors() -> Result<()> {
+    use std::process::Command;
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    let report = work.path().join("report.json");
+
+    let diff = Command::new(env!("CARGO_BIN_EXE_dft"))
+        .args(["diff", "--quiet", "-o"])
+        .arg(&patch)
+        .args([source.path(), target.path()])
+        .output()?;
+    assert!(diff.status.success());
+    assert!(diff.stdout.is_empty() && diff.stderr.is_empty());
+
+    let apply = Command::new(env!("CARGO_BIN_EXE_dft"))
+        .args(["apply", "-q", "--report"])
+        .args([&report, source.path(), Path::new("-p"), &patch])
+        .output()?;
+    assert!(apply.status.success());
+    assert!(apply.stdout.is_empty() && apply.stderr.is_empty());
+    let result: serde_json::Value = serde_json::from_str(&fs::read_to_string(&report)?)?;
+    assert_eq!(result["ok"], true);
+    assert_eq!(result["result"]["modified"][0], "config.toml");
+
+    // Errors still reach stderr and the report file
+    let missing = work.path().join("missing.tgz");
+    let failed = Command::new(env!("CARGO_BIN_EXE_dft"))
+        .args(["apply", "-q", "--report"])
+        .args([&report, source.path(), Path::new("-p"), &missing])
+        .output()?;
+    assert!(!failed.status.success());
+    assert!(failed.stdout.is_empty() && !failed.stderr.is_empty());
+    let result: serde_json::Value = serde_json::from_str(&fs::read_to_string(&report)?)?;
+    assert_eq!(result["ok"], false);
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "cli",
+    feature = "network",
+    feature = "signing",
+    feature = "watch",
+    feature = "schema"
+))]
+#[test]
+fn cli_writes_summary_file_for_apply_and_append() -> Result<()> {
+    use std::process::Command;
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(source.path(), "stale.txt", b"stale");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "mods/a.jar", b"jar");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    {
+        let _guard = patch_lock();
+        create_patch(source.path(), target.path(), &patch)?;
+    }
+    let summary_path = work.path().join("summary.json");
+    let read_summary = || -> Result<serde_json::Value> {
+        Ok(serde_json::from_str(&fs::read_to_string(&summary_path)?)?)
+    };
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let status = Command::new(env!("CARGO_BIN_EXE_dft"))
+        .args(["-q", "apply", "--summary-file"])
+        .args([&summary_path, apply_dir.path(), Path::new("-p"), &patch])
+        .status()?;
+    assert!(status.success());
+    let summary = read_summary()?;
+    assert_eq!(summary["command"], "apply");
+    assert_eq!(summary["ok"], true);
+    assert_eq!(summary["added"], 1);
+    assert_eq!(summary["modified"], 1);
+    assert_eq!(summary["deleted"], 1);
+    assert_eq!(summary["has_warnings"], false);
+    assert!(summary["timings"]["total_ms"].as_f64().is_some());
+
+    // Failures still leave a summary behind for the pipeline to inspect
+    let status = Command::new(env!("CARGO_BIN_EXE_dft"))
+        .args(["-q", "append", "--summary-file"])
+        .arg(&summary_path)
+        .args([&patch, &work.path().join("missing.tgz")])
+        .args(["-o", "merged.tgz"])
+        .status()?;
+    assert!(!status.success());
+    let summary = read_summary()?;
+    assert_eq!(summary["command"], "append");
+    assert_eq!(summary["ok"], false);
+    assert!(summary["error"].as_str().unwrap().contains("missing.tgz"));
+    Ok(())
+}
+
+#[test]
+fn mc_updater_does_not_pause_without_terminal() -> Result<()> {
+    use std::process::{Command, Stdio};
+
+    // stdin stays open but is a pipe: the updater must exit instead of waiting for Enter
+    let work = TempDir::new()?;
+    for args in [&[][..], &["--no-pause"][..]] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_mc_updater"))
+            .args(args)
+            .current_dir(work.path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let _stdin = child.stdin.take();
+        assert_eq!(child.wait()?.code(), Some(1));
+    }
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "cli",
+    feature = "network",
+    feature = "signing",
+    feature = "watch",
+    feature = "schema"
+))]
+#[test]
+fn cli_service_mode_logs_output_and_reports_exit_code() -> Result<()> {
+    use std::process::Command;
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    {
+        let _guard = patch_lock();
+        create_patch(source.path(), target.path(), &patch)?;
+    }
+    let log = work.path().join("service.log");
+    let service_apply = |dir: &Path, patch: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_dft"))
+            .args(["apply", "--service-mode", "--log-file"])
+            .args([&log, dir, Path::new("-p"), patch])
+            .output()
+    };
+
+    // Clean apply: nothing on the console, progress in the log, exit code 0
+    let clean = TempDir::new()?;
+    copy_dir(source.path(), clean.path());
+    let output = service_apply(clean.path(), &patch)?;
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+    let content = fs::read_to_string(&log)?;
+    assert!(content.contains("config.toml"));
+    assert!(content.contains("退出码 0"));
+
+    // Locally edited file: applied with a checksum warning, exit code 2
+    let edited = TempDir::new()?;
+    write_file(edited.path(), "config.toml", b"edited");
+    assert_eq!(service_apply(edited.path(), &patch)?.status.code(), Some(2));
+
+    // Failure: the error goes to the log, exit code 1
+    let output = service_apply(clean.path(), &work.path().join("missing.tgz"))?;
+    assert_eq!(output.status.code(), Some(1));
+    let content = fs::read_to_string(&log)?;
+    assert!(content.contains("错误: 补丁包不存在"));
+    assert!(content.contains("退出码 1"));
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "cli",
+    feature = "network",
+    feature = "signing",
+    feature = "watch",
+    feature = "schema"
+))]
+#[cfg(unix)]
+#[test]
+fn cli_applies_patch_to_remote_target_over_ssh() -> Result<()> {
+    use bin_diff_tool::patch::{RemoteTarget, SSH_PROGRAM_ENV, apply_patch_remote};
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    let target = RemoteTarget::parse("deploy@mc.example.com:/srv/mc server")?;
+    assert_eq!(target.host, "deploy@mc.example.com");
+    assert_eq!(target.path, "/srv/mc server");
+    assert!(RemoteTarget::parse("/srv/mc").is_err());
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "mods/a.jar", b"jar");
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    {
+        let _guard = patch_lock();
+        create_patch(source.path(), target.path(), &patch)?;
+    }
+
+    // Stand-in for ssh that runs the remote command locally
+    let ssh = write_file(
+        patch_dir.path(),
+        "fake-ssh",
+        b"#!/bin/sh\nexec sh -c \"$2\"\n",
+    );
+    fs::set_permissions(&ssh, fs::Permissions::from_mode(0o755))?;
+
+    let remote_dir = patch_dir.path().join("remote dir");
+    copy_dir(source.path(), &remote_dir);
+    let remote = format!("user@host:{}", remote_dir.display());
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_dft"))
+            .env(SSH_PROGRAM_ENV, &ssh)
+            .args(["apply", "--remote", &remote, "--remote-dft"])
+            .arg(env!("CARGO_BIN_EXE_dft"))
+            .arg("--patch")
+            .arg(&patch)
+            .args(extra)
+            .status()
+    };
+
+    // A dry run is forwarded and leaves the remote directory alone
+    assert!(run(&["--dry-run"])?.success());
+    assert!(compare_directories(&remote_dir, source.path())?.is_empty());
+    assert!(run(&[])?.success());
+    assert!(compare_directories(&remote_dir, target.path())?.is_empty());
+
+    // Options the remote command cannot honour are rejected, not dropped
+    let atomic = ApplyOptions::new().with_atomic(true);
+    let remote = RemoteTarget::parse(&remote)?;
+    assert!(apply_patch_remote(&remote, &patch, &atomic, env!("CARGO_BIN_EXE_dft")).is_err());
+    let rollback = ApplyOptions::new().with_rollback(patch_dir.path().join("rollback.tgz"));
+    assert!(apply_patch_remote(&remote, &patch, &rollback, env!("CARGO_BIN_EXE_dft")).is_err());
+    let hooks = ApplyOptions::new().with_pre_command("echo stopping");
+    assert!(apply_patch_remote(&remote, &patch, &hooks, env!("CARGO_BIN_EXE_dft")).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "network")]
+#[test]
+fn download_retries_transient_http_errors() -> Result<()> {
+    use bin_diff_tool::utils::{DownloadOptions, download_file};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    // Serves the given responses in order, one per connection
+    fn serve(responses: Vec<&'static str>) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/patch.tgz", listener.local_addr()?);
+        std::thread::spawn(move || {
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                (&stream).write_all(response.as_bytes()).unwrap();
+            }
+        });
+        Ok(url)
+    }
+
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\npatch";
+    let options = DownloadOptions::new().with_initial_backoff(Duration::from_millis(10));
+    let dir = TempDir::new()?;
+    let dest = dir.path().join("patch.tgz");
+
+    let url = serve(vec![
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        OK,
+    ])?;
+    assert_eq!(download_file(&url, &dest, &options)?, 5);
+    assert_eq!(fs::read(&dest)?, b"patch");
+
+    // Client errors are not retried
+    let url = serve(vec![
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        OK,
+    ])?;
+    assert!(download_file(&url, &dir.path().join("missing.tgz"), &options).is_err());
+
+    let url = serve(vec![
+        "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        OK,
+    ])?;
+    let no_retry = options.clone().with_max_retries(0);
+    assert!(download_file(&url, &dir.path().join("failed.tgz"), &no_retry).is_err());
+    assert!(!dir.path().join("failed.part").exists());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn apply_to_read_only_target_reports_needed_changes() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(source.path(), "stale.txt", b"stale");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "mods/a.jar", b"jar");
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    fs::set_permissions(apply_dir.path(), fs::Permissions::from_mode(0o555))?;
+    let result = apply_patch(apply_dir.path(), &patch);
+    fs::set_permissions(apply_dir.path(), fs::Permissions::from_mode(0o755))?;
+
+    let report = result?;
+    assert!(report.read_only);
+    assert_eq!(report.added, vec!["mods/a.jar"]);
+    assert_eq!(report.modified, vec!["config.toml"]);
+    assert_eq!(report.deleted, vec!["stale.txt"]);
+    // Nothing was touched
+    assert!(compare_directories(apply_dir.path(), source.path())?.is_empty());
+    assert!(!apply_dir.path().join(".dft").exists());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn restrict_to_rejects_writes_escaping_through_symlinks() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "mods/a.jar", b"jar");
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let work = TempDir::new()?;
+    let game = work.path().join("game");
+    let outside = work.path().join("outside");
+    copy_dir(source.path(), &game);
+    fs::create_dir_all(&outside)?;
+    std::os::unix::fs::symlink(&outside, game.join("mods"))?;
+
+    let options = ApplyOptions::new().with_restrict_to(&game);
+    let err = apply_patch_with_options(&game, &patch, &options).unwrap_err();
+    assert!(format!("{:#}", err).contains("逃出限制目录"), "{:#}", err);
+    assert!(!outside.join("a.jar").exists());
+
+    // Without the symlink the same restriction lets the patch through
+    fs::remove_file(game.join("mods"))?;
+    apply_patch_with_options(&game, &patch, &options)?;
+    assert_eq!(fs::read(game.join("mods/a.jar"))?, b"jar");
+    assert_eq!(fs::read(game.join("config.toml"))?, b"new");
+    Ok(())
+}
+
+#[test]
+fn verify_after_apply_checks_all_or_a_sample_of_written_files() -> Result<()> {
+    assert_eq!("full".parse::<VerifyMode>()?, VerifyMode::Full);
+    assert_eq!("sample:5%".parse::<VerifyMode>()?, VerifyMode::Sample(5.0));
+    assert!("sample:0%".parse::<VerifyMode>().is_err());
+    assert!("sample:150".parse::<VerifyMode>().is_err());
+    assert!("some".parse::<VerifyMode>().is_err());
+
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    for i in 0..10 {
+        write_file(
+            target.path(),
+            &format!("mods/{}.jar", i),
+            format!("jar {}", i).as_bytes(),
+        );
+    }
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    for (mode, checked) in [(VerifyMode::Full, 10), (VerifyMode::Sample(25.0), 3)] {
+        let apply_dir = TempDir::new()?;
+        let options = ApplyOptions::new().with_verify(mode);
+        let report = apply_patch_with_options(apply_dir.path(), &patch, &options)?;
+        let verification = report.verification.unwrap();
+        assert_eq!(verification.total, 10);
+        assert_eq!(verification.checked, checked);
+        assert!(verification.is_valid());
+    }
+    Ok(())
+}
+
+#[test]
+fn updater_orders_merges_and_applies_patches() -> Result<()> {
+    use bin_diff_tool::updater::{Updater, UpdaterUi};
+
+    #[derive(Default)]
+    struct RecordingUi {
+        ordered: Vec<PathBuf>,
+        errors: Vec<String>,
+        finished: usize,
+    }
+    impl UpdaterUi for RecordingUi {
+        fn on_ordered(&mut self, patches: &[PathBuf]) {
+            self.ordered = patches.to_vec();
+        }
+        fn on_error(&mut self, error: &anyhow::Error) {
+            self.errors.push(format!("{:#}", error));
+        }
+        fn on_finish(&mut self) {
+            self.finished += 1;
+        }
+    }
+
+    let _guard = patch_lock();
+    let v1 = TempDir::new()?;
+    let v2 = TempDir::new()?;
+    let v3 = TempDir::new()?;
+    write_file(v1.path(), "config.toml", b"1");
+    write_file(v2.path(), "config.toml", b"2");
+    write_file(v2.path(), "mods/a.jar", b"a");
+    write_file(v3.path(), "config.toml", b"3");
+    write_file(v3.path(), "mods/b.jar", b"b");
+    let work = TempDir::new()?;
+    let p12 = work.path().join("p12.tgz");
+    let p23 = work.path().join("p23.tgz");
+    create_patch(v1.path(), v2.path(), &p12)?;
+    create_patch(v2.path(), v3.path(), &p23)?;
+
+    let game = TempDir::new()?;
+    copy_dir(v1.path(), game.path());
+    let mut ui = RecordingUi::default();
+    Updater::new(game.path().to_path_buf(), &mut ui).run(&[p23.clone(), p12.clone()])?;
+    assert_eq!(ui.ordered, vec![p12.clone(), p23.clone()]);
+    assert_eq!(ui.finished, 1);
+    assert!(compare_directories(game.path(), v3.path())?.is_empty());
+
+    // A missing target is reported through the UI as well
+    let mut ui = RecordingUi::default();
+    let missing = work.path().join("missing");
+    assert!(Updater::new(missing, &mut ui).run(&[p12]).is_err());
+    assert_eq!(ui.errors.len(), 1);
+    assert_eq!(ui.finished, 1);
+    Ok(())
+}
+
+#[test]
+fn target_detectors_use_first_detector_that_finds_a_directory() -> Result<()> {
+    use bin_diff_tool::updater::{KnownPaths, TargetDetector, TargetDetectors, TargetLocator};
+
+    struct Failing;
+    impl TargetDetector for Failing {
+        fn name(&self) -> &str {
+            "registry"
+        }
+        fn detect(&self) -> Result<Option<PathBuf>> {
+            anyhow::bail!("no registry here")
+        }
+    }
+
+    let root = TempDir::new()?;
+    let install = root.path().join("install");
+    fs::create_dir(&install)?;
+    let known = |name: &str, candidates: Vec<PathBuf>| KnownPaths {
+        name: name.to_string(),
+        candidates,
+    };
+
+    let detectors = TargetDetectors::new()
+        .with_detector(Failing)
+        .with_detector(known("portable", vec![root.path().join("portable")]))
+        .with_detector(known(
+            "default",
+            vec![root.path().join("nope"), install.clone()],
+        ));
+    assert_eq!(detectors.locate()?, install);
+
+    let err = TargetDetectors::new()
+        .with_detector(Failing)
+        .with_detector(known("portable", vec![root.path().join("portable")]))
+        .locate()
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("registry (no registry here)") && err.contains("portable"),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn batch_apply_follows_version_chain_and_failure_policy() -> Result<()> {
+    let _guard = patch_lock();
+    let v1 = TempDir::new()?;
+    let v2 = TempDir::new()?;
+    let v3 = TempDir::new()?;
+    write_file(v1.path(), "config.toml", b"1");
+    write_file(v2.path(), "config.toml", b"2");
+    write_file(v2.path(), "mods/a.jar", b"a");
+    write_file(v3.path(), "config.toml", b"3");
+    write_file(v3.path(), "mods/a.jar", b"a");
+    write_file(v3.path(), "mods/b.jar", b"b");
+
+    let work = TempDir::new()?;
+    let p12 = work.path().join("p12.tgz");
+    let p23 = work.path().join("p23.tgz");
+    create_patch(v1.path(), v2.path(), &p12)?;
+    create_patch(v2.path(), v3.path(), &p23)?;
+
+    // File names and creation times both point the wrong way; only the version chain is right
+    fn set_versions(
+        source: &'static str,
+        target: &'static str,
+        created_at: &'static str,
+    ) -> impl FnOnce(&Path) {
+        move |dir| {
+            let path = dir.join("metadata.toml");
+            let mut metadata: toml::Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+            metadata.insert("source_version".into(), source.into());
+            metadata.insert("target_version".into(), target.into());
+            metadata.insert("created_at".into(), created_at.into());
+            fs::write(&path, toml::to_string(&metadata).unwrap()).unwrap();
+        }
+    }
+    let batch = work.path().join("batch");
+    fs::create_dir(&batch)?;
+    repack_patch(
+        &p23,
+        &batch.join("a.tgz"),
+        set_versions("2", "3", "2000-01-01T00:00:00Z"),
+    )?;
+    repack_patch(
+        &p12,
+        &batch.join("b.tgz"),
+        set_versions("1", "2", "2001-01-01T00:00:00Z"),
+    )?;
+
+    let target = TempDir::new()?;
+    copy_dir(v1.path(), target.path());
+    let report = apply_batch(target.path(), &batch, &BatchOptions::new())?;
+    let order: Vec<_> = report
+        .entries
+        .iter()
+        .map(|e| e.patch.file_name().unwrap())
+        .collect();
+    assert_eq!(order, ["b.tgz", "a.tgz"]);
+    assert_eq!(report.failed(), 0);
+    assert!(compare_directories(target.path(), v3.path())?.is_empty());
+
+    // A tampered first patch stops the batch unless failures are skipped; the
+    // second patch then still runs but strict mode rejects its 1.0 baseline
+    repack_patch(&batch.join("b.tgz"), &batch.join("b.tgz.tmp"), |dir| {
+        fs::write(dir.join("added/mods/a.jar"), b"evil").unwrap();
+    })?;
+    fs::rename(batch.join("b.tgz.tmp"), batch.join("b.tgz"))?;
+    let strict = ApplyOptions::new().with_strict(true);
+    for (policy, second) in [
+        (BatchPolicy::StopOnError, BatchStatus::NotRun),
+        (BatchPolicy::SkipFailed, BatchStatus::Failed),
+    ] {
+        let target = TempDir::new()?;
+        copy_dir(v1.path(), target.path());
+        let options = BatchOptions::new()
+            .with_apply_options(strict.clone())
+            .with_policy(policy);
+        let report = apply_batch(target.path(), &batch, &options)?;
+        assert_eq!(report.entries[0].status, BatchStatus::Failed);
+        assert_eq!(report.entries[1].status, second);
+    }
+    Ok(())
+}
+
+#[test]
+fn max_memory_skips_whole_file_optimizations_for_large_files() -> Result<()> {
+    let _guard = patch_lock();
+    assert_eq!("256MB".parse::<MemoryLimit>()?.bytes(), 256 << 20);
+    assert_eq!("64k".parse::<MemoryLimit>()?.bytes(), 64 << 10);
+    assert_eq!("1GiB".parse::<MemoryLimit>()?.max_jobs(), 16);
+    assert!("256XB".parse::<MemoryLimit>().is_err());
+    assert!("0".parse::<MemoryLimit>().is_err());
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let original = pseudo_random_bytes(256 * 1024, 7);
+    let mut changed = original.clone();
+    changed[1000] ^= 0xff;
+    write_file(source.path(), "libs/mod-1.0.jar", &original);
+    write_file(target.path(), "mods/mod-1.1.jar", &changed);
+
+    let patch_dir = TempDir::new()?;
+    let unlimited = patch_dir.path().join("unlimited.tgz");
+    let limited = patch_dir.path().join("limited.tgz");
+    let report = create_patch(source.path(), target.path(), &unlimited)?;
+    assert_eq!(report.checksums.renamed.len(), 1);
+
+    // A 512 KiB budget cannot hold both copies of the 256 KiB file, so it is stored whole
+    let options = CreateOptions::new()
+        .with_jobs(8)
+        .with_max_memory("512KB".parse()?);
+    let report = create_patch_with_options(source.path(), target.path(), &limited, &options)?;
+    assert!(report.checksums.renamed.is_empty());
+    assert_eq!(report.checksums.added.len(), 1);
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    apply_patch(apply_dir.path(), &limited)?;
+    assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn large_modified_file_is_diffed_by_streaming_block_matching() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let original = pseudo_random_bytes(2 * 1024 * 1024, 13);
+    let mut changed = original.clone();
+    changed.splice(500_000..500_000, b"inserted".iter().copied());
+    changed[1_500_000..1_500_016].copy_from_slice(b"overwritten here");
+    changed.truncate(1_900_000);
+    write_file(source.path(), "world/region.mca", &original);
+    write_file(target.path(), "world/region.mca", &changed);
+
+    // A 4 MB budget is too small to load both copies, so the delta is computed in blocks
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    let options = CreateOptions::new().with_max_memory("4MB".parse()?);
+    let report = create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+    assert_eq!(report.checksums.delta_modified, vec!["world/region.mca"]);
+    assert!(fs::metadata(&patch)?.len() < 64 * 1024);
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    apply_patch(apply_dir.path(), &patch)?;
+    assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn delta_thresholds_fall_back_to_whole_files() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let original = pseudo_random_bytes(256 * 1024, 11);
+    let mut changed = original.clone();
+    // Rewrite about a quarter of the file: still similar enough to count as a rename
+    changed[..64 * 1024].copy_from_slice(&pseudo_random_bytes(64 * 1024, 12));
+    write_file(source.path(), "libs/mod-1.0.jar", &original);
+    write_file(target.path(), "mods/mod-1.1.jar", &changed);
+    let patch_dir = TempDir::new()?;
+
+    let cases = [
+        (CreateOptions::new(), 1),
+        (CreateOptions::new().with_delta_max_ratio(0.1), 0),
+        (CreateOptions::new().with_delta_min_size(1024 * 1024), 0),
+    ];
+    for (i, (options, renamed)) in cases.into_iter().enumerate() {
+        let output = patch_dir.path().join(format!("patch{}.tgz", i));
+        let report = create_patch_with_options(source.path(), target.path(), &output, &options)?;
+        assert_eq!(report.checksums.renamed.len(), renamed);
+        assert_eq!(report.checksums.added.len(), 1 - renamed);
+        assert_eq!(report.checksums.deleted.len(), 1 - renamed);
+
+        let apply_dir = TempDir::new()?;
+        copy_dir(source.path(), apply_dir.path());
+        apply_patch(apply_dir.path(), &output)?;
+        assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+    }
+    Ok(())
+}
+
+#[test]
+fn checksums_record_entry_encodings() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let original = pseudo_random_bytes(64 * 1024, 3);
+    let mut changed = original.clone();
+    changed[100] ^= 0xff;
+    write_file(source.path(), "libs/mod-1.0.jar", &original);
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "mods/mod-1.1.jar", &changed);
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "readme.txt", b"hello");
+
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    let report = create_patch(source.path(), target.path(), &patch)?;
+    let encodings = &report.checksums.encodings;
+    assert_eq!(encodings["config.toml"], EntryEncoding::Store);
+    assert_eq!(encodings["readme.txt"], EntryEncoding::Store);
+    assert!(matches!(
+        encodings["mods/mod-1.1.jar"],
+        EntryEncoding::Delta { .. }
+    ));
+
+    let edit_encodings = |edit: fn(&mut toml::Table)| {
+        move |dir: &Path| {
+            let path = dir.join("checksums.toml");
+            let mut checksums: toml::Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+            edit(&mut checksums);
+            fs::write(&path, toml::to_string(&checksums).unwrap()).unwrap();
+        }
+    };
+
+    // Patches written before the field existed still apply
+    let legacy = patch_dir.path().join("legacy.tgz");
+    repack_patch(
+        &patch,
+        &legacy,
+        edit_encodings(|checksums| {
+            checksums.remove("encodings");
+        }),
+    )?;
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    apply_patch(apply_dir.path(), &legacy)?;
+    assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+
+    // An encoding from a newer version is rejected instead of being decoded wrongly
+    let newer = patch_dir.path().join("newer.tgz");
+    repack_patch(
+        &patch,
+        &newer,
+        edit_encodings(|checksums| {
+            let encodings = checksums["encodings"].as_table_mut().unwrap();
+            let mut text_diff = toml::Table::new();
+            text_diff.insert("type".into(), "text_diff".into());
+            encodings.insert("readme.txt".into(), text_diff.into());
+        }),
+    )?;
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let error = apply_patch(apply_dir.path(), &newer).unwrap_err();
+    assert!(format!("{:#}", error).contains("readme.txt"));
+    assert!(compare_directories(apply_dir.path(), source.path())?.is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn patch_signatures_require_threshold_of_trusted_signers() -> Result<()> {
+    use bin_diff_tool::patch::{
+        SignatureStatus, generate_signing_key, sign_patch, verify_patch_signatures,
+    };
+
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    let dir = TempDir::new()?;
+    let patch = dir.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let key = |name: &str| -> Result<(PathBuf, PathBuf)> {
+        let (secret, public) = (
+            dir.path().join(format!("{}.key", name)),
+            dir.path().join(format!("{}.pub", name)),
+        );
+        generate_signing_key(&secret, &public)?;
+        Ok((secret, public))
+    };
+    let (ci_key, ci_pub) = key("ci")?;
+    let (release_key, release_pub) = key("release")?;
+    let (stranger_key, _) = key("stranger")?;
+    let trusted = [ci_pub, release_pub];
+
+    sign_patch(&patch, &ci_key, "ci")?;
+    sign_patch(&patch, &stranger_key, "stranger")?;
+    let report = verify_patch_signatures(&patch, &trusted, 2)?;
+    let statuses: Vec<_> = report.signers.iter().map(|s| s.status).collect();
+    assert_eq!(
+        statuses,
+        [SignatureStatus::Valid, SignatureStatus::Untrusted]
+    );
+    assert!(!report.is_valid());
+
+    sign_patch(&patch, &release_key, "release")?;
+    assert!(verify_patch_signatures(&patch, &trusted, 2)?.is_valid());
+
+    // Any change to the patch invalidates every signature
+    let mut data = fs::read(&patch)?;
+    data.push(0);
+    fs::write(&patch, data)?;
+    let report = verify_patch_signatures(&patch, &trusted, 1)?;
+    assert!(
+        report
+            .signers
+            .iter()
+            .all(|s| s.status != SignatureStatus::Valid)
+    );
+    assert!(!report.is_valid());
+    Ok(())
+}
+
+#[test]
+fn normalize_resolves_duplicate_and_conflicting_entries() -> Result<()> {
+    let mut checksums = Checksums::new();
+    checksums.added.insert("a.txt".into(), compute_hash(b"a"));
+    checksums.modified.insert(
+        "a.txt".into(),
+        ModifiedChecksum::new(compute_hash(b"old"), compute_hash(b"a")),
+    );
+    checksums.modified.insert(
+        "b.txt".into(),
+        ModifiedChecksum::new(compute_hash(b"old"), compute_hash(b"b")),
+    );
+    checksums.deleted = vec![
+        "z.txt".into(),
+        "b.txt".into(),
+        "c.txt".into(),
+        "z.txt".into(),
+    ];
+
+    let conflicts = checksums.normalize();
+    assert_eq!(conflicts.len(), 2);
+    assert!(checksums.added.contains_key("a.txt"));
+    assert!(!checksums.modified.contains_key("a.txt"));
+    assert!(checksums.modified.contains_key("b.txt"));
+    assert_eq!(checksums.deleted, vec!["c.txt", "z.txt"]);
+    // Already normalized checksums are left alone
+    assert!(checksums.normalize().is_empty());
+
+    // A patch carrying such entries still loads and applies cleanly
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "stale.txt", b"stale");
+    write_file(target.path(), "new.txt", b"new");
+    let patch_dir = TempDir::new()?;
+    let patch = patch_dir.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+    let messy = patch_dir.path().join("messy.tgz");
+    repack_patch(&patch, &messy, |dir| {
+        let path = dir.join("checksums.toml");
+        let mut checksums: toml::Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+        let deleted = checksums["deleted"].as_array_mut().unwrap();
+        deleted.push("stale.txt".into());
+        deleted.push("new.txt".into());
+        fs::write(&path, toml::to_string(&checksums).unwrap()).unwrap();
+    })?;
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let report = apply_patch(apply_dir.path(), &messy)?;
+    assert_eq!(report.deleted, vec!["stale.txt"]);
+    assert!(compare_directories(apply_dir.path(), target.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn streaming_merge_handles_dictionary_entries_and_renamed_additions() -> Result<()> {
+    let _guard = patch_lock();
+    let v1 = TempDir::new()?;
+    let v2 = TempDir::new()?;
+    let v3 = TempDir::new()?;
+    write_file(v1.path(), "options.txt", b"v1");
+    let jar = pseudo_random_bytes(64 * 1024, 7);
+    let mut jar_next = jar.clone();
+    jar_next[10] ^= 0xff;
+    for dir in [v2.path(), v3.path()] {
+        write_file(dir, "options.txt", b"v2");
+        for i in 0..16 {
+            let config = format!("[general]\nname = \"mod_{i}\"\nenabled = true\n");
+            write_file(dir, &format!("config/mod_{}.toml", i), config.as_bytes());
+        }
+    }
+    write_file(v2.path(), "mods/a-1.0.jar", &jar);
+    // The second patch renames a file the first patch added
+    write_file(v3.path(), "mods/a-1.1.jar", &jar_next);
+
+    let work = TempDir::new()?;
+    let p12 = work.path().join("p12.tgz");
+    let p23 = work.path().join("p23.tgz");
+    let merged = work.path().join("merged.tgz");
+    let options = CreateOptions::new().with_zstd_dictionary(true);
+    let report = create_patch_with_options(v1.path(), v2.path(), &p12, &options)?;
+    assert!(!report.checksums.dictionary_compressed.is_empty());
+    let report = create_patch(v2.path(), v3.path(), &p23)?;
+    assert!(report.checksums.renamed.contains_key("mods/a-1.1.jar"));
+
+    let report = merge_patches(&p12, &p23, &merged)?;
+    assert!(report.checksums.added.contains_key("mods/a-1.1.jar"));
+    assert!(report.checksums.dictionary_compressed.is_empty());
+    let entries: Vec<String> = Patch::open(&merged)?
+        .entries()
+        .map(|e| e.path.clone())
+        .collect();
+    assert_eq!(
+        entries[entries.len() - 2..],
+        ["metadata.toml", "checksums.toml"]
+    );
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(v1.path(), apply_dir.path());
+    apply_patch(apply_dir.path(), &merged)?;
+    assert!(compare_directories(apply_dir.path(), v3.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn merge_keeps_version_chain_and_newest_creation_time() -> Result<()> {
+    let _guard = patch_lock();
+    let v1 = TempDir::new()?;
+    let v2 = TempDir::new()?;
+    let v3 = TempDir::new()?;
+    write_file(v1.path(), "config.toml", b"1");
+    write_file(v2.path(), "config.toml", b"2");
+    write_file(v3.path(), "config.toml", b"3");
+    let work = TempDir::new()?;
+    let (p12, p23) = (work.path().join("p12.tgz"), work.path().join("p23.tgz"));
+    create_patch(v1.path(), v2.path(), &p12)?;
+    create_patch(v2.path(), v3.path(), &p23)?;
+
+    let set_metadata = |source: &str, target: &str, created_at: &str| {
+        let (source, target, created_at) = (
+            source.to_string(),
+            target.to_string(),
+            created_at.to_string(),
+        );
+        move |dir: &Path| {
+            let path = dir.join("metadata.toml");
+            let mut metadata: toml::Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+            metadata.insert("source_version".into(), source.into());
+            metadata.insert("target_version".into(), target.into());
+            metadata.insert("created_at".into(), created_at.into());
+            fs::write(&path, toml::to_string(&metadata).unwrap()).unwrap();
+        }
+    };
+    let (first, second) = (
+        work.path().join("first.tgz"),
+        work.path().join("second.tgz"),
+    );
+    repack_patch(
+        &p12,
+        &first,
+        set_metadata("1.0", "1.1", "2024-05-01T00:00:00+00:00"),
+    )?;
+    repack_patch(
+        &p23,
+        &second,
+        set_metadata("1.1", "1.2", "2024-03-01T00:00:00+00:00"),
+    )?;
+
+    let merged = work.path().join("merged.tgz");
+    merge_patches(&first, &second, &merged)?;
+    let metadata = Patch::open(&merged)?.metadata().cloned().unwrap();
+    assert_eq!(metadata.source_version.as_deref(), Some("1.0"));
+    assert_eq!(metadata.target_version.as_deref(), Some("1.2"));
+    assert_eq!(metadata.created_at, "2024-05-01T00:00:00+00:00");
+    Ok(())
+}
+
+#[test]
+fn strict_merge_rejects_patches_that_do_not_chain() -> Result<()> {
+    let _guard = patch_lock();
+    let v1 = TempDir::new()?;
+    let v2 = TempDir::new()?;
+    let v3 = TempDir::new()?;
+    write_file(v1.path(), "config.toml", b"1");
+    write_file(v2.path(), "config.toml", b"2");
+    write_file(v3.path(), "config.toml", b"3");
+    let work = TempDir::new()?;
+    let (p12, p13) = (work.path().join("p12.tgz"), work.path().join("p13.tgz"));
+    create_patch(v1.path(), v2.path(), &p12)?;
+    // Both patches start from v1, so the second one does not follow the first
+    create_patch(v1.path(), v3.path(), &p13)?;
+
+    let merged = work.path().join("merged.tgz");
+    let strict = MergeOptions::new().with_strict(true);
+    let err = merge_patches_with_options(&p12, &p13, &merged, &strict).unwrap_err();
+    assert!(format!("{:#}", err).contains("config.toml"));
+    // Without --strict the mismatch is only a warning
+    merge_patches(&p12, &p13, &merged)?;
+
+    let (p23, first, second) = (
+        work.path().join("p23.tgz"),
+        work.path().join("first.tgz"),
+        work.path().join("second.tgz"),
+    );
+    create_patch(v2.path(), v3.path(), &p23)?;
+    let set_versions = |source: &'static str, target: &'static str| {
+        move |dir: &Path| {
+            let path = dir.join("metadata.toml");
+            let mut metadata: toml::Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+            metadata.insert("source_version".into(), source.into());
+            metadata.insert("target_version".into(), target.into());
+            fs::write(&path, toml::to_string(&metadata).unwrap()).unwrap();
+        }
+    };
+    // Unlabelled patches cannot be checked in strict mode
+    assert!(merge_patches_with_options(&p12, &p23, &merged, &strict).is_err());
+    repack_patch(&p12, &first, set_versions("1.0", "1.1"))?;
+    repack_patch(&p23, &second, set_versions("1.1", "1.2"))?;
+    let report = merge_patches_with_options(&first, &second, &merged, &strict)?;
+    assert_eq!(report.source_version.as_deref(), Some("1.0"));
+    assert_eq!(report.target_version.as_deref(), Some("1.2"));
+    Ok(())
+}
+
+#[test]
+fn inspect_dir_writes_html_report_with_tree_sizes_and_text_diff() -> Result<()> {
+    let old = TempDir::new()?;
+    let new = TempDir::new()?;
+    write_file(old.path(), "config/app.toml", b"name = \"a\"\nlevel = 1\n");
+    write_file(new.path(), "config/app.toml", b"name = \"a\"\nlevel = 2\n");
+    write_file(old.path(), "old.bin", &[0u8; 100]);
+    write_file(new.path(), "assets/logo&small.png", &[1u8; 4096]);
+
+    let work = TempDir::new()?;
+    let output = work.path().join("report.html");
+    let comparison = write_html_report(old.path(), new.path(), &output, &CreateOptions::new())?;
+    let changes: Vec<(&str, &str)> = comparison
+        .files
+        .iter()
+        .map(|f| (f.path.as_str(), f.change.as_str()))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            ("assets/logo&small.png", "added"),
+            ("config/app.toml", "modified"),
+            ("old.bin", "deleted"),
+        ]
+    );
+    assert_eq!(comparison.files[0].size_delta(), 4096);
+    let diff = comparison.files[1].text_diff.as_deref().unwrap();
+    assert!(diff.contains("-level = 1") && diff.contains("+level = 2"));
+
+    let html = fs::read_to_string(&output)?;
+    assert!(html.contains("差异树") && html.contains("大小变化") && html.contains("文本差异"));
+    // File names are HTML-escaped
+    assert!(html.contains("logo&amp;small.png") && !html.contains("logo&small"));
+    Ok(())
+}
+
+#[test]
+fn compare_directories_applies_ignore_rules_per_side() -> Result<()> {
+    let production = TempDir::new()?;
+    let release = TempDir::new()?;
+    for dir in [production.path(), release.path()] {
+        write_file(dir, "app.jar", b"v1");
+        write_file(dir, "config/local/app.toml", b"release");
+    }
+    write_file(production.path(), "logs/latest.log", b"log");
+    write_file(production.path(), "mods/cache/index.bin", b"cache");
+    write_file(production.path(), "config/local/app.toml", b"tuned");
+    write_file(release.path(), "README.md", b"new");
+
+    // Only the production side has runtime state to skip
+    let rules = IgnoreRules::new()
+        .with_pattern("logs/")
+        .with_pattern("cache/")
+        .with_pattern("/config/local/*.toml");
+    assert!(rules.is_ignored(Path::new("mods/cache/index.bin")));
+    assert!(!rules.is_ignored(Path::new("cache")));
+    assert!(!rules.is_ignored(Path::new("other/config/local/app.toml")));
+    let options = CreateOptions::new().with_source_ignore(rules);
+    let diffs = compare_directories_with_options(production.path(), release.path(), &options)?;
+    let diffs: Vec<String> = diffs
+        .iter()
+        .map(|d| format!("{}{}", d.symbol(), d.path().display()))
+        .collect();
+    // The ignored source copy of config/local/app.toml makes the release copy look added
+    assert_eq!(diffs, ["+README.md", "+config/local/app.toml"]);
+
+    let both = CreateOptions::new()
+        .with_ignore("logs/")
+        .with_ignore("cache/")
+        .with_ignore("config/local/")
+        .with_fast(true);
+    let diffs = compare_directories_with_options(production.path(), release.path(), &both)?;
+    assert_eq!(diffs.len(), 1);
+    assert_eq!(diffs[0].path(), Path::new("README.md"));
+    Ok(())
+}
+
+#[test]
+fn normalize_eol_skips_line_ending_and_bom_only_changes() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "crlf.txt", b"line 1\r\nline 2\r\n");
+    write_file(target.path(), "crlf.txt", b"\xEF\xBB\xBFline 1\nline 2\n");
+    write_file(source.path(), "edited.txt", b"old\r\n");
+    write_file(target.path(), "edited.txt", b"new\n");
+    // Binary files are always compared byte for byte
+    write_file(source.path(), "data.bin", b"\0\r\n");
+    write_file(target.path(), "data.bin", b"\0\n");
+
+    let work = TempDir::new()?;
+    let output = work.path().join("patch.tgz");
+    let options = CreateOptions::new().with_normalize_eol(true);
+    let report = create_patch_with_options(source.path(), target.path(), &output, &options)?;
+    let mut modified: Vec<&String> = report.checksums.modified.keys().collect();
+    modified.sort();
+    assert_eq!(modified, ["data.bin", "edited.txt"]);
+
+    let report = create_patch(source.path(), target.path(), &output)?;
+    assert_eq!(report.checksums.modified.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn file_kind_is_detected_from_magic_bytes() -> Result<()> {
+    let mut jar = b"PK\x03\x04".to_vec();
+    jar.extend_from_slice(&[0; 22]);
+    jar.extend_from_slice(&20u16.to_le_bytes());
+    jar.extend_from_slice(&[0, 0]);
+    jar.extend_from_slice(b"META-INF/MANIFEST.MF");
+    let mut pe = b"MZ".to_vec();
+    pe.resize(0x3c, 0);
+    pe.extend_from_slice(&0x40u32.to_le_bytes());
+    pe.extend_from_slice(b"PE\0\0");
+    assert_eq!(FileKind::detect(&jar), FileKind::Jar);
+    assert_eq!(FileKind::detect(b"PK\x03\x04\0\0"), FileKind::Zip);
+    assert_eq!(FileKind::detect(&pe), FileKind::Pe);
+    assert_eq!(FileKind::detect(b"\x7fELF\x02\x01"), FileKind::Elf);
+    assert_eq!(FileKind::detect(b"\xef\xbb\xbfkey = 1"), FileKind::Text);
+    assert_eq!(FileKind::detect(b"a\0b"), FileKind::Binary);
+
+    // Text detection no longer depends on the extension
+    let dir = TempDir::new()?;
+    assert!(is_text_file(&write_file(dir.path(), "LICENSE", b"MIT")));
+    assert!(!is_text_file(&write_file(
+        dir.path(),
+        "notes.txt",
+        b"\x7fELF\x02"
+    )));
+
+    // Already-compressed formats are stored as-is instead of dictionary-compressed
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    for i in 0..16 {
+        let config = format!("[general]\nname = \"mod_{i}\"\nenabled = true\n");
+        write_file(
+            target.path(),
+            &format!("config/mod_{}.toml", i),
+            config.as_bytes(),
+        );
+    }
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend(pseudo_random_bytes(2048, 3));
+    write_file(target.path(), "icon.png", &png);
+    let output = dir.path().join("patch.tgz");
+    let options = CreateOptions::new().with_zstd_dictionary(true);
+    let report = create_patch_with_options(source.path(), target.path(), &output, &options)?;
+    assert!(!report.checksums.dictionary_compressed.is_empty());
+    assert!(
+        !report
+            .checksums
+            .dictionary_compressed
+            .iter()
+            .any(|p| p == "icon.png")
+    );
+    Ok(())
+}
+
+#[test]
+fn create_and_apply_report_size_changes() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "mods/old.jar", &[1; 50]);
+    write_file(source.path(), "mods/core.jar", &[2; 100]);
+    write_file(target.path(), "mods/core.jar", &[3; 300]);
+    write_file(target.path(), "mods/new.jar", &[4; 1000]);
+
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    let report = create_patch(source.path(), target.path(), &patch)?;
+    assert_eq!(report.checksums.size_delta(), 1150);
+    assert_eq!(
+        report.checksums.sizes["mods/core.jar"],
+        FileSizes::new(Some(100), Some(300))
+    );
+    // The recorded sizes travel with the patch for `show`
+    let info = inspect_patch(&patch)?;
+    assert_eq!(info.checksums.sizes, report.checksums.sizes);
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let applied = apply_patch(apply_dir.path(), &patch)?;
+    assert_eq!(applied.size_delta(), 1150);
+    assert_eq!(
+        applied.size_changes["mods/old.jar"],
+        FileSizes::new(Some(50), None)
+    );
+    assert_eq!(
+        applied.size_changes["mods/new.jar"],
+        FileSizes::new(None, Some(1000))
+    );
+    Ok(())
+}
+
+#[test]
+fn metadata_only_changes_are_reported_and_optionally_packaged() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    for dir in [source.path(), target.path()] {
+        write_file(dir, "run.sh", b"echo hi");
+        write_file(dir, "same.txt", b"same");
+    }
+    let same = FileAttributes::read(&source.path().join("same.txt"))?;
+    same.apply_to(&target.path().join("same.txt"))?;
+    let attributes = FileAttributes {
+        mode: cfg!(unix).then_some(0o755),
+        modified: Some(1_700_000_000),
+    };
+    attributes.apply_to(&target.path().join("run.sh"))?;
+
+    // Without attribute tracking identical content means no difference
+    assert!(compare_directories(source.path(), target.path())?.is_empty());
+    let diffs = compare_directories_with_attributes(source.path(), target.path())?;
+    assert_eq!(diffs.len(), 1);
+    assert!(matches!(&diffs[0], FileDiff::MetadataOnly(path) if path == Path::new("run.sh")));
+
+    let work = TempDir::new()?;
+    let patch = work.path().join("attrs.tgz");
+    let options = CreateOptions::new().with_track_attributes(true);
+    let report = create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+    assert!(report.output.is_none());
+
+    let options = options.with_include_metadata_only(true);
+    let report = create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+    assert_eq!(report.checksums.attributes["run.sh"], attributes);
+
+    let report = apply_patch(source.path(), &patch)?;
+    assert_eq!(report.attributes, vec!["run.sh".to_string()]);
+    assert_eq!(
+        FileAttributes::read(&source.path().join("run.sh"))?,
+        attributes
+    );
+    Ok(())
+}
+
+#[test]
+fn hash_files_lists_a_file_or_every_file_under_a_directory() -> Result<()> {
+    let dir = TempDir::new()?;
+    write_file(dir.path(), "a.txt", b"alpha");
+    write_file(dir.path(), "sub/b.bin", b"beta");
+
+    let sha256 = hash_files(dir.path(), HashAlgorithm::Sha256, 4)?;
+    let keys: Vec<_> = sha256.keys().map(|k| k.replace('\\', "/")).collect();
+    assert_eq!(keys, ["a.txt", "sub/b.bin"]);
+    assert_eq!(sha256["a.txt"], compute_hash(b"alpha"));
+
+    let blake3 = hash_files(&dir.path().join("a.txt"), HashAlgorithm::Blake3, 1)?;
+    assert_eq!(blake3.len(), 1);
+    assert_eq!(blake3["a.txt"].hash, *blake3::hash(b"alpha").as_bytes());
+    assert!("md5".parse::<HashAlgorithm>().is_err());
+    Ok(())
+}
+
+#[test]
+fn patch_listing_a_path_as_both_added_and_modified_is_rejected() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "extra.txt", b"extra");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    // Smuggle an added/ copy of the modified file with different content
+    let conflicting = work.path().join("conflicting.tgz");
+    repack_patch(&patch, &conflicting, |dir| {
+        write_file(dir, "added/config.toml", b"evil");
+        let path = dir.join("checksums.toml");
+        let mut checksums: toml::Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+        let added = checksums["added"].as_table_mut().unwrap();
+        added.insert("config.toml".into(), compute_hash(b"evil").to_hex().into());
+        fs::write(&path, toml::to_string(&checksums).unwrap()).unwrap();
+    })?;
+
+    let apply_dir = TempDir::new()?;
+    copy_dir(source.path(), apply_dir.path());
+    let err = apply_patch(apply_dir.path(), &conflicting).unwrap_err();
+    assert!(format!("{:#}", err).contains("同时登记为新增与修改: config.toml"));
+    assert!(compare_directories(apply_dir.path(), source.path())?.is_empty());
+
+    let merged = work.path().join("merged.tgz");
+    assert!(merge_patches(&patch, &conflicting, &merged).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn testing_helpers_build_trees_and_patch_fixtures() -> Result<()> {
+    use bin_diff_tool::testing::{DirTree, assert_dirs_equal, patch_fixture, random_bytes};
+
+    let _guard = patch_lock();
+    assert_eq!(random_bytes(64, 7), random_bytes(64, 7));
+    assert_ne!(random_bytes(64, 7), random_bytes(64, 8));
+
+    let v1 = DirTree::new()
+        .file("config/app.toml", "level = 1")
+        .random_file("data.bin", 8192, 1)
+        .dir("empty");
+    let v2 = DirTree::new()
+        .file("config/app.toml", "level = 2")
+        .random_file("data.bin", 8192, 2)
+        .file("new.txt", "new");
+    let tree = v1.build()?;
+    assert!(tree.path().join("empty").is_dir());
+    assert_eq!(
+        fs::read(tree.path().join("data.bin"))?,
+        random_bytes(8192, 1)
+    );
+
+    let fixture = patch_fixture(&v1, &v2)?;
+    apply_patch(&fixture.source, &fixture.patch)?;
+    assert_dirs_equal(&fixture.source, &fixture.target);
+
+    let other = v1.build()?;
+    let panicked = std::panic::catch_unwind(|| assert_dirs_equal(other.path(), &fixture.target));
+    assert!(panicked.is_err());
+    Ok(())
+}
+
+#[test]
+fn verify_roundtrip_applies_the_patch_to_a_copy_and_compares() -> Result<()> {
+    let _guard = patch_lock();
+    let a = TempDir::new()?;
+    let b = TempDir::new()?;
+    write_file(a.path(), "keep.txt", b"keep");
+    write_file(a.path(), "gone.txt", b"gone");
+    write_file(a.path(), "old/name.bin", &pseudo_random_bytes(8192, 3));
+    write_file(b.path(), "keep.txt", b"kept");
+    write_file(b.path(), "new.txt", b"new");
+    write_file(b.path(), "new/name.bin", &pseudo_random_bytes(8192, 3));
+
+    let report = verify_roundtrip(a.path(), b.path())?;
+    assert!(report.is_ok(), "{:?}", report.differences);
+    assert!(report.patch_bytes > 0);
+    assert!(report.apply.is_some());
+    // The source directory itself is left untouched
+    assert_eq!(fs::read(a.path().join("keep.txt"))?, b"keep");
+    assert!(a.path().join("gone.txt").exists());
+
+    let report = verify_roundtrip(a.path(), a.path())?;
+    assert!(report.is_ok());
+    assert!(report.apply.is_none());
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "cli",
+    feature = "network",
+    feature = "signing",
+    feature = "watch",
+    feature = "schema"
+))]
+#[test]
+fn cli_paths_are_expanded_and_made_absolute() -> Result<()> {
+    use bin_diff_tool::cli::parse_path;
+
+    let cwd = std::env::current_dir()?;
+    assert_eq!(
+        parse_path("  \"some/dir/\" ").unwrap(),
+        cwd.join("some/dir")
+    );
+    assert_eq!(parse_path("'patch.tgz'").unwrap(), cwd.join("patch.tgz"));
+    assert_eq!(parse_path("-").unwrap(), Path::new("-"));
+    assert_eq!(parse_path("/").unwrap(), Path::new("/"));
+    assert!(parse_path(" ").is_err());
+    if let Some(home) = std::env::var_os("HOME") {
+        let home = PathBuf::from(home);
+        assert_eq!(parse_path("~").unwrap(), home);
+        assert_eq!(parse_path("~/patches/").unwrap(), home.join("patches"));
+    }
+    // `~user` is not expanded
+    assert_eq!(parse_path("~user/x").unwrap(), cwd.join("~user/x"));
+
+    let dir = TempDir::new()?;
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dft"))
+        .current_dir(dir.path())
+        .args(["info", "missing/"])
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let expected = dir.path().join("missing");
+    assert!(stderr.contains(&*expected.to_string_lossy()), "{}", stderr);
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "cli",
+    feature = "network",
+    feature = "signing",
+    feature = "watch",
+    feature = "schema"
+))]
+#[test]
+fn cli_reads_patches_from_urls_through_the_download_cache() -> Result<()> {
+    use bin_diff_tool::utils::CACHE_DIR_ENV;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::process::Command;
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    {
+        let _guard = patch_lock();
+        create_patch(source.path(), target.path(), &patch)?;
+    }
+
+    // Serves the patch to a single connection only
+    let body = fs::read(&patch)?;
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/releases/patch.tgz", listener.local_addr()?);
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        let header = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+        (&stream).write_all(header.as_bytes()).unwrap();
+        (&stream).write_all(&body).unwrap();
+    });
+
+    let cache = work.path().join("cache");
+    let dft = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_dft"))
+            .env(CACHE_DIR_ENV, &cache)
+            .args(args)
+            .output()
+    };
+    assert!(dft(&["show", &url])?.status.success());
+    // The second run is served from the cache; the server is gone by now
+    let dir = source.path().to_str().unwrap();
+    let output = dft(&["apply", dir, "--patch", &url])?;
+    assert!(output.status.success(), "{:?}", output);
+    assert!(compare_directories(source.path(), target.path())?.is_empty());
+
+    let cached: Vec<_> = fs::read_dir(cache.join("objects"))?.collect();
+    assert_eq!(cached.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn patch_cache_is_content_addressed_and_evicts_least_recently_used() -> Result<()> {
+    use std::time::{Duration, SystemTime};
+
+    let work = TempDir::new()?;
+    let cache = PatchCache::open(work.path().join("cache"))?.with_max_size(10);
+    let a = write_file(work.path(), "a.tgz", b"aaaa");
+    let b = write_file(work.path(), "b.tgz", b"bbbb");
+    let c = write_file(work.path(), "c.tgz", b"cccc");
+
+    let (hash_a, cached_a) = cache.insert(&a)?;
+    assert_eq!(hash_a, compute_hash(b"aaaa"));
+    assert_eq!(fs::read(&cached_a)?, b"aaaa");
+    let (hash_b, cached_b) = cache.insert(&b)?;
+    // Backdate both entries with `b` the oldest, then use `a` so `b` is least recently used
+    let past = SystemTime::now() - Duration::from_secs(3600);
+    for path in [&cached_a, &cached_b] {
+        fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(past)?;
+    }
+    fs::File::options()
+        .write(true)
+        .open(&cached_b)?
+        .set_modified(past - Duration::from_secs(60))?;
+    assert!(cache.get(&hash_a).is_some());
+
+    let (hash_c, _) = cache.insert(&c)?;
+    assert!(cache.get(&hash_b).is_none());
+    assert!(cache.get(&hash_a).is_some());
+    assert!(cache.get(&hash_c).is_some());
+    assert_eq!(cache.size()?, 8);
+
+    // A known hash is served without touching the network
+    #[cfg(feature = "network")]
+    {
+        use bin_diff_tool::utils::DownloadOptions;
+
+        let unreachable = "http://127.0.0.1:9/a.tgz";
+        let options = DownloadOptions::new().with_max_retries(0);
+        assert_eq!(cache.fetch(unreachable, Some(&hash_a), &options)?, cached_a);
+        assert!(cache.fetch(unreachable, Some(&hash_b), &options).is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn patches_requiring_a_newer_tool_are_refused() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+    let metadata = Patch::open(&patch)?.metadata().cloned().unwrap();
+    assert_eq!(metadata.min_tool_version.as_deref(), Some(TOOL_VERSION));
+
+    let future = work.path().join("future.tgz");
+    repack_patch(&patch, &future, |dir| {
+        let path = dir.join("metadata.toml");
+        let mut metadata: toml::Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+        metadata.insert("min_tool_version".into(), "999.0.0".into());
+        fs::write(&path, toml::to_string(&metadata).unwrap()).unwrap();
+    })?;
+    let err = apply_patch(source.path(), &future).unwrap_err();
+    assert!(format!("{:#}", err).contains("需要 dft 999.0.0 或更高版本"));
+    assert_eq!(fs::read(source.path().join("config.toml"))?, b"old");
+    assert!(show_patch(&future).is_err());
+
+    // Patches from older tools carry no requirement and still apply
+    let legacy = work.path().join("legacy.tgz");
+    repack_patch(&patch, &legacy, |dir| {
+        let path = dir.join("metadata.toml");
+        let mut metadata: toml::Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+        metadata.remove("min_tool_version");
+        fs::write(&path, toml::to_string(&metadata).unwrap()).unwrap();
+    })?;
+    apply_patch(source.path(), &legacy)?;
+    Ok(())
+}
+
+#[test]
+fn apply_matches_existing_file_names_case_exactly() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "mods/foo.jar", b"old mod");
+    write_file(source.path(), "keep.txt", b"keep");
+    write_file(target.path(), "keep.txt", b"keep");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    // The installed copy differs from the patch only in case
+    let dir = TempDir::new()?;
+    write_file(dir.path(), "mods/Foo.jar", b"old mod");
+    write_file(dir.path(), "keep.txt", b"keep");
+    let case_insensitive = dir.path().join("mods/FOO.JAR").exists();
+
+    let report = apply_patch(dir.path(), &patch)?;
+    if case_insensitive {
+        // Same file: renamed to the patch's spelling first, then deleted
+        assert_eq!(report.deleted, vec!["mods/foo.jar"]);
+        assert!(report.warnings.iter().any(|w| w.contains("只有大小写不同")));
+        assert!(!dir.path().join("mods/Foo.jar").exists());
+    } else {
+        // A different file on a case-sensitive file system is left alone
+        assert!(report.deleted.is_empty());
+        assert!(report.warnings.is_empty());
+        assert_eq!(fs::read(dir.path().join("mods/Foo.jar"))?, b"old mod");
+    }
+    Ok(())
+}
+
+#[test]
+fn apply_emits_events_to_subscribers() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "old.txt", b"old");
+    write_file(source.path(), "config.toml", b"v1");
+    write_file(target.path(), "config.toml", b"v2");
+    write_file(target.path(), "new.txt", b"new");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    write_file(dir.path(), "config.toml", b"locally edited");
+    let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let seen = events.clone();
+    let audit = work.path().join("audit.jsonl");
+    let options = ApplyOptions::new()
+        .with_events(EventBus::empty())
+        .with_event_sink(move |event: &PatchEvent| seen.lock().unwrap().push(event.clone()))
+        .with_event_sink(AuditLog::open(&audit)?);
+    apply_patch_with_options(dir.path(), &patch, &options)?;
+
+    let events = events.lock().unwrap();
+    let has = |f: &dyn Fn(&PatchEvent) -> bool| events.iter().any(f);
+    assert!(has(
+        &|e| matches!(e, PatchEvent::FileAdded { path, .. } if path == "new.txt")
+    ));
+    assert!(has(
+        &|e| matches!(e, PatchEvent::FileDeleted { path, .. } if path == "old.txt")
+    ));
+    assert!(has(
+        &|e| matches!(e, PatchEvent::FileModified { path, .. } if path == "config.toml")
+    ));
+    assert!(has(
+        &|e| matches!(e, PatchEvent::ChecksumMismatch { path } if path == "config.toml")
+    ));
+    assert!(has(
+        &|e| matches!(e, PatchEvent::StageCompleted(stage) if stage.name == "apply")
+    ));
+
+    // The audit log holds one timestamped JSON line per event
+    let lines: Vec<serde_json::Value> = fs::read_to_string(&audit)?
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), events.len());
+    assert!(lines.iter().all(|line| line["time"].is_string()));
+    assert!(
+        lines
+            .iter()
+            .any(|line| line["event"] == "file_added" && line["path"] == "new.txt")
+    );
+    Ok(())
+}
+
+#[test]
+fn warning_policy_can_ignore_or_deny_each_kind() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"v1");
+    write_file(target.path(), "config.toml", b"v2");
+    write_file(target.path(), "new.txt", b"new");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    // Locally edited config plus a stray file where the patch adds one
+    let prepare = || -> Result<TempDir> {
+        let dir = TempDir::new()?;
+        write_file(dir.path(), "config.toml", b"locally edited");
+        write_file(dir.path(), "new.txt", b"stray");
+        Ok(dir)
+    };
+
+    let dir = prepare()?;
+    let report = apply_patch(dir.path(), &patch)?;
+    assert_eq!(report.warnings.len(), 2);
+
+    let dir = prepare()?;
+    let policy = WarningPolicy::new().allow(WarningKind::ExtraFile);
+    let options = ApplyOptions::new().with_warning_policy(policy);
+    let report = apply_patch_with_options(dir.path(), &patch, &options)?;
+    assert_eq!(report.warnings.len(), 1);
+    assert!(report.warnings[0].contains("校验和不匹配"));
+
+    let dir = prepare()?;
+    let policy = WarningPolicy::new().deny("checksum-mismatch".parse()?);
+    let options = ApplyOptions::new().with_warning_policy(policy);
+    let err = apply_patch_with_options(dir.path(), &patch, &options).unwrap_err();
+    assert!(format!("{:#}", err).contains("`checksum-mismatch` 已设为错误"));
+    assert_eq!(fs::read(dir.path().join("config.toml"))?, b"locally edited");
+
+    assert!("no-such-kind".parse::<WarningKind>().is_err());
+    Ok(())
+}
+
+#[test]
+fn many_small_files_round_trip_through_batched_io() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    for i in 0..400 {
+        let name = format!("config/{}/file_{}.cfg", i % 7, i);
+        write_file(source.path(), &name, format!("key = {}\n", i).as_bytes());
+        if i % 3 != 0 {
+            write_file(
+                target.path(),
+                &name,
+                format!("key = {}\nnew = true\n", i).as_bytes(),
+            );
+        }
+        let added = format!("config/{}/added_{}.cfg", i % 7, i);
+        write_file(
+            target.path(),
+            &added,
+            &pseudo_random_bytes(100 + i, i as u64),
+        );
+    }
+    // A file above the prefetch size limit is read directly
+    write_file(
+        target.path(),
+        "big.bin",
+        &pseudo_random_bytes(200 * 1024, 7),
+    );
+
+    let work = TempDir::new()?;
+    for (name, options) in [
+        ("plain.tgz", CreateOptions::new()),
+        ("dict.tgz", CreateOptions::new().with_zstd_dictionary(true)),
+        ("jobs.tgz", CreateOptions::new().with_jobs(4)),
+    ] {
+        let patch = work.path().join(name);
+        create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+        let dir = TempDir::new()?;
+        copy_dir(source.path(), dir.path());
+        let report = apply_patch(dir.path(), &patch)?;
+        assert_eq!(report.added.len(), 401);
+        assert!(compare_directories(dir.path(), target.path())?.is_empty());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn scan_and_apply_do_not_follow_symlinked_entries() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "data/a.txt", b"a");
+    write_file(target.path(), "data/a.txt", b"a2");
+    write_file(target.path(), "data/b.txt", b"b");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let outside = TempDir::new()?;
+    let victim = write_file(outside.path(), "victim.txt", b"keep");
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    // Symlinks to files and directories are not part of the scanned tree
+    std::os::unix::fs::symlink(&victim, dir.path().join("data/b.txt"))?;
+    std::os::unix::fs::symlink(outside.path(), dir.path().join("linked"))?;
+    let scanned = scan_directory(dir.path())?;
+    assert_eq!(
+        scanned.keys().collect::<Vec<_>>(),
+        vec![Path::new("data/a.txt")]
+    );
+    assert_eq!(scan_file_stats(dir.path())?.len(), 1);
+
+    // The added file replaces the symlink instead of writing through it
+    apply_patch(dir.path(), &patch)?;
+    assert_eq!(fs::read(&victim)?, b"keep");
+    let b = dir.path().join("data/b.txt");
+    assert!(!fs::symlink_metadata(&b)?.file_type().is_symlink());
+    assert_eq!(fs::read(&b)?, b"b");
+    assert_eq!(fs::read(dir.path().join("data/a.txt"))?, b"a2");
+    Ok(())
+}
+
+#[test]
+fn apply_runs_pre_and_post_commands_and_reports_their_output() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "server.jar", b"v1");
+    write_file(target.path(), "server.jar", b"v2");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    let options = ApplyOptions::new()
+        .with_pre_command("echo stopping")
+        .with_post_command("echo starting");
+    let report = apply_patch_with_options(dir.path(), &patch, &options)?;
+    assert_eq!(report.hooks.len(), 2);
+    assert_eq!(report.hooks[0].stage, "pre");
+    assert!(report.hooks[0].output.contains("stopping"));
+    assert!(report.hooks[1].success);
+    assert!(report.hooks[1].output.contains("starting"));
+
+    // A failing pre command aborts before anything is touched
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    let options = ApplyOptions::new().with_pre_command("exit 3");
+    let err = apply_patch_with_options(dir.path(), &patch, &options).unwrap_err();
+    assert!(err.to_string().contains("前置命令失败 (退出码 3)"));
+    assert_eq!(fs::read(dir.path().join("server.jar"))?, b"v1");
+
+    // ... unless failures are tolerated
+    let options = options.with_hook_policy(HookPolicy::Continue);
+    let report = apply_patch_with_options(dir.path(), &patch, &options)?;
+    assert_eq!(report.hooks[0].exit_code, Some(3));
+    assert_eq!(fs::read(dir.path().join("server.jar"))?, b"v2");
+    Ok(())
+}
+
+#[test]
+fn description_author_and_notes_are_written_to_metadata_and_changelog() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "a.txt", b"a");
+    write_file(target.path(), "a.txt", b"b");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    let notes = "- fixed the crash on startup\n- updated the configs\n";
+    let options = CreateOptions::new()
+        .with_description("修复启动崩溃")
+        .with_author("nmpassthf")
+        .with_notes(notes);
+    create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+
+    let metadata = Patch::open(&patch)?.metadata().cloned().unwrap();
+    assert_eq!(metadata.description.as_deref(), Some("修复启动崩溃"));
+    assert_eq!(metadata.author.as_deref(), Some("nmpassthf"));
+    assert_eq!(metadata.notes.as_deref(), Some(notes));
+
+    let changelog = patch_changelog(&patch, ChangelogFormat::Markdown)?;
+    assert!(changelog.contains("修复启动崩溃"));
+    assert!(changelog.contains("作者: nmpassthf"));
+    assert!(changelog.contains("- fixed the crash on startup\n- updated the configs"));
+    Ok(())
+}
+
+#[cfg(feature = "schema")]
+#[test]
+fn json_schemas_cover_metadata_and_reports() -> Result<()> {
+    use bin_diff_tool::patch::{SCHEMA_NAMES, json_schema, json_schemas};
+
+    let schemas = json_schemas();
+    for (name, _) in SCHEMA_NAMES {
+        assert!(schemas.contains_key(name), "missing schema {}", name);
+    }
+    assert!(json_schema("unknown").is_none());
+
+    // Schemas describe what the tool actually writes
+    let checksums = &schemas["checksums"];
+    assert!(checksums["properties"]["added"].is_object());
+    assert!(checksums["properties"]["merge_bases"].is_object());
+    let text = serde_json::to_string(checksums)?;
+    assert!(text.contains("^[0-9a-f]{64}$"));
+    let report = &schemas["apply-report"];
+    for field in ["added", "modified", "warnings", "timings"] {
+        assert!(report["properties"][field].is_object(), "missing {}", field);
+    }
+    Ok(())
+}
+
+#[test]
+fn apply_skips_added_files_that_already_match() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "keep.txt", b"keep");
+    write_file(target.path(), "keep.txt", b"keep");
+    write_file(target.path(), "mods/new.jar", b"new jar");
+    write_file(target.path(), "mods/other.jar", b"other jar");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    // One added file is already in place with the right content and an old mtime
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    write_file(dir.path(), "mods/new.jar", b"new jar");
+    let old_mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    fs::File::options()
+        .write(true)
+        .open(dir.path().join("mods/new.jar"))?
+        .set_modified(old_mtime)?;
+
+    let report = apply_patch(dir.path(), &patch)?;
+    assert_eq!(report.unchanged, vec!["mods/new.jar"]);
+    assert_eq!(report.added, vec!["mods/other.jar"]);
+    assert!(report.warnings.is_empty());
+    let mtime = fs::metadata(dir.path().join("mods/new.jar"))?.modified()?;
+    assert_eq!(mtime, old_mtime);
+    Ok(())
+}
+
+#[test]
+fn reapplying_a_patch_is_idempotent() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old config");
+    write_file(source.path(), "big.bin", &pseudo_random_bytes(64 * 1024, 1));
+    write_file(target.path(), "config.toml", b"new config");
+    write_file(target.path(), "big.bin", &pseudo_random_bytes(64 * 1024, 2));
+    write_file(target.path(), "added.txt", b"added");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    let first = apply_patch(dir.path(), &patch)?;
+    assert_eq!(first.modified.len(), 2);
+    assert!(first.unchanged.is_empty());
+
+    // The second run writes nothing and raises no checksum warnings
+    let second = apply_patch(dir.path(), &patch)?;
+    assert!(second.modified.is_empty() && second.added.is_empty());
+    assert_eq!(
+        second.unchanged,
+        vec!["added.txt", "big.bin", "config.toml"]
+    );
+    assert!(second.warnings.is_empty(), "{:?}", second.warnings);
+    assert_eq!(fs::read(dir.path().join("config.toml"))?, b"new config");
+    Ok(())
+}
+
+#[test]
+fn derive_patch_between_two_targets_of_the_same_source() -> Result<()> {
+    let _guard = patch_lock();
+    let v1 = TempDir::new()?;
+    write_file(v1.path(), "a.txt", b"alpha version one");
+    write_file(v1.path(), "b.txt", b"bravo version one");
+    write_file(v1.path(), "c.txt", b"charlie version one");
+    write_file(v1.path(), "same.txt", b"never changes");
+    let v2 = TempDir::new()?;
+    copy_dir(v1.path(), v2.path());
+    write_file(v2.path(), "a.txt", b"alpha version two");
+    write_file(v2.path(), "d.txt", b"delta only in two");
+    fs::remove_file(v2.path().join("c.txt"))?;
+    let v4 = TempDir::new()?;
+    copy_dir(v1.path(), v4.path());
+    write_file(v4.path(), "a.txt", b"alpha version four");
+    write_file(v4.path(), "b.txt", b"bravo version four");
+    write_file(v4.path(), "e.txt", b"echo only in four");
+    fs::remove_file(v4.path().join("c.txt"))?;
+
+    let work = TempDir::new()?;
+    let (v1_v2, v1_v4) = (work.path().join("v1-v2.tgz"), work.path().join("v1-v4.tgz"));
+    create_patch(v1.path(), v2.path(), &v1_v2)?;
+    create_patch(v1.path(), v4.path(), &v1_v4)?;
+
+    let derived = work.path().join("v2-v4.tgz");
+    let report = derive_patch(&v1_v2, &v1_v4, &derived)?;
+    assert_eq!(report.checksums.deleted, vec!["d.txt"]);
+    assert!(report.checksums.added.contains_key("e.txt"));
+    assert_eq!(report.checksums.modified.len(), 2);
+
+    let dir = TempDir::new()?;
+    copy_dir(v2.path(), dir.path());
+    let applied = apply_patch(dir.path(), &derived)?;
+    assert!(applied.warnings.is_empty(), "{:?}", applied.warnings);
+    assert!(compare_directories(dir.path(), v4.path())?.is_empty());
+
+    // v4 -> v2 needs bravo's original content, which neither patch carries
+    let err = derive_patch(&v1_v4, &v1_v2, &work.path().join("bad.tgz")).unwrap_err();
+    assert!(format!("{:#}", err).contains("b.txt"));
+    Ok(())
+}
+
+#[test]
+fn patch_notice_is_embedded_and_merged_in_order() -> Result<()> {
+    let _guard = patch_lock();
+    let v1 = TempDir::new()?;
+    write_file(v1.path(), "a.txt", b"one");
+    let v2 = TempDir::new()?;
+    write_file(v2.path(), "a.txt", b"two");
+    let v3 = TempDir::new()?;
+    write_file(v3.path(), "a.txt", b"three");
+    let work = TempDir::new()?;
+    let (first, second) = (work.path().join("1.tgz"), work.path().join("2.tgz"));
+    let options = CreateOptions::new().with_notice("# 1.1\n\nBack up your saves.\n");
+    create_patch_with_options(v1.path(), v2.path(), &first, &options)?;
+    let options = CreateOptions::new().with_notice("# 1.2\n");
+    create_patch_with_options(v2.path(), v3.path(), &second, &options)?;
+
+    let patch = Patch::open(&first)?;
+    assert!(patch.entries().any(|e| e.path == "NOTICE.md"));
+    assert_eq!(
+        patch.notice()?.as_deref(),
+        Some("# 1.1\n\nBack up your saves.\n")
+    );
+    assert!(validate_patch(&first)?.is_valid());
+
+    let merged = work.path().join("merged.tgz");
+    merge_patches(&first, &second, &merged)?;
+    assert_eq!(
+        Patch::open(&merged)?.notice()?.as_deref(),
+        Some("# 1.1\n\nBack up your saves.\n\n# 1.2")
+    );
+
+    // The notice is not a file change
+    let dir = TempDir::new()?;
+    copy_dir(v1.path(), dir.path());
+    apply_patch(dir.path(), &merged)?;
+    assert!(compare_directories(dir.path(), v3.path())?.is_empty());
+
+    let plain = work.path().join("plain.tgz");
+    create_patch(v1.path(), v2.path(), &plain)?;
+    assert!(Patch::open(&plain)?.notice()?.is_none());
+    Ok(())
+}
+
+#[test]
+fn zstd_and_xz_patches_are_detected_when_applying_and_merging() -> Result<()> {
+    let _guard = patch_lock();
+    let v1 = TempDir::new()?;
+    write_file(v1.path(), "mods/a.jar", &pseudo_random_bytes(8 * 1024, 1));
+    write_file(v1.path(), "config/b.toml", b"b = 1\n");
+    let v2 = TempDir::new()?;
+    copy_dir(v1.path(), v2.path());
+    write_file(v2.path(), "config/b.toml", b"b = 2\n");
+    write_file(v2.path(), "mods/c.jar", &pseudo_random_bytes(4 * 1024, 2));
+    let v3 = TempDir::new()?;
+    copy_dir(v2.path(), v3.path());
+    fs::remove_file(v3.path().join("mods/a.jar"))?;
+
+    let work = TempDir::new()?;
+    let (first, second) = (work.path().join("1.tar.zst"), work.path().join("2.tar.xz"));
+    // Parallel packaging concatenates one zstd frame per shard
+    let options = CreateOptions::new()
+        .with_compression(CompressionFormat::Zstd)
+        .with_jobs(2);
+    create_patch_with_options(v1.path(), v2.path(), &first, &options)?;
+    let options = CreateOptions::new().with_compression(CompressionFormat::Xz);
+    create_patch_with_options(v2.path(), v3.path(), &second, &options)?;
+
+    assert_eq!(CompressionFormat::detect(&first)?, CompressionFormat::Zstd);
+    assert_eq!(CompressionFormat::detect(&second)?, CompressionFormat::Xz);
+    let metadata = Patch::open(&first)?.metadata().cloned().unwrap();
+    assert_eq!(metadata.compression, CompressionFormat::Zstd);
+    assert!(validate_patch(&second)?.is_valid());
+
+    let dir = TempDir::new()?;
+    copy_dir(v1.path(), dir.path());
+    apply_patch(dir.path(), &first)?;
+    assert!(compare_directories(dir.path(), v2.path())?.is_empty());
+    apply_patch(dir.path(), &second)?;
+    assert!(compare_directories(dir.path(), v3.path())?.is_empty());
+
+    // Merging keeps the first patch's format unless told otherwise
+    let merged = work.path().join("merged.tar.zst");
+    merge_patches(&first, &second, &merged)?;
+    assert_eq!(CompressionFormat::detect(&merged)?, CompressionFormat::Zstd);
+    let merged_gz = work.path().join("merged.tgz");
+    let options = MergeOptions::new().with_compression(CompressionFormat::Gzip);
+    merge_patches_with_options(&first, &second, &merged_gz, &options)?;
+    assert_eq!(
+        CompressionFormat::detect(&merged_gz)?,
+        CompressionFormat::Gzip
+    );
+
+    let dir = TempDir::new()?;
+    copy_dir(v1.path(), dir.path());
+    apply_patch(dir.path(), &merged_gz)?;
+    assert!(compare_directories(dir.path(), v3.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn apply_report_counts_lines_for_text_and_bytes_for_binary_changes() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.txt", b"a\nb\nc\n");
+    write_file(target.path(), "config.txt", b"a\nB\nc\nd\ne\n");
+    write_file(source.path(), "mod.bin", &[0u8; 100]);
+    write_file(target.path(), "mod.bin", &[1u8; 300]);
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    let report = apply_patch(dir.path(), &patch)?;
+    assert_eq!(report.line_changes.len(), 1);
+    let lines = report.line_changes["config.txt"];
+    assert_eq!((lines.added, lines.removed), (3, 1));
+    assert_eq!(report.describe_modification("config.txt"), "文本 +3 -1 行");
+    assert_eq!(report.describe_modification("mod.bin"), "二进制 +200 B");
+    Ok(())
+}
+
+#[test]
+fn compression_level_and_threads_trade_cpu_for_patch_size() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let text: Vec<u8> = (0..20_000)
+        .flat_map(|i| format!("line {} of the generated config\n", i % 700).into_bytes())
+        .collect();
+    write_file(target.path(), "config.txt", &text);
+    let work = TempDir::new()?;
+
+    let create = |name: &str, compression: CompressionOptions| -> Result<(PathBuf, u64)> {
+        let patch = work.path().join(name);
+        let options = CreateOptions::new()
+            .with_compression(CompressionFormat::Zstd)
+            .with_compression_options(compression);
+        create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+        let size = fs::metadata(&patch)?.len();
+        Ok((patch, size))
+    };
+    let (_, fast) = create("fast.tar.zst", CompressionOptions::new().with_level(1))?;
+    let (best, small) = create(
+        "best.tar.zst",
+        CompressionOptions::new().with_level(19).with_threads(2),
+    )?;
+    assert!(small < fast, "{} >= {}", small, fast);
+
+    let dir = TempDir::new()?;
+    apply_patch(dir.path(), &best)?;
+    assert_eq!(fs::read(dir.path().join("config.txt"))?, text);
+
+    // Levels outside the format's range are rejected
+    let options =
+        CreateOptions::new().with_compression_options(CompressionOptions::new().with_level(12));
+    let err = create_patch_with_options(
+        source.path(),
+        target.path(),
+        &work.path().join("bad.tgz"),
+        &options,
+    )
+    .unwrap_err();
+    assert!(format!("{:#}", err).contains("0-9"));
+    Ok(())
+}
+
+#[test]
+fn respect_gitignore_skips_build_outputs_and_honors_nested_rules() -> Result<()> {
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    for dir in [source.path(), target.path()] {
+        write_file(
+            dir,
+            ".gitignore",
+            b"target/\nnode_modules/\n*.log\n!keep.log\n",
+        );
+        write_file(dir, "src/main.rs", b"fn main() {}\n");
+    }
+    write_file(target.path(), "src/lib.rs", b"pub fn f() {}\n");
+    write_file(target.path(), "target/debug/app", b"binary");
+    write_file(target.path(), "web/node_modules/pkg/index.js", b"js");
+    write_file(target.path(), "build.log", b"log");
+    write_file(target.path(), "keep.log", b"kept");
+    write_file(target.path(), "assets/.gitignore", b"*.tmp\n");
+    write_file(target.path(), "assets/cache.tmp", b"tmp");
+    write_file(target.path(), ".git/HEAD", b"ref: refs/heads/main\n");
+
+    let paths = |options: &CreateOptions| -> Result<Vec<String>> {
+        let mut paths: Vec<String> =
+            compare_directories_with_options(source.path(), target.path(), options)?
+                .iter()
+                .map(|diff| diff.path().to_string_lossy().replace('\\', "/"))
+                .collect();
+        paths.sort();
+        Ok(paths)
+    };
+    assert!(paths(&CreateOptions::new())?.contains(&"target/debug/app".to_string()));
+    assert_eq!(
+        paths(&CreateOptions::new().with_respect_gitignore(true))?,
+        ["assets/.gitignore", "keep.log", "src/lib.rs"]
+    );
+
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    let options = CreateOptions::new().with_respect_gitignore(true);
+    let report = create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+    assert_eq!(report.checksums.added.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn moved_files_are_stored_as_instructions_and_moved_in_place() -> Result<()> {
+    let _guard = patch_lock();
+
+    let v1 = TempDir::new()?;
+    let v2 = TempDir::new()?;
+    let v3 = TempDir::new()?;
+    let jar = pseudo_random_bytes(256 * 1024, 11);
+    let mut changed = jar.clone();
+    changed.splice(1_000..1_000, b"patched".iter().copied());
+    write_file(v1.path(), "libs/a.jar", &jar);
+    write_file(v1.path(), "readme.txt", b"v1");
+    write_file(v2.path(), "mods/a.jar", &jar);
+    write_file(v2.path(), "readme.txt", b"v1");
+    write_file(v3.path(), "mods/core/a.jar", &changed);
+    write_file(v3.path(), "readme.txt", b"v3");
+
+    let diffs = compare_directories(v1.path(), v2.path())?;
+    assert_eq!(diffs.len(), 1);
+    assert!(matches!(
+        &diffs[0],
+        FileDiff::Renamed { from, to }
+            if from == Path::new("libs/a.jar") && to == Path::new("mods/a.jar")
+    ));
+
+    let work = TempDir::new()?;
+    let one = work.path().join("one.tgz");
+    let report = create_patch(v1.path(), v2.path(), &one)?;
+    assert_eq!(report.checksums.moved["mods/a.jar"].from, "libs/a.jar");
+    assert!(report.checksums.renamed.is_empty() && report.checksums.added.is_empty());
+    assert!(fs::metadata(&one)?.len() < 4096);
+
+    let dir = TempDir::new()?;
+    copy_dir(v1.path(), dir.path());
+    let applied = apply_patch(dir.path(), &one)?;
+    assert_eq!(applied.renamed.len(), 1);
+    assert!(!dir.path().join("libs").exists());
+    assert_eq!(scan_directory(dir.path())?, scan_directory(v2.path())?);
+
+    // A move followed by a delta rename merges into a single rename from the original path
+    let two = work.path().join("two.tgz");
+    let merged = work.path().join("merged.tgz");
+    create_patch(v2.path(), v3.path(), &two)?;
+    let merge_report = merge_patches(&one, &two, &merged)?;
+    assert_eq!(
+        merge_report.checksums.renamed["mods/core/a.jar"].from,
+        "libs/a.jar"
+    );
+    let dir = TempDir::new()?;
+    copy_dir(v1.path(), dir.path());
+    apply_patch(dir.path(), &merged)?;
+    assert_eq!(scan_directory(dir.path())?, scan_directory(v3.path())?);
+    Ok(())
+}
+
+#[test]
+fn diff_multi_builds_platform_patches_sharing_common_files() -> Result<()> {
+    let _guard = patch_lock();
+
+    let root = TempDir::new()?;
+    let assets = pseudo_random_bytes(64 * 1024, 21);
+    for platform in ["linux", "windows"] {
+        let v1 = root.path().join("v1").join(platform);
+        let v2 = root.path().join("v2").join(platform);
+        write_file(&v1, "config.txt", b"version = 1\n");
+        write_file(&v1, "keep.txt", b"same");
+        write_file(&v2, "config.txt", b"version = 2\n");
+        write_file(&v2, "keep.txt", b"same");
+        write_file(&v2, "assets/big.bin", &assets);
+        write_file(&v2, "bin/launcher", platform.as_bytes());
+    }
+    let spec_path = root.path().join("spec.toml");
+    let mut spec = String::from("output_dir = \"dist\"\n");
+    for platform in ["linux", "windows"] {
+        spec.push_str(&format!(
+            "\n[[platform]]\nname = \"{0}\"\nsource = \"v1/{0}\"\ntarget = \"v2/{0}\"\n",
+            platform
+        ));
+    }
+    fs::write(&spec_path, spec)?;
+
+    let spec = MultiSpec::load(&spec_path)?;
+    let options = CreateOptions::new().with_compression(CompressionFormat::Zstd);
+    let report = create_multi_patches(&spec, &options)?;
+    assert_eq!(report.shared_files, ["assets/big.bin", "config.txt"]);
+    assert_eq!(report.patches.len(), 2);
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(root.path().join("dist/manifest.json"))?)?;
+    assert_eq!(manifest["patches"][1]["file"], "windows.tar.zst");
+    assert_eq!(manifest["shared_files"].as_array().map(Vec::len), Some(2));
+
+    for entry in &report.patches {
+        let patch = root.path().join("dist").join(entry.file.as_ref().unwrap());
+        assert_eq!(entry.hash.as_ref(), Some(&compute_file_hash(&patch)?));
+        assert_eq!((entry.added, entry.modified), (2, 1));
+
+        let dir = TempDir::new()?;
+        copy_dir(&root.path().join("v1").join(&entry.name), dir.path());
+        apply_patch(dir.path(), &patch)?;
+        assert_eq!(
+            scan_directory(dir.path())?,
+            scan_directory(&root.path().join("v2").join(&entry.name))?
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn patch_errors_are_classified_for_library_callers() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let missing = work.path().join("missing");
+    let err = apply_patch(&missing, &patch).unwrap_err();
+    assert!(matches!(&err, PatchError::TargetNotFound(path) if path == &missing));
+
+    let not_a_patch = write_file(work.path(), "notes.txt", b"plain text");
+    let err = apply_patch(source.path(), &not_a_patch).unwrap_err();
+    assert!(matches!(err, PatchError::InvalidArchive { .. }), "{}", err);
+
+    let stripped = work.path().join("stripped.tgz");
+    repack_patch(&patch, &stripped, |dir| {
+        fs::remove_file(dir.join("checksums.toml")).unwrap();
+    })?;
+    assert!(matches!(
+        show_patch(&stripped).unwrap_err(),
+        PatchError::MissingChecksums
+    ));
+
+    // Other errors keep their cause chain instead of a flattened message
+    let garbled = work.path().join("garbled.tgz");
+    repack_patch(&patch, &garbled, |dir| {
+        fs::write(dir.join("checksums.toml"), "added = 3").unwrap();
+    })?;
+    let err = show_patch(&garbled).unwrap_err();
+    assert!(matches!(err, PatchError::Other(_)), "{}", err);
+    assert!(std::error::Error::source(&err).is_some());
+
+    let dir = TempDir::new()?;
+    write_file(dir.path(), "config.toml", b"edited");
+    let policy = WarningPolicy::new().deny(WarningKind::ChecksumMismatch);
+    let options = ApplyOptions::new().with_warning_policy(policy);
+    match apply_patch_with_options(dir.path(), &patch, &options).unwrap_err() {
+        PatchError::ChecksumMismatch {
+            path,
+            expected,
+            actual,
+            reason,
+        } => {
+            assert_eq!(path, "config.toml");
+            assert_eq!(reason, MismatchReason::Denied);
+            assert_eq!(expected, compute_hash(b"old"));
+            assert_eq!(actual, compute_hash(b"edited"));
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+
+    // The variant survives the context added while merging
+    let err = merge_patches(&patch, &not_a_patch, &work.path().join("merged.tgz")).unwrap_err();
+    assert!(matches!(err, PatchError::InvalidArchive { .. }), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn modified_files_accept_any_registered_baseline() -> Result<()> {
+    let _guard = patch_lock();
+
+    let v10 = TempDir::new()?;
+    let v11 = TempDir::new()?;
+    let v2 = TempDir::new()?;
+    let data = pseudo_random_bytes(64 * 1024, 31);
+    let variant = |marker: &[u8]| {
+        let mut content = data.clone();
+        content.splice(100..100, marker.iter().copied());
+        content
+    };
+    write_file(v10.path(), "game.bin", &variant(b"1.0"));
+    write_file(v11.path(), "game.bin", &variant(b"1.1"));
+    write_file(v2.path(), "game.bin", &variant(b"2.0"));
+    // Unchanged between 1.0 and 2.0 but different in 1.1
+    write_file(v10.path(), "lib.txt", b"stable");
+    write_file(v11.path(), "lib.txt", b"hotfix");
+    write_file(v2.path(), "lib.txt", b"stable");
+
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    let options = CreateOptions::new().with_alternative_source(v11.path());
+    let report = create_patch_with_options(v10.path(), v2.path(), &patch, &options)?;
+    let game = &report.checksums.modified["game.bin"];
+    assert_eq!(game.alternatives, [compute_hash(&variant(b"1.1"))]);
+    assert_eq!(
+        report.checksums.modified["lib.txt"].alternatives,
+        [compute_hash(b"hotfix")]
+    );
+    assert!(report.checksums.delta_modified.is_empty());
+    assert_eq!(
+        Patch::open(&patch)?.checksums().modified["game.bin"]
+            .originals()
+            .count(),
+        2
+    );
+
+    for (baseline, marker) in [(&v10, b"1.0"), (&v11, b"1.1")] {
+        let dir = TempDir::new()?;
+        copy_dir(baseline.path(), dir.path());
+        let applied = apply_patch(dir.path(), &patch)?;
+        assert!(applied.warnings.is_empty(), "{:?}", applied.warnings);
+        assert_eq!(
+            applied.baselines.get("game.bin"),
+            Some(&compute_hash(&variant(marker)))
+        );
+        assert_eq!(scan_directory(dir.path())?, scan_directory(v2.path())?);
+    }
+    Ok(())
+}
+
+#[test]
+fn strict_apply_aborts_before_any_change_on_original_mismatch() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "a.txt", b"a old");
+    write_file(source.path(), "b.txt", b"b old");
+    write_file(source.path(), "gone.txt", b"gone");
+    write_file(target.path(), "a.txt", b"a new");
+    write_file(target.path(), "b.txt", b"b new");
+    write_file(target.path(), "added.txt", b"added");
+
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    write_file(dir.path(), "b.txt", b"b edited locally");
+    let strict = ApplyOptions::new().with_strict(true);
+    let err = apply_patch_with_options(dir.path(), &patch, &strict).unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            PatchError::ChecksumMismatch { path, reason: MismatchReason::Strict, .. } if path == "b.txt"
+        ),
+        "{err}"
+    );
+    assert!(!err.to_string().contains("checksum-mismatch"), "{err}");
+    // Nothing was touched, not even the files sorted before the mismatch
+    assert_eq!(fs::read(dir.path().join("a.txt"))?, b"a old");
+    assert!(dir.path().join("gone.txt").exists());
+    assert!(!dir.path().join("added.txt").exists());
+
+    // Without strict the mismatch is only a warning
+    let report = apply_patch(dir.path(), &patch)?;
+    assert!(!report.warnings.is_empty());
+    assert_eq!(fs::read(dir.path().join("a.txt"))?, b"a new");
+    Ok(())
+}
+
+#[test]
+fn broken_patches_report_what_kind_of_file_they_are() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(target.path(), "config.toml", b"new");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let reason = |path: &Path| match apply_patch(source.path(), path).unwrap_err() {
+        PatchError::InvalidArchive { reason, .. } => reason,
+        other => panic!("unexpected error: {other}"),
+    };
+    let empty = write_file(work.path(), "empty.tgz", b"");
+    assert_eq!(reason(&empty), ArchiveIssue::Empty);
+    let text = write_file(work.path(), "notes.tgz", b"plain text");
+    assert!(matches!(reason(&text), ArchiveIssue::NotCompressed { .. }));
+
+    // Gzip-compressed, but the payload is not a tar archive
+    let gzipped = work.path().join("gzipped.tgz");
+    let mut encoder =
+        flate2::write::GzEncoder::new(fs::File::create(&gzipped)?, flate2::Compression::default());
+    encoder.write_all(&pseudo_random_bytes(4096, 3))?;
+    encoder.finish()?;
+    let expected = ArchiveIssue::NotTar {
+        format: CompressionFormat::Gzip,
+    };
+    assert_eq!(reason(&gzipped), expected);
+    let err = Patch::open(&gzipped).unwrap_err();
+    assert!(
+        matches!(&err, PatchError::InvalidArchive { reason, .. } if *reason == expected),
+        "{err:#}"
+    );
+
+    // A truncated download is reported as corrupt data
+    let truncated = work.path().join("truncated.tgz");
+    let bytes = fs::read(&patch)?;
+    fs::write(&truncated, &bytes[..bytes.len() / 2])?;
+    assert!(matches!(reason(&truncated), ArchiveIssue::Corrupt(_)));
+    Ok(())
+}
+
+#[test]
+fn dry_run_lists_changes_without_touching_the_target() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(source.path(), "stale.txt", b"stale");
+    write_file(source.path(), "edited.txt", b"v1");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "edited.txt", b"v2");
+    write_file(target.path(), "mods/a.jar", b"jar");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    write_file(dir.path(), "edited.txt", b"local edit");
+    let marker = work.path().join("hook-ran");
+    let options = ApplyOptions::new()
+        .with_dry_run(true)
+        .with_pre_command(format!("touch {}", marker.display()));
+    let report = apply_patch_with_options(dir.path(), &patch, &options)?;
+    assert!(report.dry_run);
+    assert!(!report.read_only);
+    assert_eq!(report.added, ["mods/a.jar"]);
+    assert_eq!(report.modified, ["config.toml", "edited.txt"]);
+    assert_eq!(report.deleted, ["stale.txt"]);
+    assert_eq!(report.warnings.len(), 1, "{:?}", report.warnings);
+    assert!(report.warnings[0].contains("edited.txt"));
+
+    // Neither the target nor the hook was touched
+    assert_eq!(fs::read(dir.path().join("edited.txt"))?, b"local edit");
+    assert_eq!(fs::read(dir.path().join("config.toml"))?, b"old");
+    assert!(dir.path().join("stale.txt").exists());
+    assert!(!dir.path().join(".dft").exists());
+    assert!(!marker.exists());
+
+    // Applying into a new directory only plans against the base directory
+    let output = work.path().join("out");
+    let report = apply_patch_into(dir.path(), &output, &patch, &options)?;
+    assert!(report.dry_run);
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn injected_faults_interrupt_apply_and_resume_from_checkpoint() -> Result<()> {
+    use bin_diff_tool::testing::{DirTree, Fault, assert_dirs_equal, inject_fault, patch_fixture};
+
+    let _guard = patch_lock();
+    let mut v1 = DirTree::new();
+    let mut v2 = DirTree::new();
+    for i in 0..6 {
+        v1 = v1.random_file(format!("data/{i}.bin"), 2048, i);
+        v2 = v2.random_file(format!("data/{i}.bin"), 2048, i + 100);
+    }
+    let fixture = patch_fixture(&v1, &v2)?;
+    // One file per batch so the fault lands between two placed files
+    let options = ApplyOptions::new().with_checkpoint_interval(1);
+    let resume = options.clone().with_resume(true);
+
+    for fault in [Fault::Error, Fault::Panic] {
+        let target = v1.build()?;
+        let injected = inject_fault(2, fault);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            apply_patch_with_options(target.path(), &fixture.patch, &options)
+        }));
+        drop(injected);
+        match fault {
+            Fault::Error => assert!(result.is_ok_and(|applied| applied.is_err())),
+            Fault::Panic => assert!(result.is_err()),
+        }
+        // Only the entries before the fault were written and recorded
+        let changed = compare_directories(target.path(), &fixture.target)?.len();
+        assert_eq!(changed, 4, "{fault:?}");
+
+        apply_patch_with_options(target.path(), &fixture.patch, &resume)?;
+        assert_dirs_equal(target.path(), &fixture.target);
+    }
+    Ok(())
+}
+
+#[test]
+fn atomic_apply_rolls_back_every_change_on_failure() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "gone.txt", b"gone");
+    write_file(source.path(), "config.toml", b"old");
+    write_file(
+        source.path(),
+        "lib/old-name.jar",
+        &pseudo_random_bytes(8192, 1),
+    );
+    write_file(target.path(), "config.toml", b"new");
+    write_file(
+        target.path(),
+        "lib/new-name.jar",
+        &pseudo_random_bytes(8192, 1),
+    );
+    write_file(target.path(), "mods/a.jar", b"a");
+    write_file(target.path(), "mods/b.jar", b"b");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    // A local mods/b.jar makes the addition stage fail after the move and
+    // deletion stages already ran
+    let setup = |dir: &Path| {
+        copy_dir(source.path(), dir);
+        write_file(dir, "mods/b.jar", b"local");
+    };
+    let deny = WarningPolicy::new().deny(WarningKind::ExtraFile);
+    let options = ApplyOptions::new()
+        .with_warning_policy(deny)
+        .with_atomic(true);
+
+    let plain = TempDir::new()?;
+    setup(plain.path());
+    let partial = options.clone().with_atomic(false);
+    assert!(apply_patch_with_options(plain.path(), &patch, &partial).is_err());
+    assert!(!plain.path().join("gone.txt").exists());
+
+    let dir = TempDir::new()?;
+    setup(dir.path());
+    let before = scan_directory(dir.path())?;
+    assert!(apply_patch_with_options(dir.path(), &patch, &options).is_err());
+    assert_eq!(scan_directory(dir.path())?, before);
+    assert!(!dir.path().join("lib/new-name.jar").exists());
+    assert!(!dir.path().join(".dft/journal").exists());
+    assert!(!dir.path().join(".dft/staging").exists());
+
+    // Without the conflict the atomic apply goes through and cleans up
+    fs::remove_file(dir.path().join("mods/b.jar"))?;
+    apply_patch_with_options(dir.path(), &patch, &ApplyOptions::new().with_atomic(true))?;
+    assert!(compare_directories(dir.path(), target.path())?.is_empty());
+    assert!(!dir.path().join(".dft/journal").exists());
+    Ok(())
+}
+
+#[test]
+fn streaming_hash_matches_hash_of_the_whole_content() -> Result<()> {
+    let data = pseudo_random_bytes(100_000, 9);
+    let expected = compute_hash(&data);
+    assert_eq!(hash_reader(&data[..])?, expected);
+
+    // Chunks arriving one by one, teed into a file like a download
+    let dir = TempDir::new()?;
+    let mut file = fs::File::create(dir.path().join("patch.tgz"))?;
+    let mut stream = Sha256Stream::new();
+    for chunk in data.chunks(4096) {
+        file.write_all(chunk)?;
+        stream.update(chunk);
+    }
+    assert_eq!(stream.len(), data.len() as u64);
+    assert_eq!(stream.finish(), expected);
+
+    let mut stream = Sha256Stream::new();
+    std::io::copy(&mut &data[..], &mut stream)?;
+    assert_eq!(stream.finish(), expected);
+    assert_eq!(Sha256Stream::new().finish(), compute_hash(b""));
+    Ok(())
+}
+
+#[test]
+fn rollback_patch_captured_during_apply_reverts_the_update() -> Result<()> {
+    let _guard = patch_lock();
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "gone.txt", b"gone");
+    write_file(source.path(), "config.toml", b"old");
+    write_file(
+        source.path(),
+        "lib/old-name.jar",
+        &pseudo_random_bytes(8192, 3),
+    );
+    write_file(source.path(), "mods/kept.jar", b"kept");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(
+        target.path(),
+        "lib/new-name.jar",
+        &pseudo_random_bytes(8192, 3),
+    );
+    write_file(target.path(), "mods/kept.jar", b"kept");
+    write_file(target.path(), "mods/new.jar", b"new mod");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    let rollback = work.path().join("rollback.tgz");
+    let options = ApplyOptions::new().with_rollback(&rollback);
+    let report = apply_patch_with_options(dir.path(), &patch, &options)?;
+    assert_eq!(report.rollback.as_deref(), Some(rollback.as_path()));
+    assert!(compare_directories(dir.path(), target.path())?.is_empty());
+    assert!(!dir.path().join(".dft/rollback").exists());
+    assert!(validate_patch(&rollback)?.is_valid());
+
+    // The rollback patch is the update in reverse
+    let loaded = LoadedPatch::load(&rollback, work.path().join("inspect"))?;
+    let mut deleted = loaded.checksums.deleted.clone();
+    deleted.sort();
+    assert_eq!(deleted, ["lib/new-name.jar", "mods/new.jar"]);
+    let mut added: Vec<_> = loaded.checksums.added.keys().collect();
+    added.sort();
+    assert_eq!(added, ["gone.txt", "lib/old-name.jar"]);
+    let config = &loaded.checksums.modified["config.toml"];
+    assert_eq!(config.original, compute_hash(b"new"));
+    assert_eq!(config.modified, compute_hash(b"old"));
+    drop(loaded);
+
+    // Reverting refuses to touch anything once a file changed after the update
+    write_file(dir.path(), "config.toml", b"edited");
+    let before = scan_directory(dir.path())?;
+    assert!(revert_patch(dir.path(), &rollback).is_err());
+    assert_eq!(scan_directory(dir.path())?, before);
+
+    write_file(dir.path(), "config.toml", b"new");
+    revert_patch(dir.path(), &rollback)?;
+    assert!(compare_directories(dir.path(), source.path())?.is_empty());
+
+    // Rollback capture needs the whole apply in one run
+    let resume = options.clone().with_resume(true);
+    assert!(apply_patch_with_options(dir.path(), &patch, &resume).is_err());
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "cli",
+    feature = "network",
+    feature = "signing",
+    feature = "watch",
+    feature = "schema"
+))]
+#[test]
+fn cli_prints_each_applied_move_once() -> Result<()> {
+    use std::process::Command;
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let jar = pseudo_random_bytes(4096, 11);
+    write_file(source.path(), "mods/old.jar", &jar);
+    write_file(target.path(), "mods/new.jar", &jar);
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    {
+        let _guard = patch_lock();
+        create_patch(source.path(), target.path(), &patch)?;
+    }
+
+    let dir = work.path().join("dir");
+    copy_dir(source.path(), &dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_dft"))
+        .arg("apply")
+        .arg(&dir)
+        .arg("--patch")
+        .arg(&patch)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout.matches("  > mods/old.jar -> mods/new.jar").count(),
+        1
+    );
+    Ok(())
+}
+
+#[test]
+fn skipped_files_are_reported_through_events() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "options.txt", b"v1");
+    write_file(source.path(), "data.txt", b"v1");
+    write_file(target.path(), "options.txt", b"v2");
+    write_file(target.path(), "data.txt", b"v2");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    write_file(dir.path(), "data.txt", b"v2");
+    let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let seen = events.clone();
+    let options = ApplyOptions::new()
+        .with_protected("options.txt")
+        .with_events(EventBus::empty())
+        .with_event_sink(move |event: &PatchEvent| seen.lock().unwrap().push(event.clone()));
+    let report = apply_patch_with_options(dir.path(), &patch, &options)?;
+    assert_eq!(report.protected, vec!["options.txt"]);
+    assert_eq!(report.unchanged, vec!["data.txt"]);
+
+    let events = events.lock().unwrap();
+    assert!(
+        events
+            .iter()
+            .any(|e| matches!(e, PatchEvent::FileProtected { path } if path == "options.txt"))
+    );
+    assert!(
+        events
+            .iter()
+            .any(|e| matches!(e, PatchEvent::FileUnchanged { path } if path == "data.txt"))
+    );
+    Ok(())
+}
+
+#[test]
+fn atomic_apply_verifies_restored_deltas_before_touching_the_target() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let mut data = pseudo_random_bytes(256 * 1024, 33);
+    write_file(source.path(), "world/region.mca", &data);
+    write_file(source.path(), "gone.txt", b"gone");
+    data[100_000..100_016].copy_from_slice(b"patched region!!");
+    write_file(target.path(), "world/region.mca", &data);
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    let report = create_patch(source.path(), target.path(), &patch)?;
+    assert_eq!(report.checksums.delta_modified, vec!["world/region.mca"]);
+
+    // The delta cannot be restored on a locally changed base, which is caught while
+    // staging: the deletion stage never runs
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    write_file(dir.path(), "world/region.mca", b"local edits");
+    let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let seen = events.clone();
+    let options = ApplyOptions::new()
+        .with_atomic(true)
+        .with_events(EventBus::empty())
+        .with_event_sink(move |event: &PatchEvent| seen.lock().unwrap().push(event.clone()));
+    assert!(apply_patch_with_options(dir.path(), &patch, &options).is_err());
+    assert!(
+        !events
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|e| matches!(e, PatchEvent::FileDeleted { .. }))
+    );
+    assert!(dir.path().join("gone.txt").exists());
+    assert!(!dir.path().join(".dft/staging").exists());
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    apply_patch_with_options(dir.path(), &patch, &ApplyOptions::new().with_atomic(true))?;
+    assert!(compare_directories(dir.path(), target.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn strict_check_runs_before_the_pre_command() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "server.jar", b"v1");
+    write_file(target.path(), "server.jar", b"v2");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    write_file(dir.path(), "server.jar", b"local edit");
+    let pre = work.path().join("pre-ran");
+    let post = work.path().join("post-ran");
+    let options = ApplyOptions::new()
+        .with_strict(true)
+        .with_pre_command(format!("touch {}", pre.display()))
+        .with_post_command(format!("touch {}", post.display()));
+    let err = apply_patch_with_options(dir.path(), &patch, &options).unwrap_err();
+    assert!(matches!(err, PatchError::ChecksumMismatch { .. }), "{err}");
+    // The server was never stopped, so it is not restarted either
+    assert!(!pre.exists());
+    assert!(!post.exists());
+
+    write_file(dir.path(), "server.jar", b"v1");
+    let report = apply_patch_with_options(dir.path(), &patch, &options)?;
+    assert_eq!(report.hooks.len(), 2);
+    assert!(pre.exists() && post.exists());
+    Ok(())
+}
+
+#[test]
+fn strict_apply_rejects_a_missing_delta_base_before_any_change() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let mut data = pseudo_random_bytes(256 * 1024, 41);
+    write_file(source.path(), "big.bin", &data);
+    write_file(source.path(), "old.txt", b"old");
+    data[50_000..50_008].copy_from_slice(b"v2 bytes");
+    write_file(target.path(), "big.bin", &data);
+    write_file(target.path(), "new.txt", b"new");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    let report = create_patch(source.path(), target.path(), &patch)?;
+    assert_eq!(report.checksums.delta_modified, vec!["big.bin"]);
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    fs::remove_file(dir.path().join("big.bin"))?;
+    let before = scan_directory(dir.path())?;
+    let strict = ApplyOptions::new().with_strict(true);
+    let err = apply_patch_with_options(dir.path(), &patch, &strict).unwrap_err();
+    assert!(err.to_string().contains("big.bin"), "{err}");
+    assert_eq!(scan_directory(dir.path())?, before);
+    assert!(dir.path().join("old.txt").exists());
+    assert!(!dir.path().join("new.txt").exists());
+    Ok(())
+}
+
+#[test]
+fn delta_bases_are_checked_before_any_change() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let mut data = pseudo_random_bytes(256 * 1024, 43);
+    write_file(source.path(), "big.bin", &data);
+    write_file(source.path(), "old.txt", b"old");
+    data[50_000..50_008].copy_from_slice(b"v2 bytes");
+    write_file(target.path(), "big.bin", &data);
+    write_file(target.path(), "new.txt", b"new");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    // Neither a missing nor a locally edited base can be restored, even without strict
+    for local in [None, Some(&b"local edits"[..])] {
+        let dir = TempDir::new()?;
+        copy_dir(source.path(), dir.path());
+        match local {
+            Some(content) => {
+                write_file(dir.path(), "big.bin", content);
+            }
+            None => fs::remove_file(dir.path().join("big.bin"))?,
+        }
+        let before = scan_directory(dir.path())?;
+        let err = apply_patch(dir.path(), &patch).unwrap_err();
+        assert!(err.to_string().contains("big.bin"), "{err}");
+        assert!(!err.to_string().contains("直接写入"), "{err}");
+        assert_eq!(scan_directory(dir.path())?, before);
+    }
+    Ok(())
+}
+
+#[test]
+fn atomic_rollback_restores_attributes_changed_in_place() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "x.sh", b"#!/bin/sh\n");
+    write_file(target.path(), "x.sh", b"#!/bin/sh\n");
+    let original = FileAttributes {
+        mode: cfg!(unix).then_some(0o644),
+        modified: Some(1_600_000_000),
+    };
+    original.apply_to(&source.path().join("x.sh"))?;
+    FileAttributes {
+        mode: cfg!(unix).then_some(0o755),
+        modified: Some(1_700_000_000),
+    }
+    .apply_to(&target.path().join("x.sh"))?;
+    let work = TempDir::new()?;
+    let patch = work.path().join("attrs.tgz");
+    let options = CreateOptions::new()
+        .with_track_attributes(true)
+        .with_include_metadata_only(true);
+    create_patch_with_options(source.path(), target.path(), &patch, &options)?;
+
+    // Writing the rollback patch fails after the attribute stage already ran
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    original.apply_to(&dir.path().join("x.sh"))?;
+    let blocker = write_file(work.path(), "not-a-dir", b"");
+    let options = ApplyOptions::new()
+        .with_atomic(true)
+        .with_rollback(blocker.join("rollback.tgz"));
+    assert!(apply_patch_with_options(dir.path(), &patch, &options).is_err());
+    assert_eq!(FileAttributes::read(&dir.path().join("x.sh"))?, original);
+    Ok(())
+}
+
+#[test]
+fn atomic_apply_stages_and_verifies_renamed_deltas() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    let original = pseudo_random_bytes(256 * 1024, 45);
+    let mut changed = original.clone();
+    changed.splice(100_000..100_010, b"inserted bytes".iter().copied());
+    write_file(source.path(), "libs/mod-1.0.jar", &original);
+    write_file(source.path(), "old.txt", b"old");
+    write_file(target.path(), "mods/mod-1.1.jar", &changed);
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    let report = create_patch(source.path(), target.path(), &patch)?;
+    assert!(report.checksums.renamed.contains_key("mods/mod-1.1.jar"));
+
+    // A locally edited rename source only warns, but the rebuilt file does not match
+    // the patch, which the atomic apply catches while staging
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    let mut edited = original.clone();
+    edited[200_000..200_005].copy_from_slice(b"local");
+    write_file(dir.path(), "libs/mod-1.0.jar", &edited);
+    let before = scan_directory(dir.path())?;
+    let atomic = ApplyOptions::new().with_atomic(true);
+    let err = apply_patch_with_options(dir.path(), &patch, &atomic).unwrap_err();
+    assert!(err.to_string().contains("mods/mod-1.1.jar"), "{err}");
+    assert_eq!(scan_directory(dir.path())?, before);
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    apply_patch_with_options(dir.path(), &patch, &atomic)?;
+    assert!(compare_directories(dir.path(), target.path())?.is_empty());
+    assert!(!dir.path().join(".dft/staging").exists());
+    Ok(())
+}
+
+#[test]
+fn platform_aliases_are_recognised_and_unknown_tags_rejected() -> Result<()> {
+    let _guard = patch_lock();
+    assert_eq!(
+        Platform::parse("macos-arm64"),
+        Platform::parse("macos-aarch64")
+    );
+    assert_eq!(
+        Platform::parse("linux-amd64"),
+        Platform::parse("linux-x86_64")
+    );
+    assert!(Platform::parse("linux-riscv64").is_some());
+    assert!(Platform::parse("beos-x86").is_none());
+
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "common.txt", b"same");
+    write_file(target.path(), "common.txt", b"same");
+    let other = if std::env::consts::OS == "macos" {
+        "windows-amd64"
+    } else {
+        "macos-arm64"
+    };
+    write_file(target.path(), &format!("{}/native.dylib", other), b"theirs");
+    let work = TempDir::new()?;
+    let patch = work.path().join("native.tgz");
+    let report = create_patch(source.path(), target.path(), &patch)?;
+    let path = format!("{}/native.dylib", other);
+    assert!(report.checksums.platforms.contains_key(&path));
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    let applied = apply_patch(dir.path(), &patch)?;
+    assert_eq!(applied.skipped, vec![path.clone()]);
+    assert!(!dir.path().join(&path).exists());
+
+    // A tag the tool does not understand is an error, not an entry for every platform
+    let tag = format!("= \"{}\"", report.checksums.platforms[&path]);
+    let tampered = work.path().join("tampered.tgz");
+    repack_patch(&patch, &tampered, |dir| {
+        let checksums = dir.join("checksums.toml");
+        let text = fs::read_to_string(&checksums).unwrap();
+        assert!(text.contains(&tag));
+        fs::write(&checksums, text.replace(&tag, "= \"beos-x86\"")).unwrap();
+    })?;
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    let err = apply_patch(dir.path(), &tampered).unwrap_err();
+    assert!(err.to_string().contains("beos-x86"), "{err}");
+    assert!(!dir.path().join(&path).exists());
+    Ok(())
+}
+
+#[test]
+fn helper_apis_return_patch_errors() -> Result<()> {
+    let dir = TempDir::new()?;
+    let missing = dir.path().join("missing");
+
+    let err: PatchError = bin_diff_tool::patch::MultiSpec::load(&missing).unwrap_err();
+    assert!(matches!(err, PatchError::Io { .. }), "{err}");
+    let err: PatchError = Snapshot::load(&missing).unwrap_err();
+    assert!(matches!(err, PatchError::Io { .. }), "{err}");
+    Ok(())
+}
+
+#[test]
+fn dry_run_plan_is_emitted_as_events() -> Result<()> {
+    let _guard = patch_lock();
+    let source = TempDir::new()?;
+    let target = TempDir::new()?;
+    write_file(source.path(), "config.toml", b"old");
+    write_file(source.path(), "stale.txt", b"stale");
+    write_file(target.path(), "config.toml", b"new");
+    write_file(target.path(), "mods/a.jar", b"jar");
+    let work = TempDir::new()?;
+    let patch = work.path().join("patch.tgz");
+    create_patch(source.path(), target.path(), &patch)?;
+
+    let dir = TempDir::new()?;
+    copy_dir(source.path(), dir.path());
+    let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let seen = events.clone();
+    let options = ApplyOptions::new()
+        .with_dry_run(true)
+        .with_events(EventBus::empty())
+        .with_event_sink(move |event: &PatchEvent| seen.lock().unwrap().push(event.clone()));
+    apply_patch_with_options(dir.path(), &patch, &options)?;
+
+    // The plan is reported through the sink, never as real changes
+    let events = events.lock().unwrap();
+    let planned = |wanted: &str| {
+        events.iter().any(|e| match e {
+            PatchEvent::AddPlanned { path } => wanted == format!("+{path}"),
+            PatchEvent::ModifyPlanned { path } => wanted == format!("*{path}"),
+            PatchEvent::DeletePlanned { path } => wanted == format!("-{path}"),
+            _ => false,
+        })
+    };
+    assert!(planned("+mods/a.jar"), "{events:?}");
+    assert!(planned("*config.toml"), "{events:?}");
+    assert!(planned("-stale.txt"), "{events:?}");
+    assert!(!events.iter().any(|e| matches!(
+        e,
+        PatchEvent::FileAdded { .. }
+            | PatchEvent::FileModified { .. }
+            | PatchEvent::FileDeleted { .. }
+    )));
+    Ok(())
+}